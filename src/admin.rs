@@ -1,6 +1,9 @@
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use parking_lot::Mutex;
 use serde_json::json;
 use tracing::{debug, info, warn};
 
@@ -8,22 +11,61 @@ use crate::config::Config;
 use crate::db::DatabaseManager;
 use crate::matrix::MatrixAppservice;
 
+/// An identical alert is sent at most once per window.
+const ALERT_DEDUP_WINDOW: Duration = Duration::from_secs(3600);
+/// At most `ALERT_RATE_LIMIT` alerts of any kind go out per `ALERT_RATE_WINDOW`.
+const ALERT_RATE_LIMIT: usize = 5;
+const ALERT_RATE_WINDOW: Duration = Duration::from_secs(600);
+
 #[derive(Debug, Clone)]
 pub struct AdminConfig {
     pub admin_mxid: Option<String>,
+    pub invalid_token_message: String,
     pub notify_on_token_invalid: bool,
     pub notify_on_errors: bool,
+    /// Errors within `error_window` that trigger an error alert.
     pub error_threshold: u32,
+    pub error_window: Duration,
 }
 
 impl Default for AdminConfig {
     fn default() -> Self {
         Self {
             admin_mxid: None,
+            invalid_token_message: "Your Slack bot token appears to be invalid. The bridge cannot function properly.\n\nPlease update your bot token in the configuration and restart the bridge.".to_string(),
             notify_on_token_invalid: true,
             notify_on_errors: true,
             error_threshold: 5,
+            error_window: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Drops alerts that repeat within `ALERT_DEDUP_WINDOW` or exceed the overall rate.
+#[derive(Default)]
+struct AlertThrottle {
+    last_sent: HashMap<String, Instant>,
+    recent: VecDeque<Instant>,
+}
+
+impl AlertThrottle {
+    fn allow(&mut self, message: &str, now: Instant) -> bool {
+        self.last_sent
+            .retain(|_, sent| now.duration_since(*sent) < ALERT_DEDUP_WINDOW);
+        while self
+            .recent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= ALERT_RATE_WINDOW)
+        {
+            self.recent.pop_front();
         }
+
+        if self.last_sent.contains_key(message) || self.recent.len() >= ALERT_RATE_LIMIT {
+            return false;
+        }
+        self.last_sent.insert(message.to_string(), now);
+        self.recent.push_back(now);
+        true
     }
 }
 
@@ -31,7 +73,10 @@ pub struct AdminNotifier {
     config: AdminConfig,
     matrix: Arc<MatrixAppservice>,
     db: Arc<DatabaseManager>,
-    error_count: std::sync::atomic::AtomicU32,
+    errors: Mutex<VecDeque<Instant>>,
+    throttle: Mutex<AlertThrottle>,
+    /// DM room with the admin, looked up or created on the first alert.
+    dm_room: tokio::sync::Mutex<Option<String>>,
 }
 
 impl AdminNotifier {
@@ -44,7 +89,9 @@ impl AdminNotifier {
             config,
             matrix,
             db,
-            error_count: std::sync::atomic::AtomicU32::new(0),
+            errors: Mutex::new(VecDeque::new()),
+            throttle: Mutex::new(AlertThrottle::default()),
+            dm_room: tokio::sync::Mutex::new(None),
         }
     }
 
@@ -55,42 +102,79 @@ impl AdminNotifier {
     ) -> Self {
         let admin_config = AdminConfig {
             admin_mxid: config.bridge.admin_mxid.clone(),
-            notify_on_token_invalid: true,
-            notify_on_errors: true,
-            error_threshold: 5,
+            invalid_token_message: config.bridge.invalid_token_message.clone(),
+            ..AdminConfig::default()
         };
         Self::new(admin_config, matrix, db)
     }
 
     pub async fn notify_token_invalid(&self) -> Result<()> {
-        if !self.config.notify_on_token_invalid {
+        let Some((admin_mxid, message)) = self.token_invalid_alert() else {
             return Ok(());
+        };
+        self.send_admin_message(admin_mxid, &message).await
+    }
+
+    /// Like [`AdminNotifier::notify_token_invalid`], but delivers in the background. The
+    /// throttle is applied before returning, so repeats never get as far as a task.
+    pub fn spawn_token_invalid_alert(self: Arc<Self>) {
+        let Some((admin_mxid, message)) = self.token_invalid_alert() else {
+            return;
+        };
+        if !self.allow_alert(admin_mxid, &message) {
+            return;
+        }
+        let admin_mxid = admin_mxid.to_string();
+        tokio::spawn(async move {
+            if let Err(err) = self.deliver(&admin_mxid, &message).await {
+                warn!("failed to notify admin about the invalid token: {}", err);
+            }
+        });
+    }
+
+    fn token_invalid_alert(&self) -> Option<(&str, String)> {
+        if !self.config.notify_on_token_invalid {
+            return None;
         }
 
         let Some(ref admin_mxid) = self.config.admin_mxid else {
             warn!("Token invalid but no admin_mxid configured");
-            return Ok(());
+            return None;
         };
 
         info!("Sending token invalid notification to {}", admin_mxid);
 
-        let message = "⚠️ **Slack Bot Token Invalid**\n\nYour Slack bot token appears to be invalid. The bridge cannot function properly.\n\nPlease update your bot token in the configuration and restart the bridge.";
-
-        self.send_admin_message(admin_mxid, message).await
+        let message = format!(
+            "⚠️ **Slack Bot Token Invalid**\n\n{}",
+            self.config.invalid_token_message
+        );
+        Some((admin_mxid, message))
     }
 
+    /// Records an error and alerts the admin once `error_threshold` errors happened
+    /// within `error_window`.
     pub async fn notify_error(&self, error: &str, context: Option<&str>) -> Result<()> {
         if !self.config.notify_on_errors {
             return Ok(());
         }
 
-        let count = self
-            .error_count
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-
-        if count < self.config.error_threshold {
-            return Ok(());
-        }
+        let count = {
+            let now = Instant::now();
+            let mut errors = self.errors.lock();
+            while errors
+                .front()
+                .is_some_and(|at| now.duration_since(*at) >= self.config.error_window)
+            {
+                errors.pop_front();
+            }
+            errors.push_back(now);
+            if errors.len() < self.config.error_threshold as usize {
+                return Ok(());
+            }
+            let count = errors.len();
+            errors.clear();
+            count
+        };
 
         let Some(ref admin_mxid) = self.config.admin_mxid else {
             return Ok(());
@@ -101,24 +185,20 @@ impl AdminNotifier {
             admin_mxid, count
         );
 
+        let minutes = self.config.error_window.as_secs().div_ceil(60);
         let message = if let Some(ctx) = context {
             format!(
-                "⚠️ **Bridge Error**\n\nError: {}\nContext: {}\n\nThis is error #{} since the last notification.",
-                error, ctx, count
+                "⚠️ **Bridge Errors**\n\n{} errors in the last {} minutes.\n\nLatest error: {}\nContext: {}",
+                count, minutes, error, ctx
             )
         } else {
             format!(
-                "⚠️ **Bridge Error**\n\nError: {}\n\nThis is error #{} since the last notification.",
-                error, count
+                "⚠️ **Bridge Errors**\n\n{} errors in the last {} minutes.\n\nLatest error: {}",
+                count, minutes, error
             )
         };
 
-        self.send_admin_message(admin_mxid, &message).await?;
-
-        self.error_count
-            .store(0, std::sync::atomic::Ordering::Relaxed);
-
-        Ok(())
+        self.send_admin_message(admin_mxid, &message).await
     }
 
     pub async fn notify_bridge_paused(&self, reason: &str) -> Result<()> {
@@ -150,7 +230,7 @@ impl AdminNotifier {
         };
 
         let message = format!(
-            "📊 **User Limit Reached**\n\nThe bridge has reached its user limit.\n\nCurrent users: {}\nLimit: {}",
+            "📊 **User Limit Reached**\n\nThe bridge has reached its user limit and stopped bridging messages.\n\nCurrent users: {}\nLimit: {}",
             current, limit
         );
 
@@ -158,22 +238,41 @@ impl AdminNotifier {
     }
 
     async fn send_admin_message(&self, admin_mxid: &str, message: &str) -> Result<()> {
-        match self.matrix.create_dm_room(admin_mxid).await {
-            Ok(room_id) => {
-                self.matrix.send_notice(&room_id, message).await?;
-                info!("Sent admin notification to {}", admin_mxid);
-            }
-            Err(e) => {
-                warn!("Failed to create DM room for admin {}: {}", admin_mxid, e);
-                return Err(e);
-            }
+        if !self.allow_alert(admin_mxid, message) {
+            return Ok(());
         }
+        self.deliver(admin_mxid, message).await
+    }
+
+    fn allow_alert(&self, admin_mxid: &str, message: &str) -> bool {
+        let allowed = self.throttle.lock().allow(message, Instant::now());
+        if !allowed {
+            debug!("Suppressed repeated admin notification to {}", admin_mxid);
+        }
+        allowed
+    }
+
+    async fn deliver(&self, admin_mxid: &str, message: &str) -> Result<()> {
+        let mut dm_room = self.dm_room.lock().await;
+        let room_id = match dm_room.as_ref() {
+            Some(room_id) => room_id.clone(),
+            None => match self.matrix.ensure_bot_dm_room(admin_mxid).await {
+                Ok(room_id) => dm_room.insert(room_id).clone(),
+                Err(e) => {
+                    warn!("Failed to create DM room for admin {}: {}", admin_mxid, e);
+                    return Err(e);
+                }
+            },
+        };
+        drop(dm_room);
+
+        self.matrix.send_notice(&room_id, message).await?;
+        info!("Sent admin notification to {}", admin_mxid);
         Ok(())
     }
 
     pub fn reset_error_count(&self) {
-        self.error_count
-            .store(0, std::sync::atomic::Ordering::Relaxed);
+        self.errors.lock().clear();
     }
 
     pub fn has_admin_configured(&self) -> bool {
@@ -192,4 +291,25 @@ mod tests {
         assert!(config.notify_on_token_invalid);
         assert!(config.notify_on_errors);
     }
+
+    #[test]
+    fn throttle_drops_repeats_until_the_dedup_window_passes() {
+        let mut throttle = AlertThrottle::default();
+        let start = Instant::now();
+        assert!(throttle.allow("token invalid", start));
+        assert!(!throttle.allow("token invalid", start + Duration::from_secs(60)));
+        assert!(throttle.allow("paused", start + Duration::from_secs(60)));
+        assert!(throttle.allow("token invalid", start + ALERT_DEDUP_WINDOW));
+    }
+
+    #[test]
+    fn throttle_caps_distinct_alerts_per_rate_window() {
+        let mut throttle = AlertThrottle::default();
+        let start = Instant::now();
+        for n in 0..ALERT_RATE_LIMIT {
+            assert!(throttle.allow(&format!("alert {n}"), start));
+        }
+        assert!(!throttle.allow("one more", start + Duration::from_secs(1)));
+        assert!(throttle.allow("one more", start + ALERT_RATE_WINDOW));
+    }
 }
@@ -8,36 +8,49 @@ use chrono::Utc;
 use serde_json::{json, Value};
 use tracing::{debug, info, warn};
 
+use crate::admin::AdminNotifier;
 use crate::cache::AsyncTimedCache;
-use crate::db::{DatabaseManager, MessageMapping, RoomMapping};
+use crate::config::SlackReplyMode;
+use crate::db::{DatabaseManager, MessageMapping, ReactionMapping, RoomMapping};
 use crate::slack::{
-    SlackClient, SlackCommandHandler, SlackCommandOutcome, ModerationAction,
+    SlackClient, SlackCommandHandler, SlackCommandOutcome, SlackUser, ModerationAction,
+    is_slack_error,
 };
 use crate::emoji::EmojiHandler;
 use crate::matrix::{MatrixAppservice, MatrixCommandHandler, MatrixCommandOutcome, MatrixEvent};
-use crate::media::MediaHandler;
+use crate::media::{MediaHandler, MediaProxy};
 
 pub mod backfill;
 pub mod blocker;
+pub mod dedup;
+pub mod direct;
 pub mod logic;
 pub mod message_flow;
+pub mod portal;
 pub mod presence_handler;
 pub mod provisioning;
+pub mod puppet;
 pub mod queue;
 pub mod user_sync;
 
+use self::backfill::BackfillingRooms;
+use self::dedup::EventDeduplicator;
 use self::logic::{
-    action_keyword, apply_message_relation_mappings, build_slack_typing_request,
-    slack_delete_redaction_request, preview_text, should_forward_slack_typing,
+    SlackFileRef, action_keyword, apply_message_relation_mappings,
+    build_slack_delete_redaction_request, build_slack_typing_request, file_mapping_key,
+    is_file_mapping_key, slack_delete_redaction_request, slack_file_refs, slack_thread_root, preview_text, should_forward_slack_typing,
 };
 use self::message_flow::{
-    SlackInboundMessage, MessageFlow, OutboundSlackMessage, OutboundMatrixMessage,
+    SlackInboundMessage, MessageFlow, MessageRelation, OutboundSlackMessage,
+    OutboundMatrixMessage,
 };
 use self::presence_handler::{
     SlackPresence, MatrixPresenceState, MatrixPresenceTarget, PresenceHandler,
 };
 use self::provisioning::{ApprovalResponseStatus, ProvisioningCoordinator, ProvisioningError};
+use self::puppet::{PendingLogins, TokenCipher};
 use self::queue::ChannelQueue;
+use self::user_sync::UserSyncHandler;
 
 #[derive(Debug, Clone)]
 pub struct SlackMessageContext {
@@ -50,12 +63,26 @@ pub struct SlackMessageContext {
     pub edit_of: Option<String>,
     pub permissions: HashSet<String>,
     pub thread_ts: Option<String>,
+    /// Thread reply also sent to the channel ("also send to #channel").
+    pub reply_broadcast: bool,
+    /// Original send time in unix ms of a message replayed by backfill. Such messages keep
+    /// their Slack time on Matrix and are never run as commands.
+    pub backfill_ts: Option<u64>,
     pub blocks: Option<serde_json::Value>,
     pub slack_attachments: Option<Vec<serde_json::Value>>,
     pub files: Option<Vec<serde_json::Value>>,
 }
 
 const ROOM_CACHE_TTL_SECS: u64 = 900;
+/// Slack users stop being polled for presence after this long without posting.
+const PRESENCE_WATCH_TTL: Duration = Duration::from_secs(24 * 3600);
+
+struct UploadedAttachment {
+    mxc_url: String,
+    filename: String,
+    content_type: String,
+    size: Option<u64>,
+}
 
 #[derive(Clone)]
 pub struct BridgeCore {
@@ -66,11 +93,20 @@ pub struct BridgeCore {
     matrix_command_handler: Arc<MatrixCommandHandler>,
     slack_command_handler: Arc<SlackCommandHandler>,
     presence_handler: Arc<PresenceHandler>,
+    user_sync: Arc<UserSyncHandler>,
     provisioning: Arc<ProvisioningCoordinator>,
     media_handler: Arc<MediaHandler>,
     emoji_handler: Arc<EmojiHandler>,
     message_queue: Arc<ChannelQueue>,
+    portal_queue: Arc<ChannelQueue>,
     room_cache: Arc<AsyncTimedCache<String, RoomMapping>>,
+    deduplicator: Arc<EventDeduplicator>,
+    media_proxy: Option<MediaProxy>,
+    blocker: Arc<blocker::BridgeBlocker>,
+    admin_notifier: Arc<AdminNotifier>,
+    token_cipher: TokenCipher,
+    pending_logins: PendingLogins,
+    backfilling_rooms: BackfillingRooms,
 }
 
 impl BridgeCore {
@@ -81,8 +117,14 @@ impl BridgeCore {
     ) -> Self {
         let bridge_config = matrix_client.config().bridge.clone();
         let homeserver_url = matrix_client.config().bridge.homeserver_url.clone();
+        let retention_hours = matrix_client.config().limits.processed_event_retention_hours;
 
         let media_handler = Arc::new(MediaHandler::new(&homeserver_url));
+        let admin_notifier = Arc::new(AdminNotifier::from_config(
+            matrix_client.config(),
+            matrix_client.clone(),
+            db_manager.clone(),
+        ));
         let emoji_handler = Arc::new(EmojiHandler::new(
             db_manager.clone(),
             media_handler.clone(),
@@ -101,34 +143,106 @@ impl BridgeCore {
             )),
             slack_command_handler: Arc::new(SlackCommandHandler::new()),
             presence_handler: Arc::new(PresenceHandler::new(None)),
+            user_sync: Arc::new(UserSyncHandler::new(
+                db_manager.clone(),
+                matrix_client.clone(),
+                matrix_client.config(),
+            )),
             provisioning: Arc::new(ProvisioningCoordinator::default()),
             media_handler,
             emoji_handler,
             message_queue: Arc::new(ChannelQueue::new()),
+            portal_queue: Arc::new(ChannelQueue::new()),
             room_cache: Arc::new(AsyncTimedCache::new(Duration::from_secs(
                 ROOM_CACHE_TTL_SECS,
             ))),
+            deduplicator: Arc::new(EventDeduplicator::new(
+                db_manager.processed_event_store(),
+                Duration::from_secs(retention_hours * 3600),
+            )),
+            media_proxy: MediaProxy::from_config(&matrix_client.config()),
+            blocker: Arc::new(blocker::BridgeBlocker::new(
+                db_manager.clone(),
+                matrix_client.config(),
+                admin_notifier.clone(),
+            )),
+            admin_notifier,
+            token_cipher: TokenCipher::from_config(&matrix_client.config()),
+            pending_logins: PendingLogins::default(),
+            backfilling_rooms: BackfillingRooms::default(),
             matrix_client,
             slack_client,
             db_manager,
         }
     }
 
+    fn is_bridge_admin(&self, matrix_user_id: &str) -> bool {
+        self.matrix_client.config().bridge.admin_mxid.as_deref() == Some(matrix_user_id)
+    }
+
+    pub fn deduplicator(&self) -> Arc<EventDeduplicator> {
+        self.deduplicator.clone()
+    }
+
+    pub fn media_proxy(&self) -> Option<&MediaProxy> {
+        self.media_proxy.as_ref()
+    }
+
+    /// A URL Slack can fetch for `url`: `mxc://` URIs go through the signed media proxy
+    /// when it is enabled and the homeserver's legacy download path otherwise.
+    fn slack_media_url(&self, url: &str) -> String {
+        self.proxied_media_url(url, |proxy, now| proxy.url_for(url, now))
+    }
+
+    /// Like [`BridgeCore::slack_media_url`], but proxied links only change once per TTL
+    /// window so Slack keeps its cached copy of the avatar.
+    fn slack_avatar_url(&self, url: &str) -> String {
+        self.proxied_media_url(url, |proxy, now| proxy.stable_url_for(url, now))
+    }
+
+    fn proxied_media_url(
+        &self,
+        url: &str,
+        sign: impl FnOnce(&MediaProxy, i64) -> Option<String>,
+    ) -> String {
+        if !url.starts_with("mxc://") {
+            return url.to_string();
+        }
+        if let Some(proxied) = self
+            .media_proxy
+            .as_ref()
+            .and_then(|proxy| sign(proxy, Utc::now().timestamp()))
+        {
+            return proxied;
+        }
+        let homeserver = &self.matrix_client.config().bridge.homeserver_url;
+        format!(
+            "{}/_matrix/media/r0/download/{}",
+            homeserver.trim_end_matches('/'),
+            url.trim_start_matches("mxc://")
+        )
+    }
+
     pub async fn start(&self) -> Result<()> {
         self.matrix_client.start().await?;
         self.slack_client.start().await?;
 
         info!("bridge core started");
 
+        tokio::spawn(self.deduplicator.clone().run_pruner());
+        tokio::spawn(self.blocker.clone().run_checker());
+
         let bridge_config = self.matrix_client.config().bridge.clone();
+        if !bridge_config.disable_presence {
+            tokio::spawn(self.clone().run_presence_poller());
+        }
+
         let presence_interval_ms = bridge_config.presence_interval.max(250);
         let mut ticker = tokio::time::interval(Duration::from_millis(presence_interval_ms));
         loop {
             ticker.tick().await;
             if !bridge_config.disable_presence {
-                self.presence_handler
-                    .process_next(self.matrix_client.as_ref())
-                    .await?;
+                self.process_next_presence().await?;
             }
         }
     }
@@ -145,6 +259,8 @@ impl BridgeCore {
                 content,
                 reply_to: None,
                 edit_of: None,
+                thread_ts: None,
+                quote_permalink: None,
                 attachments: Vec::new(),
                 embed: None,
                 use_embed: false,
@@ -167,7 +283,9 @@ impl BridgeCore {
                 formatted_body: None,
                 reply_to: None,
                 edit_of: None,
+                thread_root: None,
                 attachments: Vec::new(),
+                origin_ts: None,
             },
         )
         .await
@@ -279,6 +397,20 @@ impl BridgeCore {
             );
             return Ok(());
         };
+        if let Some(notice) = self.blocker.blocked_notice().await {
+            debug!(
+                "matrix inbound dropped room_id={} reason=bridge_blocked",
+                event.room_id
+            );
+            if self.blocker.should_notify(&event.room_id).await {
+                self.matrix_client
+                    .send_notice(&event.room_id, &notice)
+                    .await?;
+            }
+            return Ok(());
+        }
+        // Dropped messages don't count, or a limited bridge could never drop below its limit.
+        self.blocker.record_active_user(&event.sender).await;
         let Some(message) = MessageFlow::parse_matrix_event(event) else {
             debug!(
                 "matrix inbound dropped room_id={} event_id={:?} reason=unsupported_or_unparseable",
@@ -287,13 +419,29 @@ impl BridgeCore {
             return Ok(());
         };
 
-        let outbound = self.message_flow.matrix_to_slack(&message);
+        let mut outbound = self.message_flow.matrix_to_slack(&message);
+        match &message.relation {
+            Some(MessageRelation::Thread { root_event_id }) => {
+                outbound.thread_ts = self
+                    .get_reply_info(root_event_id)
+                    .await?
+                    .and_then(|target| slack_thread_root(&target));
+            }
+            Some(MessageRelation::Reply { event_id }) => {
+                if let Some(target) = self.get_reply_info(event_id).await? {
+                    self.route_reply_to_slack(&mut outbound, &mapping, &target)
+                        .await;
+                }
+            }
+            _ => {}
+        }
         debug!(
-            "matrix->slack outbound prepared room_id={} slack_channel={} reply_to={:?} edit_of={:?} attachments={} content_len={} content_preview={}",
+            "matrix->slack outbound prepared room_id={} slack_channel={} reply_to={:?} edit_of={:?} thread_ts={:?} attachments={} content_len={} content_preview={}",
             mapping.matrix_room_id,
             mapping.slack_channel_id,
             outbound.reply_to,
             outbound.edit_of,
+            outbound.thread_ts,
             outbound.attachments.len(),
             outbound.content.len(),
             preview_text(&outbound.content)
@@ -303,26 +451,87 @@ impl BridgeCore {
             .download_matrix_attachments(&outbound.attachments)
             .await;
 
-        self.send_to_slack_with_attachments(
-            &mapping.slack_channel_id,
-            outbound,
-            &event.sender,
-            downloaded_attachments,
-        )
-        .await?;
+        let is_edit = outbound.edit_of.is_some();
+        let slack_thread_ts = outbound.thread_ts.clone();
+        let slack_ts = self
+            .send_to_slack_with_attachments(
+                &mapping.slack_channel_id,
+                outbound,
+                &event.sender,
+                downloaded_attachments,
+            )
+            .await?;
+        self.blocker.record_message_sent().await;
+
+        // Remembered so Slack thread replies and Matrix threads can find this message.
+        if !is_edit
+            && let (Some(ts), Some(event_id)) = (slack_ts, event.event_id.clone())
+        {
+            self.db_manager
+                .message_store()
+                .upsert_message_mapping(&MessageMapping {
+                    id: 0,
+                    slack_message_id: ts,
+                    slack_channel_id: mapping.slack_channel_id.clone(),
+                    slack_team_id: mapping.slack_team_id.clone(),
+                    matrix_room_id: mapping.matrix_room_id.clone(),
+                    matrix_event_id: event_id,
+                    slack_thread_ts,
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                })
+                .await?;
+        }
         Ok(())
     }
 
+    /// Places a Matrix reply on Slack. Replies to a message inside a thread go to that
+    /// thread; replies to a top-level message follow the room's reply mode.
+    async fn route_reply_to_slack(
+        &self,
+        outbound: &mut OutboundSlackMessage,
+        mapping: &RoomMapping,
+        target: &MessageMapping,
+    ) {
+        if let Some(thread_ts) = &target.slack_thread_ts {
+            outbound.thread_ts = Some(thread_ts.clone());
+            return;
+        }
+        if is_file_mapping_key(&target.slack_message_id) {
+            return;
+        }
+        let reply_mode = self
+            .matrix_client
+            .config()
+            .bridge
+            .reply_mode_for(&mapping.matrix_room_id, &mapping.slack_channel_id);
+        match reply_mode {
+            SlackReplyMode::Thread => outbound.thread_ts = Some(target.slack_message_id.clone()),
+            SlackReplyMode::Quote => match self
+                .slack_client
+                .get_permalink(&target.slack_channel_id, &target.slack_message_id)
+                .await
+            {
+                Ok(permalink) => outbound.quote_permalink = Some(permalink),
+                Err(err) => warn!(
+                    "failed to get permalink for slack message {} in {}: {}",
+                    target.slack_message_id, target.slack_channel_id, err
+                ),
+            },
+        }
+    }
+
     async fn download_matrix_attachments(
         &self,
         urls: &[String],
     ) -> Vec<(String, Option<crate::media::MediaInfo>)> {
+        let max_size = self.matrix_client.config().limits.max_matrix_to_slack_file_bytes;
         let mut results = Vec::new();
         for url in urls {
             if url.starts_with("mxc://") {
                 match self.media_handler.download_matrix_media(url).await {
                     Ok(media) => {
-                        if media.size > 8 * 1024 * 1024 {
+                        if media.size as u64 > max_size {
                             warn!(
                                 "matrix attachment too large for slack: {} bytes, sending URL instead",
                                 media.size
@@ -344,25 +553,13 @@ impl BridgeCore {
         results
     }
 
-    async fn get_reply_info(&self, matrix_event_id: &str) -> Option<(String, String)> {
-        let mapping = self
+    /// Mapping of the Slack message a Matrix event was bridged from or to.
+    async fn get_reply_info(&self, matrix_event_id: &str) -> Result<Option<MessageMapping>> {
+        Ok(self
             .db_manager
             .message_store()
             .get_by_matrix_event_id(matrix_event_id)
-            .await
-            .ok()
-            .flatten()?;
-
-        let sender_displayname = self
-            .matrix_client
-            .get_user_profile(&mapping.matrix_room_id)
-            .await
-            .ok()
-            .flatten()
-            .map(|(name, _)| name)
-            .unwrap_or_else(|| "Unknown".to_string());
-
-        Some((sender_displayname, "(reply)".to_string()))
+            .await?)
     }
 
     pub async fn send_to_slack_with_embed(
@@ -371,14 +568,15 @@ impl BridgeCore {
         outbound: message_flow::OutboundSlackMessage,
         attachments: Vec<(String, Option<crate::media::MediaInfo>)>,
     ) -> Result<()> {
+        let max_size = self.matrix_client.config().limits.max_matrix_to_slack_file_bytes;
         for (original_url, media_opt) in &attachments {
             if let Some(media) = media_opt {
-                if media.size > 8 * 1024 * 1024 {
+                if media.size as u64 > max_size {
                     warn!(
                         "matrix attachment too large for slack: {} bytes, sending URL instead",
                         media.size
                     );
-                    let content = format!("{}: {}", media.filename, original_url);
+                    let content = format!("{}: {}", media.filename, self.slack_media_url(original_url));
                     self.slack_client
                         .send_message(slack_channel_id, &content)
                         .await?;
@@ -392,6 +590,7 @@ impl BridgeCore {
                             &media.filename,
                             None,
                             None,
+                            None,
                         )
                         .await
                     {
@@ -407,7 +606,7 @@ impl BridgeCore {
                                 "failed to upload attachment to slack: {}, sending URL instead",
                                 e
                             );
-                            let content = format!("{}: {}", media.filename, original_url);
+                            let content = format!("{}: {}", media.filename, self.slack_media_url(original_url));
                             self.slack_client
                                 .send_message(slack_channel_id, &content)
                                 .await?;
@@ -415,7 +614,7 @@ impl BridgeCore {
                     }
                 }
             } else {
-                let content = format!("Attachment: {}", original_url);
+                let content = format!("Attachment: {}", self.slack_media_url(original_url));
                 self.slack_client
                     .send_message(slack_channel_id, &content)
                     .await?;
@@ -452,7 +651,9 @@ impl BridgeCore {
         outbound: OutboundSlackMessage,
         matrix_sender: &str,
         attachments: Vec<(String, Option<crate::media::MediaInfo>)>,
-    ) -> Result<()> {
+    ) -> Result<Option<String>> {
+        let thread_ts = outbound.thread_ts.as_deref();
+        let max_size = self.matrix_client.config().limits.max_matrix_to_slack_file_bytes;
         let (username, avatar_url) = self
             .matrix_client
             .get_user_profile(matrix_sender)
@@ -460,49 +661,49 @@ impl BridgeCore {
             .unwrap_or(None)
             .unwrap_or_else(|| (matrix_sender.to_string(), None));
 
-        let avatar_for_slack = avatar_url.as_ref().map(|url| {
-            if url.starts_with("mxc://") {
-                let mxc_url = url.trim_start_matches("mxc://");
-                let homeserver = &self.matrix_client.config().bridge.homeserver_url;
-                format!(
-                    "{}/_matrix/media/r0/download/{}",
-                    homeserver.trim_end_matches('/'),
-                    mxc_url
-                )
-            } else {
-                url.to_string()
-            }
-        });
+        let avatar_for_slack = avatar_url
+            .as_deref()
+            .map(|url| self.slack_avatar_url(url));
+        // Logged-in users post from their own Slack account, without the name prefix.
+        let puppet = self.slack_puppet(matrix_sender).await?;
+        let (slack_client, sender_name, sender_avatar) = match &puppet {
+            Some(puppet) => (puppet, None, None),
+            None => (
+                self.slack_client.as_ref(),
+                Some(username.as_str()),
+                avatar_for_slack.as_deref(),
+            ),
+        };
 
         for (original_url, media_opt) in &attachments {
             if let Some(media) = media_opt {
-                if media.size > 8 * 1024 * 1024 {
+                if media.size as u64 > max_size {
                     warn!(
                         "matrix attachment too large for slack: {} bytes, sending URL instead",
                         media.size
                     );
-                    let content = format!("{}: {}", media.filename, original_url);
-                    self.slack_client
+                    let content = format!("{}: {}", media.filename, self.slack_media_url(original_url));
+                    slack_client
                         .send_message_with_metadata_as_user(
                             slack_channel_id,
                             &content,
                             &[],
+                            thread_ts,
                             None,
-                            None,
-                            Some(&username),
-                            avatar_for_slack.as_deref(),
+                            sender_name,
+                            sender_avatar,
                         )
                         .await?;
                 } else {
-                    match self
-                        .slack_client
+                    match slack_client
                         .send_file_as_user(
                             slack_channel_id,
                             &media.data,
                             &media.content_type,
                             &media.filename,
-                            Some(&username),
-                            avatar_for_slack.as_deref(),
+                            sender_name,
+                            sender_avatar,
+                            thread_ts,
                         )
                         .await
                     {
@@ -518,52 +719,84 @@ impl BridgeCore {
                                 "failed to upload attachment to slack: {}, sending URL instead",
                                 e
                             );
-                            let content = format!("{}: {}", media.filename, original_url);
-                            self.slack_client
+                            let content = format!("{}: {}", media.filename, self.slack_media_url(original_url));
+                            slack_client
                                 .send_message_with_metadata_as_user(
                                     slack_channel_id,
                                     &content,
                                     &[],
+                                    thread_ts,
                                     None,
-                                    None,
-                                    Some(&username),
-                                    avatar_for_slack.as_deref(),
+                                    sender_name,
+                                    sender_avatar,
                                 )
                                 .await?;
                         }
                     }
                 }
             } else {
-                let content = format!("Attachment: {}", original_url);
-                self.slack_client
+                let content = format!("Attachment: {}", self.slack_media_url(original_url));
+                slack_client
                     .send_message_with_metadata_as_user(
                         slack_channel_id,
                         &content,
                         &[],
+                        thread_ts,
                         None,
-                        None,
-                        Some(&username),
-                        avatar_for_slack.as_deref(),
+                        sender_name,
+                        sender_avatar,
                     )
                     .await?;
             }
         }
 
-        if !outbound.content.is_empty() {
-            self.slack_client
-                .send_message_with_metadata_as_user(
+        if outbound.content.is_empty() {
+            return Ok(None);
+        }
+        if let (Some(permalink), None, None) =
+            (&outbound.quote_permalink, thread_ts, &outbound.edit_of)
+        {
+            let ts = slack_client
+                .send_quoted_message_as_user(
                     slack_channel_id,
                     &outbound.content,
-                    &[],
-                    outbound.reply_to.as_deref(),
-                    outbound.edit_of.as_deref(),
-                    Some(&username),
-                    avatar_for_slack.as_deref(),
+                    permalink,
+                    sender_name,
+                    sender_avatar,
                 )
                 .await?;
+            return Ok(Some(ts));
+        }
+        let result = slack_client
+            .send_message_with_metadata_as_user(
+                slack_channel_id,
+                &outbound.content,
+                &[],
+                thread_ts,
+                outbound.edit_of.as_deref(),
+                sender_name,
+                sender_avatar,
+            )
+            .await;
+        match result {
+            // Messages sent before the user logged in belong to the bot.
+            Err(err) if puppet.is_some() && is_slack_error(&err, "cant_update_message") => {
+                let ts = self
+                    .slack_client
+                    .send_message_with_metadata_as_user(
+                        slack_channel_id,
+                        &outbound.content,
+                        &[],
+                        thread_ts,
+                        outbound.edit_of.as_deref(),
+                        Some(&username),
+                        avatar_for_slack.as_deref(),
+                    )
+                    .await?;
+                Ok(Some(ts))
+            }
+            result => Ok(Some(result?)),
         }
-
-        Ok(())
     }
 
     async fn handle_matrix_command_outcome(
@@ -600,6 +833,111 @@ impl BridgeCore {
                     .send_notice(&event.room_id, &reply)
                     .await?;
             }
+            MatrixCommandOutcome::BackfillRequested { limit } => {
+                let Some(mapping) = self
+                    .db_manager
+                    .room_store()
+                    .get_room_by_matrix_room(&event.room_id)
+                    .await?
+                else {
+                    self.matrix_client
+                        .send_notice(&event.room_id, "This room is not bridged.")
+                        .await?;
+                    return Ok(());
+                };
+                if self.is_backfilling(&event.room_id) {
+                    self.matrix_client
+                        .send_notice(&event.room_id, "A backfill is already running in this room.")
+                        .await?;
+                    return Ok(());
+                }
+                // The whole window is held in memory, so requests are capped by the config.
+                let configured_max = self.matrix_client.config().backfill.max_messages;
+                let max_messages = limit.map_or(configured_max, |limit| limit.min(configured_max));
+                self.matrix_client
+                    .send_notice(
+                        &event.room_id,
+                        &format!("Backfilling up to {max_messages} messages from Slack..."),
+                    )
+                    .await?;
+                let bridge = self.clone();
+                tokio::spawn(async move {
+                    let reply = match bridge.backfill_channel(&mapping, max_messages).await {
+                        Ok(count) => format!("Backfill complete: {count} messages bridged."),
+                        Err(err) => {
+                            warn!(
+                                "backfill failed for channel {}: {}",
+                                mapping.slack_channel_id, err
+                            );
+                            format!("**ERROR:** backfill failed: {err}")
+                        }
+                    };
+                    if let Err(err) = bridge
+                        .matrix_client
+                        .send_notice(&mapping.matrix_room_id, &reply)
+                        .await
+                    {
+                        warn!("failed to report backfill result: {}", err);
+                    }
+                });
+            }
+            MatrixCommandOutcome::LoginRequested => {
+                if !self.slack_logins_enabled() {
+                    self.matrix_client
+                        .send_notice(
+                            &event.room_id,
+                            "Logging in to Slack is not enabled on this bridge.",
+                        )
+                        .await?;
+                    return Ok(());
+                }
+                // Whoever opens the link logs in as the sender, so it only goes to their DM.
+                let dm_room = self.matrix_client.ensure_bot_dm_room(&event.sender).await?;
+                if let Some(url) = self.slack_login_url(&event.sender, &dm_room).await {
+                    let notice = format!(
+                        "Open {url} to log in to Slack. The link is personal to {}, works once and is valid for 10 minutes.",
+                        event.sender
+                    );
+                    self.matrix_client.send_notice(&dm_room, &notice).await?;
+                }
+                if event.room_id != dm_room {
+                    self.matrix_client
+                        .send_notice(
+                            &event.room_id,
+                            "I sent you a Slack login link in a direct message.",
+                        )
+                        .await?;
+                }
+            }
+            MatrixCommandOutcome::PauseRequested { reason } => {
+                let reply = if self.is_bridge_admin(&event.sender) {
+                    let reason = reason.unwrap_or_else(|| format!("paused by {}", event.sender));
+                    self.blocker.pause(&reason).await?;
+                    format!("Bridge paused: {reason}. Run `!slack resume` to continue.")
+                } else {
+                    "**ERROR:** only the bridge admin can pause the bridge.".to_string()
+                };
+                self.matrix_client
+                    .send_notice(&event.room_id, &reply)
+                    .await?;
+            }
+            MatrixCommandOutcome::ResumeRequested => {
+                let reply = if self.is_bridge_admin(&event.sender) {
+                    self.blocker.resume().await?;
+                    "Bridge resumed.".to_string()
+                } else {
+                    "**ERROR:** only the bridge admin can resume the bridge.".to_string()
+                };
+                self.matrix_client
+                    .send_notice(&event.room_id, &reply)
+                    .await?;
+            }
+            MatrixCommandOutcome::LogoutRequested => {
+                let reply = self.logout_slack(&event.sender).await?;
+                self.matrix_client
+                    .send_notice(&event.room_id, &reply)
+                    .await?;
+            }
         }
         Ok(())
     }
@@ -626,6 +964,9 @@ impl BridgeCore {
                 }
                 return Ok(());
             }
+            if membership == "invite" && self.handle_direct_invite(event).await? {
+                return Ok(());
+            }
             if membership == "invite" {
                 debug!(
                     "matrix invite ignored room_id={} state_key={:?} expected_bot={} sender={}",
@@ -929,10 +1270,6 @@ impl BridgeCore {
             return Ok(());
         }
 
-        if self.matrix_client.config().bridge.disable_deletion_forwarding {
-            return Ok(());
-        }
-
         let room_mapping = self.get_room_mapping_cached(&event.room_id).await?;
         let Some(mapping) = room_mapping else {
             return Ok(());
@@ -942,13 +1279,26 @@ impl BridgeCore {
         // For m.room.redaction, the redacts field contains the target event ID
         let redacted_event_id = event.content.as_ref()
             .and_then(|c| c.get("redacts").and_then(Value::as_str))
-            .or_else(|| event.state_key.as_deref());
+            .or(event.state_key.as_deref());
 
         let Some(redacted_event_id) = redacted_event_id else {
             debug!("matrix redaction missing target event_id room_id={}", event.room_id);
             return Ok(());
         };
 
+        if let Some(reaction) = self
+            .db_manager
+            .reaction_store()
+            .get_by_matrix_event_id(redacted_event_id)
+            .await?
+        {
+            return self.retract_matrix_reaction(&reaction).await;
+        }
+
+        if self.matrix_client.config().bridge.disable_deletion_forwarding {
+            return Ok(());
+        }
+
         let Some(message_mapping) = self
             .db_manager
             .message_store()
@@ -958,16 +1308,40 @@ impl BridgeCore {
             debug!("no message mapping for redacted event {}", redacted_event_id);
             return Ok(());
         };
+        // Files of captioned or multi-file messages have no `ts` of their own to delete.
+        if is_file_mapping_key(&message_mapping.slack_message_id) {
+            debug!(
+                "not deleting slack file {} for a matrix redaction",
+                message_mapping.slack_message_id
+            );
+            return Ok(());
+        }
 
-        match self.slack_client.delete_message(
-            &mapping.slack_channel_id,
-            &message_mapping.slack_message_id,
-        ).await {
+        let channel = &mapping.slack_channel_id;
+        let ts = &message_mapping.slack_message_id;
+        let result = match self.slack_puppet(&event.sender).await? {
+            // Messages the bot posted before the user logged in are deleted by the bot.
+            Some(puppet) => match puppet.delete_message(channel, ts).await {
+                Err(err) if is_slack_error(&err, "cant_delete_message") => {
+                    self.slack_client.delete_message(channel, ts).await
+                }
+                result => result,
+            },
+            None => self.slack_client.delete_message(channel, ts).await,
+        };
+        match result {
             Ok(()) => {
                 self.db_manager
                     .message_store()
                     .delete_by_matrix_event_id(redacted_event_id)
                     .await?;
+                self.db_manager
+                    .reaction_store()
+                    .delete_by_slack_message(
+                        &mapping.slack_channel_id,
+                        &message_mapping.slack_message_id,
+                    )
+                    .await?;
                 debug!(
                     "forwarded matrix redaction to slack channel={} ts={}",
                     mapping.slack_channel_id, message_mapping.slack_message_id
@@ -984,6 +1358,64 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Undoes a bridged reaction after its Matrix event was redacted. Reactions that came
+    /// from Slack are only forgotten; Matrix reactions are removed from Slack once no other
+    /// Matrix user still holds the same bot reaction on that message.
+    async fn retract_matrix_reaction(&self, reaction: &ReactionMapping) -> Result<()> {
+        let reactions = self.db_manager.reaction_store();
+        reactions
+            .delete_by_matrix_event_id(&reaction.matrix_event_id)
+            .await?;
+
+        if self.matrix_client.is_namespaced_user(&reaction.matrix_sender) {
+            return Ok(());
+        }
+
+        if reactions
+            .get_by_slack_reaction(
+                &reaction.slack_channel_id,
+                &reaction.slack_message_ts,
+                &reaction.slack_user_id,
+                &reaction.emoji,
+            )
+            .await?
+            .is_some()
+        {
+            debug!(
+                "slack reaction {} on ts={} still held by another matrix user",
+                reaction.emoji, reaction.slack_message_ts
+            );
+            return Ok(());
+        }
+
+        // Reactions are removed by whoever added them: the sender's own login or the bot.
+        let puppet = self
+            .slack_puppet(&reaction.matrix_sender)
+            .await?
+            .filter(|puppet| puppet.puppet_user_id() == Some(reaction.slack_user_id.as_str()));
+        let slack_client = puppet.as_ref().unwrap_or(self.slack_client.as_ref());
+        match slack_client
+            .remove_reaction(
+                &reaction.slack_channel_id,
+                &reaction.slack_message_ts,
+                &reaction.emoji,
+            )
+            .await
+        {
+            Ok(()) => {
+                debug!(
+                    "forwarded matrix reaction removal to slack channel={} ts={} emoji={}",
+                    reaction.slack_channel_id, reaction.slack_message_ts, reaction.emoji
+                );
+            }
+            Err(err) => {
+                warn!("failed to remove slack reaction for matrix redaction: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn handle_matrix_reaction(&self, event: &MatrixEvent) -> Result<()> {
         if self.matrix_client.is_namespaced_user(&event.sender) {
             return Ok(());
@@ -1020,19 +1452,51 @@ impl BridgeCore {
             debug!("no message mapping for reaction target {}", target_event_id);
             return Ok(());
         };
+        if is_file_mapping_key(&message_mapping.slack_message_id) {
+            debug!(
+                "reaction target {} is a file without its own slack message",
+                target_event_id
+            );
+            return Ok(());
+        }
 
-        // Convert Matrix emoji to Slack format (strip colons if present, handle unicode)
-        let slack_emoji = emoji_key
-            .trim_start_matches(':')
-            .trim_end_matches(':')
-            .to_string();
+        let Some(slack_emoji) = self.matrix_reaction_to_slack_name(emoji_key).await? else {
+            debug!("no slack emoji for matrix reaction key {}", emoji_key);
+            return Ok(());
+        };
 
-        match self.slack_client.add_reaction(
+        let puppet = self.slack_puppet(&event.sender).await?;
+        let slack_client = puppet.as_ref().unwrap_or(self.slack_client.as_ref());
+        // Held until the mapping is written, so the puppet's reaction_added echo finds it.
+        let _sending = slack_client
+            .lock_puppet_sends(&mapping.slack_channel_id)
+            .await;
+        match slack_client.add_reaction(
             &mapping.slack_channel_id,
             &message_mapping.slack_message_id,
             &slack_emoji,
         ).await {
             Ok(()) => {
+                if let Some(reaction_event_id) = &event.event_id {
+                    let slack_user_id = match slack_client.puppet_user_id() {
+                        Some(user_id) => user_id.to_string(),
+                        None => self.slack_client.bot_user_id().await.unwrap_or_default(),
+                    };
+                    self.db_manager
+                        .reaction_store()
+                        .create_reaction(&ReactionMapping {
+                            id: 0,
+                            slack_channel_id: mapping.slack_channel_id.clone(),
+                            slack_message_ts: message_mapping.slack_message_id.clone(),
+                            slack_user_id,
+                            emoji: slack_emoji.clone(),
+                            matrix_room_id: event.room_id.clone(),
+                            matrix_event_id: reaction_event_id.clone(),
+                            matrix_sender: event.sender.clone(),
+                            created_at: Utc::now(),
+                        })
+                        .await?;
+                }
                 debug!(
                     "forwarded matrix reaction to slack channel={} ts={} emoji={}",
                     mapping.slack_channel_id, message_mapping.slack_message_id, slack_emoji
@@ -1064,14 +1528,14 @@ impl BridgeCore {
 
         // Only forward typing for non-ghost users
         for user in &typing_users {
-            if let Some(user_id) = user.as_str() {
-                if !self.matrix_client.is_namespaced_user(user_id) {
-                    // Slack doesn't have a direct typing API for bots
-                    debug!(
-                        "matrix typing detected room={} user={} slack_channel={}",
-                        event.room_id, user_id, mapping.slack_channel_id
-                    );
-                }
+            if let Some(user_id) = user.as_str()
+                && !self.matrix_client.is_namespaced_user(user_id)
+            {
+                // Slack doesn't have a direct typing API for bots
+                debug!(
+                    "matrix typing detected room={} user={} slack_channel={}",
+                    event.room_id, user_id, mapping.slack_channel_id
+                );
             }
         }
 
@@ -1104,6 +1568,7 @@ impl BridgeCore {
                             .message_store()
                             .get_by_matrix_event_id(event_id)
                             .await
+                            && !is_file_mapping_key(&message_mapping.slack_message_id)
                         {
                             match self.slack_client.mark_conversation(
                                 &mapping.slack_channel_id,
@@ -1329,16 +1794,7 @@ impl BridgeCore {
         }
 
         if delete_options.unset_room_alias {
-            let alias_localpart = format!(
-                "{}{}",
-                self.matrix_client.config().room.room_alias_prefix,
-                mapping.slack_channel_id
-            );
-            let alias = format!(
-                "#{}:{}",
-                alias_localpart,
-                self.matrix_client.config().bridge.domain
-            );
+            let alias = self.matrix_client.portal_alias(&mapping.slack_channel_id);
             let _ = client.delete_room_alias(&alias).await;
         }
 
@@ -1399,20 +1855,9 @@ impl BridgeCore {
             .unwrap_or(None)
             .unwrap_or_else(|| (matrix_sender.to_string(), None));
 
-        let avatar_url_ref = avatar_url.as_deref();
-        let avatar_for_slack = avatar_url_ref.map(|url| {
-            if url.starts_with("mxc://") {
-                let mxc_url = url.trim_start_matches("mxc://");
-                let homeserver = &self.matrix_client.config().bridge.homeserver_url;
-                format!(
-                    "{}/_matrix/media/r0/download/{}",
-                    homeserver.trim_end_matches('/'),
-                    mxc_url
-                )
-            } else {
-                url.to_string()
-            }
-        });
+        let avatar_for_slack = avatar_url
+            .as_deref()
+            .map(|url| self.slack_avatar_url(url));
 
         debug!(
             "sending slack message via webhook channel_id={} sender={} username={} reply_to={:?} edit_of={:?} attachments={} content_len={} content_preview={}",
@@ -1473,6 +1918,8 @@ impl BridgeCore {
                 outbound.reply_to.as_deref(),
                 outbound.edit_of.as_deref(),
                 outbound.formatted_body.as_deref(),
+                outbound.thread_root.as_deref(),
+                outbound.origin_ts,
             )
             .await?;
         debug!(
@@ -1493,101 +1940,17 @@ impl BridgeCore {
         let mut last_event_id: Option<String> = None;
 
         for attachment_url in &outbound.attachments {
-            match self.media_handler.download_from_url(attachment_url).await {
-                Ok(media) => {
-                    if media.size > 50 * 1024 * 1024 {
-                        warn!(
-                            "attachment too large for Matrix: {} bytes, sending as URL instead",
-                            media.size
-                        );
-                        let body = format!("{}: {}", media.filename, attachment_url);
-                        last_event_id = Some(
-                            self.matrix_client
-                                .send_message_with_metadata(
-                                    matrix_room_id,
-                                    slack_sender,
-                                    &body,
-                                    &[],
-                                    outbound.reply_to.as_deref(),
-                                    None,
-                                    None,
-                                )
-                                .await?,
-                        );
-                    } else {
-                        let msgtype = match media.content_type.as_str() {
-                            ct if ct.starts_with("image/") => "m.image",
-                            ct if ct.starts_with("video/") => "m.video",
-                            ct if ct.starts_with("audio/") => "m.audio",
-                            _ => "m.file",
-                        };
-
-                        match self.matrix_client.upload_media(&media).await {
-                            Ok(mxc_url) => {
-                                let info = json!({
-                                    "mimetype": media.content_type,
-                                    "size": media.size,
-                                });
-
-                                last_event_id = Some(
-                                    self.matrix_client
-                                        .send_media_message(
-                                            matrix_room_id,
-                                            slack_sender,
-                                            msgtype,
-                                            &media.filename,
-                                            &mxc_url,
-                                            Some(&info),
-                                            outbound.reply_to.as_deref(),
-                                        )
-                                        .await?,
-                                );
-                                info!(
-                                    "uploaded slack attachment to matrix room={} file={} size={} mxc={}",
-                                    matrix_room_id, media.filename, media.size, mxc_url
-                                );
-                            }
-                            Err(e) => {
-                                warn!("failed to upload attachment to matrix: {}, sending URL", e);
-                                let body = format!("{}: {}", media.filename, attachment_url);
-                                last_event_id = Some(
-                                    self.matrix_client
-                                        .send_message_with_metadata(
-                                            matrix_room_id,
-                                            slack_sender,
-                                            &body,
-                                            &[],
-                                            outbound.reply_to.as_deref(),
-                                            None,
-                                            None,
-                                        )
-                                        .await?,
-                                );
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!(
-                        "failed to download attachment from slack: {}, sending URL",
-                        e
-                    );
-                    let body = format!("Attachment: {}", attachment_url);
-                    last_event_id = Some(
-                        self.matrix_client
-                            .send_message_with_metadata(
-                                matrix_room_id,
-                                slack_sender,
-                                &body,
-                                &[],
-                                outbound.reply_to.as_deref(),
-                                None,
-                                None,
-                            )
-                            .await?,
-                    );
-                }
-            }
+            last_event_id = Some(
+                self.send_matrix_attachment(
+                    matrix_room_id,
+                    slack_sender,
+                    &SlackFileRef::from_url(attachment_url),
+                    outbound.reply_to.as_deref(),
+                    outbound.thread_root.as_deref(),
+                    outbound.origin_ts,
+                )
+                .await?,
+            );
         }
 
         if !outbound.body.is_empty() {
@@ -1601,6 +1964,8 @@ impl BridgeCore {
                         outbound.reply_to.as_deref(),
                         outbound.edit_of.as_deref(),
                         outbound.formatted_body.as_deref(),
+                        outbound.thread_root.as_deref(),
+                        outbound.origin_ts,
                     )
                     .await?,
             );
@@ -1609,6 +1974,221 @@ impl BridgeCore {
         last_event_id.ok_or_else(|| anyhow::anyhow!("no message was sent"))
     }
 
+    /// Streams one Slack file into the Matrix media repository, falling back to posting a
+    /// link when it is too large or can't be fetched.
+    async fn send_matrix_attachment(
+        &self,
+        matrix_room_id: &str,
+        slack_sender: &str,
+        file: &SlackFileRef,
+        reply_to: Option<&str>,
+        thread_root: Option<&str>,
+        origin_ts: Option<u64>,
+    ) -> Result<String> {
+        match self.upload_slack_file(file).await {
+            Ok(upload) => {
+                let msgtype = match upload.content_type.as_str() {
+                    ct if ct.starts_with("image/") => "m.image",
+                    ct if ct.starts_with("video/") => "m.video",
+                    ct if ct.starts_with("audio/") => "m.audio",
+                    _ => "m.file",
+                };
+                let mut info = json!({ "mimetype": upload.content_type });
+                if let Some(size) = upload.size {
+                    info["size"] = json!(size);
+                }
+
+                let event_id = self
+                    .matrix_client
+                    .send_media_message(
+                        matrix_room_id,
+                        slack_sender,
+                        msgtype,
+                        &upload.filename,
+                        &upload.mxc_url,
+                        Some(&info),
+                        reply_to,
+                        thread_root,
+                        origin_ts,
+                    )
+                    .await?;
+                info!(
+                    "uploaded slack attachment to matrix room={} file={} size={:?} mxc={}",
+                    matrix_room_id, upload.filename, upload.size, upload.mxc_url
+                );
+                Ok(event_id)
+            }
+            Err(e) => {
+                warn!("failed to bridge slack attachment: {}, sending link instead", e);
+                let fallback_body = format!(
+                    "{}: {}",
+                    file.name.as_deref().unwrap_or("Attachment"),
+                    file.fallback_link().unwrap_or_default()
+                );
+                self.matrix_client
+                    .send_message_with_metadata(
+                        matrix_room_id,
+                        slack_sender,
+                        &fallback_body,
+                        &[],
+                        reply_to,
+                        None,
+                        None,
+                        thread_root,
+                        origin_ts,
+                    )
+                    .await
+            }
+        }
+    }
+
+    async fn upload_slack_file(&self, file: &SlackFileRef) -> Result<UploadedAttachment> {
+        let max_size = self
+            .matrix_client
+            .config()
+            .limits
+            .max_slack_to_matrix_file_bytes;
+        let url = file
+            .url
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("file has no download URL"))?;
+        if let Some(size) = file.size.filter(|size| *size > max_size) {
+            return Err(anyhow::anyhow!(
+                "file too large for Matrix: {} bytes (max {})",
+                size,
+                max_size
+            ));
+        }
+
+        let stream = self.slack_client.download_file(url).await?;
+        let size = stream.size.or(file.size);
+        if let Some(size) = size.filter(|size| *size > max_size) {
+            return Err(anyhow::anyhow!(
+                "file too large for Matrix: {} bytes (max {})",
+                size,
+                max_size
+            ));
+        }
+        let filename = file.name.clone().unwrap_or_else(|| stream.filename.clone());
+        let content_type = file
+            .mimetype
+            .clone()
+            .unwrap_or_else(|| stream.content_type.clone());
+        let content_length = stream.size;
+
+        let mxc_url = self
+            .matrix_client
+            .upload_media_body(
+                stream.into_body(max_size),
+                &content_type,
+                &filename,
+                content_length,
+            )
+            .await?;
+        Ok(UploadedAttachment {
+            mxc_url,
+            filename,
+            content_type,
+            size,
+        })
+    }
+
+    /// Bridges the files of a Slack message, one Matrix event per file, followed by its
+    /// text. On edits, files that were already bridged are left alone and tombstoned ones
+    /// are redacted. Returns the event the message `ts` should map to, or `None` to keep
+    /// the existing mapping.
+    async fn send_slack_files_to_matrix(
+        &self,
+        mapping: &RoomMapping,
+        slack_sender: &str,
+        files: &[SlackFileRef],
+        mut outbound: OutboundMatrixMessage,
+        slack_thread_ts: Option<&str>,
+        is_edit: bool,
+    ) -> Result<Option<String>> {
+        let message_store = self.db_manager.message_store();
+        let mut file_event_ids = HashSet::new();
+        let mut first_new_event_id = None;
+
+        for file in files {
+            let key = file_mapping_key(&file.id);
+            let existing = message_store
+                .get_by_slack_message_id(&mapping.slack_channel_id, &key)
+                .await?;
+            if file.tombstone {
+                if let Some(link) = existing {
+                    self.redact_slack_mapping(&link).await?;
+                }
+                continue;
+            }
+            if let Some(link) = existing {
+                file_event_ids.insert(link.matrix_event_id);
+                continue;
+            }
+            if file.url.is_none() {
+                continue;
+            }
+
+            let event_id = self
+                .send_matrix_attachment(
+                    &mapping.matrix_room_id,
+                    slack_sender,
+                    file,
+                    outbound.reply_to.as_deref(),
+                    outbound.thread_root.as_deref(),
+                    outbound.origin_ts,
+                )
+                .await?;
+            message_store
+                .upsert_message_mapping(&MessageMapping {
+                    id: 0,
+                    slack_message_id: key,
+                    slack_channel_id: mapping.slack_channel_id.clone(),
+                    slack_team_id: mapping.slack_team_id.clone(),
+                    matrix_room_id: mapping.matrix_room_id.clone(),
+                    matrix_event_id: event_id.clone(),
+                    slack_thread_ts: slack_thread_ts.map(ToOwned::to_owned),
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                })
+                .await?;
+            file_event_ids.insert(event_id.clone());
+            first_new_event_id.get_or_insert(event_id);
+        }
+
+        if outbound.body.trim().is_empty() {
+            return Ok(if is_edit { None } else { first_new_event_id });
+        }
+
+        // A caption added to a file-only message can't replace the media event, so it is
+        // posted as a new text event and the message `ts` is re-pointed to it.
+        let edits_file_event = outbound
+            .edit_of
+            .as_ref()
+            .is_some_and(|event_id| file_event_ids.contains(event_id));
+        if edits_file_event {
+            outbound.edit_of = None;
+        }
+        let keep_mapping = is_edit && outbound.edit_of.is_some();
+        outbound.attachments.clear();
+        let event_id = self
+            .send_to_matrix_message(&mapping.matrix_room_id, slack_sender, outbound)
+            .await?;
+        Ok((!keep_mapping).then_some(event_id))
+    }
+
+    async fn redact_slack_mapping(&self, link: &MessageMapping) -> Result<()> {
+        let request = build_slack_delete_redaction_request(link);
+        self.matrix_client
+            .redact_message(&request.room_id, &request.event_id, Some(request.reason))
+            .await?;
+        self.db_manager
+            .message_store()
+            .delete_by_slack_message_id(&link.slack_channel_id, &link.slack_message_id)
+            .await?;
+        Ok(())
+    }
+
     pub async fn handle_slack_message_with_context(
         &self,
         ctx: SlackMessageContext,
@@ -1636,7 +2216,7 @@ impl BridgeCore {
             room_mapping.is_some()
         );
 
-        if self.slack_command_handler.is_command(&ctx.content) {
+        if ctx.backfill_ts.is_none() && self.slack_command_handler.is_command(&ctx.content) {
             debug!(
                 "slack inbound command detected channel_id={} sender={} command_preview={}",
                 ctx.channel_id,
@@ -1660,20 +2240,30 @@ impl BridgeCore {
             );
             return Ok(());
         };
+        if let Some(notice) = self.blocker.blocked_notice().await {
+            debug!(
+                "slack inbound dropped channel_id={} reason=bridge_blocked",
+                ctx.channel_id
+            );
+            if ctx.backfill_ts.is_none() && self.blocker.should_notify(&ctx.channel_id).await {
+                self.slack_client
+                    .send_message(&ctx.channel_id, &notice)
+                    .await?;
+            }
+            return Ok(());
+        }
+        if ctx.backfill_ts.is_none() {
+            self.blocker.record_active_user(&ctx.sender_id).await;
+            self.presence_handler.watch_user(&ctx.sender_id);
+        }
 
         if let Some(slack_user) = self.slack_client.get_user(&ctx.sender_id).await? {
-            let vars = [
-                ("id", slack_user.id.as_str()),
-                ("tag", slack_user.discriminator.as_str()),
-                ("username", slack_user.username.as_str()),
-            ];
-            let display_name = crate::utils::formatting::apply_pattern_string(
-                &self.matrix_client.config().ghosts.username_pattern,
-                &vars,
-            );
-            self.matrix_client
-                .ensure_ghost_user_registered(&ctx.sender_id, Some(&display_name))
-                .await?;
+            if let Err(err) = self.user_sync.on_user_update(&slack_user, false).await {
+                warn!("failed to sync ghost profile for {}: {}", ctx.sender_id, err);
+                self.matrix_client
+                    .ensure_ghost_user_registered(&ctx.sender_id, None)
+                    .await?;
+            }
         } else {
             self.matrix_client
                 .ensure_ghost_user_registered(&ctx.sender_id, None)
@@ -1708,20 +2298,25 @@ impl BridgeCore {
         // Use block-rendered plain text if available, otherwise fall back to the original content
         let content_for_body = body_from_blocks.unwrap_or_else(|| ctx.content.clone());
 
-        // If the message has a thread_ts that differs from its own ts (source_message_id),
-        // treat it as a reply to the thread root message
-        let reply_to = if ctx.reply_to.is_some() {
-            ctx.reply_to
-        } else if let Some(ref thread_ts) = ctx.thread_ts {
-            if ctx.source_message_id.as_deref() != Some(thread_ts.as_str()) {
-                Some(thread_ts.clone())
-            } else {
-                None
-            }
+        // Thread replies go into the Matrix thread of the root message. Broadcast replies
+        // are also shown in the channel on Slack, so they stay in the main timeline as a
+        // reply to the root instead.
+        let thread_ts = ctx
+            .thread_ts
+            .filter(|thread_ts| ctx.source_message_id.as_deref() != Some(thread_ts.as_str()));
+        let slack_thread_ts = thread_ts.clone();
+        let (reply_to, thread_root) = if ctx.reply_broadcast {
+            (ctx.reply_to.or(thread_ts), None)
         } else {
-            None
+            (ctx.reply_to, thread_ts)
         };
 
+        let is_edit = ctx.edit_of.is_some();
+        let files = ctx
+            .files
+            .as_deref()
+            .map(slack_file_refs)
+            .unwrap_or_default();
         let mut outbound = self.message_flow.slack_to_matrix(&SlackInboundMessage {
             channel_id: ctx.channel_id,
             sender_id: ctx.sender_id.clone(),
@@ -1729,7 +2324,9 @@ impl BridgeCore {
             attachments: ctx.attachments,
             reply_to,
             edit_of: ctx.edit_of,
+            thread_root,
         });
+        outbound.origin_ts = ctx.backfill_ts;
 
         // Apply the rendered HTML formatted body from blocks/attachments
         if final_formatted.is_some() {
@@ -1739,7 +2336,7 @@ impl BridgeCore {
         let reply_mapping = if let Some(reply_slack_message_id) = outbound.reply_to.clone() {
             self.db_manager
                 .message_store()
-                .get_by_slack_message_id(&reply_slack_message_id)
+                .get_by_slack_message_id(&mapping.slack_channel_id, &reply_slack_message_id)
                 .await?
         } else {
             None
@@ -1748,7 +2345,16 @@ impl BridgeCore {
         let edit_mapping = if let Some(edit_slack_message_id) = outbound.edit_of.clone() {
             self.db_manager
                 .message_store()
-                .get_by_slack_message_id(&edit_slack_message_id)
+                .get_by_slack_message_id(&mapping.slack_channel_id, &edit_slack_message_id)
+                .await?
+        } else {
+            None
+        };
+
+        let thread_mapping = if let Some(thread_ts) = outbound.thread_root.clone() {
+            self.db_manager
+                .message_store()
+                .get_by_slack_message_id(&mapping.slack_channel_id, &thread_ts)
                 .await?
         } else {
             None
@@ -1758,6 +2364,7 @@ impl BridgeCore {
             &mut outbound,
             reply_mapping.as_ref(),
             edit_mapping.as_ref(),
+            thread_mapping.as_ref(),
         );
         debug!(
             "slack->matrix outbound prepared channel_id={} matrix_room={} sender={} reply_to={:?} edit_of={:?} attachments={} body_len={} body_preview={}",
@@ -1771,22 +2378,46 @@ impl BridgeCore {
             preview_text(&outbound.body)
         );
 
-        let matrix_event_id = if !outbound.attachments.is_empty() {
-            self.send_to_matrix_with_attachments(&mapping.matrix_room_id, &ctx.sender_id, &outbound)
-                .await?
+        let matrix_event_id = if !files.is_empty() {
+            self.send_slack_files_to_matrix(
+                &mapping,
+                &ctx.sender_id,
+                &files,
+                outbound,
+                slack_thread_ts.as_deref(),
+                is_edit,
+            )
+            .await?
+        } else if !outbound.attachments.is_empty() {
+            Some(
+                self.send_to_matrix_with_attachments(
+                    &mapping.matrix_room_id,
+                    &ctx.sender_id,
+                    &outbound,
+                )
+                .await?,
+            )
         } else {
-            self.send_to_matrix_message(&mapping.matrix_room_id, &ctx.sender_id, outbound)
-                .await?
+            Some(
+                self.send_to_matrix_message(&mapping.matrix_room_id, &ctx.sender_id, outbound)
+                    .await?,
+            )
         };
+        self.blocker.record_message_received().await;
 
-        if let Some(source_message_id) = ctx.source_message_id {
+        if let Some(source_message_id) = ctx.source_message_id
+            && let Some(matrix_event_id) = matrix_event_id
+        {
             self.db_manager
                 .message_store()
                 .upsert_message_mapping(&MessageMapping {
                     id: 0,
                     slack_message_id: source_message_id,
+                    slack_channel_id: mapping.slack_channel_id.clone(),
+                    slack_team_id: mapping.slack_team_id.clone(),
                     matrix_room_id: mapping.matrix_room_id.clone(),
                     matrix_event_id,
+                    slack_thread_ts,
                     created_at: Utc::now(),
                     updated_at: Utc::now(),
                 })
@@ -1797,15 +2428,34 @@ impl BridgeCore {
 
     pub async fn handle_slack_message_delete(
         &self,
-        _slack_channel_id: &str,
+        slack_channel_id: &str,
         slack_message_id: &str,
+        file_ids: &[String],
     ) -> Result<()> {
-        let link = self
-            .db_manager
-            .message_store()
-            .get_by_slack_message_id(slack_message_id)
+        let message_store = self.db_manager.message_store();
+        let link = message_store
+            .get_by_slack_message_id(slack_channel_id, slack_message_id)
             .await?;
 
+        for file_id in file_ids {
+            let Some(file_link) = message_store
+                .get_by_slack_message_id(slack_channel_id, &file_mapping_key(file_id))
+                .await?
+            else {
+                continue;
+            };
+            if link
+                .as_ref()
+                .is_some_and(|link| link.matrix_event_id == file_link.matrix_event_id)
+            {
+                message_store
+                    .delete_by_slack_message_id(slack_channel_id, &file_link.slack_message_id)
+                    .await?;
+            } else {
+                self.redact_slack_mapping(&file_link).await?;
+            }
+        }
+
         let Some(request) = slack_delete_redaction_request(link.as_ref()) else {
             return Ok(());
         };
@@ -1813,13 +2463,68 @@ impl BridgeCore {
         self.matrix_client
             .redact_message(&request.room_id, &request.event_id, Some(request.reason))
             .await?;
+        message_store
+            .delete_by_slack_message_id(slack_channel_id, slack_message_id)
+            .await?;
         self.db_manager
-            .message_store()
-            .delete_by_slack_message_id(slack_message_id)
+            .reaction_store()
+            .delete_by_slack_message(slack_channel_id, slack_message_id)
             .await?;
         Ok(())
     }
 
+    /// Applies file additions and removals on a Slack message whose text did not change.
+    pub async fn handle_slack_file_changes(
+        &self,
+        slack_channel_id: &str,
+        slack_sender_id: &str,
+        files: &[Value],
+    ) -> Result<()> {
+        let Some(mapping) = self
+            .db_manager
+            .room_store()
+            .get_room_by_slack_channel(slack_channel_id)
+            .await?
+        else {
+            return Ok(());
+        };
+        self.matrix_client
+            .ensure_ghost_user_registered(slack_sender_id, None)
+            .await?;
+        let outbound = OutboundMatrixMessage {
+            body: String::new(),
+            formatted_body: None,
+            reply_to: None,
+            edit_of: None,
+            thread_root: None,
+            attachments: Vec::new(),
+            origin_ts: None,
+        };
+        self.send_slack_files_to_matrix(
+            &mapping,
+            slack_sender_id,
+            &slack_file_refs(files),
+            outbound,
+            None,
+            true,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Handles `file_deleted`, which only carries the file id.
+    pub async fn handle_slack_file_deleted(&self, file_id: &str) -> Result<()> {
+        let Some(link) = self
+            .db_manager
+            .message_store()
+            .find_by_slack_message_id(&file_mapping_key(file_id))
+            .await?
+        else {
+            return Ok(());
+        };
+        self.redact_slack_mapping(&link).await
+    }
+
     pub async fn handle_slack_typing(
         &self,
         slack_channel_id: &str,
@@ -1868,12 +2573,91 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Maps a Slack reaction name to a Matrix reaction key: the Unicode emoji for standard
+    /// shortcodes, or the `mxc://` URI of a custom workspace emoji.
+    async fn slack_reaction_to_matrix_key(&self, reaction: &str) -> String {
+        let name = reaction.trim_matches(':');
+        if let Some(emoji) = crate::emoji::shortcode_to_unicode(name) {
+            return emoji;
+        }
+
+        match self.resolve_custom_emoji(name).await {
+            Ok(Some(key)) => key,
+            Ok(None) => format!(":{name}:"),
+            Err(err) => {
+                warn!("failed to resolve custom slack emoji {}: {}", name, err);
+                format!(":{name}:")
+            }
+        }
+    }
+
+    async fn resolve_custom_emoji(&self, name: &str) -> Result<Option<String>> {
+        if let Some(mxc_url) = self.emoji_handler.get_emoji_mxc_by_name(name).await? {
+            return Ok(Some(mxc_url));
+        }
+
+        let list = self.slack_client.get_emoji_list().await?;
+        let emoji = list.get("emoji");
+        let mut target = name;
+        // Custom emoji may alias another custom emoji or a standard shortcode.
+        for _ in 0..2 {
+            let Some(value) = emoji.and_then(|e| e.get(target)).and_then(Value::as_str) else {
+                return Ok(None);
+            };
+            match value.strip_prefix("alias:") {
+                Some(alias) => {
+                    if let Some(unicode) = crate::emoji::shortcode_to_unicode(alias) {
+                        return Ok(Some(unicode));
+                    }
+                    target = alias;
+                }
+                None => {
+                    return self
+                        .emoji_handler
+                        .cache_custom_emoji(name, value)
+                        .await
+                        .map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Maps a Matrix reaction key to the Slack emoji name for `reactions.add`.
+    async fn matrix_reaction_to_slack_name(&self, key: &str) -> Result<Option<String>> {
+        if key.starts_with("mxc://") {
+            return self.emoji_handler.get_emoji_name_by_mxc(key).await;
+        }
+        if let Some(name) = crate::emoji::unicode_to_shortcode(key) {
+            return Ok(Some(name));
+        }
+        let name = key.trim_matches(':');
+        if key.starts_with(':') && key.ends_with(':') && !name.is_empty() {
+            return Ok(Some(name.to_string()));
+        }
+        Ok(None)
+    }
+
     pub async fn handle_slack_reaction_added(
         &self,
         slack_channel_id: &str,
         slack_message_id: &str,
         slack_user_id: &str,
         reaction: &str,
+    ) -> Result<()> {
+        self.add_slack_reaction(slack_channel_id, slack_message_id, slack_user_id, reaction, None)
+            .await
+    }
+
+    /// Bridges one Slack reaction unless it already was. `origin_ts` is set when replaying
+    /// reactions during backfill.
+    async fn add_slack_reaction(
+        &self,
+        slack_channel_id: &str,
+        slack_message_id: &str,
+        slack_user_id: &str,
+        reaction: &str,
+        origin_ts: Option<u64>,
     ) -> Result<()> {
         let Some(mapping) = self
             .db_manager
@@ -1888,32 +2672,55 @@ impl BridgeCore {
         let Some(message_mapping) = self
             .db_manager
             .message_store()
-            .get_by_slack_message_id(slack_message_id)
+            .get_by_slack_message_id(slack_channel_id, slack_message_id)
             .await?
         else {
             debug!("no message mapping for reaction ts={}", slack_message_id);
             return Ok(());
         };
 
+        let name = reaction.trim_matches(':');
+        let reactions = self.db_manager.reaction_store();
+        if reactions
+            .get_by_slack_reaction(slack_channel_id, slack_message_id, slack_user_id, name)
+            .await?
+            .is_some()
+        {
+            debug!("slack reaction {} on ts={} already bridged", name, slack_message_id);
+            return Ok(());
+        }
+
         self.matrix_client
             .ensure_ghost_user_registered(slack_user_id, None)
             .await?;
 
-        let emoji = if reaction.starts_with(':') && reaction.ends_with(':') {
-            reaction[1..reaction.len()-1].to_string()
-        } else {
-            reaction.to_string()
-        };
+        let emoji = self.slack_reaction_to_matrix_key(reaction).await;
 
-        self.matrix_client
+        let reaction_event_id = self
+            .matrix_client
             .send_reaction_as_ghost(
                 &mapping.matrix_room_id,
                 &message_mapping.matrix_event_id,
                 slack_user_id,
                 &emoji,
+                origin_ts,
             )
             .await?;
 
+        reactions
+            .create_reaction(&ReactionMapping {
+                id: 0,
+                slack_channel_id: slack_channel_id.to_string(),
+                slack_message_ts: slack_message_id.to_string(),
+                slack_user_id: slack_user_id.to_string(),
+                emoji: name.to_string(),
+                matrix_room_id: mapping.matrix_room_id.clone(),
+                matrix_event_id: reaction_event_id,
+                matrix_sender: self.matrix_client.ghost_user_id(slack_user_id),
+                created_at: Utc::now(),
+            })
+            .await?;
+
         debug!(
             "slack reaction added forwarded channel={} ts={} user={} reaction={}",
             slack_channel_id, slack_message_id, slack_user_id, reaction
@@ -1929,38 +2736,29 @@ impl BridgeCore {
         slack_user_id: &str,
         reaction: &str,
     ) -> Result<()> {
-        let Some(mapping) = self
-            .db_manager
-            .room_store()
-            .get_room_by_slack_channel(slack_channel_id)
-            .await?
-        else {
-            return Ok(());
-        };
-
-        let Some(message_mapping) = self
-            .db_manager
-            .message_store()
-            .get_by_slack_message_id(slack_message_id)
+        let name = reaction.trim_matches(':');
+        let reactions = self.db_manager.reaction_store();
+        let Some(reaction_mapping) = reactions
+            .get_by_slack_reaction(slack_channel_id, slack_message_id, slack_user_id, name)
             .await?
         else {
+            debug!(
+                "no reaction mapping for removal channel={} ts={} user={} reaction={}",
+                slack_channel_id, slack_message_id, slack_user_id, name
+            );
             return Ok(());
         };
 
-        let emoji = if reaction.starts_with(':') && reaction.ends_with(':') {
-            reaction[1..reaction.len()-1].to_string()
-        } else {
-            reaction.to_string()
-        };
-
         self.matrix_client
             .redact_reaction_as_ghost(
-                &mapping.matrix_room_id,
-                &message_mapping.matrix_event_id,
+                &reaction_mapping.matrix_room_id,
+                &reaction_mapping.matrix_event_id,
                 slack_user_id,
-                &emoji,
             )
             .await?;
+        reactions
+            .delete_by_matrix_event_id(&reaction_mapping.matrix_event_id)
+            .await?;
 
         debug!(
             "slack reaction removed forwarded channel={} ts={} user={} reaction={}",
@@ -2280,6 +3078,7 @@ impl BridgeCore {
                 &channel.id,
                 &format!("[Slack] #{}", channel.name),
                 channel.topic.as_deref(),
+                channel.is_private,
             )
             .await
         {
@@ -2331,6 +3130,8 @@ impl BridgeCore {
             edit_of: None,
             permissions: HashSet::new(),
             thread_ts: None,
+            reply_broadcast: false,
+            backfill_ts: None,
             blocks: None,
             slack_attachments: None,
             files: None,
@@ -2342,6 +3143,51 @@ impl BridgeCore {
         self.presence_handler.enqueue_user(presence);
     }
 
+    /// Pushes the next queued Slack presence to its Matrix ghost.
+    pub async fn process_next_presence(&self) -> Result<bool> {
+        self.presence_handler
+            .process_next(self.matrix_client.as_ref())
+            .await
+    }
+
+    /// Periodically re-reads the presence of recently active Slack users. The queued
+    /// results are pushed to Matrix by the presence timer in `start`.
+    async fn run_presence_poller(self) {
+        let poll_interval = self.matrix_client.config().bridge.presence_poll_interval.max(1);
+        let mut ticker = tokio::time::interval(Duration::from_secs(poll_interval));
+        loop {
+            ticker.tick().await;
+            for user_id in self.presence_handler.watched_users(PRESENCE_WATCH_TTL) {
+                if let Err(err) = self.refresh_slack_presence(&user_id).await {
+                    debug!("failed to poll slack presence for {}: {}", user_id, err);
+                }
+            }
+        }
+    }
+
+    pub async fn refresh_slack_presence(&self, slack_user_id: &str) -> Result<()> {
+        let presence = self.slack_client.get_user_presence(slack_user_id).await?;
+        // Both need extra scopes; without them the user is reported without DND or status.
+        let dnd = self
+            .slack_client
+            .get_dnd_info(slack_user_id)
+            .await
+            .unwrap_or(Value::Null);
+        let profile = self
+            .slack_client
+            .get_user_profile(slack_user_id)
+            .await
+            .unwrap_or(Value::Null);
+        self.enqueue_slack_presence(SlackPresence::from_slack_api(
+            slack_user_id,
+            &presence,
+            &dnd,
+            &profile,
+            Utc::now().timestamp(),
+        ));
+        Ok(())
+    }
+
     pub async fn handle_slack_channel_update(
         &self,
         slack_channel_id: &str,
@@ -2461,14 +3307,13 @@ impl BridgeCore {
             if let Ok(state) = client
                 .get_room_state_event(&mapping.matrix_room_id, "m.room.name", "")
                 .await
+                && let Some(name) = state.get("name").and_then(|n| n.as_str())
             {
-                if let Some(name) = state.get("name").and_then(|n| n.as_str()) {
-                    let new_name = format!("{}{}", prefix, name);
-                    let event_content = json!({ "name": new_name });
-                    let _ = client
-                        .send_state_event(&mapping.matrix_room_id, "m.room.name", "", &event_content)
-                        .await;
-                }
+                let new_name = format!("{}{}", prefix, name);
+                let event_content = json!({ "name": new_name });
+                let _ = client
+                    .send_state_event(&mapping.matrix_room_id, "m.room.name", "", &event_content)
+                    .await;
             }
         }
 
@@ -2573,35 +3418,27 @@ impl BridgeCore {
         Ok(())
     }
 
-    pub async fn handle_slack_user_update(
-        &self,
-        slack_user_id: &str,
-        new_username: &str,
-        new_avatar_url: Option<&str>,
-    ) -> Result<()> {
-        let user_mapping = self
+    pub async fn handle_slack_user_update(&self, user: &SlackUser) -> Result<()> {
+        // Only users the bridge has already seen have ghosts worth updating.
+        if self
             .db_manager
             .user_store()
-            .get_user_by_slack_id(slack_user_id)
-            .await?;
-
-        let Some(mapping) = user_mapping else {
-            debug!("ignoring user update for unmapped user {}", slack_user_id);
+            .get_user_by_slack_id(&user.id)
+            .await?
+            .is_none()
+        {
+            debug!("ignoring user update for unmapped user {}", user.id);
             return Ok(());
-        };
+        }
 
-        let mut updated = mapping.clone();
-        updated.slack_username = new_username.to_string();
-        updated.slack_avatar = new_avatar_url.map(ToOwned::to_owned);
-        updated.updated_at = chrono::Utc::now();
-        self.db_manager
-            .user_store()
-            .update_user_mapping(&updated)
-            .await?;
+        if user.deleted {
+            self.presence_handler.dequeue_user(&user.id);
+        }
+        self.user_sync.on_user_update(user, false).await?;
 
         info!(
-            "updated user mapping for {} with new username {}",
-            slack_user_id, new_username
+            "synced ghost profile for {} with username {}",
+            user.id, user.username
         );
         Ok(())
     }
@@ -2858,10 +3695,22 @@ impl BridgeCore {
     }
 
     pub fn blocker(&self) -> Arc<blocker::BridgeBlocker> {
-        Arc::new(blocker::BridgeBlocker::new(
-            self.db_manager.clone(),
-            self.matrix_client.config(),
-        ))
+        self.blocker.clone()
+    }
+
+    pub fn admin_notifier(&self) -> Arc<AdminNotifier> {
+        self.admin_notifier.clone()
+    }
+
+    /// Counts a failure towards the admin's error-rate alert.
+    pub async fn report_error(&self, context: &str, err: &anyhow::Error) {
+        if let Err(notify_err) = self
+            .admin_notifier
+            .notify_error(&err.to_string(), Some(context))
+            .await
+        {
+            warn!("failed to notify admin about errors: {}", notify_err);
+        }
     }
 }
 
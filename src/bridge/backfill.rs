@@ -1,10 +1,16 @@
-use anyhow::Result;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, bail};
 use serde_json::Value;
 use tracing::{debug, info, warn};
 
 use crate::bridge::BridgeCore;
 use crate::db::RoomMapping;
 
+/// Largest page Slack returns from `conversations.history` and `conversations.replies`.
+const HISTORY_PAGE_SIZE: u32 = 100;
+
 /// Configuration for backfill operations
 #[derive(Debug, Clone)]
 pub struct BackfillConfig {
@@ -26,28 +32,69 @@ impl Default for BackfillConfig {
     }
 }
 
+/// Matrix rooms with a backfill in progress.
+pub type BackfillingRooms = Arc<Mutex<HashSet<String>>>;
+
+/// Marks a room as backfilling until dropped.
+struct BackfillGuard {
+    rooms: BackfillingRooms,
+    room_id: String,
+}
+
+impl BackfillGuard {
+    fn claim(rooms: &BackfillingRooms, room_id: &str) -> Option<Self> {
+        let claimed = rooms
+            .lock()
+            .expect("backfilling rooms lock")
+            .insert(room_id.to_string());
+        claimed.then(|| Self {
+            rooms: rooms.clone(),
+            room_id: room_id.to_string(),
+        })
+    }
+}
+
+impl Drop for BackfillGuard {
+    fn drop(&mut self) {
+        self.rooms
+            .lock()
+            .expect("backfilling rooms lock")
+            .remove(&self.room_id);
+    }
+}
+
 impl BridgeCore {
-    /// Backfill message history for a specific channel
-    pub async fn backfill_channel(
-        &self,
-        mapping: &RoomMapping,
-        max_messages: u32,
-    ) -> Result<u32> {
+    pub fn is_backfilling(&self, matrix_room_id: &str) -> bool {
+        self.backfilling_rooms
+            .lock()
+            .expect("backfilling rooms lock")
+            .contains(matrix_room_id)
+    }
+
+    /// Backfill up to `max_messages` of a channel's most recent history, oldest first.
+    /// Messages go through the same conversion as live ones and keep their Slack time;
+    /// thread replies and reactions are replayed along with their message. Only one
+    /// backfill runs per room at a time.
+    pub async fn backfill_channel(&self, mapping: &RoomMapping, max_messages: u32) -> Result<u32> {
         if max_messages == 0 {
             return Ok(0);
         }
+        let Some(_guard) = BackfillGuard::claim(&self.backfilling_rooms, &mapping.matrix_room_id)
+        else {
+            bail!("a backfill is already running in this room");
+        };
 
         info!(
             "starting backfill for channel {} -> room {}",
             mapping.slack_channel_id, mapping.matrix_room_id
         );
 
-        let mut total_sent = 0u32;
+        // History pages run newest to oldest, so the whole window is collected before
+        // anything is sent.
+        let mut messages = Vec::new();
         let mut cursor: Option<String> = None;
-        let remaining = max_messages;
-
-        loop {
-            let batch_size = remaining.min(100);
+        while messages.len() < max_messages as usize {
+            let batch_size = (max_messages - messages.len() as u32).min(HISTORY_PAGE_SIZE);
             let history = self
                 .slack_client
                 .get_conversation_history(
@@ -57,140 +104,42 @@ impl BridgeCore {
                 )
                 .await?;
 
-            let messages = history
+            let page = history
                 .get("messages")
                 .and_then(Value::as_array)
                 .cloned()
                 .unwrap_or_default();
-
-            if messages.is_empty() {
+            if page.is_empty() {
                 break;
             }
+            messages.extend(page);
 
-            // Messages come in reverse chronological order, reverse them
-            let mut messages = messages;
-            messages.reverse();
-
-            for msg in &messages {
-                if total_sent >= max_messages {
-                    break;
-                }
-
-                let sender_id = msg.get("user").and_then(Value::as_str);
-                let text = msg.get("text").and_then(Value::as_str).unwrap_or_default();
-                let ts = msg.get("ts").and_then(Value::as_str);
-                let thread_ts = msg.get("thread_ts").and_then(Value::as_str);
-                let subtype = msg.get("subtype").and_then(Value::as_str);
-
-                // Skip subtypes that aren't real messages
-                match subtype {
-                    Some("channel_join") | Some("channel_leave") | Some("channel_topic")
-                    | Some("channel_purpose") | Some("channel_name") => continue,
-                    _ => {}
-                }
-
-                let Some(sender_id) = sender_id else {
-                    continue;
-                };
-
-                if text.is_empty() && msg.get("files").is_none() {
-                    continue;
-                }
-
-                // Check if we already have this message bridged
-                if let Some(ts) = ts {
-                    if self
-                        .db_manager
-                        .message_store()
-                        .get_by_slack_message_id(ts)
-                        .await?
-                        .is_some()
-                    {
-                        continue;
-                    }
-                }
-
-                // Extract file attachments
-                let attachments = extract_backfill_attachments(msg);
-
-                let reply_to = thread_ts
-                    .filter(|thread| ts.is_some_and(|t| *thread != t))
-                    .map(ToOwned::to_owned);
-
-                self.matrix_client
-                    .ensure_ghost_user_registered(sender_id, None)
-                    .await?;
-
-                let outbound = crate::bridge::message_flow::OutboundMatrixMessage {
-                    body: crate::parsers::common::MessageUtils::normalize_slack_text_basic(text),
-                    formatted_body: None,
-                    reply_to,
-                    edit_of: None,
-                    attachments,
-                };
-
-                match if !outbound.attachments.is_empty() {
-                    self.send_to_matrix_with_attachments(
-                        &mapping.matrix_room_id,
-                        sender_id,
-                        &outbound,
-                    )
-                    .await
-                } else {
-                    self.send_to_matrix_message(
-                        &mapping.matrix_room_id,
-                        sender_id,
-                        outbound,
-                    )
-                    .await
-                } {
-                    Ok(event_id) => {
-                        if let Some(ts) = ts {
-                            let _ = self
-                                .db_manager
-                                .message_store()
-                                .upsert_message_mapping(
-                                    &crate::db::MessageMapping {
-                                        id: 0,
-                                        slack_message_id: ts.to_string(),
-                                        matrix_room_id: mapping.matrix_room_id.clone(),
-                                        matrix_event_id: event_id,
-                                        created_at: chrono::Utc::now(),
-                                        updated_at: chrono::Utc::now(),
-                                    },
-                                )
-                                .await;
-                        }
-                        total_sent += 1;
-                    }
-                    Err(err) => {
-                        warn!("failed to send backfill message to matrix: {}", err);
-                    }
-                }
-            }
-
-            if total_sent >= max_messages {
+            cursor = next_cursor(&history);
+            if cursor.is_none() {
                 break;
             }
+        }
+        messages.truncate(max_messages as usize);
+        messages.reverse();
 
-            // Check for pagination
-            let has_more = history
-                .get("has_more")
-                .and_then(Value::as_bool)
-                .unwrap_or(false);
-            if !has_more {
-                break;
+        let mut total_sent = 0u32;
+        for message in &messages {
+            if self.backfill_message(mapping, message).await? {
+                total_sent += 1;
             }
 
-            cursor = history
-                .get("response_metadata")
-                .and_then(|m| m.get("next_cursor"))
-                .and_then(Value::as_str)
-                .filter(|c| !c.is_empty())
-                .map(ToOwned::to_owned);
-
-            if cursor.is_none() {
-                break;
+            let has_replies = message
+                .get("reply_count")
+                .and_then(Value::as_u64)
+                .is_some_and(|count| count > 0);
+            if has_replies && let Some(thread_ts) = message.get("ts").and_then(Value::as_str) {
+                match self.backfill_thread(mapping, thread_ts, max_messages).await {
+                    Ok(count) => total_sent += count,
+                    Err(err) => warn!(
+                        "failed to backfill thread {} in channel {}: {}",
+                        thread_ts, mapping.slack_channel_id, err
+                    ),
+                }
             }
         }
 
@@ -202,7 +151,7 @@ impl BridgeCore {
         Ok(total_sent)
     }
 
-    /// Backfill thread replies for a specific thread
+    /// Backfill up to `max_messages` replies of one thread into its Matrix thread.
     pub async fn backfill_thread(
         &self,
         mapping: &RoomMapping,
@@ -214,120 +163,211 @@ impl BridgeCore {
             mapping.slack_channel_id, thread_ts, mapping.matrix_room_id
         );
 
-        let replies = self
-            .slack_client
-            .get_conversation_replies(
-                &mapping.slack_channel_id,
-                thread_ts,
-                Some(max_messages),
-                None,
-            )
-            .await?;
-
-        let messages = replies
-            .get("messages")
-            .and_then(Value::as_array)
-            .cloned()
-            .unwrap_or_default();
-
         let mut total_sent = 0u32;
+        let mut seen = 0u32;
+        let mut cursor: Option<String> = None;
+        loop {
+            let replies = self
+                .slack_client
+                .get_conversation_replies(
+                    &mapping.slack_channel_id,
+                    thread_ts,
+                    Some(max_messages.min(HISTORY_PAGE_SIZE)),
+                    cursor.as_deref(),
+                )
+                .await?;
 
-        for msg in &messages {
-            let sender_id = msg.get("user").and_then(Value::as_str);
-            let text = msg.get("text").and_then(Value::as_str).unwrap_or_default();
-            let ts = msg.get("ts").and_then(Value::as_str);
-
-            let Some(sender_id) = sender_id else {
-                continue;
-            };
-
-            if text.is_empty() {
-                continue;
-            }
-
-            // Skip if already bridged
-            if let Some(ts) = ts {
-                if self
-                    .db_manager
-                    .message_store()
-                    .get_by_slack_message_id(ts)
-                    .await?
-                    .is_some()
-                {
+            let messages = replies
+                .get("messages")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            for message in &messages {
+                // Every page starts with the thread root, which the caller already sent.
+                if message.get("ts").and_then(Value::as_str) == Some(thread_ts) {
                     continue;
                 }
+                if seen >= max_messages {
+                    break;
+                }
+                seen += 1;
+                if self.backfill_message(mapping, message).await? {
+                    total_sent += 1;
+                }
             }
 
-            let reply_to = if ts != Some(thread_ts) {
-                Some(thread_ts.to_string())
-            } else {
-                None
-            };
+            cursor = next_cursor(&replies);
+            if messages.is_empty() || seen >= max_messages || cursor.is_none() {
+                break;
+            }
+        }
 
-            self.matrix_client
-                .ensure_ghost_user_registered(sender_id, None)
-                .await?;
+        info!("thread backfill complete: {} messages sent", total_sent);
 
-            let outbound = crate::bridge::message_flow::OutboundMatrixMessage {
-                body: crate::parsers::common::MessageUtils::normalize_slack_text_basic(text),
-                formatted_body: None,
-                reply_to,
-                edit_of: None,
-                attachments: extract_backfill_attachments(msg),
+        Ok(total_sent)
+    }
+
+    /// Replays one history message and its reactions. Returns whether the message itself
+    /// was newly bridged; messages bridged before only get missing reactions added.
+    async fn backfill_message(&self, mapping: &RoomMapping, message: &Value) -> Result<bool> {
+        if !is_backfillable(message) {
+            return Ok(false);
+        }
+        let Some(ts) = message.get("ts").and_then(Value::as_str) else {
+            return Ok(false);
+        };
+        let origin_ts = slack_ts_to_millis(ts);
+        let message_store = self.db_manager.message_store();
+        let already_bridged = message_store
+            .get_by_slack_message_id(&mapping.slack_channel_id, ts)
+            .await?
+            .is_some();
+
+        let mut sent = false;
+        if !already_bridged {
+            let Some(mut ctx) = self
+                .slack_client
+                .message_context(&mapping.slack_channel_id, message, false)
+                .await
+            else {
+                return Ok(false);
             };
+            ctx.backfill_ts = origin_ts;
+            if let Err(err) = self.handle_slack_message_with_context(ctx).await {
+                warn!("failed to send backfill message {} to matrix: {}", ts, err);
+                return Ok(false);
+            }
+            sent = message_store
+                .get_by_slack_message_id(&mapping.slack_channel_id, ts)
+                .await?
+                .is_some();
+        }
 
-            match self
-                .send_to_matrix_message(&mapping.matrix_room_id, sender_id, outbound)
+        for (user, name) in message_reactions(message) {
+            if let Err(err) = self
+                .add_slack_reaction(&mapping.slack_channel_id, ts, user, name, origin_ts)
                 .await
             {
-                Ok(event_id) => {
-                    if let Some(ts) = ts {
-                        let _ = self
-                            .db_manager
-                            .message_store()
-                            .upsert_message_mapping(
-                                &crate::db::MessageMapping {
-                                    id: 0,
-                                    slack_message_id: ts.to_string(),
-                                    matrix_room_id: mapping.matrix_room_id.clone(),
-                                    matrix_event_id: event_id,
-                                    created_at: chrono::Utc::now(),
-                                    updated_at: chrono::Utc::now(),
-                                },
-                            )
-                            .await;
-                    }
-                    total_sent += 1;
-                }
-                Err(err) => {
-                    warn!("failed to send backfill thread reply to matrix: {}", err);
-                }
+                debug!(
+                    "failed to backfill reaction {} by {} on {}: {}",
+                    name, user, ts, err
+                );
             }
         }
 
-        info!(
-            "thread backfill complete: {} messages sent",
-            total_sent
-        );
+        Ok(sent)
+    }
+}
 
-        Ok(total_sent)
+/// Whether a history message is one the live event handler would bridge.
+fn is_backfillable(message: &Value) -> bool {
+    match message.get("subtype").and_then(Value::as_str) {
+        None | Some("me_message") | Some("file_share") | Some("thread_broadcast") => true,
+        Some("bot_message") => message
+            .get("text")
+            .and_then(Value::as_str)
+            .is_some_and(|text| !text.trim().is_empty()),
+        Some(_) => false,
     }
 }
 
-fn extract_backfill_attachments(message: &Value) -> Vec<String> {
-    let mut output = Vec::new();
-    if let Some(files) = message.get("files").and_then(Value::as_array) {
-        for file in files {
-            let link = file
-                .get("permalink_public")
-                .and_then(Value::as_str)
-                .or_else(|| file.get("permalink").and_then(Value::as_str))
-                .or_else(|| file.get("url_private_download").and_then(Value::as_str))
-                .or_else(|| file.get("url_private").and_then(Value::as_str));
-            if let Some(link) = link {
-                output.push(link.to_string());
-            }
-        }
+/// `(user, reaction name)` pairs from a message's `reactions` summary.
+fn message_reactions(message: &Value) -> Vec<(&str, &str)> {
+    message
+        .get("reactions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|reaction| {
+            let name = reaction.get("name").and_then(Value::as_str)?;
+            let users = reaction.get("users").and_then(Value::as_array)?;
+            Some(
+                users
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(move |user| (user, name)),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+/// Converts a Slack `ts` ("seconds.micros") to unix milliseconds.
+fn slack_ts_to_millis(ts: &str) -> Option<u64> {
+    let (secs, fraction) = ts.split_once('.').unwrap_or((ts, "0"));
+    let secs = secs.parse::<u64>().ok()?;
+    let millis = format!("{fraction:0<3}").get(..3)?.parse::<u64>().ok()?;
+    Some(secs * 1000 + millis)
+}
+
+fn next_cursor(response: &Value) -> Option<String> {
+    let has_more = response
+        .get("has_more")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    if !has_more {
+        return None;
+    }
+    response
+        .pointer("/response_metadata/next_cursor")
+        .and_then(Value::as_str)
+        .filter(|cursor| !cursor.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        BackfillGuard, BackfillingRooms, is_backfillable, message_reactions, slack_ts_to_millis,
+    };
+
+    #[test]
+    fn only_one_backfill_claims_a_room_until_it_finishes() {
+        let rooms = BackfillingRooms::default();
+        let guard = BackfillGuard::claim(&rooms, "!room:example.org").unwrap();
+        assert!(BackfillGuard::claim(&rooms, "!room:example.org").is_none());
+        assert!(BackfillGuard::claim(&rooms, "!other:example.org").is_some());
+        drop(guard);
+        assert!(BackfillGuard::claim(&rooms, "!room:example.org").is_some());
+    }
+
+    #[test]
+    fn slack_ts_converts_to_unix_millis() {
+        assert_eq!(
+            slack_ts_to_millis("1700000000.123456"),
+            Some(1_700_000_000_123)
+        );
+        assert_eq!(slack_ts_to_millis("1700000000.5"), Some(1_700_000_000_500));
+        assert_eq!(slack_ts_to_millis("1700000000"), Some(1_700_000_000_000));
+        assert_eq!(slack_ts_to_millis("not-a-ts"), None);
+    }
+
+    #[test]
+    fn only_message_subtypes_the_live_path_bridges_are_backfilled() {
+        assert!(is_backfillable(&json!({ "text": "hi" })));
+        assert!(is_backfillable(&json!({ "subtype": "thread_broadcast" })));
+        assert!(is_backfillable(
+            &json!({ "subtype": "bot_message", "text": "build ok" })
+        ));
+        assert!(!is_backfillable(
+            &json!({ "subtype": "bot_message", "text": " " })
+        ));
+        assert!(!is_backfillable(&json!({ "subtype": "channel_join" })));
+    }
+
+    #[test]
+    fn reactions_expand_to_one_entry_per_user() {
+        let message = json!({
+            "reactions": [
+                { "name": "tada", "users": ["U1", "U2"], "count": 2 },
+                { "name": "eyes", "users": ["U3"], "count": 1 },
+            ],
+        });
+        assert_eq!(
+            message_reactions(&message),
+            vec![("U1", "tada"), ("U2", "tada"), ("U3", "eyes")]
+        );
     }
-    output
 }
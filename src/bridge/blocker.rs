@@ -1,15 +1,21 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tracing::{info, warn};
 
+use crate::admin::AdminNotifier;
+use crate::cache::AsyncTimedCache;
 use crate::config::Config;
 use crate::db::DatabaseManager;
 
-const DEFAULT_USER_LIMIT: u32 = 100;
 const DEFAULT_CHECK_INTERVAL_SECS: u64 = 60;
+/// Users count towards `bridge.user_limit` for this long after their last message.
+const ACTIVE_USER_WINDOW_SECS: u64 = 30 * 24 * 60 * 60;
+/// A user's last-active time is written at most this often.
+const ACTIVITY_WRITE_INTERVAL_SECS: u64 = 60 * 60;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BridgeState {
@@ -18,12 +24,24 @@ pub enum BridgeState {
     Limited,
 }
 
+impl BridgeState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Paused => "paused",
+            Self::Limited => "limited",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BridgeStatus {
     pub state: BridgeState,
     pub active_users: u64,
     pub total_rooms: u64,
+    /// Matrix messages posted to Slack.
     pub messages_sent: u64,
+    /// Slack messages delivered to Matrix.
     pub messages_received: u64,
     pub last_check: Instant,
     pub pause_reason: Option<String>,
@@ -33,12 +51,23 @@ pub struct BridgeBlocker {
     db: Arc<DatabaseManager>,
     config: Arc<Config>,
     state: Arc<RwLock<BridgeStatus>>,
-    user_limit: u32,
+    /// `None` when `bridge.user_limit` is unset, which leaves the user count unlimited.
+    user_limit: Option<u32>,
+    /// Rooms and channels already told about the current block.
+    notified: Mutex<HashSet<String>>,
+    /// Users whose activity was written recently, so busy senders don't write on every
+    /// message.
+    recorded_users: AsyncTimedCache<String, ()>,
+    notifier: Arc<AdminNotifier>,
 }
 
 impl BridgeBlocker {
-    pub fn new(db: Arc<DatabaseManager>, config: Arc<Config>) -> Self {
-        let user_limit = config.bridge.user_limit.unwrap_or(DEFAULT_USER_LIMIT);
+    pub fn new(
+        db: Arc<DatabaseManager>,
+        config: Arc<Config>,
+        notifier: Arc<AdminNotifier>,
+    ) -> Self {
+        let user_limit = config.bridge.user_limit;
 
         Self {
             db,
@@ -53,48 +82,86 @@ impl BridgeBlocker {
                 pause_reason: None,
             })),
             user_limit,
+            notified: Mutex::new(HashSet::new()),
+            recorded_users: AsyncTimedCache::new(Duration::from_secs(
+                ACTIVITY_WRITE_INTERVAL_SECS,
+            )),
+            notifier,
         }
     }
 
     pub async fn check_and_update(&self) -> Result<BridgeState> {
         let active_users = self.count_active_users().await?;
         let total_rooms = self.count_rooms().await?;
+        let pause_reason = self.db.bridge_state_store().get_pause_reason().await?;
 
         let mut status = self.state.write().await;
         status.active_users = active_users;
         status.total_rooms = total_rooms;
         status.last_check = Instant::now();
 
-        if active_users > self.user_limit as u64 {
+        // A manual pause outlasts any change in the user count, and restarts.
+        if let Some(reason) = pause_reason {
+            status.state = BridgeState::Paused;
+            status.pause_reason = Some(reason);
+            return Ok(BridgeState::Paused);
+        }
+
+        let exceeded_limit = self.user_limit.filter(|limit| active_users > *limit as u64);
+        if let Some(limit) = exceeded_limit {
+            if status.state == BridgeState::Limited {
+                return Ok(BridgeState::Limited);
+            }
             status.state = BridgeState::Limited;
-            status.pause_reason = Some(format!(
-                "User limit exceeded: {} > {}",
-                active_users, self.user_limit
-            ));
+            status.pause_reason =
+                Some(format!("User limit exceeded: {} > {}", active_users, limit));
             warn!("Bridge limited: {}", status.pause_reason.as_ref().unwrap());
-        } else if status.state == BridgeState::Limited && active_users <= self.user_limit as u64 {
+            drop(status);
+            if let Err(err) = self
+                .notifier
+                .notify_user_limit_reached(active_users, limit)
+                .await
+            {
+                warn!("failed to notify admin about the user limit: {}", err);
+            }
+            return Ok(BridgeState::Limited);
+        } else if status.state == BridgeState::Limited {
             status.state = BridgeState::Active;
             status.pause_reason = None;
+            self.notified.lock().await.clear();
             info!("Bridge restored to active state");
+            drop(status);
+            if let Err(err) = self.notifier.notify_bridge_resumed().await {
+                warn!("failed to notify admin about the bridge resuming: {}", err);
+            }
+            return Ok(BridgeState::Active);
         }
 
         Ok(status.state.clone())
     }
 
     pub async fn pause(&self, reason: &str) -> Result<()> {
+        self.db
+            .bridge_state_store()
+            .set_pause_reason(Some(reason))
+            .await?;
         let mut status = self.state.write().await;
         status.state = BridgeState::Paused;
         status.pause_reason = Some(reason.to_string());
         info!("Bridge paused: {}", reason);
-        Ok(())
+        drop(status);
+        self.notifier.notify_bridge_paused(reason).await
     }
 
     pub async fn resume(&self) -> Result<()> {
+        self.db.bridge_state_store().set_pause_reason(None).await?;
         let mut status = self.state.write().await;
         status.state = BridgeState::Active;
         status.pause_reason = None;
+        self.notified.lock().await.clear();
         info!("Bridge resumed");
-        Ok(())
+        drop(status);
+        self.notifier.notify_bridge_resumed().await
     }
 
     pub async fn is_blocked(&self) -> bool {
@@ -102,6 +169,26 @@ impl BridgeBlocker {
         status.state != BridgeState::Active
     }
 
+    /// The notice shown where messages are dropped, or `None` while the bridge is active.
+    pub async fn blocked_notice(&self) -> Option<String> {
+        let status = self.state.read().await;
+        let reason = match status.state {
+            BridgeState::Active => return None,
+            BridgeState::Paused => "the bridge is paused",
+            BridgeState::Limited => "the bridge has reached its user limit",
+        };
+        Some(match &status.pause_reason {
+            Some(detail) => format!("Messages are not being bridged: {reason} ({detail})."),
+            None => format!("Messages are not being bridged: {reason}."),
+        })
+    }
+
+    /// Whether `target` still needs to be told the bridge is blocked; true once per
+    /// room or channel until the bridge is active again.
+    pub async fn should_notify(&self, target: &str) -> bool {
+        self.notified.lock().await.insert(target.to_string())
+    }
+
     pub async fn get_status(&self) -> BridgeStatus {
         self.state.read().await.clone()
     }
@@ -116,9 +203,30 @@ impl BridgeBlocker {
         status.messages_received += 1;
     }
 
+    /// Counts the sender of a bridged message as active.
+    pub async fn record_active_user(&self, user_id: &str) {
+        if self.recorded_users.get(&user_id.to_string()).await.is_some() {
+            return;
+        }
+        match self
+            .db
+            .bridge_state_store()
+            .touch_active_user(user_id, chrono::Utc::now())
+            .await
+        {
+            Ok(()) => self.recorded_users.insert(user_id.to_string(), ()).await,
+            Err(err) => warn!("failed to record activity of {}: {}", user_id, err),
+        }
+    }
+
     async fn count_active_users(&self) -> Result<u64> {
-        let user_ids = self.db.user_store().get_all_user_ids().await?;
-        Ok(user_ids.len() as u64)
+        let cutoff = chrono::Utc::now() - Duration::from_secs(ACTIVE_USER_WINDOW_SECS);
+        let count = self
+            .db
+            .bridge_state_store()
+            .count_active_users_since(cutoff)
+            .await?;
+        Ok(count as u64)
     }
 
     async fn count_rooms(&self) -> Result<u64> {
@@ -126,16 +234,27 @@ impl BridgeBlocker {
         Ok(count as u64)
     }
 
-    pub fn user_limit(&self) -> u32 {
+    pub fn user_limit(&self) -> Option<u32> {
         self.user_limit
     }
 
+    pub async fn run_checker(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(Duration::from_secs(DEFAULT_CHECK_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.check_and_update().await {
+                warn!("failed to update bridge blocker state: {}", err);
+            }
+        }
+    }
+
     pub async fn should_accept_new_user(&self) -> bool {
         let status = self.state.read().await;
         if status.state != BridgeState::Active {
             return false;
         }
-        status.active_users < self.user_limit as u64
+        self.user_limit
+            .is_none_or(|limit| status.active_users < limit as u64)
     }
 }
 
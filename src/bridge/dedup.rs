@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use tracing::{debug, info, warn};
+
+use crate::db::{ProcessedEvent, ProcessedEventStore};
+
+pub const SOURCE_MATRIX: &str = "matrix";
+pub const SOURCE_SLACK: &str = "slack";
+
+const MATRIX_TRANSACTION_TYPE: &str = "m.transaction";
+const PRUNE_INTERVAL_SECS: u64 = 3600;
+
+/// Remembers which Matrix transactions and inbound events were already handled, so
+/// retries from the homeserver or Slack do not get bridged twice.
+pub struct EventDeduplicator {
+    store: Arc<dyn ProcessedEventStore>,
+    retention: Duration,
+}
+
+impl EventDeduplicator {
+    pub fn new(store: Arc<dyn ProcessedEventStore>, retention: Duration) -> Self {
+        Self { store, retention }
+    }
+
+    fn transaction_key(txn_id: &str) -> String {
+        format!("txn:{txn_id}")
+    }
+
+    pub async fn is_transaction_processed(&self, txn_id: &str) -> bool {
+        match self
+            .store
+            .is_event_processed(&Self::transaction_key(txn_id))
+            .await
+        {
+            Ok(processed) => processed,
+            Err(err) => {
+                warn!("failed to look up matrix transaction {}: {}", txn_id, err);
+                false
+            }
+        }
+    }
+
+    pub async fn mark_transaction_processed(&self, txn_id: &str) {
+        self.claim(
+            SOURCE_MATRIX,
+            MATRIX_TRANSACTION_TYPE,
+            &Self::transaction_key(txn_id),
+        )
+        .await;
+    }
+
+    /// Records `event_id` and returns `true` the first time it is seen. Storage errors
+    /// let the event through rather than dropping it.
+    pub async fn claim(&self, source: &str, event_type: &str, event_id: &str) -> bool {
+        let event = ProcessedEvent {
+            id: 0,
+            event_id: event_id.to_string(),
+            event_type: event_type.to_string(),
+            source: source.to_string(),
+            processed_at: Utc::now(),
+        };
+        match self.store.mark_event_processed(&event).await {
+            Ok(true) => true,
+            Ok(false) => {
+                debug!(
+                    "duplicate event skipped source={} type={} event_id={}",
+                    source, event_type, event_id
+                );
+                false
+            }
+            Err(err) => {
+                warn!("failed to record processed event {}: {}", event_id, err);
+                true
+            }
+        }
+    }
+
+    pub async fn prune(&self) -> Result<u64> {
+        let retention = chrono::Duration::from_std(self.retention)?;
+        let deleted = self
+            .store
+            .delete_processed_events_before(Utc::now() - retention)
+            .await?;
+        if deleted > 0 {
+            info!("pruned {} processed event records", deleted);
+        }
+        Ok(deleted)
+    }
+
+    pub async fn run_pruner(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(Duration::from_secs(PRUNE_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.prune().await {
+                warn!("failed to prune processed events: {}", err);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::DatabaseManager;
+
+    #[tokio::test]
+    async fn claims_each_event_once_and_prunes_expired_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dedup.db");
+        let config = crate::config::DatabaseConfig::from_url(format!(
+            "sqlite://{}",
+            path.display()
+        ));
+        let db_manager = DatabaseManager::new(&config).await.unwrap();
+        db_manager.migrate().await.unwrap();
+
+        let dedup = EventDeduplicator::new(db_manager.processed_event_store(), Duration::ZERO);
+        assert!(!dedup.is_transaction_processed("1").await);
+        assert!(dedup.claim(SOURCE_MATRIX, "m.room.message", "$a").await);
+        assert!(!dedup.claim(SOURCE_MATRIX, "m.room.message", "$a").await);
+        dedup.mark_transaction_processed("1").await;
+        assert!(dedup.is_transaction_processed("1").await);
+
+        assert_eq!(dedup.prune().await.unwrap(), 2);
+        assert!(!dedup.is_transaction_processed("1").await);
+        assert!(dedup.claim(SOURCE_MATRIX, "m.room.message", "$a").await);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::Result;
+use tracing::{debug, info, warn};
+
+use crate::bridge::BridgeCore;
+use crate::db::RoomMapping;
+use crate::matrix::MatrixEvent;
+
+impl BridgeCore {
+    /// Gives a Slack DM or group DM with the bot a Matrix DM room when it has none yet.
+    /// The room is owned by a ghost of the conversation, holds the ghosts of everyone
+    /// else in it and is shared with `bridge.admin_mxid`, the only Matrix user a
+    /// conversation with the bot can be addressed to.
+    pub async fn ensure_direct_portal(&self, channel_id: &str) -> Result<()> {
+        // Messages arriving together must not each create a room for the conversation.
+        let _guard = self.portal_queue.lock(channel_id).await;
+        if self
+            .db_manager
+            .room_store()
+            .get_room_by_slack_channel(channel_id)
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+        let config = self.matrix_client.config();
+        let Some(recipient) = config.bridge.admin_mxid.as_deref() else {
+            debug!(
+                "not bridging slack conversation {}: no admin_mxid to share it with",
+                channel_id
+            );
+            return Ok(());
+        };
+
+        let bot_user_id = self.slack_client.bot_user_id().await;
+        let mut users = Vec::new();
+        for member in self
+            .slack_client
+            .get_conversation_members(channel_id)
+            .await?
+        {
+            if bot_user_id.as_deref() == Some(member.as_str()) {
+                continue;
+            }
+            if let Some(user) = self.provision_ghost(&member).await? {
+                users.push(user);
+            }
+        }
+        let Some((owner, others)) = users.split_first() else {
+            debug!(
+                "not bridging slack conversation {}: no active members",
+                channel_id
+            );
+            return Ok(());
+        };
+
+        let mut invite = vec![recipient.to_string(), self.matrix_client.bot_user_id()];
+        invite.extend(
+            others
+                .iter()
+                .map(|user| self.matrix_client.ghost_user_id(&user.id)),
+        );
+        let matrix_room_id = self
+            .matrix_client
+            .create_direct_room(&owner.id, &invite)
+            .await?;
+        for user in others {
+            self.matrix_client
+                .join_room_as_ghost(&user.id, &matrix_room_id)
+                .await?;
+        }
+        self.matrix_client
+            .appservice
+            .client
+            .join_room(&matrix_room_id)
+            .await?;
+        for user in &users {
+            if let Err(err) = self
+                .matrix_client
+                .add_ghost_direct_room(&user.id, recipient, &matrix_room_id)
+                .await
+            {
+                warn!("failed to update m.direct of {}: {}", user.id, err);
+            }
+        }
+
+        let name = users
+            .iter()
+            .map(|user| user.username.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.create_direct_mapping(&matrix_room_id, channel_id, &name)
+            .await?;
+        info!(
+            "created matrix dm {} for slack conversation {}",
+            matrix_room_id, channel_id
+        );
+        Ok(())
+    }
+
+    /// Handles a Matrix user inviting a ghost into a DM room: opens the inviter's Slack DM
+    /// with that user through their login and bridges the room to it. Only `admin_mxid`
+    /// may use the bot's DM instead. Returns whether the invite was a DM invite the
+    /// bridge took care of.
+    pub async fn handle_direct_invite(&self, event: &MatrixEvent) -> Result<bool> {
+        let is_direct = event
+            .content
+            .as_ref()
+            .and_then(|content| content.get("is_direct"))
+            .and_then(|is_direct| is_direct.as_bool())
+            .unwrap_or(false);
+        // Ghosts inviting each other happens while the bridge sets up a DM itself.
+        if !is_direct || self.matrix_client.is_namespaced_user(&event.sender) {
+            return Ok(false);
+        }
+        let Some(slack_user_id) = event
+            .state_key
+            .as_deref()
+            .and_then(|user_id| self.matrix_client.slack_user_from_ghost(user_id))
+        else {
+            return Ok(false);
+        };
+        if self
+            .get_room_mapping_cached(&event.room_id)
+            .await?
+            .is_some()
+        {
+            debug!(
+                "ghost invited to already bridged room {}, ignoring",
+                event.room_id
+            );
+            return Ok(true);
+        }
+        let Some(user) = self.provision_ghost(&slack_user_id).await? else {
+            warn!(
+                "not opening slack dm with {}: no active slack user",
+                slack_user_id
+            );
+            return Ok(true);
+        };
+
+        let puppet = self.slack_puppet(&event.sender).await?;
+        let slack_client = match puppet.as_ref() {
+            Some(puppet) => puppet,
+            None if self.matrix_client.config().bridge.admin_mxid.as_deref()
+                == Some(event.sender.as_str()) =>
+            {
+                self.slack_client.as_ref()
+            }
+            None => {
+                warn!(
+                    "rejecting dm invite of {} to {}: {} is not logged in to slack",
+                    user.id, event.room_id, event.sender
+                );
+                self.reject_direct_invite(
+                    &user.id,
+                    &event.room_id,
+                    "Log in to Slack with `!slack login` to message Slack users directly.",
+                )
+                .await;
+                return Ok(true);
+            }
+        };
+
+        let channel_id = slack_client.open_conversation(&[&user.id]).await?;
+        if let Some(existing) = self
+            .db_manager
+            .room_store()
+            .get_room_by_slack_channel(&channel_id)
+            .await?
+        {
+            warn!(
+                "slack dm {} is already bridged to {}, rejecting invite to {}",
+                channel_id, existing.matrix_room_id, event.room_id
+            );
+            self.reject_direct_invite(
+                &user.id,
+                &event.room_id,
+                "This Slack conversation is already bridged to another room.",
+            )
+            .await;
+            return Ok(true);
+        }
+
+        self.matrix_client
+            .join_room_as_ghost(&user.id, &event.room_id)
+            .await?;
+        let bot_user_id = self.matrix_client.bot_user_id();
+        self.matrix_client
+            .invite_as_ghost(&user.id, &event.room_id, &bot_user_id)
+            .await?;
+        self.matrix_client
+            .appservice
+            .client
+            .join_room(&event.room_id)
+            .await?;
+        if let Err(err) = self
+            .matrix_client
+            .add_ghost_direct_room(&user.id, &event.sender, &event.room_id)
+            .await
+        {
+            warn!("failed to update m.direct of {}: {}", user.id, err);
+        }
+
+        self.create_direct_mapping(&event.room_id, &channel_id, &user.username)
+            .await?;
+        info!(
+            "bridged matrix dm {} to slack conversation {} with {}",
+            event.room_id, channel_id, user.id
+        );
+        Ok(true)
+    }
+
+    /// Joins the invited ghost just long enough to tell the inviter why the DM won't be
+    /// bridged.
+    async fn reject_direct_invite(&self, slack_user_id: &str, room_id: &str, reason: &str) {
+        if let Err(err) = self
+            .matrix_client
+            .join_room_as_ghost(slack_user_id, room_id)
+            .await
+        {
+            warn!("failed to join {} to reject dm invite: {}", room_id, err);
+            return;
+        }
+        if let Err(err) = self
+            .matrix_client
+            .send_notice_as_ghost(room_id, slack_user_id, reason)
+            .await
+        {
+            warn!("failed to explain rejected dm invite in {}: {}", room_id, err);
+        }
+        if let Err(err) = self
+            .matrix_client
+            .leave_room_as_ghost(slack_user_id, room_id)
+            .await
+        {
+            warn!("failed to leave rejected dm {}: {}", room_id, err);
+        }
+    }
+
+    async fn create_direct_mapping(
+        &self,
+        matrix_room_id: &str,
+        channel_id: &str,
+        name: &str,
+    ) -> Result<()> {
+        let now = chrono::Utc::now();
+        self.db_manager
+            .room_store()
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: matrix_room_id.to_string(),
+                slack_channel_id: channel_id.to_string(),
+                slack_channel_name: name.to_string(),
+                slack_team_id: self.slack_client.fallback_team_id().await,
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use serde_json::Value;
+
 use super::message_flow::OutboundMatrixMessage;
 use crate::db::{MessageMapping, RoomMapping};
 use crate::slack::ModerationAction;
@@ -17,8 +19,41 @@ pub(crate) struct TypingRequest {
     pub(crate) timeout_ms: Option<u64>,
 }
 
+/// A file attached to a Slack message. Deleted files stay in the message as tombstones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SlackFileRef {
+    pub(crate) id: String,
+    pub(crate) url: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) mimetype: Option<String>,
+    pub(crate) size: Option<u64>,
+    pub(crate) permalink: Option<String>,
+    pub(crate) tombstone: bool,
+}
+
+impl SlackFileRef {
+    /// An attachment known only by its URL, without Slack file metadata.
+    pub(crate) fn from_url(url: &str) -> Self {
+        Self {
+            id: String::new(),
+            url: Some(url.to_string()),
+            name: None,
+            mimetype: None,
+            size: None,
+            permalink: None,
+            tombstone: false,
+        }
+    }
+
+    /// The link posted instead of the file when it can't be re-uploaded to Matrix.
+    pub(crate) fn fallback_link(&self) -> Option<&str> {
+        self.permalink.as_deref().or(self.url.as_deref())
+    }
+}
+
 pub(crate) const SLACK_TYPING_TIMEOUT_MS: u64 = 4000;
 const MAX_PREVIEW_CHARS: usize = 120;
+const FILE_MAPPING_PREFIX: &str = "file:";
 
 pub(crate) fn preview_text(value: &str) -> String {
     let mut chars = value.chars();
@@ -30,10 +65,13 @@ pub(crate) fn preview_text(value: &str) -> String {
     }
 }
 
+/// Swaps Slack `ts` references for the Matrix events they were bridged to. A thread whose
+/// root was never bridged is dropped, so the message lands in the main timeline.
 pub(crate) fn apply_message_relation_mappings(
     outbound: &mut OutboundMatrixMessage,
     reply_mapping: Option<&MessageMapping>,
     edit_mapping: Option<&MessageMapping>,
+    thread_mapping: Option<&MessageMapping>,
 ) {
     if let Some(link) = reply_mapping {
         outbound.reply_to = Some(link.matrix_event_id.clone());
@@ -42,6 +80,8 @@ pub(crate) fn apply_message_relation_mappings(
     if let Some(link) = edit_mapping {
         outbound.edit_of = Some(link.matrix_event_id.clone());
     }
+
+    outbound.thread_root = thread_mapping.map(|link| link.matrix_event_id.clone());
 }
 
 pub(crate) fn build_slack_delete_redaction_request(link: &MessageMapping) -> RedactionRequest {
@@ -58,6 +98,47 @@ pub(crate) fn slack_delete_redaction_request(
     link.map(build_slack_delete_redaction_request)
 }
 
+/// Files get their own message mapping, keyed by file id rather than message `ts`, so a
+/// single file can be redacted when it is removed from a Slack message.
+pub(crate) fn file_mapping_key(file_id: &str) -> String {
+    format!("{FILE_MAPPING_PREFIX}{file_id}")
+}
+
+pub(crate) fn is_file_mapping_key(slack_message_id: &str) -> bool {
+    slack_message_id.starts_with(FILE_MAPPING_PREFIX)
+}
+
+/// Slack `thread_ts` that replies threaded under `link` belong in: its own thread if it
+/// is a thread reply, otherwise the message itself. File mappings have no usable `ts`.
+pub(crate) fn slack_thread_root(link: &MessageMapping) -> Option<String> {
+    link.slack_thread_ts.clone().or_else(|| {
+        (!is_file_mapping_key(&link.slack_message_id)).then(|| link.slack_message_id.clone())
+    })
+}
+
+pub(crate) fn slack_file_refs(files: &[Value]) -> Vec<SlackFileRef> {
+    files
+        .iter()
+        .filter_map(|file| {
+            let id = file.get("id").and_then(Value::as_str)?;
+            let url = ["url_private_download", "url_private", "permalink_public", "permalink"]
+                .iter()
+                .find_map(|key| file.get(*key).and_then(Value::as_str))
+                .map(ToOwned::to_owned);
+            let text = |key: &str| file.get(key).and_then(Value::as_str).map(ToOwned::to_owned);
+            Some(SlackFileRef {
+                id: id.to_string(),
+                url,
+                name: text("name"),
+                mimetype: text("mimetype"),
+                size: file.get("size").and_then(Value::as_u64),
+                permalink: text("permalink"),
+                tombstone: file.get("mode").and_then(Value::as_str) == Some("tombstone"),
+            })
+        })
+        .collect()
+}
+
 pub(crate) fn build_slack_typing_request(
     matrix_room_id: &str,
     slack_user_id: &str,
@@ -88,11 +169,13 @@ pub(crate) fn action_keyword(action: &ModerationAction) -> &'static str {
 #[cfg(test)]
 mod tests {
     use chrono::Utc;
+    use serde_json::json;
 
     use super::{
-        OutboundMatrixMessage, action_keyword, apply_message_relation_mappings,
-        build_slack_delete_redaction_request, build_slack_typing_request,
-        slack_delete_redaction_request, preview_text, should_forward_slack_typing,
+        OutboundMatrixMessage, SlackFileRef, action_keyword, apply_message_relation_mappings,
+        build_slack_delete_redaction_request, build_slack_typing_request, file_mapping_key,
+        slack_delete_redaction_request, slack_file_refs, slack_thread_root, preview_text,
+        should_forward_slack_typing,
     };
     use crate::db::{MessageMapping, RoomMapping};
     use crate::slack::ModerationAction;
@@ -101,8 +184,11 @@ mod tests {
         MessageMapping {
             id: 0,
             slack_message_id: slack_message_id.to_string(),
+            slack_channel_id: "C123".to_string(),
+            slack_team_id: "T123".to_string(),
             matrix_room_id: "!room:example.org".to_string(),
             matrix_event_id: matrix_event_id.to_string(),
+            slack_thread_ts: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -115,16 +201,20 @@ mod tests {
             formatted_body: None,
             reply_to: Some("slack-reply-id".to_string()),
             edit_of: Some("slack-edit-id".to_string()),
+            thread_root: Some("slack-thread-ts".to_string()),
             attachments: Vec::new(),
+            origin_ts: None,
         };
 
         let reply = mapping("slack-reply-id", "$matrix-reply");
         let edit = mapping("slack-edit-id", "$matrix-edit");
+        let thread = mapping("slack-thread-ts", "$matrix-thread-root");
 
-        apply_message_relation_mappings(&mut outbound, Some(&reply), Some(&edit));
+        apply_message_relation_mappings(&mut outbound, Some(&reply), Some(&edit), Some(&thread));
 
         assert_eq!(outbound.reply_to, Some("$matrix-reply".to_string()));
         assert_eq!(outbound.edit_of, Some("$matrix-edit".to_string()));
+        assert_eq!(outbound.thread_root, Some("$matrix-thread-root".to_string()));
     }
 
     #[test]
@@ -134,13 +224,16 @@ mod tests {
             formatted_body: None,
             reply_to: Some("slack-reply-id".to_string()),
             edit_of: Some("slack-edit-id".to_string()),
+            thread_root: Some("slack-thread-ts".to_string()),
             attachments: Vec::new(),
+            origin_ts: None,
         };
 
-        apply_message_relation_mappings(&mut outbound, None, None);
+        apply_message_relation_mappings(&mut outbound, None, None, None);
 
         assert_eq!(outbound.reply_to, Some("slack-reply-id".to_string()));
         assert_eq!(outbound.edit_of, Some("slack-edit-id".to_string()));
+        assert_eq!(outbound.thread_root, None);
     }
 
     #[test]
@@ -172,6 +265,48 @@ mod tests {
         assert_eq!(request.reason, "Deleted on Slack");
     }
 
+    #[test]
+    fn slack_file_refs_prefers_download_url_and_flags_tombstones() {
+        let files = [
+            json!({
+                "id": "F1",
+                "url_private": "https://files.slack.com/F1/view",
+                "url_private_download": "https://files.slack.com/F1/download",
+                "name": "report.pdf",
+                "mimetype": "application/pdf",
+                "size": 2048,
+                "permalink": "https://team.slack.com/files/U1/F1/report.pdf",
+            }),
+            json!({ "id": "F2", "mode": "tombstone" }),
+            json!({ "name": "missing id" }),
+        ];
+
+        assert_eq!(
+            slack_file_refs(&files),
+            vec![
+                SlackFileRef {
+                    id: "F1".to_string(),
+                    url: Some("https://files.slack.com/F1/download".to_string()),
+                    name: Some("report.pdf".to_string()),
+                    mimetype: Some("application/pdf".to_string()),
+                    size: Some(2048),
+                    permalink: Some("https://team.slack.com/files/U1/F1/report.pdf".to_string()),
+                    tombstone: false,
+                },
+                SlackFileRef {
+                    id: "F2".to_string(),
+                    url: None,
+                    name: None,
+                    mimetype: None,
+                    size: None,
+                    permalink: None,
+                    tombstone: true,
+                },
+            ]
+        );
+        assert_eq!(file_mapping_key("F1"), "file:F1");
+    }
+
     #[test]
     fn build_slack_typing_request_maps_fields() {
         let request = build_slack_typing_request("!room:example.org", "slack-user-1");
@@ -234,6 +369,17 @@ mod tests {
         assert!(preview.ends_with('…'));
     }
 
+    #[test]
+    fn slack_thread_root_prefers_owning_thread_and_skips_file_keys() {
+        let mut link = mapping("1700000000.000200", "$reply");
+        assert_eq!(slack_thread_root(&link).as_deref(), Some("1700000000.000200"));
+        link.slack_thread_ts = Some("1700000000.000100".to_string());
+        assert_eq!(slack_thread_root(&link).as_deref(), Some("1700000000.000100"));
+
+        let file = mapping(&file_mapping_key("F1"), "$file");
+        assert_eq!(slack_thread_root(&file), None);
+    }
+
     #[test]
     fn action_keyword_maps_all_moderation_actions() {
         assert_eq!(action_keyword(&ModerationAction::Kick), "kick");
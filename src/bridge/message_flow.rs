@@ -13,6 +13,8 @@ const ATTACHMENT_TYPES: &[&str] = &["m.image", "m.audio", "m.video", "m.file", "
 pub enum MessageRelation {
     Reply { event_id: String },
     Replace { event_id: String },
+    /// A message in the thread rooted at `root_event_id`.
+    Thread { root_event_id: String },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -40,6 +42,8 @@ pub struct SlackInboundMessage {
     pub attachments: Vec<String>,
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
+    /// `ts` of the Slack thread the message was posted in.
+    pub thread_root: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -47,6 +51,10 @@ pub struct OutboundSlackMessage {
     pub content: String,
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
+    /// Slack `thread_ts` to post into, resolved from the Matrix thread or reply target.
+    pub thread_ts: Option<String>,
+    /// Permalink of the message a reply quotes inline instead of threading under.
+    pub quote_permalink: Option<String>,
     pub attachments: Vec<String>,
     pub embed: Option<SlackEmbed>,
     pub use_embed: bool,
@@ -58,6 +66,8 @@ impl OutboundSlackMessage {
             content,
             reply_to: None,
             edit_of: None,
+            thread_ts: None,
+            quote_permalink: None,
             attachments: Vec::new(),
             embed: None,
             use_embed: false,
@@ -94,7 +104,11 @@ pub struct OutboundMatrixMessage {
     pub formatted_body: Option<String>,
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
+    /// Root event of the `m.thread` the message belongs to.
+    pub thread_root: Option<String>,
     pub attachments: Vec<String>,
+    /// Original Slack send time in unix ms, set for backfilled history.
+    pub origin_ts: Option<u64>,
 }
 
 impl OutboundMatrixMessage {
@@ -205,6 +219,8 @@ impl MessageFlow {
             content: self.matrix_converter.format_for_slack(&message.body),
             reply_to,
             edit_of,
+            thread_ts: None,
+            quote_permalink: None,
             attachments,
             embed: None,
             use_embed: false,
@@ -243,6 +259,8 @@ impl MessageFlow {
             content: String::new(),
             reply_to,
             edit_of,
+            thread_ts: None,
+            quote_permalink: None,
             attachments,
             embed: Some(embed),
             use_embed: true,
@@ -255,7 +273,9 @@ impl MessageFlow {
             formatted_body: None,
             reply_to: message.reply_to.clone(),
             edit_of: message.edit_of.clone(),
+            thread_root: message.thread_root.clone(),
             attachments: message.attachments.clone(),
+            origin_ts: None,
         }
     }
 
@@ -278,6 +298,14 @@ impl MessageFlow {
 
 fn parse_relation(content: &Value) -> Option<MessageRelation> {
     let relates_to = content.get("m.relates_to")?;
+    // Thread events also carry an `m.in_reply_to` fallback, so the thread wins.
+    if relates_to.get("rel_type").and_then(Value::as_str) == Some("m.thread")
+        && let Some(root_event_id) = relates_to.get("event_id").and_then(Value::as_str)
+    {
+        return Some(MessageRelation::Thread {
+            root_event_id: root_event_id.to_string(),
+        });
+    }
     if let Some(reply_event_id) = relates_to
         .get("m.in_reply_to")
         .and_then(|inner| inner.get("event_id"))
@@ -330,7 +358,7 @@ mod tests {
     use crate::config::{
         AuthConfig, BridgeConfig, ChannelConfig, ChannelDeleteOptionsConfig, Config,
         DatabaseConfig, GhostsConfig, LimitsConfig, LoggingConfig, MetricsConfig,
-        RegistrationConfig, RoomConfig,
+        RegistrationConfig, RoomConfig, SlackEventMode,
     };
     use crate::slack::SlackClient;
     use crate::matrix::{MatrixAppservice, MatrixEvent};
@@ -343,6 +371,7 @@ mod tests {
                 bind_address: "127.0.0.1".to_string(),
                 homeserver_url: "http://localhost:8008".to_string(),
                 presence_interval: 500,
+                presence_poll_interval: 60,
                 disable_presence: false,
                 disable_typing_notifications: false,
                 disable_slack_mentions: false,
@@ -365,6 +394,8 @@ mod tests {
                 participant_sync_count: 5,
                 participant_sync_only_on_create: true,
                 mute_channels_by_default: false,
+                reply_mode: Default::default(),
+                room_reply_modes: Default::default(),
             },
             registration: RegistrationConfig {
                 bridge_id: "test-bridge".to_string(),
@@ -378,6 +409,11 @@ mod tests {
                 client_id: None,
                 client_secret: None,
                 use_privileged_intents: false,
+                api_base_url: None,
+                event_mode: SlackEventMode::Socket,
+                signing_secret: None,
+                oauth_redirect_url: None,
+                token_encryption_key: None,
             },
             logging: LoggingConfig {
                 level: "info".to_string(),
@@ -421,6 +457,7 @@ mod tests {
             },
             metrics: MetricsConfig::default(),
             backfill: Default::default(),
+            media_proxy: Default::default(),
         })
     }
 
@@ -456,6 +493,36 @@ mod tests {
         assert_eq!(parsed.attachments[0].url, "mxc://example.org/cat");
     }
 
+    #[test]
+    fn parse_matrix_event_prefers_thread_over_reply_fallback() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": "in thread",
+                "m.relates_to": {
+                    "rel_type": "m.thread",
+                    "event_id": "$root",
+                    "is_falling_back": true,
+                    "m.in_reply_to": { "event_id": "$latest" }
+                }
+            })),
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        assert_eq!(
+            parsed.relation,
+            Some(MessageRelation::Thread {
+                root_event_id: "$root".to_string()
+            })
+        );
+    }
+
     #[tokio::test]
     async fn matrix_to_slack_marks_edit_messages() {
         let config = test_config();
@@ -503,6 +570,7 @@ mod tests {
             attachments: vec!["https://example.org/a.png".to_string()],
             reply_to: Some("slack-msg-1".to_string()),
             edit_of: None,
+            thread_root: None,
         });
 
         assert_eq!(outbound.body, "*bold*".to_string());
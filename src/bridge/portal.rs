@@ -0,0 +1,122 @@
+use anyhow::Result;
+use tracing::{debug, info, warn};
+
+use crate::bridge::BridgeCore;
+use crate::db::RoomMapping;
+use crate::slack::SlackUser;
+
+impl BridgeCore {
+    /// Answers a homeserver query for a portal alias like `#_slack_C123:domain`, creating
+    /// the Slack channel's Matrix room when it has none yet. Returns whether the alias
+    /// exists afterwards.
+    pub async fn handle_room_alias_query(&self, alias: &str) -> Result<bool> {
+        let room_config = &self.matrix_client.config().room;
+        if !room_config.enable_room_creation {
+            debug!(
+                "ignoring alias query for {}: room creation is disabled",
+                alias
+            );
+            return Ok(false);
+        }
+        let Some(channel_id) = self.matrix_client.slack_channel_from_alias(alias) else {
+            debug!("ignoring alias query for {}: not a portal alias", alias);
+            return Ok(false);
+        };
+        // Concurrent queries for the alias would otherwise each create a room, and all but
+        // one fail once the alias is taken.
+        let _guard = self.portal_queue.lock(&channel_id).await;
+
+        // Rooms bridged by command may predate their alias; point it at them.
+        if let Some(mapping) = self
+            .db_manager
+            .room_store()
+            .get_room_by_slack_channel(&channel_id)
+            .await?
+        {
+            self.matrix_client
+                .set_room_alias(&mapping.matrix_room_id, alias)
+                .await?;
+            return Ok(true);
+        }
+
+        let Some(channel) = self.slack_client.get_channel(&channel_id).await? else {
+            return Ok(false);
+        };
+        if channel.is_private {
+            warn!(
+                "refusing to create a portal for private slack channel {}",
+                channel_id
+            );
+            return Ok(false);
+        }
+        // The bot only receives events of channels it is in, so a portal it cannot join
+        // would stay silent.
+        if !channel.is_member
+            && let Err(err) = self.slack_client.join_channel(&channel.id).await
+        {
+            warn!(
+                "refusing to create a portal for slack channel {}: failed to join: {}",
+                channel_id, err
+            );
+            return Ok(false);
+        }
+
+        let matrix_room_id = self
+            .matrix_client
+            .create_room(
+                &channel.id,
+                &format!("[Slack] #{}", channel.name),
+                channel.topic.as_deref(),
+                false,
+            )
+            .await?;
+        let now = chrono::Utc::now();
+        self.db_manager
+            .room_store()
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: matrix_room_id.clone(),
+                slack_channel_id: channel.id.clone(),
+                slack_channel_name: channel.name.clone(),
+                slack_team_id: channel.guild_id.clone(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+
+        info!(
+            "created portal room {} for slack channel {}",
+            matrix_room_id, channel.id
+        );
+        Ok(true)
+    }
+
+    /// Answers a homeserver query for a ghost like `@_slack_U123:domain`, registering it
+    /// with the Slack user's name and avatar so it can be invited before it ever spoke.
+    /// Returns whether the user exists afterwards.
+    pub async fn handle_user_query(&self, user_id: &str) -> Result<bool> {
+        let Some(slack_user_id) = self.matrix_client.slack_user_from_ghost(user_id) else {
+            debug!("ignoring user query for {}: not a slack ghost", user_id);
+            return Ok(false);
+        };
+        let Some(user) = self.provision_ghost(&slack_user_id).await? else {
+            debug!("ignoring user query for {}: no active slack user", user_id);
+            return Ok(false);
+        };
+        info!("provisioned ghost {} for slack user {}", user_id, user.id);
+        Ok(true)
+    }
+
+    /// Registers the ghost of an active Slack user and syncs its profile. Returns `None`
+    /// for unknown and deactivated users.
+    pub(crate) async fn provision_ghost(&self, slack_user_id: &str) -> Result<Option<SlackUser>> {
+        let Some(user) = self.slack_client.get_user(slack_user_id).await? else {
+            return Ok(None);
+        };
+        if user.deleted {
+            return Ok(None);
+        }
+        self.user_sync.on_user_update(&user, false).await?;
+        Ok(Some(user))
+    }
+}
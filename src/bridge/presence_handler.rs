@@ -1,8 +1,10 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use parking_lot::Mutex;
+use serde_json::Value;
 use tracing::warn;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -25,9 +27,90 @@ pub struct SlackPresence {
     pub user_id: String,
     pub username: Option<String>,
     pub state: SlackPresenceState,
+    /// Custom Slack status, with its emoji, e.g. "🌴 On vacation".
+    pub status_text: Option<String>,
     pub activities: Vec<SlackActivity>,
 }
 
+impl SlackPresence {
+    /// Builds a presence from the `users.getPresence`, `dnd.info` and `users.profile.get`
+    /// responses for `user_id`. `now` is the current unix time in seconds.
+    pub fn from_slack_api(
+        user_id: &str,
+        presence: &Value,
+        dnd: &Value,
+        profile: &Value,
+        now: i64,
+    ) -> Self {
+        let online = presence.get("presence").and_then(Value::as_str) == Some("active");
+        let auto_away = presence.get("online").and_then(Value::as_bool) == Some(true)
+            && presence.get("auto_away").and_then(Value::as_bool) == Some(true);
+        let in_dnd = {
+            let snoozed = dnd.get("snooze_enabled").and_then(Value::as_bool) == Some(true)
+                && dnd
+                    .get("snooze_endtime")
+                    .and_then(Value::as_i64)
+                    .is_none_or(|end| end > now);
+            let scheduled = dnd.get("dnd_enabled").and_then(Value::as_bool) == Some(true)
+                && dnd
+                    .get("next_dnd_start_ts")
+                    .and_then(Value::as_i64)
+                    .is_some_and(|start| start <= now)
+                && dnd
+                    .get("next_dnd_end_ts")
+                    .and_then(Value::as_i64)
+                    .is_some_and(|end| end > now);
+            snoozed || scheduled
+        };
+        let state = match (online, auto_away, in_dnd) {
+            (true, _, true) => SlackPresenceState::Dnd,
+            (true, _, false) => SlackPresenceState::Online,
+            (false, true, _) => SlackPresenceState::Idle,
+            (false, false, _) => SlackPresenceState::Offline,
+        };
+
+        let status_expired = profile
+            .get("status_expiration")
+            .and_then(Value::as_i64)
+            .is_some_and(|expiration| expiration > 0 && expiration <= now);
+        let status_text = (!status_expired)
+            .then(|| {
+                let emoji = profile
+                    .get("status_emoji")
+                    .and_then(Value::as_str)
+                    .map(|emoji| emoji.trim_matches(':'))
+                    .filter(|emoji| !emoji.is_empty())
+                    .map(|emoji| {
+                        crate::emoji::shortcode_to_unicode(emoji)
+                            .unwrap_or_else(|| format!(":{emoji}:"))
+                    });
+                let text = profile
+                    .get("status_text")
+                    .and_then(Value::as_str)
+                    .map(str::trim)
+                    .filter(|text| !text.is_empty());
+                match (emoji, text) {
+                    (Some(emoji), Some(text)) => Some(format!("{emoji} {text}")),
+                    (emoji, text) => emoji.or(text.map(ToOwned::to_owned)),
+                }
+            })
+            .flatten();
+        let username = ["display_name", "real_name"]
+            .iter()
+            .filter_map(|key| profile.get(*key).and_then(Value::as_str))
+            .find(|name| !name.is_empty())
+            .map(ToOwned::to_owned);
+
+        Self {
+            user_id: user_id.to_string(),
+            username,
+            state,
+            status_text,
+            activities: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MatrixPresenceState {
     Online,
@@ -61,6 +144,8 @@ pub trait MatrixPresenceTarget: Send + Sync {
 pub struct PresenceHandler {
     bot_slack_user_id: Option<String>,
     queue: Mutex<VecDeque<SlackPresence>>,
+    /// Slack users whose presence is polled, with when they were last seen active.
+    watched: Mutex<HashMap<String, Instant>>,
 }
 
 impl PresenceHandler {
@@ -68,9 +153,49 @@ impl PresenceHandler {
         Self {
             bot_slack_user_id,
             queue: Mutex::new(VecDeque::new()),
+            watched: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Marks a Slack user as active so their presence gets polled.
+    pub fn watch_user(&self, user_id: &str) {
+        if self.bot_slack_user_id.as_deref() == Some(user_id) {
+            return;
+        }
+        self.watched
+            .lock()
+            .insert(user_id.to_string(), Instant::now());
+    }
+
+    /// Users seen within `max_idle`; users seen earlier stop being watched, and any presence
+    /// still queued for them is replaced by a final offline update.
+    pub fn watched_users(&self, max_idle: Duration) -> Vec<String> {
+        let mut watched = self.watched.lock();
+        let mut evicted = Vec::new();
+        watched.retain(|user_id, last_seen| {
+            let keep = last_seen.elapsed() < max_idle;
+            if !keep {
+                evicted.push(user_id.clone());
+            }
+            keep
+        });
+        let mut users: Vec<_> = watched.keys().cloned().collect();
+        drop(watched);
+
+        let mut queue = self.queue.lock();
+        for item in queue.iter_mut() {
+            if evicted.contains(&item.user_id) {
+                item.state = SlackPresenceState::Offline;
+                item.status_text = None;
+                item.activities.clear();
+            }
+        }
+        drop(queue);
+
+        users.sort();
+        users
+    }
+
     pub fn queue_count(&self) -> usize {
         self.queue.lock().len()
     }
@@ -138,19 +263,22 @@ impl PresenceHandler {
     }
 
     pub fn map_presence(presence: &SlackPresence) -> PresenceDecision {
-        let mut status_message = String::new();
+        let mut status_message = presence.status_text.clone().unwrap_or_default();
 
         if let Some(activity) = presence.activities.first() {
+            if !status_message.is_empty() {
+                status_message.push_str(" | ");
+            }
             let mut chars = activity.kind.chars();
             if let Some(first) = chars.next() {
-                status_message = format!(
+                status_message.push_str(&format!(
                     "{}{} {}",
                     first.to_uppercase(),
                     chars.as_str().to_lowercase(),
                     activity.name
-                );
+                ));
             } else {
-                status_message = activity.name.clone();
+                status_message.push_str(&activity.name);
             }
             if let Some(url) = &activity.url {
                 status_message.push_str(" | ");
@@ -195,6 +323,7 @@ mod tests {
     use std::sync::Arc;
 
     use parking_lot::Mutex;
+    use serde_json::json;
 
     use super::{
         SlackActivity, SlackPresence, SlackPresenceState, MatrixPresenceState,
@@ -242,6 +371,7 @@ mod tests {
             user_id: "1".to_string(),
             username: Some("alice".to_string()),
             state: SlackPresenceState::Online,
+            status_text: None,
             activities: vec![],
         };
         let second = SlackPresence {
@@ -260,6 +390,7 @@ mod tests {
             user_id: "1".to_string(),
             username: None,
             state: SlackPresenceState::Dnd,
+            status_text: None,
             activities: vec![SlackActivity {
                 kind: "STREAMING".to_string(),
                 name: "Rust".to_string(),
@@ -283,6 +414,7 @@ mod tests {
             user_id: "1".to_string(),
             username: Some("alice".to_string()),
             state: SlackPresenceState::Offline,
+            status_text: None,
             activities: vec![],
         });
 
@@ -299,11 +431,83 @@ mod tests {
             user_id: "1".to_string(),
             username: Some("alice".to_string()),
             state: SlackPresenceState::Online,
+            status_text: None,
             activities: vec![],
         });
 
         handler.process_next(&target).await.expect("process_next");
         assert_eq!(handler.queue_count(), 1);
     }
-}
 
+    #[test]
+    fn slack_api_presence_maps_dnd_and_status_text() {
+        let now = 1_700_000_000;
+        let presence = SlackPresence::from_slack_api(
+            "U1",
+            &json!({ "ok": true, "presence": "active" }),
+            &json!({ "ok": true, "dnd_enabled": true, "next_dnd_start_ts": now - 60, "next_dnd_end_ts": now + 60 }),
+            &json!({ "ok": true, "display_name": "alice", "status_emoji": ":palm_tree:", "status_text": "On vacation" }),
+            now,
+        );
+        assert_eq!(presence.state, SlackPresenceState::Dnd);
+        assert_eq!(presence.username.as_deref(), Some("alice"));
+
+        let decision = PresenceHandler::map_presence(&presence);
+        assert_eq!(decision.presence, MatrixPresenceState::Online);
+        assert_eq!(decision.status_message, "Do not disturb | 🌴 On vacation");
+    }
+
+    #[test]
+    fn slack_api_presence_drops_expired_status_and_maps_away_to_offline() {
+        let now = 1_700_000_000;
+        let presence = SlackPresence::from_slack_api(
+            "U1",
+            &json!({ "ok": true, "presence": "away" }),
+            &json!({ "ok": true, "dnd_enabled": false }),
+            &json!({ "ok": true, "status_text": "In a meeting", "status_expiration": now - 1 }),
+            now,
+        );
+        assert_eq!(presence.state, SlackPresenceState::Offline);
+        assert_eq!(presence.status_text, None);
+    }
+
+    #[test]
+    fn watched_users_skip_the_bridge_bot() {
+        let handler = PresenceHandler::new(Some("UBOT".to_string()));
+        handler.watch_user("UBOT");
+        handler.watch_user("U2");
+        handler.watch_user("U1");
+        assert_eq!(
+            handler.watched_users(std::time::Duration::from_secs(60)),
+            vec!["U1".to_string(), "U2".to_string()]
+        );
+        assert!(handler.watched_users(std::time::Duration::ZERO).is_empty());
+    }
+
+    #[tokio::test]
+    async fn evicted_users_go_offline_once_and_leave_the_queue() {
+        let handler = PresenceHandler::new(None);
+        let target = MockPresenceTarget::default();
+        handler.watch_user("U1");
+        handler.enqueue_user(SlackPresence {
+            user_id: "U1".to_string(),
+            username: Some("alice".to_string()),
+            state: SlackPresenceState::Online,
+            status_text: Some("Working".to_string()),
+            activities: vec![],
+        });
+
+        assert!(handler.watched_users(std::time::Duration::ZERO).is_empty());
+        handler.process_next(&target).await.expect("process_next");
+        assert_eq!(handler.queue_count(), 0);
+        assert!(!handler.process_next(&target).await.expect("process_next"));
+        assert_eq!(
+            *target.calls.lock(),
+            vec![(
+                "U1".to_string(),
+                MatrixPresenceState::Offline,
+                String::new()
+            )]
+        );
+    }
+}
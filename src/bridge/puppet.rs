@@ -0,0 +1,358 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+use crate::bridge::BridgeCore;
+use crate::cache::AsyncTimedCache;
+use crate::config::Config;
+use crate::db::SlackPuppet;
+use crate::slack::SlackClient;
+use crate::utils::hex::{decode_hex, encode_hex};
+
+const SLACK_AUTHORIZE_URL: &str = "https://slack.com/oauth/v2/authorize";
+/// User scopes a puppet needs to post, edit, delete, react and upload files as its user.
+const PUPPET_USER_SCOPES: &str = "chat:write,reactions:write,files:write";
+/// How long a `!slack login` link stays valid.
+const LOGIN_STATE_TTL_SECS: i64 = 600;
+const NONCE_LEN: usize = 12;
+
+/// Seals puppet tokens for the database and signs the `state` of OAuth logins, with keys
+/// derived from `auth.token_encryption_key` or, without one, the appservice token.
+#[derive(Clone)]
+pub struct TokenCipher {
+    token_key: [u8; 32],
+    state_key: [u8; 32],
+}
+
+impl TokenCipher {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            token_key: derive_key(secret, "slack-user-token"),
+            state_key: derive_key(secret, "slack-login-state"),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let secret = config
+            .auth
+            .token_encryption_key
+            .as_deref()
+            .filter(|value| !value.is_empty())
+            .unwrap_or(&config.registration.appservice_token);
+        Self::new(secret)
+    }
+
+    /// Encrypts `token` with AES-256-GCM under a fresh nonce, hex encoded as nonce then
+    /// ciphertext.
+    pub fn seal(&self, token: &str) -> Result<String> {
+        let cipher = Aes256Gcm::new(&self.token_key.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, token.as_bytes())
+            .map_err(|_| anyhow!("failed to encrypt slack token"))?;
+        Ok(encode_hex(&[nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn open(&self, sealed: &str) -> Result<String> {
+        let bytes = decode_hex(sealed)
+            .filter(|bytes| bytes.len() > NONCE_LEN)
+            .ok_or_else(|| anyhow!("malformed sealed slack token"))?;
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new(&self.token_key.into());
+        let token = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("slack token does not decrypt with the configured key"))?;
+        Ok(String::from_utf8(token)?)
+    }
+
+    /// OAuth `state` tying a login to the Matrix user who asked for it and the room to
+    /// report back to, valid until `expires`. The `nonce` makes each link single-use.
+    pub fn login_state(&self, login: &LoginState, expires: i64) -> String {
+        let user = encode_hex(login.matrix_user_id.as_bytes());
+        let room = encode_hex(login.room_id.as_bytes());
+        let signed = format!("{expires}.{user}.{room}.{}", login.nonce);
+        let signature = encode_hex(&self.state_mac(&signed).finalize().into_bytes());
+        format!("{signed}.{signature}")
+    }
+
+    /// Reads a `state` that is untampered and unexpired.
+    pub fn verify_login_state(&self, state: &str, now: i64) -> Option<LoginState> {
+        let (signed, signature) = state.rsplit_once('.')?;
+        let mut parts = signed.splitn(4, '.');
+        let expires = parts.next()?.parse::<i64>().ok()?;
+        let user = String::from_utf8(decode_hex(parts.next()?)?).ok()?;
+        let room = String::from_utf8(decode_hex(parts.next()?)?).ok()?;
+        let nonce = parts.next()?;
+        self.state_mac(signed)
+            .verify_slice(&decode_hex(signature)?)
+            .ok()?;
+        (expires >= now).then(|| LoginState {
+            matrix_user_id: user,
+            room_id: room,
+            nonce: nonce.to_string(),
+        })
+    }
+
+    fn state_mac(&self, signed: &str) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.state_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(signed.as_bytes());
+        mac
+    }
+}
+
+/// What the OAuth `state` of a `!slack login` link carries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginState {
+    pub matrix_user_id: String,
+    /// Room the login confirmation is sent to.
+    pub room_id: String,
+    pub nonce: String,
+}
+
+/// Nonces of `!slack login` links that have not been used yet.
+#[derive(Clone)]
+pub struct PendingLogins {
+    nonces: Arc<AsyncTimedCache<String, ()>>,
+}
+
+impl PendingLogins {
+    /// Returns a fresh nonce, valid for as long as a login link.
+    pub async fn issue(&self) -> String {
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        self.nonces.insert(nonce.clone(), ()).await;
+        nonce
+    }
+
+    /// Uses up `nonce`. Returns whether it was still unused.
+    pub async fn consume(&self, nonce: &str) -> bool {
+        self.nonces.remove(&nonce.to_string()).await.is_some()
+    }
+}
+
+impl Default for PendingLogins {
+    fn default() -> Self {
+        Self {
+            nonces: Arc::new(AsyncTimedCache::new(Duration::from_secs(
+                LOGIN_STATE_TTL_SECS as u64,
+            ))),
+        }
+    }
+}
+
+fn derive_key(secret: &str, purpose: &str) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(purpose.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+impl BridgeCore {
+    /// Whether `!slack login` is configured.
+    pub fn slack_logins_enabled(&self) -> bool {
+        self.slack_login_app().is_some()
+    }
+
+    /// Client id and redirect URL of the Slack app, when logins are configured.
+    fn slack_login_app(&self) -> Option<(String, String)> {
+        let config = self.matrix_client.config();
+        let auth = &config.auth;
+        let client_id = auth
+            .client_id
+            .as_deref()
+            .filter(|value| !value.is_empty())?;
+        auth.client_secret
+            .as_deref()
+            .filter(|value| !value.is_empty())?;
+        let redirect_url = auth
+            .oauth_redirect_url
+            .as_deref()
+            .filter(|value| !value.is_empty())?;
+        Some((client_id.to_string(), redirect_url.to_string()))
+    }
+
+    /// Slack authorize link for `!slack login`, or `None` while logins are not configured.
+    /// Each link can be used once.
+    pub async fn slack_login_url(&self, matrix_user_id: &str, room_id: &str) -> Option<String> {
+        let (client_id, redirect_url) = self.slack_login_app()?;
+        let login = LoginState {
+            matrix_user_id: matrix_user_id.to_string(),
+            room_id: room_id.to_string(),
+            nonce: self.pending_logins.issue().await,
+        };
+        let expires = Utc::now().timestamp() + LOGIN_STATE_TTL_SECS;
+        let state = self.token_cipher.login_state(&login, expires);
+        url::Url::parse_with_params(
+            SLACK_AUTHORIZE_URL,
+            [
+                ("client_id", client_id.as_str()),
+                ("user_scope", PUPPET_USER_SCOPES),
+                ("redirect_uri", redirect_url.as_str()),
+                ("state", state.as_str()),
+            ],
+        )
+        .ok()
+        .map(String::from)
+    }
+
+    /// Finishes an OAuth login from the `/slack/oauth` redirect: trades `code` for the
+    /// user's token and stores it sealed for the Matrix user named in `state`. Returns the
+    /// text shown in the browser; the user also gets a notice in the room the link was
+    /// sent to.
+    pub async fn complete_slack_login(&self, code: &str, state: &str) -> Result<String> {
+        let Some(LoginState {
+            matrix_user_id,
+            room_id,
+            nonce,
+        }) = self
+            .token_cipher
+            .verify_login_state(state, Utc::now().timestamp())
+        else {
+            return Err(anyhow!(
+                "this login link is invalid or has expired, run `!slack login` again"
+            ));
+        };
+        if !self.pending_logins.consume(&nonce).await {
+            return Err(anyhow!(
+                "this login link has already been used, run `!slack login` again"
+            ));
+        }
+        let config = self.matrix_client.config();
+        let redirect_url = config
+            .auth
+            .oauth_redirect_url
+            .as_deref()
+            .ok_or_else(|| anyhow!("slack logins are not enabled on this bridge"))?;
+
+        let login = self.slack_client.oauth_access(code, redirect_url).await?;
+        if let Some(team_id) = self.slack_client.get_team_id().await
+            && team_id != login.team_id
+        {
+            return Err(anyhow!(
+                "the Slack account belongs to another workspace than the bridge"
+            ));
+        }
+
+        let now = Utc::now();
+        self.db_manager
+            .puppet_store()
+            .upsert_puppet(&SlackPuppet {
+                id: 0,
+                matrix_user_id: matrix_user_id.clone(),
+                slack_user_id: login.user_id.clone(),
+                slack_team_id: login.team_id,
+                access_token: self.token_cipher.seal(&login.access_token)?,
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+        info!(
+            "matrix user {} logged in as slack user {}",
+            matrix_user_id, login.user_id
+        );
+
+        let name = match self.slack_client.get_user(&login.user_id).await {
+            Ok(Some(user)) => user.username,
+            _ => login.user_id.clone(),
+        };
+        let message = format!(
+            "Logged in to Slack as {name}. Your messages are now sent from your Slack account."
+        );
+        if let Err(err) = self.matrix_client.send_notice(&room_id, &message).await {
+            warn!("failed to confirm slack login in {}: {}", room_id, err);
+        }
+        Ok(message)
+    }
+
+    /// Forgets the Matrix user's Slack login and revokes its token.
+    pub async fn logout_slack(&self, matrix_user_id: &str) -> Result<String> {
+        let Some(puppet) = self.slack_puppet(matrix_user_id).await? else {
+            return Ok("You are not logged in to Slack.".to_string());
+        };
+        if let Err(err) = puppet.revoke_token().await {
+            warn!(
+                "failed to revoke slack token of {}: {}",
+                matrix_user_id, err
+            );
+        }
+        self.db_manager
+            .puppet_store()
+            .delete_puppet(matrix_user_id)
+            .await?;
+        info!("matrix user {} logged out of slack", matrix_user_id);
+        Ok("Logged out of Slack. Your messages are sent by the bridge bot again.".to_string())
+    }
+
+    /// Client acting as the Matrix user's own Slack account, if they are logged in.
+    pub(crate) async fn slack_puppet(&self, matrix_user_id: &str) -> Result<Option<SlackClient>> {
+        let Some(puppet) = self
+            .db_manager
+            .puppet_store()
+            .get_puppet_by_matrix_user(matrix_user_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+        match self.token_cipher.open(&puppet.access_token) {
+            Ok(token) => Ok(Some(
+                self.slack_client.as_puppet(&puppet.slack_user_id, &token),
+            )),
+            Err(err) => {
+                warn!("ignoring slack login of {}: {}", matrix_user_id, err);
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoginState, PendingLogins, TokenCipher};
+
+    #[test]
+    fn sealed_tokens_only_open_with_the_same_key() {
+        let cipher = TokenCipher::new("secret");
+        let sealed = cipher.seal("xoxp-1234").unwrap();
+        assert!(!sealed.contains("xoxp"));
+        assert_ne!(sealed, cipher.seal("xoxp-1234").unwrap());
+        assert_eq!(cipher.open(&sealed).unwrap(), "xoxp-1234");
+        assert!(TokenCipher::new("other").open(&sealed).is_err());
+        assert!(cipher.open("not-hex").is_err());
+    }
+
+    #[test]
+    fn login_state_rejects_tampering_and_expiry() {
+        let cipher = TokenCipher::new("secret");
+        let login = LoginState {
+            matrix_user_id: "@alice:example.org".to_string(),
+            room_id: "!room:example.org".to_string(),
+            nonce: "abc123".to_string(),
+        };
+        let state = cipher.login_state(&login, 1_000);
+        assert_eq!(cipher.verify_login_state(&state, 900), Some(login));
+        assert_eq!(cipher.verify_login_state(&state, 1_001), None);
+        let forged = state.replacen("1000", "9000", 1);
+        assert_eq!(cipher.verify_login_state(&forged, 900), None);
+        assert_eq!(
+            TokenCipher::new("other").verify_login_state(&state, 900),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn login_nonces_can_be_used_once() {
+        let pending = PendingLogins::default();
+        let nonce = pending.issue().await;
+        assert_ne!(nonce, pending.issue().await);
+        assert!(pending.consume(&nonce).await);
+        assert!(!pending.consume(&nonce).await);
+        assert!(!pending.consume("unknown").await);
+    }
+}
@@ -50,6 +50,18 @@ impl ChannelQueue {
             task.await;
         });
     }
+
+    /// Waits for the channel's queue and holds it until the guard is dropped.
+    pub async fn lock(&self, channel_id: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let mutex = {
+            let mut queues = self.queues.lock().await;
+            queues
+                .entry(channel_id.to_string())
+                .or_insert_with(|| Arc::new(tokio::sync::Mutex::new(())))
+                .clone()
+        };
+        mutex.lock_owned().await
+    }
 }
 
 impl Default for ChannelQueue {
@@ -3,19 +3,16 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use chrono::Utc;
+use parking_lot::Mutex;
 use tracing::{debug, info, warn};
 
 use crate::config::Config;
 use crate::db::{DatabaseManager, UserMapping};
 use crate::matrix::MatrixAppservice;
+use crate::slack::SlackUser;
 
 const CACHE_TTL_SECS: i64 = 300;
 
-struct DisplaynameCacheEntry {
-    displayname: String,
-    timestamp: i64,
-}
-
 struct AvatarCacheEntry {
     mxc_url: String,
     timestamp: i64,
@@ -25,8 +22,9 @@ pub struct UserSyncHandler {
     db: Arc<DatabaseManager>,
     matrix: Arc<MatrixAppservice>,
     config: Arc<Config>,
-    avatar_cache: HashMap<String, AvatarCacheEntry>,
-    displayname_cache: HashMap<String, DisplaynameCacheEntry>,
+    /// Uploaded avatars by Slack avatar hash, so repeated `user_change` events for the
+    /// same edit don't upload the image again before the mapping is saved.
+    avatar_cache: Mutex<HashMap<String, AvatarCacheEntry>>,
 }
 
 impl UserSyncHandler {
@@ -39,100 +37,89 @@ impl UserSyncHandler {
             db,
             matrix,
             config,
-            avatar_cache: HashMap::new(),
-            displayname_cache: HashMap::new(),
+            avatar_cache: Mutex::new(HashMap::new()),
         }
     }
 
-    pub async fn on_user_update(
-        &self,
-        slack_user_id: &str,
-        slack_username: Option<&str>,
-        slack_avatar_url: Option<&str>,
-        is_webhook: bool,
-    ) -> Result<()> {
-        let user_state = self
-            .get_user_update_state(
-                slack_user_id,
-                slack_username,
-                slack_avatar_url,
-                is_webhook,
-            )
-            .await?;
+    /// Brings the ghost of `user` in line with its Slack profile: the global displayname
+    /// and avatar, and the member state in every bridged room the ghost has joined.
+    /// Deactivated users leave those rooms instead.
+    pub async fn on_user_update(&self, user: &SlackUser, is_webhook: bool) -> Result<()> {
+        let user_state = self.get_user_update_state(user, is_webhook).await?;
+
+        if user_state.deactivated {
+            if !user_state.create_user {
+                self.remove_from_bridged_rooms(&user_state).await?;
+            }
+            return Ok(());
+        }
+
+        if !user_state.has_changes() {
+            return self
+                .matrix
+                .ensure_ghost_user_registered(&user_state.id, None)
+                .await;
+        }
 
         if user_state.create_user {
-            info!(
-                "Creating new ghost user for Slack user {}",
-                slack_user_id
-            );
+            info!("Creating new ghost user for Slack user {}", user.id);
         }
 
-        self.apply_state_to_profile(&user_state).await?;
+        let avatar_mxc = self.apply_state_to_profile(&user_state).await?;
 
-        if user_state.displayname.is_some() || user_state.avatar_url.is_some() {
-            self.update_state_for_guilds(&user_state).await?;
+        if user_state.displayname.is_some() || avatar_mxc.is_some() {
+            self.update_state_for_guilds(&user_state, avatar_mxc.as_deref())
+                .await?;
         }
 
         Ok(())
     }
 
-    async fn get_user_update_state(
-        &self,
-        slack_user_id: &str,
-        slack_username: Option<&str>,
-        slack_avatar_url: Option<&str>,
-        is_webhook: bool,
-    ) -> Result<UserState> {
+    async fn get_user_update_state(&self, user: &SlackUser, is_webhook: bool) -> Result<UserState> {
         let mxid_extra = if is_webhook {
-            format!(
-                "_{}",
-                self.sanitize_for_mxid(slack_username.unwrap_or("unknown"))
-            )
+            format!("_{}", self.sanitize_for_mxid(&user.username))
         } else {
             String::new()
         };
+        let id = format!("{}{}", user.id, mxid_extra);
+
+        let displayname = crate::utils::formatting::apply_pattern_string(
+            &self.config.ghosts.username_pattern,
+            &[
+                ("id", user.id.as_str()),
+                ("tag", user.discriminator.as_str()),
+                ("username", user.username.as_str()),
+            ],
+        );
 
-        let displayname = slack_username.map(|name| {
-            crate::utils::formatting::apply_pattern_string(
-                &self.config.ghosts.username_pattern,
-                &[("id", slack_user_id), ("tag", "0000"), ("username", name)],
-            )
-        });
-
-        let existing = self
-            .db
-            .user_store()
-            .get_user_by_slack_id(&format!("{}{}", slack_user_id, mxid_extra))
-            .await?;
+        let existing = self.db.user_store().get_user_by_slack_id(&id).await?;
 
         let user_state = match existing {
             None => UserState {
-                id: format!("{}{}", slack_user_id, mxid_extra),
-                mx_user_id: format!(
-                    "@_slack_{}{}:{}",
-                    slack_user_id, mxid_extra, self.config.bridge.domain
-                ),
+                mx_user_id: format!("@_slack_{}:{}", id, self.config.bridge.domain),
+                id,
                 create_user: true,
-                displayname: displayname.clone(),
-                avatar_url: slack_avatar_url.map(ToOwned::to_owned),
+                deactivated: user.deleted,
+                displayname: Some(displayname),
+                avatar_url: user.avatar.clone(),
+                avatar_hash: user.avatar_hash.clone(),
                 remove_avatar: false,
+                existing: None,
                 roles: Vec::new(),
             },
             Some(existing) => UserState {
-                id: format!("{}{}", slack_user_id, mxid_extra),
                 mx_user_id: existing.matrix_user_id.clone(),
+                id,
                 create_user: false,
-                displayname: if displayname.as_ref() != Some(&existing.slack_username) {
-                    displayname.clone()
-                } else {
-                    None
-                },
-                avatar_url: if slack_avatar_url != existing.slack_avatar.as_deref() {
-                    slack_avatar_url.map(ToOwned::to_owned)
-                } else {
-                    None
-                },
-                remove_avatar: slack_avatar_url.is_none() && existing.slack_avatar.is_some(),
+                deactivated: user.deleted,
+                displayname: (displayname != existing.slack_username).then_some(displayname),
+                avatar_url: user
+                    .avatar
+                    .clone()
+                    .filter(|_| user.avatar_hash != existing.slack_avatar_hash),
+                avatar_hash: user.avatar_hash.clone(),
+                remove_avatar: user.avatar.is_none() && existing.slack_avatar.is_some(),
+                existing: Some(existing),
                 roles: Vec::new(),
             },
         };
@@ -140,28 +127,11 @@ impl UserSyncHandler {
         Ok(user_state)
     }
 
-    async fn apply_state_to_profile(&self, state: &UserState) -> Result<()> {
-        let mapping = UserMapping {
-            id: 0,
-            matrix_user_id: state.mx_user_id.clone(),
-            slack_user_id: state.id.clone(),
-            slack_username: state.displayname.clone().unwrap_or_default(),
-            slack_discriminator: "0000".to_string(),
-            slack_avatar: state.avatar_url.clone(),
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-        };
-
-        if state.create_user {
-            self.db.user_store().create_user_mapping(&mapping).await?;
-            info!("Created user mapping for Slack user {}", state.id);
-        } else {
-            self.db.user_store().update_user_mapping(&mapping).await?;
-            debug!("Updated user mapping for Slack user {}", state.id);
-        }
-
+    /// Updates the ghost's global profile and saves the mapping. Returns the new avatar's
+    /// `mxc://` URI when it changed, or an empty string when it was removed.
+    async fn apply_state_to_profile(&self, state: &UserState) -> Result<Option<String>> {
         self.matrix
-            .ensure_ghost_user_registered(&state.id, state.displayname.as_deref())
+            .ensure_ghost_user_registered(&state.id, None)
             .await?;
 
         if let Some(displayname) = &state.displayname {
@@ -178,17 +148,31 @@ impl UserSyncHandler {
             }
         }
 
+        let mut avatar_mxc = None;
+        // Without a successful upload the old hash is kept, so the next update retries.
+        let mut saved_avatar = state
+            .existing
+            .as_ref()
+            .map(|existing| {
+                (
+                    existing.slack_avatar.clone(),
+                    existing.slack_avatar_hash.clone(),
+                )
+            })
+            .unwrap_or_default();
         if let Some(avatar_url) = &state.avatar_url {
             debug!(
                 "Setting avatar for {} from {}",
                 state.mx_user_id, avatar_url
             );
-            match self.upload_avatar_to_matrix(&state.id, avatar_url).await {
-                Ok(mxc_url) => {
-                    if let Err(e) = self.matrix.set_ghost_avatar(&state.id, &mxc_url).await {
-                        warn!("Failed to set avatar for {}: {}", state.mx_user_id, e);
+            match self.upload_avatar_to_matrix(state, avatar_url).await {
+                Ok(mxc_url) => match self.matrix.set_ghost_avatar(&state.id, &mxc_url).await {
+                    Ok(()) => {
+                        saved_avatar = (Some(avatar_url.clone()), state.avatar_hash.clone());
+                        avatar_mxc = Some(mxc_url);
                     }
-                }
+                    Err(e) => warn!("Failed to set avatar for {}: {}", state.mx_user_id, e),
+                },
                 Err(e) => {
                     warn!("Failed to upload avatar for {}: {}", state.mx_user_id, e);
                 }
@@ -197,21 +181,62 @@ impl UserSyncHandler {
 
         if state.remove_avatar {
             debug!("Removing avatar for {}", state.mx_user_id);
-            if let Err(e) = self.matrix.set_ghost_avatar(&state.id, "").await {
-                warn!("Failed to remove avatar for {}: {}", state.mx_user_id, e);
+            match self.matrix.set_ghost_avatar(&state.id, "").await {
+                Ok(()) => {
+                    saved_avatar = (None, None);
+                    avatar_mxc = Some(String::new());
+                }
+                Err(e) => warn!("Failed to remove avatar for {}: {}", state.mx_user_id, e),
             }
         }
 
-        Ok(())
+        let now = Utc::now();
+        let (slack_avatar, slack_avatar_hash) = saved_avatar;
+        match &state.existing {
+            None => {
+                self.db
+                    .user_store()
+                    .create_user_mapping(&UserMapping {
+                        id: 0,
+                        matrix_user_id: state.mx_user_id.clone(),
+                        slack_user_id: state.id.clone(),
+                        slack_username: state.displayname.clone().unwrap_or_default(),
+                        slack_discriminator: "0000".to_string(),
+                        slack_avatar,
+                        slack_avatar_hash,
+                        created_at: now,
+                        updated_at: now,
+                    })
+                    .await?;
+                info!("Created user mapping for Slack user {}", state.id);
+            }
+            Some(existing) => {
+                let mut mapping = existing.clone();
+                if let Some(displayname) = &state.displayname {
+                    mapping.slack_username = displayname.clone();
+                }
+                mapping.slack_avatar = slack_avatar;
+                mapping.slack_avatar_hash = slack_avatar_hash;
+                mapping.updated_at = now;
+                self.db.user_store().update_user_mapping(&mapping).await?;
+                debug!("Updated user mapping for Slack user {}", state.id);
+            }
+        }
+
+        Ok(avatar_mxc)
     }
 
-    async fn upload_avatar_to_matrix(
-        &self,
-        slack_user_id: &str,
-        avatar_url: &str,
-    ) -> Result<String> {
+    async fn upload_avatar_to_matrix(&self, state: &UserState, avatar_url: &str) -> Result<String> {
         use crate::media::MediaHandler;
 
+        let now = Utc::now().timestamp();
+        if let Some(hash) = &state.avatar_hash
+            && let Some(cached) = self.avatar_cache.lock().get(hash)
+            && now - cached.timestamp < CACHE_TTL_SECS
+        {
+            return Ok(cached.mxc_url.clone());
+        }
+
         let media_handler = MediaHandler::new(&self.config.bridge.homeserver_url);
         let media = media_handler.download_from_url(avatar_url).await?;
 
@@ -220,14 +245,29 @@ impl UserSyncHandler {
 
         let mxc_url = self
             .matrix
-            .upload_media_for_ghost(slack_user_id, &media.data, &content_type, &filename)
+            .upload_media_for_ghost(&state.id, &media.data, &content_type, &filename)
             .await?;
 
-        info!("Uploaded avatar for {} to {}", slack_user_id, mxc_url);
+        info!("Uploaded avatar for {} to {}", state.id, mxc_url);
+        if let Some(hash) = &state.avatar_hash {
+            let mut cache = self.avatar_cache.lock();
+            cache.retain(|_, entry| now - entry.timestamp < CACHE_TTL_SECS);
+            cache.insert(
+                hash.clone(),
+                AvatarCacheEntry {
+                    mxc_url: mxc_url.clone(),
+                    timestamp: now,
+                },
+            );
+        }
         Ok(mxc_url)
     }
 
-    async fn update_state_for_guilds(&self, state: &UserState) -> Result<()> {
+    async fn update_state_for_guilds(
+        &self,
+        state: &UserState,
+        avatar_mxc: Option<&str>,
+    ) -> Result<()> {
         let room_mappings = self.db.room_store().list_room_mappings(i64::MAX, 0).await?;
 
         if room_mappings.is_empty() {
@@ -235,44 +275,38 @@ impl UserSyncHandler {
             return Ok(());
         }
 
-        let mut guild_rooms: HashMap<String, Vec<String>> = HashMap::new();
         for mapping in room_mappings {
-            guild_rooms
-                .entry(mapping.slack_team_id.clone())
-                .or_default()
-                .push(mapping.matrix_room_id);
-        }
-
-        for (_guild_id, room_ids) in guild_rooms {
-            for room_id in room_ids {
-                if let Err(e) = self.apply_state_to_room(state, &room_id).await {
-                    warn!("Failed to update user state in room {}: {}", room_id, e);
-                }
+            if let Err(e) = self
+                .apply_state_to_room(state, &mapping.matrix_room_id, avatar_mxc)
+                .await
+            {
+                warn!(
+                    "Failed to update user state in room {}: {}",
+                    mapping.matrix_room_id, e
+                );
             }
         }
 
         Ok(())
     }
 
-    async fn apply_state_to_room(&self, state: &UserState, room_id: &str) -> Result<()> {
+    async fn apply_state_to_room(
+        &self,
+        state: &UserState,
+        room_id: &str,
+        avatar_mxc: Option<&str>,
+    ) -> Result<()> {
         debug!(
             "Applying member state for {} in room {}",
             state.mx_user_id, room_id
         );
 
-        if let Some(displayname) = &state.displayname {
-            self.matrix
-                .set_ghost_room_displayname(&state.id, room_id, displayname)
-                .await?;
-        }
-
-        if let Some(avatar_mxc) = &state.avatar_url {
-            self.matrix
-                .set_ghost_room_avatar(&state.id, room_id, avatar_mxc)
-                .await?;
-        }
+        let joined = self
+            .matrix
+            .set_ghost_room_profile(&state.id, room_id, state.displayname.as_deref(), avatar_mxc)
+            .await?;
 
-        if !state.roles.is_empty() {
+        if joined && !state.roles.is_empty() {
             self.matrix
                 .set_ghost_room_roles(&state.id, room_id, &state.roles)
                 .await?;
@@ -281,6 +315,27 @@ impl UserSyncHandler {
         Ok(())
     }
 
+    async fn remove_from_bridged_rooms(&self, state: &UserState) -> Result<()> {
+        info!(
+            "Slack user {} was deactivated, leaving bridged rooms",
+            state.id
+        );
+        let room_mappings = self.db.room_store().list_room_mappings(i64::MAX, 0).await?;
+        for mapping in room_mappings {
+            if let Err(e) = self
+                .matrix
+                .leave_room_as_ghost(&state.id, &mapping.matrix_room_id)
+                .await
+            {
+                debug!(
+                    "{} did not leave room {}: {}",
+                    state.mx_user_id, mapping.matrix_room_id, e
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub async fn ensure_user_in_room(&self, slack_user_id: &str, room_id: &str) -> Result<()> {
         self.matrix
             .ensure_ghost_user_registered(slack_user_id, None)
@@ -345,12 +400,25 @@ struct UserState {
     id: String,
     mx_user_id: String,
     create_user: bool,
+    deactivated: bool,
     displayname: Option<String>,
+    /// Slack URL of an avatar that still has to be uploaded.
     avatar_url: Option<String>,
+    avatar_hash: Option<String>,
     remove_avatar: bool,
+    existing: Option<UserMapping>,
     roles: Vec<String>,
 }
 
+impl UserState {
+    fn has_changes(&self) -> bool {
+        self.create_user
+            || self.displayname.is_some()
+            || self.avatar_url.is_some()
+            || self.remove_avatar
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -388,4 +456,3 @@ mod tests {
         assert_eq!(result, "user-name.test_123");
     }
 }
-
@@ -1,8 +1,17 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use anyhow::{Context, Result, bail};
 use clap::{Parser, Subcommand};
 use serde_json::json;
 
+use crate::bridge::BridgeCore;
+use crate::config::{Config, ConfigError, DatabaseConfig};
+use crate::db::manager::{DbType, SchemaStatus};
+use crate::db::{DatabaseManager, transfer};
+use crate::matrix::MatrixAppservice;
+use crate::slack::SlackClient;
+
 #[derive(Parser, Debug)]
 #[command(name = "matrix-slack-bridge")]
 #[command(about = "Matrix-Slack Bridge", long_about = None)]
@@ -10,10 +19,19 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
 
-    #[arg(short, long, env = "CONFIG_PATH", default_value = "config.yaml")]
-    pub config: PathBuf,
+    #[arg(
+        short,
+        long,
+        env = "CONFIG_PATH",
+        help = "Config file (defaults to config.kdl if present, else config.yaml)"
+    )]
+    pub config: Option<PathBuf>,
 
-    #[arg(short, long, env = "REGISTRATION_PATH")]
+    #[arg(
+        short,
+        long,
+        help = "Registration file (defaults to REGISTRATION_PATH, resolved against the config directory)"
+    )]
     pub registration: Option<PathBuf>,
 }
 
@@ -83,6 +101,261 @@ pub enum Commands {
     Status,
 }
 
+impl Cli {
+    pub fn config_path(&self) -> PathBuf {
+        self.config.clone().unwrap_or_else(Config::default_path)
+    }
+
+    pub fn load_config(&self) -> Result<Config, ConfigError> {
+        Config::load_with_registration(self.config_path(), self.registration.as_deref())
+    }
+}
+
+/// Runs a one-shot subcommand to completion. The bridge itself is only started when no
+/// subcommand is given.
+pub async fn run(cli: &Cli, command: &Commands) -> Result<()> {
+    match command {
+        Commands::GenerateRegistration {
+            output,
+            id,
+            homeserver_url,
+            domain,
+        } => {
+            let yaml = generate_registration(id, homeserver_url, domain);
+            std::fs::write(output, yaml)
+                .with_context(|| format!("failed to write {}", output.display()))?;
+            println!("Registration written to {}", output.display());
+            Ok(())
+        }
+        Commands::ValidateConfig => {
+            let path = cli.config_path();
+            cli.load_config()
+                .with_context(|| format!("{} is invalid", path.display()))?;
+            println!("{} is valid", path.display());
+            Ok(())
+        }
+        Commands::Migrate { from, to, dry_run } => run_migrate(from, to, *dry_run).await,
+        Commands::Adminme {
+            user,
+            room,
+            power_level,
+        } => {
+            let config = Arc::new(cli.load_config()?);
+            let db_manager = open_database(&config).await?;
+            let matrix_client = MatrixAppservice::new(config).await?;
+            run_adminme(&db_manager, &matrix_client, user, room.as_deref(), *power_level).await
+        }
+        Commands::ListRooms { guild, limit } => {
+            let config = cli.load_config()?;
+            let db_manager = open_database(&config).await?;
+            run_list_rooms(&db_manager, guild.as_deref(), *limit).await
+        }
+        Commands::Unbridge { room, leave } => {
+            let config = Arc::new(cli.load_config()?);
+            let db_manager = Arc::new(open_database(&config).await?);
+            let matrix_client = Arc::new(MatrixAppservice::new(config.clone()).await?);
+            let slack_client = Arc::new(SlackClient::new(config).await?);
+            let bridge = BridgeCore::new(matrix_client.clone(), slack_client, db_manager);
+
+            let reply = bridge.unbridge_matrix_room(room).await?;
+            println!("{}", reply);
+            if *leave {
+                matrix_client
+                    .leave_room(room)
+                    .await
+                    .with_context(|| format!("failed to leave {}", room))?;
+                println!("Left {}", room);
+            }
+            Ok(())
+        }
+        Commands::Status => {
+            let config = Arc::new(cli.load_config()?);
+            let db_manager = open_database(&config).await?;
+            let matrix_client = MatrixAppservice::new(config.clone()).await?;
+            run_status(&config, &db_manager, &matrix_client).await
+        }
+    }
+}
+
+async fn open_database(config: &Config) -> Result<DatabaseManager> {
+    let db_manager = DatabaseManager::new(&config.database).await?;
+    db_manager.migrate().await?;
+    Ok(db_manager)
+}
+
+async fn run_migrate(from: &str, to: &str, dry_run: bool) -> Result<()> {
+    if from == to {
+        bail!("source and destination databases must differ");
+    }
+
+    let source = DatabaseManager::new(&DatabaseConfig::from_url(from))
+        .await
+        .context("failed to open source database")?;
+    let destination = DatabaseManager::new(&DatabaseConfig::from_url(to))
+        .await
+        .context("failed to open destination database")?;
+
+    if dry_run {
+        // A dry run writes to neither database, so an older source can't be brought up to
+        // date and a missing destination is compared as empty instead of being created.
+        match source
+            .schema_status()
+            .await
+            .context("failed to inspect source database schema")?
+        {
+            SchemaStatus::Current => {}
+            SchemaStatus::Missing => bail!("source database has no bridge tables"),
+            SchemaStatus::Outdated => bail!("source schema is outdated, run without --dry-run"),
+        }
+        let destination = match destination
+            .schema_status()
+            .await
+            .context("failed to inspect destination database schema")?
+        {
+            SchemaStatus::Current => Some(&destination),
+            SchemaStatus::Missing => None,
+            SchemaStatus::Outdated => {
+                bail!("destination schema is outdated, run without --dry-run")
+            }
+        };
+        let report = transfer::copy_database(&source, destination, true).await?;
+
+        println!("Dry run: nothing was written.");
+        println!("{:<18} {:>8} {:>10} {:>10}", "table", "read", "would copy", "would skip");
+        for (table, count) in report.tables() {
+            println!(
+                "{:<18} {:>8} {:>10} {:>10}",
+                table, count.read, count.copied, count.skipped
+            );
+        }
+        return Ok(());
+    }
+
+    // Older sources lack columns the stores select, so they are brought up to date first.
+    source
+        .migrate()
+        .await
+        .context("failed to migrate source database schema")?;
+    destination
+        .migrate()
+        .await
+        .context("failed to migrate destination database schema")?;
+    let report = transfer::copy_database(&source, Some(&destination), false).await?;
+
+    println!("{:<18} {:>8} {:>8} {:>8}", "table", "read", "copied", "skipped");
+    for (table, count) in report.tables() {
+        println!(
+            "{:<18} {:>8} {:>8} {:>8}",
+            table, count.read, count.copied, count.skipped
+        );
+    }
+    Ok(())
+}
+
+async fn run_adminme(
+    db_manager: &DatabaseManager,
+    matrix_client: &MatrixAppservice,
+    user: &str,
+    room: Option<&str>,
+    power_level: i64,
+) -> Result<()> {
+    let rooms = match room {
+        Some(room) => vec![room.to_string()],
+        None => {
+            let room_store = db_manager.room_store();
+            let count = room_store.count_rooms().await?;
+            room_store
+                .list_room_mappings(count, 0)
+                .await?
+                .into_iter()
+                .map(|mapping| mapping.matrix_room_id)
+                .collect()
+        }
+    };
+
+    if rooms.is_empty() {
+        println!("No bridged rooms found");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for room_id in &rooms {
+        match matrix_client
+            .set_user_power_level(room_id, user, power_level)
+            .await
+        {
+            Ok(()) => println!("Set power level of {} to {} in {}", user, power_level, room_id),
+            Err(err) => {
+                failed += 1;
+                eprintln!("Failed to set power level in {}: {}", room_id, err);
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("failed to update {} of {} rooms", failed, rooms.len());
+    }
+    Ok(())
+}
+
+async fn run_list_rooms(
+    db_manager: &DatabaseManager,
+    guild: Option<&str>,
+    limit: i64,
+) -> Result<()> {
+    let room_store = db_manager.room_store();
+    let mut rooms = match guild {
+        Some(team_id) => room_store.get_rooms_by_guild(team_id).await?,
+        None => room_store.list_room_mappings(limit, 0).await?,
+    };
+    rooms.truncate(limit.max(0) as usize);
+
+    if rooms.is_empty() {
+        println!("No bridged rooms found");
+        return Ok(());
+    }
+
+    for room in &rooms {
+        println!(
+            "{}\t{}\t#{}\t{}\t{}",
+            room.id,
+            room.matrix_room_id,
+            room.slack_channel_name,
+            room.slack_channel_id,
+            room.slack_team_id
+        );
+    }
+    println!("{} room(s)", rooms.len());
+    Ok(())
+}
+
+async fn run_status(
+    config: &Config,
+    db_manager: &DatabaseManager,
+    matrix_client: &MatrixAppservice,
+) -> Result<()> {
+    let backend = match db_manager.db_type() {
+        DbType::Postgres => "postgres",
+        DbType::Sqlite => "sqlite",
+        DbType::Mysql => "mysql",
+    };
+    let rooms = db_manager.room_store().count_rooms().await?;
+
+    println!("version: {}", env!("CARGO_PKG_VERSION"));
+    println!("domain: {}", config.bridge.domain);
+    println!("bot user: {}", matrix_client.bot_user_id());
+    println!("database: {}", backend);
+    println!("bridged rooms: {}", rooms);
+    match matrix_client.appservice.client.get_server_versions().await {
+        Ok(_) => println!("homeserver: reachable ({})", config.bridge.homeserver_url),
+        Err(err) => println!(
+            "homeserver: unreachable ({}): {}",
+            config.bridge.homeserver_url, err
+        ),
+    }
+    Ok(())
+}
+
 pub fn generate_registration(id: &str, homeserver_url: &str, domain: &str) -> String {
     let as_token = generate_token();
     let hs_token = generate_token();
@@ -138,4 +411,61 @@ mod tests {
         assert!(yaml.contains("hs_token:"));
         assert!(yaml.contains("protocols:"));
     }
+
+    #[test]
+    fn parses_global_flags_with_subcommand() {
+        let cli = Cli::try_parse_from([
+            "matrix-slack-bridge",
+            "--config",
+            "/etc/bridge/config.yaml",
+            "--registration",
+            "/etc/bridge/registration.yaml",
+            "list-rooms",
+            "--limit",
+            "5",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.config_path(), PathBuf::from("/etc/bridge/config.yaml"));
+        assert_eq!(
+            cli.registration,
+            Some(PathBuf::from("/etc/bridge/registration.yaml"))
+        );
+        assert!(matches!(
+            cli.command,
+            Some(Commands::ListRooms { guild: None, limit: 5 })
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn migrate_dry_run_writes_to_neither_database() {
+        use diesel::{Connection, RunQueryDsl, SqliteConnection};
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.db");
+        let destination = dir.path().join("destination.db");
+        let from = format!("sqlite://{}", source.display());
+        let to = format!("sqlite://{}", destination.display());
+
+        let source_manager = DatabaseManager::new(&DatabaseConfig::from_url(&from))
+            .await
+            .unwrap();
+        source_manager.migrate().await.unwrap();
+        run_migrate(&from, &to, true).await.unwrap();
+        assert!(!destination.exists());
+
+        // A source from before thread support still needs its upgrade.
+        let mut conn = SqliteConnection::establish(&from).unwrap();
+        diesel::sql_query("ALTER TABLE message_mappings DROP COLUMN slack_thread_ts")
+            .execute(&mut conn)
+            .unwrap();
+        let err = run_migrate(&from, &to, true).await.unwrap_err();
+        assert!(err.to_string().contains("run without --dry-run"), "{err}");
+        assert_eq!(
+            source_manager.schema_status().await.unwrap(),
+            SchemaStatus::Outdated
+        );
+        assert!(!destination.exists());
+    }
 }
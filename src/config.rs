@@ -1,7 +1,8 @@
 pub use self::parser::{
     AuthConfig, BackfillConfig, BridgeConfig, ChannelConfig, ChannelDeleteOptionsConfig, Config,
     DatabaseConfig, DbType, GhostsConfig, LimitsConfig, LoggingConfig, LoggingFileConfig,
-    MetricsConfig, RegistrationConfig, RoomConfig, UserActivityConfig,
+    MediaProxyConfig, MetricsConfig, RegistrationConfig, RoomConfig, SlackEventMode, SlackReplyMode,
+    UserActivityConfig,
 };
 pub use self::validator::ConfigError;
 
@@ -39,7 +39,7 @@ fn nodes_to_json(nodes: &[kdl::KdlNode]) -> Value {
 fn node_to_json(node: &kdl::KdlNode) -> Value {
     let has_children = node
         .children()
-        .map_or(false, |c| !c.nodes().is_empty());
+        .is_some_and(|c| !c.nodes().is_empty());
     let args: Vec<_> = node.entries().iter().filter(|e| e.name().is_none()).collect();
     let props: Vec<_> = node.entries().iter().filter(|e| e.name().is_some()).collect();
 
@@ -55,7 +55,7 @@ fn node_to_json(node: &kdl::KdlNode) -> Value {
             let arr: Vec<Value> = children_doc
                 .nodes()
                 .iter()
-                .map(|n| dash_node_to_json(n))
+                .map(dash_node_to_json)
                 .collect();
             return Value::Array(arr);
         }
@@ -117,7 +117,7 @@ fn dash_node_to_json(node: &kdl::KdlNode) -> Value {
     let props: Vec<_> = node.entries().iter().filter(|e| e.name().is_some()).collect();
     let has_children = node
         .children()
-        .map_or(false, |c| !c.nodes().is_empty());
+        .is_some_and(|c| !c.nodes().is_empty());
 
     if has_children {
         let mut obj = match kdl_document_to_json(node.children().unwrap()) {
@@ -172,7 +172,7 @@ fn kdl_value_to_json(value: &kdl::KdlValue) -> Value {
 
 /// Returns `true` if the file path has a `.kdl` extension.
 pub fn is_kdl_file(path: &std::path::Path) -> bool {
-    path.extension().map_or(false, |ext| ext == "kdl")
+    path.extension().is_some_and(|ext| ext == "kdl")
 }
 
 #[cfg(test)]
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Deserializer, Serialize};
@@ -21,6 +22,33 @@ pub struct Config {
     pub metrics: MetricsConfig,
     #[serde(default)]
     pub backfill: BackfillConfig,
+    #[serde(default)]
+    pub media_proxy: MediaProxyConfig,
+}
+
+/// Signed, expiring links through which Slack can fetch Matrix media (avatars and files
+/// too large to upload) without homeserver credentials.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaProxyConfig {
+    /// Base URL at which Slack can reach this bridge's web server. The proxy is disabled
+    /// while unset.
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// Key used to sign proxy links; falls back to the appservice token.
+    #[serde(default)]
+    pub signing_key: Option<String>,
+    #[serde(default = "default_media_proxy_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for MediaProxyConfig {
+    fn default() -> Self {
+        Self {
+            public_url: None,
+            signing_key: None,
+            ttl_secs: default_media_proxy_ttl_secs(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +79,9 @@ pub struct BridgeConfig {
     pub homeserver_url: String,
     #[serde(default = "default_presence_interval")]
     pub presence_interval: u64,
+    /// Seconds between Slack presence polls of recently active users.
+    #[serde(default = "default_presence_poll_interval")]
+    pub presence_poll_interval: u64,
     #[serde(default)]
     pub disable_presence: bool,
     #[serde(default)]
@@ -95,6 +126,34 @@ pub struct BridgeConfig {
     pub participant_sync_only_on_create: bool,
     #[serde(default)]
     pub mute_channels_by_default: bool,
+    /// How Matrix replies to top-level Slack messages are posted.
+    #[serde(default)]
+    pub reply_mode: SlackReplyMode,
+    /// Per-room overrides of `reply_mode`, keyed by Matrix room id or Slack channel id.
+    #[serde(default)]
+    pub room_reply_modes: HashMap<String, SlackReplyMode>,
+}
+
+impl BridgeConfig {
+    pub fn reply_mode_for(&self, matrix_room_id: &str, slack_channel_id: &str) -> SlackReplyMode {
+        self.room_reply_modes
+            .get(matrix_room_id)
+            .or_else(|| self.room_reply_modes.get(slack_channel_id))
+            .copied()
+            .unwrap_or(self.reply_mode)
+    }
+}
+
+/// Replies to messages already inside a Slack thread always go to that thread; this only
+/// decides what happens to replies to top-level messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlackReplyMode {
+    /// Start a Slack thread under the replied-to message.
+    #[default]
+    Thread,
+    /// Post in the channel with a context block linking to the replied-to message.
+    Quote,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -176,6 +235,31 @@ pub struct AuthConfig {
     pub client_secret: Option<String>,
     #[serde(default = "default_use_privileged_intents")]
     pub use_privileged_intents: bool,
+    /// Overrides `https://slack.com/api/`, e.g. to point the bridge at a local stand-in.
+    #[serde(default)]
+    pub api_base_url: Option<String>,
+    /// Whether Slack events arrive over Socket Mode or as Events API HTTP requests.
+    #[serde(default)]
+    pub event_mode: SlackEventMode,
+    /// Signing secret used to verify Events API requests in `http` mode.
+    #[serde(default)]
+    pub signing_secret: Option<String>,
+    /// Public URL of the bridge's `/slack/oauth` route, registered as a redirect URL of
+    /// the Slack app. `!slack login` is disabled while it is unset.
+    #[serde(default)]
+    pub oauth_redirect_url: Option<String>,
+    /// Key the Slack user tokens of logged-in Matrix users are encrypted with in the
+    /// database. Defaults to the appservice token.
+    #[serde(default)]
+    pub token_encryption_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SlackEventMode {
+    #[default]
+    Socket,
+    Http,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -228,6 +312,18 @@ pub struct DatabaseConfig {
 }
 
 impl DatabaseConfig {
+    pub fn from_url(url: impl Into<String>) -> Self {
+        Self {
+            url: Some(url.into()),
+            conn_string: None,
+            filename: None,
+            user_store_path: None,
+            room_store_path: None,
+            max_connections: None,
+            min_connections: None,
+        }
+    }
+
     pub fn db_type(&self) -> DbType {
         let url = self.connection_string();
         if url.starts_with("sqlite://") {
@@ -356,6 +452,19 @@ pub struct LimitsConfig {
     pub room_count: i32,
     #[serde(default = "default_matrix_event_age_limit_ms")]
     pub matrix_event_age_limit_ms: u64,
+    #[serde(default = "default_slack_request_queue_size")]
+    pub slack_request_queue_size: usize,
+    #[serde(default = "default_slack_rate_limit_retries")]
+    pub slack_rate_limit_retries: u32,
+    /// How long processed transaction and event ids are kept for deduplication.
+    #[serde(default = "default_processed_event_retention_hours")]
+    pub processed_event_retention_hours: u64,
+    /// Largest Slack file re-uploaded to Matrix; bigger files are bridged as a link.
+    #[serde(default = "default_max_slack_to_matrix_file_bytes")]
+    pub max_slack_to_matrix_file_bytes: u64,
+    /// Largest Matrix attachment uploaded to Slack; bigger files are bridged as a link.
+    #[serde(default = "default_max_matrix_to_slack_file_bytes")]
+    pub max_matrix_to_slack_file_bytes: u64,
 }
 
 impl Default for LimitsConfig {
@@ -365,6 +474,11 @@ impl Default for LimitsConfig {
             slack_send_delay: 1500,
             room_count: -1,
             matrix_event_age_limit_ms: 900_000,
+            slack_request_queue_size: 1000,
+            slack_rate_limit_retries: 5,
+            processed_event_retention_hours: 72,
+            max_slack_to_matrix_file_bytes: default_max_slack_to_matrix_file_bytes(),
+            max_matrix_to_slack_file_bytes: default_max_matrix_to_slack_file_bytes(),
         }
     }
 }
@@ -395,17 +509,32 @@ pub struct MetricsConfig {
 
 impl Config {
     pub fn load() -> Result<Self, ConfigError> {
-        let config_path = std::env::var("CONFIG_PATH").ok().unwrap_or_else(|| {
-            if std::path::Path::new("config.kdl").exists() {
-                "config.kdl".to_string()
-            } else {
-                "config.yaml".to_string()
-            }
-        });
+        let config_path = std::env::var("CONFIG_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| Self::default_path());
         Self::load_from_file(&config_path)
     }
 
+    /// The config file used when no path is given: `config.kdl` if present, otherwise
+    /// `config.yaml`.
+    pub fn default_path() -> PathBuf {
+        if Path::new("config.kdl").exists() {
+            PathBuf::from("config.kdl")
+        } else {
+            PathBuf::from("config.yaml")
+        }
+    }
+
     pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::load_with_registration(path, None)
+    }
+
+    /// Loads the config file, reading appservice registration values from `registration`
+    /// instead of `REGISTRATION_PATH`/`slack-registration.yaml` when it is given.
+    pub fn load_with_registration<P: AsRef<Path>>(
+        path: P,
+        registration: Option<&Path>,
+    ) -> Result<Self, ConfigError> {
         let content = std::fs::read_to_string(&path)?;
 
         if super::kdl_support::is_kdl_file(path.as_ref()) {
@@ -413,6 +542,9 @@ impl Config {
                 .map_err(ConfigError::Kdl)?;
             config.apply_env_overrides();
             config.normalize();
+            if registration.is_some() {
+                config.load_registration(path.as_ref(), registration)?;
+            }
             config.validate()?;
             return Ok(config);
         }
@@ -420,7 +552,7 @@ impl Config {
         let mut config: Config = serde_yaml::from_str(&content)?;
         config.apply_env_overrides();
         config.normalize();
-        config.load_registration(path.as_ref())?;
+        config.load_registration(path.as_ref(), registration)?;
         config.validate()?;
         Ok(config)
     }
@@ -464,7 +596,20 @@ impl Config {
                     .to_string(),
             ));
         }
-        if self
+        if self.auth.event_mode == SlackEventMode::Http {
+            if self
+                .auth
+                .signing_secret
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .is_none()
+            {
+                return Err(ConfigError::InvalidConfig(
+                    "auth.signing_secret cannot be empty when auth.event_mode is http".to_string(),
+                ));
+            }
+        } else if self
             .auth
             .app_token
             .as_deref()
@@ -512,6 +657,9 @@ impl Config {
         if let Ok(value) = std::env::var("APPSERVICE_SLACK_AUTH_APP_TOKEN") {
             self.auth.app_token = Some(value);
         }
+        if let Ok(value) = std::env::var("APPSERVICE_SLACK_AUTH_SIGNING_SECRET") {
+            self.auth.signing_secret = Some(value);
+        }
         if let Ok(value) = std::env::var("APPSERVICE_SLACK_AUTH_CLIENT_ID")
             .or_else(|_| std::env::var("APPSERVICE_slack_AUTH_CLIENT_ID"))
         {
@@ -522,6 +670,12 @@ impl Config {
         {
             self.auth.client_secret = Some(value);
         }
+        if let Ok(value) = std::env::var("APPSERVICE_SLACK_AUTH_OAUTH_REDIRECT_URL") {
+            self.auth.oauth_redirect_url = Some(value);
+        }
+        if let Ok(value) = std::env::var("APPSERVICE_SLACK_AUTH_TOKEN_ENCRYPTION_KEY") {
+            self.auth.token_encryption_key = Some(value);
+        }
         if let Ok(value) = std::env::var("APPSERVICE_SLACK_REGISTRATION_ID")
             .or_else(|_| std::env::var("APPSERVICE_slack_REGISTRATION_ID"))
         {
@@ -544,14 +698,29 @@ impl Config {
         }
     }
 
-    fn load_registration(&mut self, config_path: &Path) -> Result<(), ConfigError> {
-        let registration_path = std::env::var("REGISTRATION_PATH")
-            .ok()
-            .filter(|value| !value.trim().is_empty())
-            .unwrap_or_else(default_registration_file);
-        let registration_path = resolve_registration_path(config_path, &registration_path);
+    fn load_registration(
+        &mut self,
+        config_path: &Path,
+        explicit_path: Option<&Path>,
+    ) -> Result<(), ConfigError> {
+        let registration_path = match explicit_path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let registration_path = std::env::var("REGISTRATION_PATH")
+                    .ok()
+                    .filter(|value| !value.trim().is_empty())
+                    .unwrap_or_else(default_registration_file);
+                resolve_registration_path(config_path, &registration_path)
+            }
+        };
 
         if !registration_path.exists() {
+            if explicit_path.is_some() {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "registration file {} does not exist",
+                    registration_path.display()
+                )));
+            }
             return Ok(());
         }
 
@@ -640,6 +809,10 @@ fn default_presence_interval() -> u64 {
     500
 }
 
+fn default_presence_poll_interval() -> u64 {
+    60
+}
+
 fn default_invalid_token_message() -> String {
     "Your Slack bot token seems to be invalid, and the bridge cannot function. Please update it in your bridge settings and restart the bridge".to_string()
 }
@@ -724,6 +897,30 @@ fn default_matrix_event_age_limit_ms() -> u64 {
     900_000
 }
 
+fn default_slack_request_queue_size() -> usize {
+    1000
+}
+
+fn default_slack_rate_limit_retries() -> u32 {
+    5
+}
+
+fn default_processed_event_retention_hours() -> u64 {
+    72
+}
+
+fn default_max_slack_to_matrix_file_bytes() -> u64 {
+    50 * 1024 * 1024
+}
+
+fn default_max_matrix_to_slack_file_bytes() -> u64 {
+    8 * 1024 * 1024
+}
+
+fn default_media_proxy_ttl_secs() -> u64 {
+    7 * 24 * 3600
+}
+
 fn default_nick_pattern() -> String {
     ":nick".to_string()
 }
@@ -1,10 +1,13 @@
 pub use self::error::DatabaseError;
 pub use self::manager::DatabaseManager;
 pub use self::models::{
-    EmojiMapping, MessageMapping, ProcessedEvent, RemoteRoomInfo, RemoteUserInfo, RoomMapping,
-    UserMapping,
+    EmojiMapping, MessageMapping, ProcessedEvent, ReactionMapping, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, SlackPuppet, UserMapping,
+};
+pub use self::stores::{
+    BridgeStateStore, EmojiStore, MessageStore, ProcessedEventStore, PuppetStore, ReactionStore,
+    RoomStore, UserStore,
 };
-pub use self::stores::{EmojiStore, MessageStore, RoomStore, UserStore};
 
 pub mod error;
 pub mod manager;
@@ -12,6 +15,7 @@ pub mod models;
 #[cfg(feature = "postgres")]
 pub mod schema;
 pub mod stores;
+pub mod transfer;
 
 #[cfg(feature = "postgres")]
 pub mod postgres;
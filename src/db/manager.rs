@@ -11,12 +11,19 @@ use diesel::r2d2::{self, ConnectionManager};
 
 use crate::config::{DatabaseConfig as ConfigDatabaseConfig, DbType as ConfigDbType};
 #[cfg(feature = "mysql")]
-use crate::db::mysql::{MysqlEmojiStore, MysqlMessageStore, MysqlRoomStore, MysqlUserStore};
+use crate::db::mysql::{
+    MysqlBridgeStateStore, MysqlEmojiStore, MysqlMessageStore, MysqlProcessedEventStore, MysqlReactionStore,
+    MysqlPuppetStore, MysqlRoomStore, MysqlUserStore,
+};
 #[cfg(feature = "postgres")]
 use crate::db::postgres::{
-    PostgresEmojiStore, PostgresMessageStore, PostgresRoomStore, PostgresUserStore,
+    PostgresBridgeStateStore, PostgresEmojiStore, PostgresMessageStore, PostgresProcessedEventStore, PostgresReactionStore,
+    PostgresPuppetStore, PostgresRoomStore, PostgresUserStore,
+};
+use crate::db::{
+    BridgeStateStore, DatabaseError, EmojiStore, MessageStore, ProcessedEventStore, PuppetStore,
+    ReactionStore, RoomStore, UserStore,
 };
-use crate::db::{DatabaseError, EmojiStore, MessageStore, RoomStore, UserStore};
 
 #[cfg(feature = "postgres")]
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
@@ -29,7 +36,69 @@ use diesel::Connection;
 use diesel::sqlite::SqliteConnection;
 
 #[cfg(feature = "sqlite")]
-use crate::db::sqlite::{SqliteEmojiStore, SqliteMessageStore, SqliteRoomStore, SqliteUserStore};
+use crate::db::sqlite::{
+    SqliteBridgeStateStore, SqliteEmojiStore, SqliteMessageStore, SqliteProcessedEventStore, SqliteReactionStore,
+    SqlitePuppetStore, SqliteRoomStore, SqliteUserStore,
+};
+
+#[cfg(feature = "sqlite")]
+const SQLITE_MESSAGE_MAPPINGS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS message_mappings (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        slack_message_id TEXT NOT NULL,
+        slack_channel_id TEXT NOT NULL DEFAULT '',
+        slack_team_id TEXT NOT NULL DEFAULT '',
+        matrix_room_id TEXT NOT NULL,
+        matrix_event_id TEXT NOT NULL,
+        slack_thread_ts TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+        UNIQUE (slack_channel_id, slack_message_id)
+    )
+"#;
+
+#[cfg(any(feature = "postgres", feature = "mysql", feature = "sqlite"))]
+#[derive(diesel::QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    count: i64,
+}
+
+/// A column of every table the stores read, including the newest column each upgrade
+/// added. A schema that has all of them needs no migration.
+#[cfg(any(feature = "postgres", feature = "mysql", feature = "sqlite"))]
+const SCHEMA_COLUMNS: &[(&str, &str)] = &[
+    ("user_mappings", "slack_avatar_hash"),
+    ("room_mappings", "slack_team_id"),
+    ("processed_events", "event_id"),
+    ("message_mappings", "slack_channel_id"),
+    ("message_mappings", "slack_thread_ts"),
+    ("emoji_mappings", "mxc_url"),
+    ("reaction_mappings", "matrix_sender"),
+    ("slack_puppets", "access_token"),
+    ("active_users", "last_active_at"),
+    ("bridge_state", "value"),
+];
+
+/// How far a database's schema is from the one [`DatabaseManager::migrate`] creates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchemaStatus {
+    /// None of the bridge tables exist, or the SQLite file does not.
+    Missing,
+    Outdated,
+    Current,
+}
+
+impl SchemaStatus {
+    #[cfg(any(feature = "postgres", feature = "mysql", feature = "sqlite"))]
+    fn from_found_columns(found: usize) -> Self {
+        match found {
+            0 => Self::Missing,
+            found if found == SCHEMA_COLUMNS.len() => Self::Current,
+            _ => Self::Outdated,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct DatabaseManager {
@@ -43,6 +112,10 @@ pub struct DatabaseManager {
     user_store: Arc<dyn UserStore>,
     message_store: Arc<dyn MessageStore>,
     emoji_store: Arc<dyn EmojiStore>,
+    processed_event_store: Arc<dyn ProcessedEventStore>,
+    reaction_store: Arc<dyn ReactionStore>,
+    puppet_store: Arc<dyn PuppetStore>,
+    bridge_state_store: Arc<dyn BridgeStateStore>,
     db_type: DbType,
 }
 
@@ -88,6 +161,10 @@ impl DatabaseManager {
                 let user_store = Arc::new(PostgresUserStore::new(pool.clone()));
                 let message_store = Arc::new(PostgresMessageStore::new(pool.clone()));
                 let emoji_store = Arc::new(PostgresEmojiStore::new(pool.clone()));
+                let processed_event_store = Arc::new(PostgresProcessedEventStore::new(pool.clone()));
+                let reaction_store = Arc::new(PostgresReactionStore::new(pool.clone()));
+                let puppet_store = Arc::new(PostgresPuppetStore::new(pool.clone()));
+                let bridge_state_store = Arc::new(PostgresBridgeStateStore::new(pool.clone()));
 
                 Ok(Self {
                     postgres_pool: Some(pool),
@@ -99,6 +176,10 @@ impl DatabaseManager {
                     user_store,
                     message_store,
                     emoji_store,
+                    processed_event_store,
+                    reaction_store,
+                    puppet_store,
+                    bridge_state_store,
                     db_type,
                 })
             }
@@ -110,7 +191,11 @@ impl DatabaseManager {
                 let room_store = Arc::new(SqliteRoomStore::new(path_arc.clone()));
                 let user_store = Arc::new(SqliteUserStore::new(path_arc.clone()));
                 let message_store = Arc::new(SqliteMessageStore::new(Arc::new(path.clone())));
-                let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc));
+                let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc.clone()));
+                let processed_event_store = Arc::new(SqliteProcessedEventStore::new(path_arc.clone()));
+                let reaction_store = Arc::new(SqliteReactionStore::new(path_arc.clone()));
+                let puppet_store = Arc::new(SqlitePuppetStore::new(path_arc.clone()));
+        let bridge_state_store = Arc::new(SqliteBridgeStateStore::new(path_arc));
 
                 Ok(Self {
                     #[cfg(feature = "postgres")]
@@ -122,6 +207,10 @@ impl DatabaseManager {
                     user_store,
                     message_store,
                     emoji_store,
+                    processed_event_store,
+                    reaction_store,
+                    puppet_store,
+                    bridge_state_store,
                     db_type,
                 })
             }
@@ -145,6 +234,10 @@ impl DatabaseManager {
                 let user_store = Arc::new(MysqlUserStore::new(pool.clone()));
                 let message_store = Arc::new(MysqlMessageStore::new(pool.clone()));
                 let emoji_store = Arc::new(MysqlEmojiStore::new(pool.clone()));
+                let processed_event_store = Arc::new(MysqlProcessedEventStore::new(pool.clone()));
+                let reaction_store = Arc::new(MysqlReactionStore::new(pool.clone()));
+                let puppet_store = Arc::new(MysqlPuppetStore::new(pool.clone()));
+                let bridge_state_store = Arc::new(MysqlBridgeStateStore::new(pool.clone()));
 
                 Ok(Self {
                     #[cfg(feature = "postgres")]
@@ -156,6 +249,10 @@ impl DatabaseManager {
                     user_store,
                     message_store,
                     emoji_store,
+                    processed_event_store,
+                    reaction_store,
+                    puppet_store,
+                    bridge_state_store,
                     db_type,
                 })
             }
@@ -189,7 +286,11 @@ impl DatabaseManager {
         let room_store = Arc::new(SqliteRoomStore::new(path_arc.clone()));
         let user_store = Arc::new(SqliteUserStore::new(path_arc.clone()));
         let message_store = Arc::new(SqliteMessageStore::new(path_arc.clone()));
-        let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc));
+        let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc.clone()));
+        let processed_event_store = Arc::new(SqliteProcessedEventStore::new(path_arc.clone()));
+        let reaction_store = Arc::new(SqliteReactionStore::new(path_arc.clone()));
+        let puppet_store = Arc::new(SqlitePuppetStore::new(path_arc.clone()));
+        let bridge_state_store = Arc::new(SqliteBridgeStateStore::new(path_arc));
 
         Ok(Self {
             #[cfg(feature = "postgres")]
@@ -201,6 +302,10 @@ impl DatabaseManager {
             user_store,
             message_store,
             emoji_store,
+            processed_event_store,
+            reaction_store,
+            puppet_store,
+            bridge_state_store,
             db_type: DbType::Sqlite,
         })
     }
@@ -260,6 +365,7 @@ impl DatabaseManager {
                     slack_username TEXT NOT NULL,
                     slack_discriminator TEXT NOT NULL,
                     slack_avatar TEXT,
+                    slack_avatar_hash TEXT,
                     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
@@ -287,13 +393,29 @@ impl DatabaseManager {
                 r#"
                 CREATE TABLE IF NOT EXISTS message_mappings (
                     id BIGSERIAL PRIMARY KEY,
-                    slack_message_id TEXT NOT NULL UNIQUE,
+                    slack_message_id TEXT NOT NULL,
+                    slack_channel_id TEXT NOT NULL DEFAULT '',
+                    slack_team_id TEXT NOT NULL DEFAULT '',
                     matrix_room_id TEXT NOT NULL,
                     matrix_event_id TEXT NOT NULL,
+                    slack_thread_ts TEXT,
                     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
                 "#,
+                // Slack `ts` values are only unique per channel; older databases keyed on `ts` alone.
+                "ALTER TABLE message_mappings ADD COLUMN IF NOT EXISTS slack_channel_id TEXT NOT NULL DEFAULT ''",
+                "ALTER TABLE message_mappings ADD COLUMN IF NOT EXISTS slack_team_id TEXT NOT NULL DEFAULT ''",
+                "ALTER TABLE message_mappings DROP CONSTRAINT IF EXISTS message_mappings_slack_message_id_key",
+                "ALTER TABLE message_mappings ADD COLUMN IF NOT EXISTS slack_thread_ts TEXT",
+                "ALTER TABLE user_mappings ADD COLUMN IF NOT EXISTS slack_avatar_hash TEXT",
+                r#"
+                UPDATE message_mappings
+                SET slack_channel_id = r.slack_channel_id, slack_team_id = r.slack_team_id
+                FROM room_mappings r
+                WHERE message_mappings.matrix_room_id = r.matrix_room_id
+                    AND message_mappings.slack_channel_id = ''
+                "#,
                 r#"
                 CREATE TABLE IF NOT EXISTS user_activity (
                     id BIGSERIAL PRIMARY KEY,
@@ -314,17 +436,55 @@ impl DatabaseManager {
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
                 "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS reaction_mappings (
+                    id BIGSERIAL PRIMARY KEY,
+                    slack_channel_id TEXT NOT NULL,
+                    slack_message_ts TEXT NOT NULL,
+                    slack_user_id TEXT NOT NULL,
+                    emoji TEXT NOT NULL,
+                    matrix_room_id TEXT NOT NULL,
+                    matrix_event_id TEXT NOT NULL UNIQUE,
+                    matrix_sender TEXT NOT NULL,
+                    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS slack_puppets (
+                    id BIGSERIAL PRIMARY KEY,
+                    matrix_user_id TEXT NOT NULL UNIQUE,
+                    slack_user_id TEXT NOT NULL,
+                    slack_team_id TEXT NOT NULL,
+                    access_token TEXT NOT NULL,
+                    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS active_users (
+                    user_id TEXT PRIMARY KEY,
+                    last_active_at TIMESTAMP WITH TIME ZONE NOT NULL
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS bridge_state (
+                    name TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )
+                "#,
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_matrix_id ON user_mappings(matrix_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_slack_id ON user_mappings(slack_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_room_mappings_matrix_id ON room_mappings(matrix_room_id)",
                 "CREATE INDEX IF NOT EXISTS idx_room_mappings_slack_id ON room_mappings(slack_channel_id)",
                 "CREATE INDEX IF NOT EXISTS idx_processed_events_event_id ON processed_events(event_id)",
                 "CREATE INDEX IF NOT EXISTS idx_message_mappings_slack_id ON message_mappings(slack_message_id)",
+                "CREATE UNIQUE INDEX IF NOT EXISTS idx_message_mappings_channel_ts ON message_mappings(slack_channel_id, slack_message_id)",
                 "CREATE INDEX IF NOT EXISTS idx_message_mappings_matrix_event ON message_mappings(matrix_event_id)",
                 "CREATE INDEX IF NOT EXISTS idx_user_activity_user_mapping ON user_activity(user_mapping_id)",
                 "CREATE INDEX IF NOT EXISTS idx_user_activity_timestamp ON user_activity(timestamp)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_slack_id ON emoji_mappings(slack_emoji_id)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_mxc ON emoji_mappings(mxc_url)",
+                "CREATE INDEX IF NOT EXISTS idx_reaction_mappings_slack ON reaction_mappings(slack_channel_id, slack_message_ts)",
             ];
 
             for statement in statements {
@@ -356,6 +516,7 @@ impl DatabaseManager {
                     slack_username VARCHAR(255) NOT NULL,
                     slack_discriminator VARCHAR(32) NOT NULL,
                     slack_avatar TEXT NULL,
+                    slack_avatar_hash VARCHAR(64) NULL,
                     created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
                     updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6)
                 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
@@ -384,11 +545,15 @@ impl DatabaseManager {
                 r#"
                 CREATE TABLE IF NOT EXISTS message_mappings (
                     id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
-                    slack_message_id VARCHAR(64) NOT NULL UNIQUE,
+                    slack_message_id VARCHAR(64) NOT NULL,
+                    slack_channel_id VARCHAR(64) NOT NULL DEFAULT '',
+                    slack_team_id VARCHAR(64) NOT NULL DEFAULT '',
                     matrix_room_id VARCHAR(255) NOT NULL,
                     matrix_event_id VARCHAR(255) NOT NULL,
+                    slack_thread_ts VARCHAR(64) NULL,
                     created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
                     updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
+                    UNIQUE KEY idx_message_mappings_channel_ts (slack_channel_id, slack_message_id),
                     KEY idx_message_mappings_matrix_event (matrix_event_id)
                 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
                 "#,
@@ -417,6 +582,43 @@ impl DatabaseManager {
                     KEY idx_emoji_mappings_mxc (mxc_url)
                 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
                 "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS reaction_mappings (
+                    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    slack_channel_id VARCHAR(64) NOT NULL,
+                    slack_message_ts VARCHAR(64) NOT NULL,
+                    slack_user_id VARCHAR(64) NOT NULL,
+                    emoji VARCHAR(255) NOT NULL,
+                    matrix_room_id VARCHAR(255) NOT NULL,
+                    matrix_event_id VARCHAR(255) NOT NULL UNIQUE,
+                    matrix_sender VARCHAR(255) NOT NULL,
+                    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+                    KEY idx_reaction_mappings_slack (slack_channel_id, slack_message_ts)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS slack_puppets (
+                    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    matrix_user_id VARCHAR(255) NOT NULL UNIQUE,
+                    slack_user_id VARCHAR(64) NOT NULL,
+                    slack_team_id VARCHAR(64) NOT NULL,
+                    access_token TEXT NOT NULL,
+                    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+                    updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS active_users (
+                    user_id VARCHAR(255) NOT NULL PRIMARY KEY,
+                    last_active_at DATETIME(6) NOT NULL
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS bridge_state (
+                    name VARCHAR(64) NOT NULL PRIMARY KEY,
+                    value TEXT NOT NULL
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
             ];
 
             for statement in statements {
@@ -425,6 +627,76 @@ impl DatabaseManager {
                     .map_err(|e| DatabaseError::Migration(e.to_string()))?;
             }
 
+            let channel_columns = diesel::sql_query(
+                r#"
+                SELECT COUNT(*) AS count FROM information_schema.COLUMNS
+                WHERE TABLE_SCHEMA = DATABASE()
+                    AND TABLE_NAME = 'message_mappings'
+                    AND COLUMN_NAME = 'slack_channel_id'
+                "#,
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+
+            if channel_columns.count == 0 {
+                // Slack `ts` values are only unique per channel; older databases keyed on `ts` alone.
+                let upgrade = [
+                    r#"
+                    ALTER TABLE message_mappings
+                        ADD COLUMN slack_channel_id VARCHAR(64) NOT NULL DEFAULT '' AFTER slack_message_id,
+                        ADD COLUMN slack_team_id VARCHAR(64) NOT NULL DEFAULT '' AFTER slack_channel_id
+                    "#,
+                    "ALTER TABLE message_mappings DROP INDEX slack_message_id",
+                    r#"
+                    UPDATE message_mappings m
+                    JOIN room_mappings r ON r.matrix_room_id = m.matrix_room_id
+                    SET m.slack_channel_id = r.slack_channel_id, m.slack_team_id = r.slack_team_id
+                    "#,
+                    "ALTER TABLE message_mappings ADD UNIQUE KEY idx_message_mappings_channel_ts (slack_channel_id, slack_message_id)",
+                ];
+                for statement in upgrade {
+                    diesel::sql_query(statement)
+                        .execute(&mut conn)
+                        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+                }
+            }
+
+            let thread_columns = diesel::sql_query(
+                r#"
+                SELECT COUNT(*) AS count FROM information_schema.COLUMNS
+                WHERE TABLE_SCHEMA = DATABASE()
+                    AND TABLE_NAME = 'message_mappings'
+                    AND COLUMN_NAME = 'slack_thread_ts'
+                "#,
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            if thread_columns.count == 0 {
+                diesel::sql_query(
+                    "ALTER TABLE message_mappings ADD COLUMN slack_thread_ts VARCHAR(64) NULL AFTER matrix_event_id",
+                )
+                .execute(&mut conn)
+                .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            }
+
+            let avatar_hash_columns = diesel::sql_query(
+                r#"
+                SELECT COUNT(*) AS count FROM information_schema.COLUMNS
+                WHERE TABLE_SCHEMA = DATABASE()
+                    AND TABLE_NAME = 'user_mappings'
+                    AND COLUMN_NAME = 'slack_avatar_hash'
+                "#,
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            if avatar_hash_columns.count == 0 {
+                diesel::sql_query(
+                    "ALTER TABLE user_mappings ADD COLUMN slack_avatar_hash VARCHAR(64) NULL AFTER slack_avatar",
+                )
+                .execute(&mut conn)
+                .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            }
+
             Ok(())
         })
         .await
@@ -439,6 +711,44 @@ impl DatabaseManager {
             let mut conn = SqliteConnection::establish(&conn_string)
                 .map_err(|e| DatabaseError::Connection(e.to_string()))?;
 
+            let channel_columns = diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM pragma_table_info('message_mappings') WHERE name = 'slack_channel_id'",
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            let existing_columns = diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM pragma_table_info('message_mappings')",
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+
+            if existing_columns.count > 0 && channel_columns.count == 0 {
+                // Slack `ts` values are only unique per channel, and SQLite cannot drop the old
+                // `UNIQUE(slack_message_id)` constraint in place, so the table is rebuilt.
+                conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                    let upgrade = [
+                        "ALTER TABLE message_mappings RENAME TO message_mappings_old",
+                        SQLITE_MESSAGE_MAPPINGS_TABLE,
+                        r#"
+                        INSERT INTO message_mappings
+                            (id, slack_message_id, slack_channel_id, slack_team_id,
+                             matrix_room_id, matrix_event_id, created_at, updated_at)
+                        SELECT m.id, m.slack_message_id,
+                            COALESCE(r.slack_channel_id, ''), COALESCE(r.slack_team_id, ''),
+                            m.matrix_room_id, m.matrix_event_id, m.created_at, m.updated_at
+                        FROM message_mappings_old m
+                        LEFT JOIN room_mappings r ON r.matrix_room_id = m.matrix_room_id
+                        "#,
+                        "DROP TABLE message_mappings_old",
+                    ];
+                    for statement in upgrade {
+                        diesel::sql_query(statement).execute(conn)?;
+                    }
+                    Ok(())
+                })
+                .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            }
+
             let statements = [
                 r#"
                 CREATE TABLE IF NOT EXISTS user_mappings (
@@ -448,6 +758,7 @@ impl DatabaseManager {
                     slack_username TEXT NOT NULL,
                     slack_discriminator TEXT NOT NULL,
                     slack_avatar TEXT,
+                    slack_avatar_hash TEXT,
                     created_at TEXT NOT NULL DEFAULT (datetime('now')),
                     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
@@ -472,16 +783,7 @@ impl DatabaseManager {
                     processed_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
                 "#,
-                r#"
-                CREATE TABLE IF NOT EXISTS message_mappings (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    slack_message_id TEXT NOT NULL UNIQUE,
-                    matrix_room_id TEXT NOT NULL,
-                    matrix_event_id TEXT NOT NULL,
-                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-                )
-                "#,
+                SQLITE_MESSAGE_MAPPINGS_TABLE,
                 r#"
                 CREATE TABLE IF NOT EXISTS user_activity (
                     id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -502,6 +804,42 @@ impl DatabaseManager {
                     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
                 "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS reaction_mappings (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    slack_channel_id TEXT NOT NULL,
+                    slack_message_ts TEXT NOT NULL,
+                    slack_user_id TEXT NOT NULL,
+                    emoji TEXT NOT NULL,
+                    matrix_room_id TEXT NOT NULL,
+                    matrix_event_id TEXT NOT NULL UNIQUE,
+                    matrix_sender TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS slack_puppets (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    matrix_user_id TEXT NOT NULL UNIQUE,
+                    slack_user_id TEXT NOT NULL,
+                    slack_team_id TEXT NOT NULL,
+                    access_token TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS active_users (
+                    user_id TEXT PRIMARY KEY,
+                    last_active_at TEXT NOT NULL
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS bridge_state (
+                    name TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )
+                "#,
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_matrix_id ON user_mappings(matrix_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_slack_id ON user_mappings(slack_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_room_mappings_matrix_id ON room_mappings(matrix_room_id)",
@@ -513,6 +851,7 @@ impl DatabaseManager {
                 "CREATE INDEX IF NOT EXISTS idx_user_activity_timestamp ON user_activity(timestamp)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_slack_id ON emoji_mappings(slack_emoji_id)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_mxc ON emoji_mappings(mxc_url)",
+                "CREATE INDEX IF NOT EXISTS idx_reaction_mappings_slack ON reaction_mappings(slack_channel_id, slack_message_ts)",
             ];
 
             for statement in statements {
@@ -521,12 +860,170 @@ impl DatabaseManager {
                     .map_err(|e| DatabaseError::Migration(e.to_string()))?;
             }
 
+            let thread_columns = diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM pragma_table_info('message_mappings') WHERE name = 'slack_thread_ts'",
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            if thread_columns.count == 0 {
+                diesel::sql_query("ALTER TABLE message_mappings ADD COLUMN slack_thread_ts TEXT")
+                    .execute(&mut conn)
+                    .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            }
+
+            let avatar_hash_columns = diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM pragma_table_info('user_mappings') WHERE name = 'slack_avatar_hash'",
+            )
+            .get_result::<CountRow>(&mut conn)
+            .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            if avatar_hash_columns.count == 0 {
+                diesel::sql_query("ALTER TABLE user_mappings ADD COLUMN slack_avatar_hash TEXT")
+                    .execute(&mut conn)
+                    .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+            }
+
             Ok(())
         })
         .await
         .map_err(|e| DatabaseError::Migration(format!("migration task failed: {e}")))?
     }
 
+    /// Inspects the schema without changing it. A missing SQLite file is not created.
+    pub async fn schema_status(&self) -> Result<SchemaStatus, DatabaseError> {
+        match self.db_type {
+            #[cfg(feature = "postgres")]
+            DbType::Postgres => {
+                let pool = self.postgres_pool.clone().unwrap();
+                tokio::task::spawn_blocking(move || {
+                    let mut conn = pool
+                        .get()
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                    let mut found = 0;
+                    for (table, column) in SCHEMA_COLUMNS {
+                        let row = diesel::sql_query(format!(
+                            "SELECT COUNT(*) AS count FROM information_schema.columns \
+                             WHERE table_schema = current_schema() \
+                             AND table_name = '{table}' AND column_name = '{column}'"
+                        ))
+                        .get_result::<CountRow>(&mut conn)
+                        .map_err(|e| DatabaseError::Query(e.to_string()))?;
+                        found += usize::from(row.count > 0);
+                    }
+                    Ok(SchemaStatus::from_found_columns(found))
+                })
+                .await
+                .map_err(|e| DatabaseError::Connection(format!("schema task failed: {e}")))?
+            }
+            #[cfg(feature = "sqlite")]
+            DbType::Sqlite => {
+                let path = self.sqlite_path.clone().unwrap();
+                if path != ":memory:" && !std::path::Path::new(&path).exists() {
+                    return Ok(SchemaStatus::Missing);
+                }
+                tokio::task::spawn_blocking(move || {
+                    let mut conn = SqliteConnection::establish(&format!("sqlite://{}", path))
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                    let mut found = 0;
+                    for (table, column) in SCHEMA_COLUMNS {
+                        let row = diesel::sql_query(format!(
+                            "SELECT COUNT(*) AS count FROM pragma_table_info('{table}') \
+                             WHERE name = '{column}'"
+                        ))
+                        .get_result::<CountRow>(&mut conn)
+                        .map_err(|e| DatabaseError::Query(e.to_string()))?;
+                        found += usize::from(row.count > 0);
+                    }
+                    Ok(SchemaStatus::from_found_columns(found))
+                })
+                .await
+                .map_err(|e| DatabaseError::Connection(format!("schema task failed: {e}")))?
+            }
+            #[cfg(feature = "mysql")]
+            DbType::Mysql => {
+                let pool = self.mysql_pool.clone().unwrap();
+                tokio::task::spawn_blocking(move || {
+                    let mut conn = pool
+                        .get()
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                    let mut found = 0;
+                    for (table, column) in SCHEMA_COLUMNS {
+                        let row = diesel::sql_query(format!(
+                            "SELECT COUNT(*) AS count FROM information_schema.COLUMNS \
+                             WHERE TABLE_SCHEMA = DATABASE() \
+                             AND TABLE_NAME = '{table}' AND COLUMN_NAME = '{column}'"
+                        ))
+                        .get_result::<CountRow>(&mut conn)
+                        .map_err(|e| DatabaseError::Query(e.to_string()))?;
+                        found += usize::from(row.count > 0);
+                    }
+                    Ok(SchemaStatus::from_found_columns(found))
+                })
+                .await
+                .map_err(|e| DatabaseError::Connection(format!("schema task failed: {e}")))?
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(DatabaseError::Connection(format!(
+                "{:?} feature not enabled",
+                self.db_type
+            ))),
+        }
+    }
+
+    /// Opens a connection and runs a trivial query, without touching the schema.
+    pub async fn check_connection(&self) -> Result<(), DatabaseError> {
+        match self.db_type {
+            #[cfg(feature = "postgres")]
+            DbType::Postgres => {
+                let pool = self.postgres_pool.clone().unwrap();
+                tokio::task::spawn_blocking(move || {
+                    let mut conn = pool
+                        .get()
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                    diesel::sql_query("SELECT 1")
+                        .execute(&mut conn)
+                        .map(|_| ())
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))
+                })
+                .await
+                .map_err(|e| DatabaseError::Connection(format!("connection task failed: {e}")))?
+            }
+            #[cfg(feature = "sqlite")]
+            DbType::Sqlite => {
+                let path = self.sqlite_path.clone().unwrap();
+                tokio::task::spawn_blocking(move || {
+                    let mut conn = SqliteConnection::establish(&format!("sqlite://{}", path))
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                    diesel::sql_query("SELECT 1")
+                        .execute(&mut conn)
+                        .map(|_| ())
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))
+                })
+                .await
+                .map_err(|e| DatabaseError::Connection(format!("connection task failed: {e}")))?
+            }
+            #[cfg(feature = "mysql")]
+            DbType::Mysql => {
+                let pool = self.mysql_pool.clone().unwrap();
+                tokio::task::spawn_blocking(move || {
+                    let mut conn = pool
+                        .get()
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                    diesel::sql_query("SELECT 1")
+                        .execute(&mut conn)
+                        .map(|_| ())
+                        .map_err(|e| DatabaseError::Connection(e.to_string()))
+                })
+                .await
+                .map_err(|e| DatabaseError::Connection(format!("connection task failed: {e}")))?
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(DatabaseError::Connection(format!(
+                "{:?} feature not enabled",
+                self.db_type
+            ))),
+        }
+    }
+
     pub fn room_store(&self) -> Arc<dyn RoomStore> {
         self.room_store.clone()
     }
@@ -543,6 +1040,22 @@ impl DatabaseManager {
         self.emoji_store.clone()
     }
 
+    pub fn processed_event_store(&self) -> Arc<dyn ProcessedEventStore> {
+        self.processed_event_store.clone()
+    }
+
+    pub fn reaction_store(&self) -> Arc<dyn ReactionStore> {
+        self.reaction_store.clone()
+    }
+
+    pub fn puppet_store(&self) -> Arc<dyn PuppetStore> {
+        self.puppet_store.clone()
+    }
+
+    pub fn bridge_state_store(&self) -> Arc<dyn BridgeStateStore> {
+        self.bridge_state_store.clone()
+    }
+
     #[cfg(feature = "postgres")]
     pub fn pool(&self) -> Option<&Pool> {
         self.postgres_pool.as_ref()
@@ -553,3 +1066,171 @@ impl DatabaseManager {
     }
 }
 
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use chrono::Utc;
+    use diesel::{Connection, RunQueryDsl, SqliteConnection};
+
+    use super::*;
+    use crate::db::{MessageMapping, ReactionMapping, SlackPuppet};
+
+    #[tokio::test]
+    async fn sqlite_upgrade_scopes_message_mappings_to_channel() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("legacy.db");
+        let path = path.to_str().unwrap();
+
+        let mut conn = SqliteConnection::establish(&format!("sqlite://{path}")).unwrap();
+        for statement in [
+            r#"
+            CREATE TABLE room_mappings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                matrix_room_id TEXT NOT NULL UNIQUE,
+                slack_channel_id TEXT NOT NULL UNIQUE,
+                slack_channel_name TEXT NOT NULL,
+                slack_team_id TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )
+            "#,
+            r#"
+            CREATE TABLE message_mappings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                slack_message_id TEXT NOT NULL UNIQUE,
+                matrix_room_id TEXT NOT NULL,
+                matrix_event_id TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )
+            "#,
+            "INSERT INTO room_mappings (matrix_room_id, slack_channel_id, slack_channel_name, slack_team_id) VALUES ('!a:example.org', 'CA', 'a', 'T1')",
+            "INSERT INTO message_mappings (slack_message_id, matrix_room_id, matrix_event_id, created_at, updated_at) VALUES ('1700000000.000100', '!a:example.org', '$a', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00')",
+        ] {
+            diesel::sql_query(statement).execute(&mut conn).unwrap();
+        }
+        drop(conn);
+
+        DatabaseManager::migrate_sqlite(path).await.unwrap();
+        DatabaseManager::migrate_sqlite(path).await.unwrap();
+
+        let store = SqliteMessageStore::new(Arc::new(path.to_string()));
+        let legacy = store
+            .get_by_slack_message_id("CA", "1700000000.000100")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(legacy.matrix_event_id, "$a");
+        assert_eq!(legacy.slack_team_id, "T1");
+
+        let now = Utc::now();
+        store
+            .upsert_message_mapping(&MessageMapping {
+                id: 0,
+                slack_message_id: "1700000000.000100".to_string(),
+                slack_channel_id: "CB".to_string(),
+                slack_team_id: "T1".to_string(),
+                matrix_room_id: "!b:example.org".to_string(),
+                matrix_event_id: "$b".to_string(),
+                slack_thread_ts: None,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+
+        let other = store
+            .get_by_slack_message_id("CB", "1700000000.000100")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(other.matrix_event_id, "$b");
+        let legacy = store
+            .get_by_slack_message_id("CA", "1700000000.000100")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(legacy.matrix_event_id, "$a");
+    }
+    #[tokio::test]
+    async fn sqlite_reaction_store_tracks_each_matrix_reaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reactions.db");
+        let path = path.to_str().unwrap();
+        DatabaseManager::migrate_sqlite(path).await.unwrap();
+
+        let store = SqliteReactionStore::new(Arc::new(path.to_string()));
+        let reaction = |event_id: &str, sender: &str| ReactionMapping {
+            id: 0,
+            slack_channel_id: "C1".to_string(),
+            slack_message_ts: "1700000000.000100".to_string(),
+            slack_user_id: "UBOT".to_string(),
+            emoji: "tada".to_string(),
+            matrix_room_id: "!a:example.org".to_string(),
+            matrix_event_id: event_id.to_string(),
+            matrix_sender: sender.to_string(),
+            created_at: Utc::now(),
+        };
+        store
+            .create_reaction(&reaction("$alice", "@alice:example.org"))
+            .await
+            .unwrap();
+        store
+            .create_reaction(&reaction("$bob", "@bob:example.org"))
+            .await
+            .unwrap();
+
+        store.delete_by_matrix_event_id("$alice").await.unwrap();
+        let remaining = store
+            .get_by_slack_reaction("C1", "1700000000.000100", "UBOT", "tada")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(remaining.matrix_sender, "@bob:example.org");
+
+        store
+            .delete_by_slack_message("C1", "1700000000.000100")
+            .await
+            .unwrap();
+        assert!(store.list_reactions(10, 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sqlite_puppet_store_keeps_one_login_per_matrix_user() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("puppets.db");
+        let path = path.to_str().unwrap();
+        DatabaseManager::migrate_sqlite(path).await.unwrap();
+
+        let store = SqlitePuppetStore::new(Arc::new(path.to_string()));
+        let puppet = |slack_user_id: &str, token: &str| SlackPuppet {
+            id: 0,
+            matrix_user_id: "@alice:example.org".to_string(),
+            slack_user_id: slack_user_id.to_string(),
+            slack_team_id: "T1".to_string(),
+            access_token: token.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        store.upsert_puppet(&puppet("U1", "sealed-1")).await.unwrap();
+        store.upsert_puppet(&puppet("U2", "sealed-2")).await.unwrap();
+
+        let stored = store
+            .get_puppet_by_matrix_user("@alice:example.org")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.slack_user_id, "U2");
+        assert_eq!(stored.access_token, "sealed-2");
+        assert_eq!(store.list_puppets(10, 0).await.unwrap().len(), 1);
+
+        store.delete_puppet("@alice:example.org").await.unwrap();
+        assert!(
+            store
+                .get_puppet_by_matrix_user("@alice:example.org")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
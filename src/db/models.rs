@@ -20,6 +20,8 @@ pub struct UserMapping {
     pub slack_username: String,
     pub slack_discriminator: String,
     pub slack_avatar: Option<String>,
+    /// Slack's `avatar_hash` for the avatar last uploaded to the ghost.
+    pub slack_avatar_hash: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -37,8 +39,40 @@ pub struct ProcessedEvent {
 pub struct MessageMapping {
     pub id: i64,
     pub slack_message_id: String,
+    pub slack_channel_id: String,
+    pub slack_team_id: String,
+    pub matrix_room_id: String,
+    pub matrix_event_id: String,
+    /// Root `ts` of the Slack thread the message lives in, if any.
+    pub slack_thread_ts: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Links a Slack reaction to the Matrix `m.reaction` event bridged for it. Reactions
+/// bridged from Matrix are stored with the bot's Slack user id and the Matrix sender.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionMapping {
+    pub id: i64,
+    pub slack_channel_id: String,
+    pub slack_message_ts: String,
+    pub slack_user_id: String,
+    pub emoji: String,
     pub matrix_room_id: String,
     pub matrix_event_id: String,
+    pub matrix_sender: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A Matrix user logged in to Slack with their own user token, which the bridge uses
+/// to post as them. `access_token` is stored encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlackPuppet {
+    pub id: i64,
+    pub matrix_user_id: String,
+    pub slack_user_id: String,
+    pub slack_team_id: String,
+    pub access_token: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -5,10 +5,14 @@ use diesel::prelude::*;
 
 use super::DatabaseError;
 use super::models::{
-    EmojiMapping, MessageMapping, RemoteRoomInfo, RemoteUserInfo, RoomMapping, UserMapping,
+    EmojiMapping, MessageMapping, ProcessedEvent, ReactionMapping, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, SlackPuppet, UserMapping,
 };
 use crate::db::manager::Pool;
-use crate::db::schema::{message_mappings, room_mappings, user_mappings};
+use crate::db::schema::{
+    message_mappings, processed_events, reaction_mappings, room_mappings, slack_puppets,
+    user_mappings,
+};
 
 #[derive(Debug, Clone, Queryable, Selectable)]
 #[diesel(table_name = room_mappings)]
@@ -66,6 +70,7 @@ struct DbUserMapping {
     slack_username: String,
     slack_discriminator: String,
     slack_avatar: Option<String>,
+    slack_avatar_hash: Option<String>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
@@ -79,6 +84,7 @@ impl From<DbUserMapping> for UserMapping {
             slack_username: value.slack_username,
             slack_discriminator: value.slack_discriminator,
             slack_avatar: value.slack_avatar,
+            slack_avatar_hash: value.slack_avatar_hash,
             created_at: value.created_at,
             updated_at: value.updated_at,
         }
@@ -93,16 +99,18 @@ struct NewUserMapping<'a> {
     slack_username: &'a str,
     slack_discriminator: &'a str,
     slack_avatar: Option<&'a str>,
+    slack_avatar_hash: Option<&'a str>,
     created_at: &'a DateTime<Utc>,
     updated_at: &'a DateTime<Utc>,
 }
 
 #[derive(AsChangeset)]
-#[diesel(table_name = user_mappings)]
+#[diesel(table_name = user_mappings, treat_none_as_null = true)]
 struct UpdateUserMapping<'a> {
     slack_username: &'a str,
     slack_discriminator: &'a str,
     slack_avatar: Option<&'a str>,
+    slack_avatar_hash: Option<&'a str>,
     updated_at: &'a DateTime<Utc>,
 }
 
@@ -111,8 +119,11 @@ struct UpdateUserMapping<'a> {
 struct DbMessageMapping {
     id: i64,
     slack_message_id: String,
+    slack_channel_id: String,
+    slack_team_id: String,
     matrix_room_id: String,
     matrix_event_id: String,
+    slack_thread_ts: Option<String>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
 }
@@ -122,8 +133,11 @@ impl From<DbMessageMapping> for MessageMapping {
         Self {
             id: value.id,
             slack_message_id: value.slack_message_id,
+            slack_channel_id: value.slack_channel_id,
+            slack_team_id: value.slack_team_id,
             matrix_room_id: value.matrix_room_id,
             matrix_event_id: value.matrix_event_id,
+            slack_thread_ts: value.slack_thread_ts,
             created_at: value.created_at,
             updated_at: value.updated_at,
         }
@@ -134,8 +148,11 @@ impl From<DbMessageMapping> for MessageMapping {
 #[diesel(table_name = message_mappings)]
 struct NewMessageMapping<'a> {
     slack_message_id: &'a str,
+    slack_channel_id: &'a str,
+    slack_team_id: &'a str,
     matrix_room_id: &'a str,
     matrix_event_id: &'a str,
+    slack_thread_ts: Option<&'a str>,
     created_at: &'a DateTime<Utc>,
     updated_at: &'a DateTime<Utc>,
 }
@@ -143,11 +160,133 @@ struct NewMessageMapping<'a> {
 #[derive(AsChangeset)]
 #[diesel(table_name = message_mappings)]
 struct UpdateMessageMapping<'a> {
+    slack_team_id: &'a str,
+    matrix_room_id: &'a str,
+    matrix_event_id: &'a str,
+    slack_thread_ts: Option<&'a str>,
+    updated_at: &'a DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = reaction_mappings)]
+struct DbReactionMapping {
+    id: i64,
+    slack_channel_id: String,
+    slack_message_ts: String,
+    slack_user_id: String,
+    emoji: String,
+    matrix_room_id: String,
+    matrix_event_id: String,
+    matrix_sender: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<DbReactionMapping> for ReactionMapping {
+    fn from(value: DbReactionMapping) -> Self {
+        Self {
+            id: value.id,
+            slack_channel_id: value.slack_channel_id,
+            slack_message_ts: value.slack_message_ts,
+            slack_user_id: value.slack_user_id,
+            emoji: value.emoji,
+            matrix_room_id: value.matrix_room_id,
+            matrix_event_id: value.matrix_event_id,
+            matrix_sender: value.matrix_sender,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = reaction_mappings)]
+struct NewReactionMapping<'a> {
+    slack_channel_id: &'a str,
+    slack_message_ts: &'a str,
+    slack_user_id: &'a str,
+    emoji: &'a str,
     matrix_room_id: &'a str,
     matrix_event_id: &'a str,
+    matrix_sender: &'a str,
+    created_at: &'a DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = slack_puppets)]
+struct DbSlackPuppet {
+    id: i64,
+    matrix_user_id: String,
+    slack_user_id: String,
+    slack_team_id: String,
+    access_token: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<DbSlackPuppet> for SlackPuppet {
+    fn from(value: DbSlackPuppet) -> Self {
+        Self {
+            id: value.id,
+            matrix_user_id: value.matrix_user_id,
+            slack_user_id: value.slack_user_id,
+            slack_team_id: value.slack_team_id,
+            access_token: value.access_token,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = slack_puppets)]
+struct NewSlackPuppet<'a> {
+    matrix_user_id: &'a str,
+    slack_user_id: &'a str,
+    slack_team_id: &'a str,
+    access_token: &'a str,
+    created_at: &'a DateTime<Utc>,
+    updated_at: &'a DateTime<Utc>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = slack_puppets)]
+struct UpdateSlackPuppet<'a> {
+    slack_user_id: &'a str,
+    slack_team_id: &'a str,
+    access_token: &'a str,
     updated_at: &'a DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = processed_events)]
+struct DbProcessedEvent {
+    id: i64,
+    event_id: String,
+    event_type: String,
+    source: String,
+    processed_at: DateTime<Utc>,
+}
+
+impl From<DbProcessedEvent> for ProcessedEvent {
+    fn from(value: DbProcessedEvent) -> Self {
+        Self {
+            id: value.id,
+            event_id: value.event_id,
+            event_type: value.event_type,
+            source: value.source,
+            processed_at: value.processed_at,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = processed_events)]
+struct NewProcessedEvent<'a> {
+    event_id: &'a str,
+    event_type: &'a str,
+    source: &'a str,
+    processed_at: &'a DateTime<Utc>,
+}
+
 async fn with_connection<T, F>(pool: Pool, operation: F) -> Result<T, DatabaseError>
 where
     T: Send + 'static,
@@ -386,6 +525,7 @@ impl super::UserStore for PostgresUserStore {
                 slack_username: &mapping.slack_username,
                 slack_discriminator: &mapping.slack_discriminator,
                 slack_avatar: mapping.slack_avatar.as_deref(),
+                slack_avatar_hash: mapping.slack_avatar_hash.as_deref(),
                 created_at: &mapping.created_at,
                 updated_at: &mapping.updated_at,
             };
@@ -407,6 +547,7 @@ impl super::UserStore for PostgresUserStore {
                 slack_username: &mapping.slack_username,
                 slack_discriminator: &mapping.slack_discriminator,
                 slack_avatar: mapping.slack_avatar.as_deref(),
+                slack_avatar_hash: mapping.slack_avatar_hash.as_deref(),
                 updated_at: &mapping.updated_at,
             };
 
@@ -475,6 +616,26 @@ impl super::UserStore for PostgresUserStore {
         })
         .await
     }
+
+    async fn list_user_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::user_mappings::dsl::*;
+            user_mappings
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbUserMapping::as_select())
+                .load::<DbUserMapping>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
 }
 
 pub struct PostgresMessageStore {
@@ -490,6 +651,28 @@ impl PostgresMessageStore {
 #[async_trait]
 impl super::MessageStore for PostgresMessageStore {
     async fn get_by_slack_message_id(
+        &self,
+        slack_channel_id_param: &str,
+        slack_message_id_param: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        let slack_channel_id_param = slack_channel_id_param.to_string();
+        let slack_message_id_param = slack_message_id_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::message_mappings::dsl::*;
+            message_mappings
+                .filter(slack_channel_id.eq(slack_channel_id_param))
+                .filter(slack_message_id.eq(slack_message_id_param))
+                .select(DbMessageMapping::as_select())
+                .first::<DbMessageMapping>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn find_by_slack_message_id(
         &self,
         slack_message_id_param: &str,
     ) -> Result<Option<MessageMapping>, DatabaseError> {
@@ -518,6 +701,9 @@ impl super::MessageStore for PostgresMessageStore {
             use crate::db::schema::message_mappings::dsl::*;
             message_mappings
                 .filter(matrix_event_id.eq(matrix_event_id_param))
+                // A file-only message maps both its `ts` and a `file:` key to one event;
+                // the `ts` row is the one Slack API calls can use.
+                .order(slack_message_id.like("file:%"))
                 .select(DbMessageMapping::as_select())
                 .first::<DbMessageMapping>(conn)
                 .optional()
@@ -534,6 +720,7 @@ impl super::MessageStore for PostgresMessageStore {
             use crate::db::schema::message_mappings::dsl::*;
 
             let existing = message_mappings
+                .filter(slack_channel_id.eq(&mapping.slack_channel_id))
                 .filter(slack_message_id.eq(&mapping.slack_message_id))
                 .select(DbMessageMapping::as_select())
                 .first::<DbMessageMapping>(conn)
@@ -542,8 +729,10 @@ impl super::MessageStore for PostgresMessageStore {
 
             if let Some(existing) = existing {
                 let changes = UpdateMessageMapping {
+                    slack_team_id: &mapping.slack_team_id,
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    slack_thread_ts: mapping.slack_thread_ts.as_deref(),
                     updated_at: &mapping.updated_at,
                 };
                 diesel::update(message_mappings.filter(id.eq(existing.id)))
@@ -554,8 +743,11 @@ impl super::MessageStore for PostgresMessageStore {
             } else {
                 let new_mapping = NewMessageMapping {
                     slack_message_id: &mapping.slack_message_id,
+                    slack_channel_id: &mapping.slack_channel_id,
+                    slack_team_id: &mapping.slack_team_id,
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    slack_thread_ts: mapping.slack_thread_ts.as_deref(),
                     created_at: &mapping.created_at,
                     updated_at: &mapping.updated_at,
                 };
@@ -571,13 +763,19 @@ impl super::MessageStore for PostgresMessageStore {
 
     async fn delete_by_slack_message_id(
         &self,
+        slack_channel_id_param: &str,
         slack_message_id_param: &str,
     ) -> Result<(), DatabaseError> {
         let pool = self.pool.clone();
+        let slack_channel_id_param = slack_channel_id_param.to_string();
         let slack_message_id_param = slack_message_id_param.to_string();
         with_connection(pool, move |conn| {
             use crate::db::schema::message_mappings::dsl::*;
-            diesel::delete(message_mappings.filter(slack_message_id.eq(slack_message_id_param)))
+            diesel::delete(
+                message_mappings
+                    .filter(slack_channel_id.eq(slack_channel_id_param))
+                    .filter(slack_message_id.eq(slack_message_id_param)),
+            )
                 .execute(conn)
                 .map(|_| ())
                 .map_err(|e| DatabaseError::Query(e.to_string()))
@@ -600,6 +798,25 @@ impl super::MessageStore for PostgresMessageStore {
         })
         .await
     }
+    async fn list_message_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::message_mappings::dsl::*;
+            message_mappings
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbMessageMapping::as_select())
+                .load::<DbMessageMapping>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
 }
 
 pub struct PostgresEmojiStore {
@@ -821,5 +1038,459 @@ impl super::EmojiStore for PostgresEmojiStore {
         })
         .await
     }
+
+    async fn list_emojis(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<EmojiMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            diesel::sql_query(
+                "SELECT id, slack_emoji_id, emoji_name, animated, mxc_url, created_at, updated_at FROM emoji_mappings ORDER BY id LIMIT $1 OFFSET $2"
+            )
+            .bind::<diesel::sql_types::BigInt, _>(limit)
+            .bind::<diesel::sql_types::BigInt, _>(offset)
+            .load::<DbEmojiMapping>(conn)
+            .map(|rows| rows.into_iter().map(Into::into).collect())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+pub struct PostgresProcessedEventStore {
+    pool: Pool,
+}
+
+impl PostgresProcessedEventStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::ProcessedEventStore for PostgresProcessedEventStore {
+    async fn mark_event_processed(&self, event: &ProcessedEvent) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+        let event = event.clone();
+        with_connection(pool, move |conn| {
+            let new_event = NewProcessedEvent {
+                event_id: &event.event_id,
+                event_type: &event.event_type,
+                source: &event.source,
+                processed_at: &event.processed_at,
+            };
+
+            diesel::insert_into(processed_events::table)
+                .values(&new_event)
+                .on_conflict_do_nothing()
+                .execute(conn)
+                .map(|inserted| inserted > 0)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn is_event_processed(&self, event_id_param: &str) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+        let event_id_param = event_id_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::processed_events::dsl::*;
+            processed_events
+                .filter(event_id.eq(event_id_param))
+                .count()
+                .get_result::<i64>(conn)
+                .map(|count| count > 0)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn delete_processed_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::processed_events::dsl::*;
+            diesel::delete(processed_events.filter(processed_at.lt(cutoff)))
+                .execute(conn)
+                .map(|deleted| deleted as u64)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_processed_events(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ProcessedEvent>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::processed_events::dsl::*;
+            processed_events
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbProcessedEvent::as_select())
+                .load::<DbProcessedEvent>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+pub struct PostgresReactionStore {
+    pool: Pool,
+}
+
+impl PostgresReactionStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::ReactionStore for PostgresReactionStore {
+    async fn create_reaction(&self, reaction: &ReactionMapping) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let reaction = reaction.clone();
+        with_connection(pool, move |conn| {
+            let new_reaction = NewReactionMapping {
+                slack_channel_id: &reaction.slack_channel_id,
+                slack_message_ts: &reaction.slack_message_ts,
+                slack_user_id: &reaction.slack_user_id,
+                emoji: &reaction.emoji,
+                matrix_room_id: &reaction.matrix_room_id,
+                matrix_event_id: &reaction.matrix_event_id,
+                matrix_sender: &reaction.matrix_sender,
+                created_at: &reaction.created_at,
+            };
+
+            diesel::insert_into(reaction_mappings::table)
+                .values(&new_reaction)
+                .on_conflict_do_nothing()
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_by_matrix_event_id(
+        &self,
+        matrix_event_id_param: &str,
+    ) -> Result<Option<ReactionMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        let matrix_event_id_param = matrix_event_id_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::reaction_mappings::dsl::*;
+            reaction_mappings
+                .filter(matrix_event_id.eq(matrix_event_id_param))
+                .select(DbReactionMapping::as_select())
+                .first::<DbReactionMapping>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_by_slack_reaction(
+        &self,
+        slack_channel_id_param: &str,
+        slack_message_ts_param: &str,
+        slack_user_id_param: &str,
+        emoji_param: &str,
+    ) -> Result<Option<ReactionMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        let slack_channel_id_param = slack_channel_id_param.to_string();
+        let slack_message_ts_param = slack_message_ts_param.to_string();
+        let slack_user_id_param = slack_user_id_param.to_string();
+        let emoji_param = emoji_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::reaction_mappings::dsl::*;
+            reaction_mappings
+                .filter(slack_channel_id.eq(slack_channel_id_param))
+                .filter(slack_message_ts.eq(slack_message_ts_param))
+                .filter(slack_user_id.eq(slack_user_id_param))
+                .filter(emoji.eq(emoji_param))
+                .order(id.asc())
+                .select(DbReactionMapping::as_select())
+                .first::<DbReactionMapping>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn delete_by_matrix_event_id(
+        &self,
+        matrix_event_id_param: &str,
+    ) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let matrix_event_id_param = matrix_event_id_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::reaction_mappings::dsl::*;
+            diesel::delete(reaction_mappings.filter(matrix_event_id.eq(matrix_event_id_param)))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn delete_by_slack_message(
+        &self,
+        slack_channel_id_param: &str,
+        slack_message_ts_param: &str,
+    ) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let slack_channel_id_param = slack_channel_id_param.to_string();
+        let slack_message_ts_param = slack_message_ts_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::reaction_mappings::dsl::*;
+            diesel::delete(
+                reaction_mappings
+                    .filter(slack_channel_id.eq(slack_channel_id_param))
+                    .filter(slack_message_ts.eq(slack_message_ts_param)),
+            )
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_reactions(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ReactionMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::reaction_mappings::dsl::*;
+            reaction_mappings
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbReactionMapping::as_select())
+                .load::<DbReactionMapping>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+pub struct PostgresPuppetStore {
+    pool: Pool,
 }
 
+impl PostgresPuppetStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::PuppetStore for PostgresPuppetStore {
+    async fn get_puppet_by_matrix_user(
+        &self,
+        matrix_user_id_param: &str,
+    ) -> Result<Option<SlackPuppet>, DatabaseError> {
+        let pool = self.pool.clone();
+        let matrix_user_id_param = matrix_user_id_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::slack_puppets::dsl::*;
+            slack_puppets
+                .filter(matrix_user_id.eq(matrix_user_id_param))
+                .select(DbSlackPuppet::as_select())
+                .first::<DbSlackPuppet>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn upsert_puppet(&self, puppet: &SlackPuppet) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let puppet = puppet.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::slack_puppets::dsl::*;
+
+            let existing = slack_puppets
+                .filter(matrix_user_id.eq(&puppet.matrix_user_id))
+                .select(id)
+                .first::<i64>(conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            if let Some(existing_id) = existing {
+                let changes = UpdateSlackPuppet {
+                    slack_user_id: &puppet.slack_user_id,
+                    slack_team_id: &puppet.slack_team_id,
+                    access_token: &puppet.access_token,
+                    updated_at: &puppet.updated_at,
+                };
+                diesel::update(slack_puppets.filter(id.eq(existing_id)))
+                    .set(changes)
+                    .execute(conn)
+                    .map(|_| ())
+                    .map_err(|e| DatabaseError::Query(e.to_string()))
+            } else {
+                let new_puppet = NewSlackPuppet {
+                    matrix_user_id: &puppet.matrix_user_id,
+                    slack_user_id: &puppet.slack_user_id,
+                    slack_team_id: &puppet.slack_team_id,
+                    access_token: &puppet.access_token,
+                    created_at: &puppet.created_at,
+                    updated_at: &puppet.updated_at,
+                };
+                diesel::insert_into(slack_puppets)
+                    .values(new_puppet)
+                    .execute(conn)
+                    .map(|_| ())
+                    .map_err(|e| DatabaseError::Query(e.to_string()))
+            }
+        })
+        .await
+    }
+
+    async fn delete_puppet(&self, matrix_user_id_param: &str) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let matrix_user_id_param = matrix_user_id_param.to_string();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::slack_puppets::dsl::*;
+            diesel::delete(slack_puppets.filter(matrix_user_id.eq(matrix_user_id_param)))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_puppets(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SlackPuppet>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            use crate::db::schema::slack_puppets::dsl::*;
+            slack_puppets
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbSlackPuppet::as_select())
+                .load::<DbSlackPuppet>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+/// Key of the `bridge_state` row holding the reason of a manual pause.
+const PAUSE_REASON_KEY: &str = "pause_reason";
+
+#[derive(QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct ValueRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    value: String,
+}
+
+pub struct PostgresBridgeStateStore {
+    pool: Pool,
+}
+
+impl PostgresBridgeStateStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::BridgeStateStore for PostgresBridgeStateStore {
+    async fn touch_active_user(
+        &self,
+        user_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let user_id = user_id.to_string();
+        with_connection(pool, move |conn| {
+            diesel::sql_query(
+                "INSERT INTO active_users (user_id, last_active_at) VALUES ($1, $2) \
+                 ON CONFLICT (user_id) DO UPDATE SET last_active_at = $2",
+            )
+            .bind::<diesel::sql_types::Text, _>(&user_id)
+            .bind::<diesel::sql_types::Timestamptz, _>(&at)
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn count_active_users_since(&self, cutoff: DateTime<Utc>) -> Result<i64, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM active_users WHERE last_active_at >= $1",
+            )
+            .bind::<diesel::sql_types::Timestamptz, _>(&cutoff)
+            .get_result::<CountRow>(conn)
+            .map(|row| row.count)
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_pause_reason(&self) -> Result<Option<String>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            diesel::sql_query("SELECT value FROM bridge_state WHERE name = $1")
+                .bind::<diesel::sql_types::Text, _>(PAUSE_REASON_KEY)
+                .get_result::<ValueRow>(conn)
+                .optional()
+                .map(|row| row.map(|row| row.value))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn set_pause_reason(&self, reason: Option<&str>) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let reason = reason.map(ToOwned::to_owned);
+        with_connection(pool, move |conn| {
+            let query = match reason {
+                Some(reason) => diesel::sql_query(
+                    "INSERT INTO bridge_state (name, value) VALUES ($1, $2) \
+                     ON CONFLICT (name) DO UPDATE SET value = $2",
+                )
+                .bind::<diesel::sql_types::Text, _>(PAUSE_REASON_KEY)
+                .bind::<diesel::sql_types::Text, _>(reason)
+                .execute(conn),
+                None => diesel::sql_query("DELETE FROM bridge_state WHERE name = $1")
+                    .bind::<diesel::sql_types::Text, _>(PAUSE_REASON_KEY)
+                    .execute(conn),
+            };
+            query
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
@@ -18,6 +18,7 @@ diesel::table! {
         slack_username -> Text,
         slack_discriminator -> Text,
         slack_avatar -> Nullable<Text>,
+        slack_avatar_hash -> Nullable<Text>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
@@ -37,13 +38,30 @@ diesel::table! {
     message_mappings (id) {
         id -> BigInt,
         slack_message_id -> Text,
+        slack_channel_id -> Text,
+        slack_team_id -> Text,
         matrix_room_id -> Text,
         matrix_event_id -> Text,
+        slack_thread_ts -> Nullable<Text>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
 }
 
+diesel::table! {
+    reaction_mappings (id) {
+        id -> BigInt,
+        slack_channel_id -> Text,
+        slack_message_ts -> Text,
+        slack_user_id -> Text,
+        emoji -> Text,
+        matrix_room_id -> Text,
+        matrix_event_id -> Text,
+        matrix_sender -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     emoji_mappings (id) {
         id -> BigInt,
@@ -56,10 +74,24 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    slack_puppets (id) {
+        id -> BigInt,
+        matrix_user_id -> Text,
+        slack_user_id -> Text,
+        slack_team_id -> Text,
+        access_token -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     room_mappings,
     user_mappings,
     processed_events,
     message_mappings,
+    reaction_mappings,
     emoji_mappings,
+    slack_puppets,
 );
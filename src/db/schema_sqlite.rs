@@ -21,6 +21,7 @@ diesel::table! {
         slack_username -> Text,
         slack_discriminator -> Text,
         slack_avatar -> Nullable<Text>,
+        slack_avatar_hash -> Nullable<Text>,
         created_at -> Text,
         updated_at -> Text,
     }
@@ -40,13 +41,30 @@ diesel::table! {
     message_mappings (id) {
         id -> Integer,
         slack_message_id -> Text,
+        slack_channel_id -> Text,
+        slack_team_id -> Text,
         matrix_room_id -> Text,
         matrix_event_id -> Text,
+        slack_thread_ts -> Nullable<Text>,
         created_at -> Text,
         updated_at -> Text,
     }
 }
 
+diesel::table! {
+    reaction_mappings (id) {
+        id -> Integer,
+        slack_channel_id -> Text,
+        slack_message_ts -> Text,
+        slack_user_id -> Text,
+        emoji -> Text,
+        matrix_room_id -> Text,
+        matrix_event_id -> Text,
+        matrix_sender -> Text,
+        created_at -> Text,
+    }
+}
+
 diesel::table! {
     emoji_mappings (id) {
         id -> Integer,
@@ -59,10 +77,24 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    slack_puppets (id) {
+        id -> Integer,
+        matrix_user_id -> Text,
+        slack_user_id -> Text,
+        slack_team_id -> Text,
+        access_token -> Text,
+        created_at -> Text,
+        updated_at -> Text,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     room_mappings,
     user_mappings,
     processed_events,
     message_mappings,
+    reaction_mappings,
     emoji_mappings,
+    slack_puppets,
 );
@@ -2,14 +2,19 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 
 use super::DatabaseError;
 use super::models::{
-    EmojiMapping, MessageMapping, RemoteRoomInfo, RemoteUserInfo, RoomMapping, UserMapping,
+    EmojiMapping, MessageMapping, ProcessedEvent, ReactionMapping, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, SlackPuppet, UserMapping,
+};
+use crate::db::schema_sqlite::{
+    message_mappings, processed_events, reaction_mappings, room_mappings, slack_puppets,
+    user_mappings,
 };
-use crate::db::schema_sqlite::{message_mappings, room_mappings, user_mappings};
 
 // Helper function to convert DateTime to ISO string for SQLite
 fn datetime_to_string(dt: &DateTime<Utc>) -> String {
@@ -80,6 +85,7 @@ struct DbUserMapping {
     slack_username: String,
     slack_discriminator: String,
     slack_avatar: Option<String>,
+    slack_avatar_hash: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -93,6 +99,7 @@ impl DbUserMapping {
             slack_username: self.slack_username.clone(),
             slack_discriminator: self.slack_discriminator.clone(),
             slack_avatar: self.slack_avatar.clone(),
+            slack_avatar_hash: self.slack_avatar_hash.clone(),
             created_at: string_to_datetime(&self.created_at)?,
             updated_at: string_to_datetime(&self.updated_at)?,
         })
@@ -107,16 +114,18 @@ struct NewUserMapping<'a> {
     slack_username: &'a str,
     slack_discriminator: &'a str,
     slack_avatar: Option<&'a str>,
+    slack_avatar_hash: Option<&'a str>,
     created_at: String,
     updated_at: String,
 }
 
 #[derive(AsChangeset)]
-#[diesel(table_name = user_mappings)]
+#[diesel(table_name = user_mappings, treat_none_as_null = true)]
 struct UpdateUserMapping<'a> {
     slack_username: &'a str,
     slack_discriminator: &'a str,
     slack_avatar: Option<&'a str>,
+    slack_avatar_hash: Option<&'a str>,
     updated_at: String,
 }
 
@@ -125,8 +134,11 @@ struct UpdateUserMapping<'a> {
 struct DbMessageMapping {
     id: i32,
     slack_message_id: String,
+    slack_channel_id: String,
+    slack_team_id: String,
     matrix_room_id: String,
     matrix_event_id: String,
+    slack_thread_ts: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -136,8 +148,11 @@ impl DbMessageMapping {
         Ok(MessageMapping {
             id: self.id as i64,
             slack_message_id: self.slack_message_id.clone(),
+            slack_channel_id: self.slack_channel_id.clone(),
+            slack_team_id: self.slack_team_id.clone(),
             matrix_room_id: self.matrix_room_id.clone(),
             matrix_event_id: self.matrix_event_id.clone(),
+            slack_thread_ts: self.slack_thread_ts.clone(),
             created_at: string_to_datetime(&self.created_at)?,
             updated_at: string_to_datetime(&self.updated_at)?,
         })
@@ -148,8 +163,11 @@ impl DbMessageMapping {
 #[diesel(table_name = message_mappings)]
 struct NewMessageMapping<'a> {
     slack_message_id: &'a str,
+    slack_channel_id: &'a str,
+    slack_team_id: &'a str,
     matrix_room_id: &'a str,
     matrix_event_id: &'a str,
+    slack_thread_ts: Option<&'a str>,
     created_at: String,
     updated_at: String,
 }
@@ -157,13 +175,143 @@ struct NewMessageMapping<'a> {
 #[derive(AsChangeset)]
 #[diesel(table_name = message_mappings)]
 struct UpdateMessageMapping<'a> {
+    slack_team_id: &'a str,
     matrix_room_id: &'a str,
     matrix_event_id: &'a str,
+    slack_thread_ts: Option<&'a str>,
+    updated_at: String,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = reaction_mappings)]
+struct DbReactionMapping {
+    id: i32,
+    slack_channel_id: String,
+    slack_message_ts: String,
+    slack_user_id: String,
+    emoji: String,
+    matrix_room_id: String,
+    matrix_event_id: String,
+    matrix_sender: String,
+    created_at: String,
+}
+
+impl DbReactionMapping {
+    fn to_reaction_mapping(&self) -> Result<ReactionMapping, DatabaseError> {
+        Ok(ReactionMapping {
+            id: self.id as i64,
+            slack_channel_id: self.slack_channel_id.clone(),
+            slack_message_ts: self.slack_message_ts.clone(),
+            slack_user_id: self.slack_user_id.clone(),
+            emoji: self.emoji.clone(),
+            matrix_room_id: self.matrix_room_id.clone(),
+            matrix_event_id: self.matrix_event_id.clone(),
+            matrix_sender: self.matrix_sender.clone(),
+            created_at: string_to_datetime(&self.created_at)?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = reaction_mappings)]
+struct NewReactionMapping<'a> {
+    slack_channel_id: &'a str,
+    slack_message_ts: &'a str,
+    slack_user_id: &'a str,
+    emoji: &'a str,
+    matrix_room_id: &'a str,
+    matrix_event_id: &'a str,
+    matrix_sender: &'a str,
+    created_at: String,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = slack_puppets)]
+struct DbSlackPuppet {
+    id: i32,
+    matrix_user_id: String,
+    slack_user_id: String,
+    slack_team_id: String,
+    access_token: String,
+    created_at: String,
+    updated_at: String,
+}
+
+impl DbSlackPuppet {
+    fn to_slack_puppet(&self) -> Result<SlackPuppet, DatabaseError> {
+        Ok(SlackPuppet {
+            id: self.id as i64,
+            matrix_user_id: self.matrix_user_id.clone(),
+            slack_user_id: self.slack_user_id.clone(),
+            slack_team_id: self.slack_team_id.clone(),
+            access_token: self.access_token.clone(),
+            created_at: string_to_datetime(&self.created_at)?,
+            updated_at: string_to_datetime(&self.updated_at)?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = slack_puppets)]
+struct NewSlackPuppet<'a> {
+    matrix_user_id: &'a str,
+    slack_user_id: &'a str,
+    slack_team_id: &'a str,
+    access_token: &'a str,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = slack_puppets)]
+struct UpdateSlackPuppet<'a> {
+    slack_user_id: &'a str,
+    slack_team_id: &'a str,
+    access_token: &'a str,
     updated_at: String,
 }
 
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = processed_events)]
+struct DbProcessedEvent {
+    id: i32,
+    event_id: String,
+    event_type: String,
+    source: String,
+    processed_at: String,
+}
+
+impl DbProcessedEvent {
+    fn to_processed_event(&self) -> Result<ProcessedEvent, DatabaseError> {
+        Ok(ProcessedEvent {
+            id: self.id as i64,
+            event_id: self.event_id.clone(),
+            event_type: self.event_type.clone(),
+            source: self.source.clone(),
+            processed_at: string_to_datetime(&self.processed_at)?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = processed_events)]
+struct NewProcessedEvent<'a> {
+    event_id: &'a str,
+    event_type: &'a str,
+    source: &'a str,
+    processed_at: String,
+}
+
+/// Every store call opens its own connection, so concurrent writers wait on the lock
+/// instead of failing with "database is locked".
+const SQLITE_BUSY_TIMEOUT_MS: u32 = 5_000;
+
 fn establish_connection(path: &str) -> Result<SqliteConnection, DatabaseError> {
-    SqliteConnection::establish(path).map_err(|e| DatabaseError::Connection(e.to_string()))
+    let mut conn =
+        SqliteConnection::establish(path).map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    conn.batch_execute(&format!("PRAGMA busy_timeout = {SQLITE_BUSY_TIMEOUT_MS}"))
+        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    Ok(conn)
 }
 
 pub struct SqliteRoomStore {
@@ -417,6 +565,7 @@ impl super::UserStore for SqliteUserStore {
                 slack_username: &mapping.slack_username,
                 slack_discriminator: &mapping.slack_discriminator,
                 slack_avatar: mapping.slack_avatar.as_deref(),
+                slack_avatar_hash: mapping.slack_avatar_hash.as_deref(),
                 created_at: datetime_to_string(&mapping.created_at),
                 updated_at: datetime_to_string(&mapping.updated_at),
             };
@@ -440,6 +589,7 @@ impl super::UserStore for SqliteUserStore {
                 slack_username: &mapping.slack_username,
                 slack_discriminator: &mapping.slack_discriminator,
                 slack_avatar: mapping.slack_avatar.as_deref(),
+                slack_avatar_hash: mapping.slack_avatar_hash.as_deref(),
                 updated_at: datetime_to_string(&mapping.updated_at),
             };
 
@@ -517,6 +667,29 @@ impl super::UserStore for SqliteUserStore {
         .await
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
+
+    async fn list_user_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::user_mappings::dsl::*;
+            let results = user_mappings
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbUserMapping::as_select())
+                .load::<DbUserMapping>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.into_iter().map(|m| m.to_user_mapping()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
 }
 
 pub struct SqliteMessageStore {
@@ -532,6 +705,31 @@ impl SqliteMessageStore {
 #[async_trait]
 impl super::MessageStore for SqliteMessageStore {
     async fn get_by_slack_message_id(
+        &self,
+        slack_channel_id_param: &str,
+        slack_message_id_param: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError> {
+        let slack_channel_id_param = slack_channel_id_param.to_string();
+        let slack_message_id_param = slack_message_id_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::message_mappings::dsl::*;
+            message_mappings
+                .filter(slack_channel_id.eq(slack_channel_id_param))
+                .filter(slack_message_id.eq(slack_message_id_param))
+                .select(DbMessageMapping::as_select())
+                .first::<DbMessageMapping>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|m| m.to_message_mapping())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn find_by_slack_message_id(
         &self,
         slack_message_id_param: &str,
     ) -> Result<Option<MessageMapping>, DatabaseError> {
@@ -564,6 +762,9 @@ impl super::MessageStore for SqliteMessageStore {
             use crate::db::schema_sqlite::message_mappings::dsl::*;
             message_mappings
                 .filter(matrix_event_id.eq(matrix_event_id_param))
+                // A file-only message maps both its `ts` and a `file:` key to one event;
+                // the `ts` row is the one Slack API calls can use.
+                .order(slack_message_id.like("file:%"))
                 .select(DbMessageMapping::as_select())
                 .first::<DbMessageMapping>(&mut conn)
                 .optional()
@@ -583,6 +784,7 @@ impl super::MessageStore for SqliteMessageStore {
             use crate::db::schema_sqlite::message_mappings::dsl::*;
 
             let existing = message_mappings
+                .filter(slack_channel_id.eq(&mapping.slack_channel_id))
                 .filter(slack_message_id.eq(&mapping.slack_message_id))
                 .select(DbMessageMapping::as_select())
                 .first::<DbMessageMapping>(&mut conn)
@@ -591,8 +793,10 @@ impl super::MessageStore for SqliteMessageStore {
 
             if let Some(existing) = existing {
                 let changes = UpdateMessageMapping {
+                    slack_team_id: &mapping.slack_team_id,
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    slack_thread_ts: mapping.slack_thread_ts.as_deref(),
                     updated_at: datetime_to_string(&mapping.updated_at),
                 };
 
@@ -604,8 +808,11 @@ impl super::MessageStore for SqliteMessageStore {
             } else {
                 let new_mapping = NewMessageMapping {
                     slack_message_id: &mapping.slack_message_id,
+                    slack_channel_id: &mapping.slack_channel_id,
+                    slack_team_id: &mapping.slack_team_id,
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    slack_thread_ts: mapping.slack_thread_ts.as_deref(),
                     created_at: datetime_to_string(&mapping.created_at),
                     updated_at: datetime_to_string(&mapping.updated_at),
                 };
@@ -623,14 +830,20 @@ impl super::MessageStore for SqliteMessageStore {
 
     async fn delete_by_slack_message_id(
         &self,
+        slack_channel_id_param: &str,
         slack_message_id_param: &str,
     ) -> Result<(), DatabaseError> {
+        let slack_channel_id_param = slack_channel_id_param.to_string();
         let slack_message_id_param = slack_message_id_param.to_string();
         let db_path = self.db_path.clone();
         tokio::task::spawn_blocking(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::message_mappings::dsl::*;
-            diesel::delete(message_mappings.filter(slack_message_id.eq(slack_message_id_param)))
+            diesel::delete(
+                message_mappings
+                    .filter(slack_channel_id.eq(slack_channel_id_param))
+                    .filter(slack_message_id.eq(slack_message_id_param)),
+            )
                 .execute(&mut conn)
                 .map(|_| ())
                 .map_err(|e| DatabaseError::Query(e.to_string()))
@@ -656,6 +869,28 @@ impl super::MessageStore for SqliteMessageStore {
         .await
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
+    async fn list_message_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::message_mappings::dsl::*;
+            let results = message_mappings
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbMessageMapping::as_select())
+                .load::<DbMessageMapping>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.into_iter().map(|m| m.to_message_mapping()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
 }
 
 pub struct SqliteEmojiStore {
@@ -891,5 +1126,505 @@ impl super::EmojiStore for SqliteEmojiStore {
         .await
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
+
+    async fn list_emojis(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<EmojiMapping>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::sql_query(
+                "SELECT id, slack_emoji_id, emoji_name, animated, mxc_url, created_at, updated_at FROM emoji_mappings ORDER BY id LIMIT ? OFFSET ?"
+            )
+            .bind::<diesel::sql_types::BigInt, _>(limit)
+            .bind::<diesel::sql_types::BigInt, _>(offset)
+            .load::<DbEmojiMapping>(&mut conn)
+            .map_err(|e| DatabaseError::Query(e.to_string()))?
+            .into_iter()
+            .map(|m| m.to_emoji_mapping())
+            .collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+pub struct SqliteProcessedEventStore {
+    db_path: Arc<String>,
+}
+
+impl SqliteProcessedEventStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::ProcessedEventStore for SqliteProcessedEventStore {
+    async fn mark_event_processed(&self, event: &ProcessedEvent) -> Result<bool, DatabaseError> {
+        let event = event.clone();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let new_event = NewProcessedEvent {
+                event_id: &event.event_id,
+                event_type: &event.event_type,
+                source: &event.source,
+                processed_at: datetime_to_string(&event.processed_at),
+            };
+
+            diesel::insert_or_ignore_into(processed_events::table)
+                .values(&new_event)
+                .execute(&mut conn)
+                .map(|inserted| inserted > 0)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn is_event_processed(&self, event_id_param: &str) -> Result<bool, DatabaseError> {
+        let event_id_param = event_id_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::processed_events::dsl::*;
+            processed_events
+                .filter(event_id.eq(event_id_param))
+                .count()
+                .get_result::<i64>(&mut conn)
+                .map(|count| count > 0)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_processed_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let cutoff = datetime_to_string(&cutoff);
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::processed_events::dsl::*;
+            diesel::delete(processed_events.filter(processed_at.lt(cutoff)))
+                .execute(&mut conn)
+                .map(|deleted| deleted as u64)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn list_processed_events(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ProcessedEvent>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::processed_events::dsl::*;
+            let results = processed_events
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbProcessedEvent::as_select())
+                .load::<DbProcessedEvent>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.into_iter().map(|e| e.to_processed_event()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
 }
 
+pub struct SqliteReactionStore {
+    db_path: Arc<String>,
+}
+
+impl SqliteReactionStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::ReactionStore for SqliteReactionStore {
+    async fn create_reaction(&self, reaction: &ReactionMapping) -> Result<(), DatabaseError> {
+        let reaction = reaction.clone();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let new_reaction = NewReactionMapping {
+                slack_channel_id: &reaction.slack_channel_id,
+                slack_message_ts: &reaction.slack_message_ts,
+                slack_user_id: &reaction.slack_user_id,
+                emoji: &reaction.emoji,
+                matrix_room_id: &reaction.matrix_room_id,
+                matrix_event_id: &reaction.matrix_event_id,
+                matrix_sender: &reaction.matrix_sender,
+                created_at: datetime_to_string(&reaction.created_at),
+            };
+
+            diesel::insert_or_ignore_into(reaction_mappings::table)
+                .values(&new_reaction)
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn get_by_matrix_event_id(
+        &self,
+        matrix_event_id_param: &str,
+    ) -> Result<Option<ReactionMapping>, DatabaseError> {
+        let matrix_event_id_param = matrix_event_id_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::reaction_mappings::dsl::*;
+            reaction_mappings
+                .filter(matrix_event_id.eq(matrix_event_id_param))
+                .select(DbReactionMapping::as_select())
+                .first::<DbReactionMapping>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|r| r.to_reaction_mapping())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn get_by_slack_reaction(
+        &self,
+        slack_channel_id_param: &str,
+        slack_message_ts_param: &str,
+        slack_user_id_param: &str,
+        emoji_param: &str,
+    ) -> Result<Option<ReactionMapping>, DatabaseError> {
+        let slack_channel_id_param = slack_channel_id_param.to_string();
+        let slack_message_ts_param = slack_message_ts_param.to_string();
+        let slack_user_id_param = slack_user_id_param.to_string();
+        let emoji_param = emoji_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::reaction_mappings::dsl::*;
+            reaction_mappings
+                .filter(slack_channel_id.eq(slack_channel_id_param))
+                .filter(slack_message_ts.eq(slack_message_ts_param))
+                .filter(slack_user_id.eq(slack_user_id_param))
+                .filter(emoji.eq(emoji_param))
+                .order(id.asc())
+                .select(DbReactionMapping::as_select())
+                .first::<DbReactionMapping>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|r| r.to_reaction_mapping())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_by_matrix_event_id(
+        &self,
+        matrix_event_id_param: &str,
+    ) -> Result<(), DatabaseError> {
+        let matrix_event_id_param = matrix_event_id_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::reaction_mappings::dsl::*;
+            diesel::delete(reaction_mappings.filter(matrix_event_id.eq(matrix_event_id_param)))
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_by_slack_message(
+        &self,
+        slack_channel_id_param: &str,
+        slack_message_ts_param: &str,
+    ) -> Result<(), DatabaseError> {
+        let slack_channel_id_param = slack_channel_id_param.to_string();
+        let slack_message_ts_param = slack_message_ts_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::reaction_mappings::dsl::*;
+            diesel::delete(
+                reaction_mappings
+                    .filter(slack_channel_id.eq(slack_channel_id_param))
+                    .filter(slack_message_ts.eq(slack_message_ts_param)),
+            )
+            .execute(&mut conn)
+            .map(|_| ())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn list_reactions(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ReactionMapping>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::reaction_mappings::dsl::*;
+            let results = reaction_mappings
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbReactionMapping::as_select())
+                .load::<DbReactionMapping>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.into_iter().map(|r| r.to_reaction_mapping()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+pub struct SqlitePuppetStore {
+    db_path: Arc<String>,
+}
+
+impl SqlitePuppetStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::PuppetStore for SqlitePuppetStore {
+    async fn get_puppet_by_matrix_user(
+        &self,
+        matrix_user_id_param: &str,
+    ) -> Result<Option<SlackPuppet>, DatabaseError> {
+        let matrix_user_id_param = matrix_user_id_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::slack_puppets::dsl::*;
+            slack_puppets
+                .filter(matrix_user_id.eq(matrix_user_id_param))
+                .select(DbSlackPuppet::as_select())
+                .first::<DbSlackPuppet>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|p| p.to_slack_puppet())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn upsert_puppet(&self, puppet: &SlackPuppet) -> Result<(), DatabaseError> {
+        let puppet = puppet.clone();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::slack_puppets::dsl::*;
+
+            let existing = slack_puppets
+                .filter(matrix_user_id.eq(&puppet.matrix_user_id))
+                .select(id)
+                .first::<i32>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            if let Some(existing_id) = existing {
+                let changes = UpdateSlackPuppet {
+                    slack_user_id: &puppet.slack_user_id,
+                    slack_team_id: &puppet.slack_team_id,
+                    access_token: &puppet.access_token,
+                    updated_at: datetime_to_string(&puppet.updated_at),
+                };
+                diesel::update(slack_puppets.filter(id.eq(existing_id)))
+                    .set(changes)
+                    .execute(&mut conn)
+                    .map(|_| ())
+                    .map_err(|e| DatabaseError::Query(e.to_string()))
+            } else {
+                let new_puppet = NewSlackPuppet {
+                    matrix_user_id: &puppet.matrix_user_id,
+                    slack_user_id: &puppet.slack_user_id,
+                    slack_team_id: &puppet.slack_team_id,
+                    access_token: &puppet.access_token,
+                    created_at: datetime_to_string(&puppet.created_at),
+                    updated_at: datetime_to_string(&puppet.updated_at),
+                };
+                diesel::insert_into(slack_puppets)
+                    .values(new_puppet)
+                    .execute(&mut conn)
+                    .map(|_| ())
+                    .map_err(|e| DatabaseError::Query(e.to_string()))
+            }
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_puppet(&self, matrix_user_id_param: &str) -> Result<(), DatabaseError> {
+        let matrix_user_id_param = matrix_user_id_param.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::slack_puppets::dsl::*;
+            diesel::delete(slack_puppets.filter(matrix_user_id.eq(matrix_user_id_param)))
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn list_puppets(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SlackPuppet>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::slack_puppets::dsl::*;
+            let results = slack_puppets
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbSlackPuppet::as_select())
+                .load::<DbSlackPuppet>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.into_iter().map(|p| p.to_slack_puppet()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+/// Key of the `bridge_state` row holding the reason of a manual pause.
+const PAUSE_REASON_KEY: &str = "pause_reason";
+
+#[derive(QueryableByName)]
+struct CountRow {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    count: i64,
+}
+
+#[derive(QueryableByName)]
+struct ValueRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    value: String,
+}
+
+pub struct SqliteBridgeStateStore {
+    db_path: Arc<String>,
+}
+
+impl SqliteBridgeStateStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::BridgeStateStore for SqliteBridgeStateStore {
+    async fn touch_active_user(
+        &self,
+        user_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        let user_id = user_id.to_string();
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::sql_query(
+                "INSERT INTO active_users (user_id, last_active_at) VALUES (?, ?) \
+                 ON CONFLICT (user_id) DO UPDATE SET last_active_at = excluded.last_active_at",
+            )
+            .bind::<diesel::sql_types::Text, _>(&user_id)
+            .bind::<diesel::sql_types::Text, _>(datetime_to_string(&at))
+            .execute(&mut conn)
+            .map(|_| ())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn count_active_users_since(&self, cutoff: DateTime<Utc>) -> Result<i64, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            // Timestamps are stored as RFC 3339 in UTC, which sorts chronologically.
+            diesel::sql_query(
+                "SELECT COUNT(*) AS count FROM active_users WHERE last_active_at >= ?",
+            )
+            .bind::<diesel::sql_types::Text, _>(datetime_to_string(&cutoff))
+            .get_result::<CountRow>(&mut conn)
+            .map(|row| row.count)
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn get_pause_reason(&self) -> Result<Option<String>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::sql_query("SELECT value FROM bridge_state WHERE name = ?")
+                .bind::<diesel::sql_types::Text, _>(PAUSE_REASON_KEY)
+                .get_result::<ValueRow>(&mut conn)
+                .optional()
+                .map(|row| row.map(|row| row.value))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn set_pause_reason(&self, reason: Option<&str>) -> Result<(), DatabaseError> {
+        let reason = reason.map(ToOwned::to_owned);
+        let db_path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let query = match reason {
+                Some(reason) => diesel::sql_query(
+                    "INSERT INTO bridge_state (name, value) VALUES (?, ?) \
+                     ON CONFLICT (name) DO UPDATE SET value = excluded.value",
+                )
+                .bind::<diesel::sql_types::Text, _>(PAUSE_REASON_KEY)
+                .bind::<diesel::sql_types::Text, _>(reason)
+                .execute(&mut conn),
+                None => diesel::sql_query("DELETE FROM bridge_state WHERE name = ?")
+                    .bind::<diesel::sql_types::Text, _>(PAUSE_REASON_KEY)
+                    .execute(&mut conn),
+            };
+            query
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
@@ -1,8 +1,10 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
 use super::DatabaseError;
 use super::models::{
-    EmojiMapping, MessageMapping, RemoteRoomInfo, RemoteUserInfo, RoomMapping, UserMapping,
+    EmojiMapping, MessageMapping, ProcessedEvent, ReactionMapping, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, SlackPuppet, UserMapping,
 };
 
 #[async_trait]
@@ -60,14 +62,27 @@ pub trait UserStore: Send + Sync {
         info: &RemoteUserInfo,
     ) -> Result<(), DatabaseError>;
     async fn get_all_user_ids(&self) -> Result<Vec<String>, DatabaseError>;
+    async fn list_user_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError>;
 }
 
 #[async_trait]
 pub trait MessageStore: Send + Sync {
     async fn get_by_slack_message_id(
+        &self,
+        slack_channel_id: &str,
+        slack_message_id: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError>;
+    /// Looks a mapping up without a channel, for ids that are unique across the workspace
+    /// such as file mapping keys.
+    async fn find_by_slack_message_id(
         &self,
         slack_message_id: &str,
     ) -> Result<Option<MessageMapping>, DatabaseError>;
+    /// Prefers the row keyed by the message `ts` over file mapping keys of the same event.
     async fn get_by_matrix_event_id(
         &self,
         matrix_event_id: &str,
@@ -75,9 +90,15 @@ pub trait MessageStore: Send + Sync {
     async fn upsert_message_mapping(&self, mapping: &MessageMapping) -> Result<(), DatabaseError>;
     async fn delete_by_slack_message_id(
         &self,
+        slack_channel_id: &str,
         slack_message_id: &str,
     ) -> Result<(), DatabaseError>;
     async fn delete_by_matrix_event_id(&self, matrix_event_id: &str) -> Result<(), DatabaseError>;
+    async fn list_message_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError>;
 }
 
 #[async_trait]
@@ -104,5 +125,87 @@ pub trait EmojiStore: Send + Sync {
         old_name: &str,
         new_name: &str,
     ) -> Result<(), DatabaseError>;
+    async fn list_emojis(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<EmojiMapping>, DatabaseError>;
 }
 
+#[async_trait]
+pub trait ReactionStore: Send + Sync {
+    async fn create_reaction(&self, reaction: &ReactionMapping) -> Result<(), DatabaseError>;
+    async fn get_by_matrix_event_id(
+        &self,
+        matrix_event_id: &str,
+    ) -> Result<Option<ReactionMapping>, DatabaseError>;
+    async fn get_by_slack_reaction(
+        &self,
+        slack_channel_id: &str,
+        slack_message_ts: &str,
+        slack_user_id: &str,
+        emoji: &str,
+    ) -> Result<Option<ReactionMapping>, DatabaseError>;
+    async fn delete_by_matrix_event_id(&self, matrix_event_id: &str) -> Result<(), DatabaseError>;
+    /// Drops every reaction on a message, used once the message itself is deleted.
+    async fn delete_by_slack_message(
+        &self,
+        slack_channel_id: &str,
+        slack_message_ts: &str,
+    ) -> Result<(), DatabaseError>;
+    async fn list_reactions(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ReactionMapping>, DatabaseError>;
+}
+
+#[async_trait]
+pub trait PuppetStore: Send + Sync {
+    async fn get_puppet_by_matrix_user(
+        &self,
+        matrix_user_id: &str,
+    ) -> Result<Option<SlackPuppet>, DatabaseError>;
+    /// Stores the puppet, replacing the login of the same Matrix user.
+    async fn upsert_puppet(&self, puppet: &SlackPuppet) -> Result<(), DatabaseError>;
+    async fn delete_puppet(&self, matrix_user_id: &str) -> Result<(), DatabaseError>;
+    async fn list_puppets(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SlackPuppet>, DatabaseError>;
+}
+
+#[async_trait]
+pub trait ProcessedEventStore: Send + Sync {
+    /// Records the event, returning `false` if its `event_id` was already stored.
+    async fn mark_event_processed(&self, event: &ProcessedEvent) -> Result<bool, DatabaseError>;
+    async fn is_event_processed(&self, event_id: &str) -> Result<bool, DatabaseError>;
+    /// Removes events processed before `cutoff`, returning how many rows were deleted.
+    async fn delete_processed_events_before(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError>;
+    async fn list_processed_events(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ProcessedEvent>, DatabaseError>;
+}
+
+
+/// Bridge-wide state that has to survive restarts: when users last sent a message and
+/// whether an admin paused the bridge.
+#[async_trait]
+pub trait BridgeStateStore: Send + Sync {
+    /// Records that the Matrix or Slack user `user_id` sent a message at `at`.
+    async fn touch_active_user(
+        &self,
+        user_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<(), DatabaseError>;
+    async fn count_active_users_since(&self, cutoff: DateTime<Utc>) -> Result<i64, DatabaseError>;
+    async fn get_pause_reason(&self) -> Result<Option<String>, DatabaseError>;
+    /// Pauses the bridge with `reason`, or lifts the pause with `None`.
+    async fn set_pause_reason(&self, reason: Option<&str>) -> Result<(), DatabaseError>;
+}
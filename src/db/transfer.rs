@@ -0,0 +1,472 @@
+//! Copies bridge data between database backends through the store traits, so any
+//! combination of SQLite, PostgreSQL and MySQL works as source or destination.
+
+use tracing::info;
+
+use super::{DatabaseError, DatabaseManager};
+
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableCount {
+    pub read: u64,
+    pub copied: u64,
+    pub skipped: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferReport {
+    pub room_mappings: TableCount,
+    pub user_mappings: TableCount,
+    pub message_mappings: TableCount,
+    pub emoji_mappings: TableCount,
+    pub processed_events: TableCount,
+    pub reaction_mappings: TableCount,
+    pub slack_puppets: TableCount,
+}
+
+impl TransferReport {
+    pub fn tables(&self) -> [(&'static str, TableCount); 7] {
+        [
+            ("room_mappings", self.room_mappings),
+            ("user_mappings", self.user_mappings),
+            ("message_mappings", self.message_mappings),
+            ("emoji_mappings", self.emoji_mappings),
+            ("processed_events", self.processed_events),
+            ("reaction_mappings", self.reaction_mappings),
+            ("slack_puppets", self.slack_puppets),
+        ]
+    }
+}
+
+/// Copies every table from `source` into `destination`. Rows that already exist in the
+/// destination are skipped, so an interrupted copy can be re-run. A dry run only reads,
+/// counting the rows it would copy or skip; without a destination, as when the dry run
+/// targets a database that does not exist yet, every row counts as copied.
+pub async fn copy_database(
+    source: &DatabaseManager,
+    destination: Option<&DatabaseManager>,
+    dry_run: bool,
+) -> Result<TransferReport, DatabaseError> {
+    let mut report = TransferReport::default();
+
+    let source_rooms = source.room_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_rooms.list_room_mappings(BATCH_SIZE, offset).await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for mapping in &batch {
+            report.room_mappings.read += 1;
+            let Some(destination) = destination else {
+                report.room_mappings.copied += 1;
+                continue;
+            };
+            let rooms = destination.room_store();
+            if rooms
+                .get_room_by_matrix_room(&mapping.matrix_room_id)
+                .await?
+                .is_some()
+                || rooms
+                    .get_room_by_slack_channel(&mapping.slack_channel_id)
+                    .await?
+                    .is_some()
+            {
+                report.room_mappings.skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                rooms.create_room_mapping(mapping).await?;
+            }
+            report.room_mappings.copied += 1;
+        }
+    }
+    info!("room_mappings: {:?}", report.room_mappings);
+
+    let source_users = source.user_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_users.list_user_mappings(BATCH_SIZE, offset).await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for mapping in &batch {
+            report.user_mappings.read += 1;
+            let Some(destination) = destination else {
+                report.user_mappings.copied += 1;
+                continue;
+            };
+            let users = destination.user_store();
+            if users
+                .get_user_by_slack_id(&mapping.slack_user_id)
+                .await?
+                .is_some()
+                || users
+                    .get_user_by_matrix_id(&mapping.matrix_user_id)
+                    .await?
+                    .is_some()
+            {
+                report.user_mappings.skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                users.create_user_mapping(mapping).await?;
+            }
+            report.user_mappings.copied += 1;
+        }
+    }
+    info!("user_mappings: {:?}", report.user_mappings);
+
+    let source_messages = source.message_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_messages
+            .list_message_mappings(BATCH_SIZE, offset)
+            .await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for mapping in &batch {
+            report.message_mappings.read += 1;
+            let Some(destination) = destination else {
+                report.message_mappings.copied += 1;
+                continue;
+            };
+            let messages = destination.message_store();
+            if messages
+                .get_by_slack_message_id(&mapping.slack_channel_id, &mapping.slack_message_id)
+                .await?
+                .is_some()
+            {
+                report.message_mappings.skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                messages.upsert_message_mapping(mapping).await?;
+            }
+            report.message_mappings.copied += 1;
+        }
+    }
+    info!("message_mappings: {:?}", report.message_mappings);
+
+    let source_emojis = source.emoji_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_emojis.list_emojis(BATCH_SIZE, offset).await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for emoji in &batch {
+            report.emoji_mappings.read += 1;
+            let Some(destination) = destination else {
+                report.emoji_mappings.copied += 1;
+                continue;
+            };
+            let emojis = destination.emoji_store();
+            if emojis
+                .get_emoji_by_slack_id(&emoji.slack_emoji_id)
+                .await?
+                .is_some()
+            {
+                report.emoji_mappings.skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                emojis.create_emoji(emoji).await?;
+            }
+            report.emoji_mappings.copied += 1;
+        }
+    }
+    info!("emoji_mappings: {:?}", report.emoji_mappings);
+
+    let source_events = source.processed_event_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_events
+            .list_processed_events(BATCH_SIZE, offset)
+            .await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for event in &batch {
+            report.processed_events.read += 1;
+            let Some(destination) = destination else {
+                report.processed_events.copied += 1;
+                continue;
+            };
+            let events = destination.processed_event_store();
+            let new = if dry_run {
+                !events.is_event_processed(&event.event_id).await?
+            } else {
+                events.mark_event_processed(event).await?
+            };
+            if new {
+                report.processed_events.copied += 1;
+            } else {
+                report.processed_events.skipped += 1;
+            }
+        }
+    }
+    info!("processed_events: {:?}", report.processed_events);
+
+    let source_reactions = source.reaction_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_reactions.list_reactions(BATCH_SIZE, offset).await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for reaction in &batch {
+            report.reaction_mappings.read += 1;
+            let Some(destination) = destination else {
+                report.reaction_mappings.copied += 1;
+                continue;
+            };
+            let reactions = destination.reaction_store();
+            if reactions
+                .get_by_matrix_event_id(&reaction.matrix_event_id)
+                .await?
+                .is_some()
+            {
+                report.reaction_mappings.skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                reactions.create_reaction(reaction).await?;
+            }
+            report.reaction_mappings.copied += 1;
+        }
+    }
+    info!("reaction_mappings: {:?}", report.reaction_mappings);
+
+    let source_puppets = source.puppet_store();
+    let mut offset = 0;
+    loop {
+        let batch = source_puppets.list_puppets(BATCH_SIZE, offset).await?;
+        if batch.is_empty() {
+            break;
+        }
+        offset += batch.len() as i64;
+        for puppet in &batch {
+            report.slack_puppets.read += 1;
+            let Some(destination) = destination else {
+                report.slack_puppets.copied += 1;
+                continue;
+            };
+            let puppets = destination.puppet_store();
+            if puppets
+                .get_puppet_by_matrix_user(&puppet.matrix_user_id)
+                .await?
+                .is_some()
+            {
+                report.slack_puppets.skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                puppets.upsert_puppet(puppet).await?;
+            }
+            report.slack_puppets.copied += 1;
+        }
+    }
+    info!("slack_puppets: {:?}", report.slack_puppets);
+
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::config::DatabaseConfig;
+    use crate::db::{
+        EmojiMapping, MessageMapping, ProcessedEvent, ReactionMapping, RoomMapping, SlackPuppet,
+        UserMapping,
+    };
+
+    async fn sqlite_manager(dir: &tempfile::TempDir, name: &str) -> DatabaseManager {
+        let path = dir.path().join(name);
+        let config = DatabaseConfig::from_url(format!("sqlite://{}", path.display()));
+        let manager = DatabaseManager::new(&config).await.unwrap();
+        manager.migrate().await.unwrap();
+        manager
+    }
+
+    async fn seed(manager: &DatabaseManager) {
+        let now = Utc::now();
+        manager
+            .room_store()
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: "!room:example.org".to_string(),
+                slack_channel_id: "C123".to_string(),
+                slack_channel_name: "general".to_string(),
+                slack_team_id: "T123".to_string(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        manager
+            .user_store()
+            .create_user_mapping(&UserMapping {
+                id: 0,
+                matrix_user_id: "@_slack_U123:example.org".to_string(),
+                slack_user_id: "U123".to_string(),
+                slack_username: "alice".to_string(),
+                slack_discriminator: String::new(),
+                slack_avatar: None,
+                slack_avatar_hash: None,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        manager
+            .message_store()
+            .upsert_message_mapping(&MessageMapping {
+                id: 0,
+                slack_message_id: "1700000000.000100".to_string(),
+                slack_channel_id: "C123".to_string(),
+                slack_team_id: "T123".to_string(),
+                matrix_room_id: "!room:example.org".to_string(),
+                matrix_event_id: "$event".to_string(),
+                slack_thread_ts: Some("1700000000.000050".to_string()),
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        manager
+            .emoji_store()
+            .create_emoji(&EmojiMapping::new(
+                "partyparrot".to_string(),
+                "partyparrot".to_string(),
+                true,
+                "mxc://example.org/parrot".to_string(),
+            ))
+            .await
+            .unwrap();
+        manager
+            .processed_event_store()
+            .mark_event_processed(&ProcessedEvent {
+                id: 0,
+                event_id: "$event".to_string(),
+                event_type: "m.room.message".to_string(),
+                source: "matrix".to_string(),
+                processed_at: now,
+            })
+            .await
+            .unwrap();
+        manager
+            .reaction_store()
+            .create_reaction(&ReactionMapping {
+                id: 0,
+                slack_channel_id: "C123".to_string(),
+                slack_message_ts: "1700000000.000100".to_string(),
+                slack_user_id: "U123".to_string(),
+                emoji: "thumbsup".to_string(),
+                matrix_room_id: "!room:example.org".to_string(),
+                matrix_event_id: "$reaction".to_string(),
+                matrix_sender: "@_slack_U123:example.org".to_string(),
+                created_at: now,
+            })
+            .await
+            .unwrap();
+        manager
+            .puppet_store()
+            .upsert_puppet(&SlackPuppet {
+                id: 0,
+                matrix_user_id: "@alice:example.org".to_string(),
+                slack_user_id: "U456".to_string(),
+                slack_team_id: "T123".to_string(),
+                access_token: "sealed-token".to_string(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run_counts_rows_against_the_destination_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = sqlite_manager(&dir, "source.db").await;
+        let destination = sqlite_manager(&dir, "destination.db").await;
+        seed(&source).await;
+
+        let without_destination = copy_database(&source, None, true).await.unwrap();
+        let empty_destination = copy_database(&source, Some(&destination), true).await.unwrap();
+        for report in [without_destination, empty_destination] {
+            for (table, count) in report.tables() {
+                assert_eq!(
+                    count,
+                    TableCount {
+                        read: 1,
+                        copied: 1,
+                        skipped: 0
+                    },
+                    "{table}"
+                );
+            }
+        }
+        assert_eq!(destination.room_store().count_rooms().await.unwrap(), 0);
+        assert!(
+            !destination
+                .processed_event_store()
+                .is_event_processed("$event")
+                .await
+                .unwrap()
+        );
+
+        copy_database(&source, Some(&destination), false).await.unwrap();
+        let rerun = copy_database(&source, Some(&destination), true).await.unwrap();
+        for (table, count) in rerun.tables() {
+            assert_eq!(count.skipped, 1, "{table}");
+            assert_eq!(count.copied, 0, "{table}");
+        }
+    }
+
+    #[tokio::test]
+    async fn copies_all_tables_and_skips_existing_rows_on_rerun() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = sqlite_manager(&dir, "source.db").await;
+        let destination = sqlite_manager(&dir, "destination.db").await;
+        seed(&source).await;
+
+        let first = copy_database(&source, Some(&destination), false).await.unwrap();
+        for (table, count) in first.tables() {
+            assert_eq!(count.copied, 1, "{table}");
+        }
+
+        let message = destination
+            .message_store()
+            .get_by_slack_message_id("C123", "1700000000.000100")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.matrix_event_id, "$event");
+        assert_eq!(message.slack_thread_ts.as_deref(), Some("1700000000.000050"));
+
+        let reaction = destination
+            .reaction_store()
+            .get_by_slack_reaction("C123", "1700000000.000100", "U123", "thumbsup")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reaction.matrix_event_id, "$reaction");
+
+        let second = copy_database(&source, Some(&destination), false).await.unwrap();
+        for (table, count) in second.tables() {
+            assert_eq!(count.skipped, 1, "{table}");
+            assert_eq!(count.copied, 0, "{table}");
+        }
+    }
+}
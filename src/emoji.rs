@@ -1,11 +1,86 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Result;
+use once_cell::sync::Lazy;
 use tracing::{debug, info, warn};
 
 use crate::db::{DatabaseManager, EmojiMapping};
 use crate::media::MediaHandler;
 
+mod shortcodes;
+
+const VARIATION_SELECTOR: char = '\u{fe0f}';
+const SKIN_TONE_SUFFIX: &str = "::skin-tone-";
+/// Fitzpatrick modifiers, indexed by Slack's `skin-tone-2` .. `skin-tone-6`.
+const SKIN_TONES: [char; 5] = ['\u{1f3fb}', '\u{1f3fc}', '\u{1f3fd}', '\u{1f3fe}', '\u{1f3ff}'];
+
+static SHORTCODE_TO_UNICODE: Lazy<HashMap<&'static str, &'static str>> =
+    Lazy::new(|| shortcodes::SHORTCODES.iter().copied().collect());
+
+static UNICODE_TO_SHORTCODE: Lazy<HashMap<String, &'static str>> = Lazy::new(|| {
+    let mut map = HashMap::new();
+    for (name, emoji) in shortcodes::SHORTCODES {
+        map.entry(strip_variation_selectors(emoji)).or_insert(*name);
+    }
+    map
+});
+
+/// Converts a standard Slack shortcode (`thumbsup`, `:+1:`, `+1::skin-tone-3`) to its
+/// Unicode emoji. Returns `None` for names that are not in the bundled table, which
+/// usually means a custom workspace emoji.
+pub fn shortcode_to_unicode(name: &str) -> Option<String> {
+    let name = name.trim_matches(':');
+    let (base, tone) = match name.split_once(SKIN_TONE_SUFFIX) {
+        Some((base, tone)) => {
+            let index = tone.parse::<usize>().ok()?.checked_sub(2)?;
+            (base, Some(*SKIN_TONES.get(index)?))
+        }
+        None => (name, None),
+    };
+
+    let emoji = SHORTCODE_TO_UNICODE.get(base)?;
+    Some(match tone {
+        Some(tone) => apply_skin_tone(emoji, tone),
+        None => (*emoji).to_string(),
+    })
+}
+
+/// Converts a Unicode emoji to the Slack shortcode used for reactions, appending
+/// `::skin-tone-N` when the emoji carries a skin tone modifier.
+pub fn unicode_to_shortcode(emoji: &str) -> Option<String> {
+    let normalized = strip_variation_selectors(emoji);
+    if let Some(name) = UNICODE_TO_SHORTCODE.get(&normalized) {
+        return Some((*name).to_string());
+    }
+
+    let tone = SKIN_TONES.iter().position(|tone| normalized.contains(*tone))?;
+    let base: String = normalized
+        .chars()
+        .filter(|c| !SKIN_TONES.contains(c))
+        .collect();
+    let name = UNICODE_TO_SHORTCODE.get(&base)?;
+    Some(format!("{name}{SKIN_TONE_SUFFIX}{}", tone + 2))
+}
+
+fn strip_variation_selectors(emoji: &str) -> String {
+    emoji.chars().filter(|c| *c != VARIATION_SELECTOR).collect()
+}
+
+/// Inserts the modifier after the base character, dropping the variation selector it
+/// replaces (`✌️` becomes `✌🏽`, `🤷‍♂️` becomes `🤷🏽‍♂️`).
+fn apply_skin_tone(emoji: &str, tone: char) -> String {
+    let mut chars = emoji.chars();
+    let mut output = String::with_capacity(emoji.len() + tone.len_utf8());
+    if let Some(first) = chars.next() {
+        output.push(first);
+        output.push(tone);
+    }
+    let rest = chars.as_str();
+    output.push_str(rest.strip_prefix(VARIATION_SELECTOR).unwrap_or(rest));
+    output
+}
+
 pub struct EmojiHandler {
     db: Arc<DatabaseManager>,
     media_handler: Arc<MediaHandler>,
@@ -115,6 +190,33 @@ impl EmojiHandler {
             .map(|e| e.mxc_url))
     }
 
+    pub async fn get_emoji_mxc_by_name(&self, emoji_name: &str) -> Result<Option<String>> {
+        Ok(self
+            .db
+            .emoji_store()
+            .get_emoji_by_name(emoji_name)
+            .await?
+            .map(|e| e.mxc_url))
+    }
+
+    pub async fn get_emoji_name_by_mxc(&self, mxc_url: &str) -> Result<Option<String>> {
+        Ok(self
+            .db
+            .emoji_store()
+            .get_emoji_by_mxc(mxc_url)
+            .await?
+            .map(|e| e.emoji_name))
+    }
+
+    /// Downloads a custom workspace emoji from `url`, uploads it to Matrix and caches the
+    /// mapping under `name`.
+    pub async fn cache_custom_emoji(&self, name: &str, url: &str) -> Result<String> {
+        self.download_and_cache_emoji(name, url).await?;
+        self.get_emoji_mxc_by_name(name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("emoji {} missing after caching", name))
+    }
+
     pub async fn delete_emoji(&self, emoji_id: &str) -> Result<()> {
         self.db.emoji_store().delete_emoji(emoji_id).await?;
         info!("Deleted emoji cache for {}", emoji_id);
@@ -265,5 +367,52 @@ mod tests {
         let plain = handler.emoji_to_matrix_plain("smile");
         assert_eq!(plain, ":smile:");
     }
+
+    #[test]
+    fn shortcode_to_unicode_resolves_names_and_aliases() {
+        assert_eq!(shortcode_to_unicode("+1").as_deref(), Some("\u{1f44d}"));
+        assert_eq!(shortcode_to_unicode("thumbsup").as_deref(), Some("\u{1f44d}"));
+        assert_eq!(shortcode_to_unicode(":heart:").as_deref(), Some("\u{2764}\u{fe0f}"));
+        assert_eq!(shortcode_to_unicode("flag-us").as_deref(), Some("\u{1f1fa}\u{1f1f8}"));
+        assert_eq!(shortcode_to_unicode("partyparrot"), None);
+    }
+
+    #[test]
+    fn shortcode_to_unicode_applies_skin_tones() {
+        assert_eq!(
+            shortcode_to_unicode("+1::skin-tone-3").as_deref(),
+            Some("\u{1f44d}\u{1f3fc}")
+        );
+        assert_eq!(
+            shortcode_to_unicode("v::skin-tone-4").as_deref(),
+            Some("\u{270c}\u{1f3fd}")
+        );
+        assert_eq!(
+            shortcode_to_unicode("man-shrugging::skin-tone-6").as_deref(),
+            Some("\u{1f937}\u{1f3ff}\u{200d}\u{2642}\u{fe0f}")
+        );
+        assert_eq!(shortcode_to_unicode("+1::skin-tone-7"), None);
+    }
+
+    #[test]
+    fn unicode_to_shortcode_prefers_canonical_name() {
+        assert_eq!(unicode_to_shortcode("\u{1f44d}").as_deref(), Some("+1"));
+        assert_eq!(unicode_to_shortcode("\u{2764}").as_deref(), Some("heart"));
+        assert_eq!(unicode_to_shortcode("\u{2764}\u{fe0f}").as_deref(), Some("heart"));
+        assert_eq!(unicode_to_shortcode("not an emoji"), None);
+    }
+
+    #[test]
+    fn unicode_to_shortcode_round_trips_skin_tones() {
+        assert_eq!(
+            unicode_to_shortcode("\u{1f44d}\u{1f3fc}").as_deref(),
+            Some("+1::skin-tone-3")
+        );
+        assert_eq!(
+            unicode_to_shortcode("\u{1f937}\u{1f3ff}\u{200d}\u{2642}\u{fe0f}").as_deref(),
+            Some("man-shrugging::skin-tone-6")
+        );
+        assert_eq!(unicode_to_shortcode("\u{1f3fd}").as_deref(), Some("skin-tone-4"));
+    }
 }
 
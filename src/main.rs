@@ -6,6 +6,7 @@
 use std::sync::Arc;
 
 use anyhow::Result;
+use clap::Parser;
 use tracing::{error, info};
 
 mod admin;
@@ -22,14 +23,19 @@ mod parsers;
 mod utils;
 mod web;
 
-use config::Config;
+use cli::Cli;
 use web::WebServer;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     utils::logging::init_tracing();
 
-    let config = Arc::new(Config::load()?);
+    let cli = Cli::parse();
+    if let Some(command) = &cli.command {
+        return cli::run(&cli, command).await;
+    }
+
+    let config = Arc::new(cli.load_config()?);
     info!("matrix-slack bridge starting up");
 
     let db_manager = Arc::new(db::DatabaseManager::new(&config.database).await?);
@@ -54,6 +60,8 @@ async fn main() -> Result<()> {
         config.limits.matrix_event_age_limit_ms,
     ));
     matrix_client.set_processor(processor).await;
+    matrix_client.set_deduplicator(bridge.deduplicator()).await;
+    matrix_client.set_bridge(bridge.clone()).await;
 
     let web_server = WebServer::new(
         config.clone(),
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use matrix_bot_sdk::appservice::{Appservice, AppserviceHandler};
+use matrix_bot_sdk::appservice::{Appservice, AppserviceHandler, UnstableAppserviceApis};
 use matrix_bot_sdk::client::{MatrixAuth, MatrixClient};
 use matrix_bot_sdk::models::CreateRoom;
 use serde_json::{Value, json};
@@ -9,6 +9,8 @@ use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 use url::Url;
 
+use crate::bridge::BridgeCore;
+use crate::bridge::dedup::{EventDeduplicator, SOURCE_MATRIX};
 use crate::config::Config;
 
 pub mod command_handler;
@@ -27,14 +29,22 @@ mod urlencoding {
 
 pub struct BridgeAppserviceHandler {
     processor: Option<Arc<MatrixEventProcessor>>,
+    deduplicator: Option<Arc<EventDeduplicator>>,
+    bridge: Option<Arc<BridgeCore>>,
 }
 
 #[async_trait::async_trait]
 impl AppserviceHandler for BridgeAppserviceHandler {
-    async fn on_transaction(&self, _txn_id: &str, body: &Value) -> Result<()> {
+    async fn on_transaction(&self, txn_id: &str, body: &Value) -> Result<()> {
         let Some(processor) = &self.processor else {
             return Ok(());
         };
+        if let Some(deduplicator) = &self.deduplicator
+            && deduplicator.is_transaction_processed(txn_id).await
+        {
+            debug!("skipping already processed matrix transaction {}", txn_id);
+            return Ok(());
+        }
 
         if let Some(events) = body.get("events").and_then(|v| v.as_array()) {
             for event in events {
@@ -48,6 +58,15 @@ impl AppserviceHandler for BridgeAppserviceHandler {
                     continue;
                 };
 
+                if let Some(deduplicator) = &self.deduplicator
+                    && let Some(event_id) = event.get("event_id").and_then(|v| v.as_str())
+                    && !deduplicator
+                        .claim(SOURCE_MATRIX, event_type, event_id)
+                        .await
+                {
+                    continue;
+                }
+
                 let matrix_event = MatrixEvent {
                     event_id: event
                         .get("event_id")
@@ -69,8 +88,28 @@ impl AppserviceHandler for BridgeAppserviceHandler {
                 }
             }
         }
+
+        if let Some(deduplicator) = &self.deduplicator {
+            deduplicator.mark_transaction_processed(txn_id).await;
+        }
         Ok(())
     }
+
+    /// Slack ghosts are registered on demand so they can be invited or mentioned before
+    /// they have spoken.
+    async fn query_user(&self, user_id: &str) -> Result<Option<Value>> {
+        let Some(bridge) = &self.bridge else {
+            return Ok(None);
+        };
+        match bridge.handle_user_query(user_id).await {
+            Ok(true) => Ok(Some(json!({}))),
+            Ok(false) => Ok(None),
+            Err(err) => {
+                warn!("failed to provision ghost {}: {}", user_id, err);
+                Err(err)
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -139,6 +178,19 @@ fn build_matrix_message_content(
     content
 }
 
+/// Puts `content` in the thread rooted at `thread_root`. Without an explicit reply, the
+/// reply fallback points at the root so clients without thread support keep the context.
+fn apply_thread_relation(content: &mut Value, thread_root: &str, reply_to: Option<&str>) {
+    content["m.relates_to"] = json!({
+        "rel_type": "m.thread",
+        "event_id": thread_root,
+        "is_falling_back": reply_to.is_none(),
+        "m.in_reply_to": {
+            "event_id": reply_to.unwrap_or(thread_root)
+        }
+    });
+}
+
 fn ghost_user_id(slack_user_id: &str, domain: &str) -> String {
     format!("@_slack_{}:{}", slack_user_id, domain)
 }
@@ -147,6 +199,68 @@ fn is_namespaced_user(user_id: &str) -> bool {
     user_id.starts_with("@_slack_")
 }
 
+/// Slack user id behind a ghost MXID such as `@_slack_U123:example.org`. Only plain user
+/// (`U…`) and enterprise user (`W…`) ids are accepted, not webhook ghosts.
+fn slack_user_from_ghost(user_id: &str, domain: &str) -> Option<String> {
+    let slack_user_id = user_id
+        .strip_prefix("@_slack_")?
+        .strip_suffix(domain)?
+        .strip_suffix(':')?;
+    let valid = slack_user_id.len() > 1
+        && slack_user_id.starts_with(['U', 'W'])
+        && slack_user_id
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    valid.then(|| slack_user_id.to_string())
+}
+
+/// `m.direct` content with `room_id` added to the DMs with `partner`.
+fn with_direct_room(current: &Value, partner: &str, room_id: &str) -> Value {
+    let mut direct: serde_json::Map<String, Value> = current
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, rooms)| rooms.is_array())
+        .map(|(user_id, rooms)| (user_id.clone(), rooms.clone()))
+        .collect();
+    let rooms = direct
+        .entry(partner.to_string())
+        .or_insert_with(|| json!([]));
+    if let Some(rooms) = rooms.as_array_mut()
+        && !rooms.iter().any(|room| room == room_id)
+    {
+        rooms.push(json!(room_id));
+    }
+    Value::Object(direct)
+}
+
+const DEFAULT_ROOM_ALIAS_PREFIX: &str = "_slack_";
+
+fn portal_alias_localpart(prefix: &str, slack_channel_id: &str) -> String {
+    let prefix = if prefix.is_empty() {
+        DEFAULT_ROOM_ALIAS_PREFIX
+    } else {
+        prefix
+    };
+    format!("{}{}", prefix, slack_channel_id)
+}
+
+/// Slack channel id named by a portal alias such as `#_slack_C123:example.org`. Only
+/// channel (`C…`) and private group (`G…`) ids are accepted.
+fn slack_channel_from_alias(alias: &str, prefix: &str, domain: &str) -> Option<String> {
+    let localpart = alias
+        .strip_prefix('#')?
+        .strip_suffix(domain)?
+        .strip_suffix(':')?;
+    let channel_id = localpart.strip_prefix(&portal_alias_localpart(prefix, ""))?;
+    let valid = channel_id.len() > 1
+        && channel_id.starts_with(['C', 'G'])
+        && channel_id
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit());
+    valid.then(|| channel_id.to_string())
+}
+
 impl MatrixAppservice {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
         info!(
@@ -158,7 +272,11 @@ impl MatrixAppservice {
         let auth = MatrixAuth::new(&config.registration.appservice_token);
         let client = MatrixClient::new(homeserver_url, auth);
 
-        let handler = Arc::new(RwLock::new(BridgeAppserviceHandler { processor: None }));
+        let handler = Arc::new(RwLock::new(BridgeAppserviceHandler {
+            processor: None,
+            deduplicator: None,
+            bridge: None,
+        }));
 
         // Use a wrapper to bridge AppserviceHandler to our internal handler
         struct HandlerWrapper(Arc<RwLock<BridgeAppserviceHandler>>);
@@ -167,6 +285,10 @@ impl MatrixAppservice {
             async fn on_transaction(&self, txn_id: &str, body: &Value) -> Result<()> {
                 self.0.read().await.on_transaction(txn_id, body).await
             }
+
+            async fn query_user(&self, user_id: &str) -> Result<Option<Value>> {
+                self.0.read().await.query_user(user_id).await
+            }
         }
 
         let appservice = Appservice::new(
@@ -195,10 +317,35 @@ impl MatrixAppservice {
         )
     }
 
+    /// Full alias of the portal room for a Slack channel.
+    pub fn portal_alias(&self, slack_channel_id: &str) -> String {
+        format!(
+            "#{}:{}",
+            portal_alias_localpart(&self.config.room.room_alias_prefix, slack_channel_id),
+            self.config.bridge.domain
+        )
+    }
+
+    pub fn slack_channel_from_alias(&self, alias: &str) -> Option<String> {
+        slack_channel_from_alias(
+            alias,
+            &self.config.room.room_alias_prefix,
+            &self.config.bridge.domain,
+        )
+    }
+
     pub fn is_namespaced_user(&self, user_id: &str) -> bool {
         is_namespaced_user(user_id)
     }
 
+    pub fn ghost_user_id(&self, slack_user_id: &str) -> String {
+        ghost_user_id(slack_user_id, &self.config.bridge.domain)
+    }
+
+    pub fn slack_user_from_ghost(&self, user_id: &str) -> Option<String> {
+        slack_user_from_ghost(user_id, &self.config.bridge.domain)
+    }
+
     async fn ensure_bot_joined_room(&self, room_id: &str) -> Result<bool> {
         let bot_user_id = self.bot_user_id();
         let membership = self
@@ -255,6 +402,14 @@ impl MatrixAppservice {
         self.handler.write().await.processor = Some(processor);
     }
 
+    pub async fn set_deduplicator(&self, deduplicator: Arc<EventDeduplicator>) {
+        self.handler.write().await.deduplicator = Some(deduplicator);
+    }
+
+    pub async fn set_bridge(&self, bridge: Arc<BridgeCore>) {
+        self.handler.write().await.bridge = Some(bridge);
+    }
+
     pub async fn start(&self) -> Result<()> {
         info!("matrix appservice starting");
         Ok(())
@@ -278,40 +433,82 @@ impl MatrixAppservice {
             .password_register(&localpart, "", display_name)
             .await;
 
-        if let Some(display) = display_name {
-            let _ = ghost_client.set_display_name(display).await;
+        if let Some(display) = display_name
+            && let Err(err) = self.set_ghost_displayname(slack_user_id, display).await
+        {
+            debug!("failed to set displayname for {}: {}", user_id, err);
         }
 
         Ok(user_id)
     }
 
+    /// Creates the portal room for a Slack channel under its portal alias. Rooms of public
+    /// channels can be joined by anyone and are listed in the room directory when
+    /// `room.default_visibility` is `public`; room state is reserved for the bridge bot.
     pub async fn create_room(
         &self,
         slack_channel_id: &str,
         name: &str,
         topic: Option<&str>,
+        is_private: bool,
     ) -> Result<String> {
-        let alias_localpart = format!("_slack_{}", slack_channel_id);
-
-        let visibility = match self.config.room.default_visibility.to_lowercase().as_str() {
-            "public" => Some("public".to_string()),
-            _ => Some("private".to_string()),
-        };
-
-        let opt = CreateRoom {
-            visibility,
-            room_alias_name: Some(alias_localpart),
-            name: Some(name.to_owned()),
-            topic: topic.map(ToOwned::to_owned),
-            ..Default::default()
-        };
+        let room_config = &self.config.room;
+        let listed = room_config
+            .default_visibility
+            .eq_ignore_ascii_case("public");
+        let mut body = json!({
+            "visibility": if listed && !is_private { "public" } else { "private" },
+            "preset": if is_private { "private_chat" } else { "public_chat" },
+            "room_alias_name": portal_alias_localpart(&room_config.room_alias_prefix, slack_channel_id),
+            "name": name,
+            "power_level_content_override": {
+                "users": { self.bot_user_id(): 100 },
+                "users_default": 0,
+                "events_default": 0,
+                "state_default": 50,
+                "events": {
+                    "m.room.name": 100,
+                    "m.room.topic": 100,
+                    "m.room.avatar": 100,
+                    "m.room.canonical_alias": 100,
+                    "m.room.power_levels": 100,
+                },
+            },
+        });
+        if let Some(topic) = topic {
+            body["topic"] = json!(topic);
+        }
 
-        let room_id = self.appservice.client.create_room(&opt).await?;
-        Ok(room_id)
+        let url = format!(
+            "{}/_matrix/client/v3/createRoom",
+            self.config.bridge.homeserver_url.trim_end_matches('/')
+        );
+        let response = reqwest::Client::new()
+            .post(&url)
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.config.registration.appservice_token),
+            )
+            .json(&body)
+            .send()
+            .await
+            .context("createRoom request failed")?;
+        let status = response.status();
+        let value: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() {
+            anyhow::bail!("createRoom failed: {} - {}", status, value);
+        }
+        value
+            .get("room_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("createRoom response has no room_id"))
     }
 
     pub async fn send_message(&self, room_id: &str, sender: &str, content: &str) -> Result<()> {
-        self.send_message_with_metadata(room_id, sender, content, &[], None, None, None)
+        self.send_message_with_metadata(
+            room_id, sender, content, &[], None, None, None, None, None,
+        )
             .await
             .map(|_| ())
     }
@@ -343,6 +540,27 @@ impl MatrixAppservice {
         }
     }
 
+    /// Sends a room event, using the appservice `ts` override when `origin_ts` (unix ms)
+    /// is given so backfilled history keeps its original time.
+    async fn send_room_event(
+        &self,
+        client: &MatrixClient,
+        room_id: &str,
+        event_type: &str,
+        content: &Value,
+        origin_ts: Option<u64>,
+    ) -> Result<String> {
+        match origin_ts {
+            Some(ts) => {
+                self.appservice
+                    .send_event_with_timestamp(room_id, event_type, content, ts)
+                    .await
+            }
+            None => client.send_event(room_id, event_type, content).await,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn send_message_with_metadata(
         &self,
         room_id: &str,
@@ -352,19 +570,23 @@ impl MatrixAppservice {
         reply_to: Option<&str>,
         edit_of: Option<&str>,
         formatted_body: Option<&str>,
+        thread_root: Option<&str>,
+        origin_ts: Option<u64>,
     ) -> Result<String> {
         let ghost_client = self.appservice.client.clone();
         ghost_client
             .impersonate_user_id(Some(sender), None::<&str>)
             .await;
 
-        let content = build_matrix_message_content(body, reply_to, edit_of, formatted_body);
-
-        let event_id = ghost_client
-            .send_event(room_id, "m.room.message", &content)
-            .await?;
+        let mut content = build_matrix_message_content(body, reply_to, edit_of, formatted_body);
+        if let Some(thread_root) = thread_root
+            && edit_of.is_none()
+        {
+            apply_thread_relation(&mut content, thread_root, reply_to);
+        }
 
-        Ok(event_id)
+        self.send_room_event(&ghost_client, room_id, "m.room.message", &content, origin_ts)
+            .await
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -377,6 +599,8 @@ impl MatrixAppservice {
         url: &str,
         info: Option<&serde_json::Value>,
         reply_to: Option<&str>,
+        thread_root: Option<&str>,
+        origin_ts: Option<u64>,
     ) -> Result<String> {
         let ghost_client = self.appservice.client.clone();
         ghost_client
@@ -393,7 +617,9 @@ impl MatrixAppservice {
             content["info"] = info.clone();
         }
 
-        if let Some(reply_event_id) = reply_to {
+        if let Some(thread_root) = thread_root {
+            apply_thread_relation(&mut content, thread_root, reply_to);
+        } else if let Some(reply_event_id) = reply_to {
             content["m.relates_to"] = json!({
                 "m.in_reply_to": {
                     "event_id": reply_event_id
@@ -401,33 +627,52 @@ impl MatrixAppservice {
             });
         }
 
-        let event_id = ghost_client
-            .send_event(room_id, "m.room.message", &content)
-            .await?;
-
-        Ok(event_id)
+        self.send_room_event(&ghost_client, room_id, "m.room.message", &content, origin_ts)
+            .await
     }
 
     pub async fn upload_media(&self, media: &crate::media::MediaInfo) -> Result<String> {
+        self.upload_media_body(
+            media.data.clone().into(),
+            &media.content_type,
+            &media.filename,
+            None,
+        )
+        .await
+    }
+
+    /// Uploads a (possibly streaming) body to the media repository. `content_length` is
+    /// forwarded when known so the homeserver can reject oversized uploads up front.
+    pub async fn upload_media_body(
+        &self,
+        body: reqwest::Body,
+        content_type: &str,
+        filename: &str,
+        content_length: Option<u64>,
+    ) -> Result<String> {
         use reqwest::Client;
 
         let upload_url = format!(
             "{}/_matrix/media/v3/upload?filename={}",
             self.config.bridge.homeserver_url.trim_end_matches('/'),
-            urlencoding::encode(&media.filename)
+            urlencoding::encode(filename)
         );
 
-        debug!("uploading media {} to Matrix", media.filename);
+        debug!("uploading media {} to Matrix", filename);
 
         let client = Client::new();
-        let response = client
+        let mut request = client
             .post(&upload_url)
             .header(
                 "Authorization",
                 format!("Bearer {}", self.config.registration.appservice_token),
             )
-            .header("Content-Type", &media.content_type)
-            .body(media.data.clone())
+            .header("Content-Type", content_type);
+        if let Some(length) = content_length {
+            request = request.header("Content-Length", length);
+        }
+        let response = request
+            .body(body)
             .send()
             .await
             .map_err(|e| anyhow::anyhow!("failed to upload media: {}", e))?;
@@ -459,6 +704,51 @@ impl MatrixAppservice {
         Ok(content_uri)
     }
 
+    /// Opens a media download as the appservice, using the authenticated media API and
+    /// falling back to the legacy endpoint on homeservers that predate it.
+    pub async fn open_media(
+        &self,
+        server_name: &str,
+        media_id: &str,
+    ) -> Result<crate::media::MediaStream> {
+        let homeserver = self.config.bridge.homeserver_url.trim_end_matches('/');
+        let endpoints = [
+            format!("{homeserver}/_matrix/client/v1/media/download/{server_name}/{media_id}"),
+            format!("{homeserver}/_matrix/media/v3/download/{server_name}/{media_id}"),
+        ];
+
+        let client = reqwest::Client::new();
+        for url in &endpoints {
+            let response = client
+                .get(url)
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", self.config.registration.appservice_token),
+                )
+                .send()
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to download media: {}", e))?;
+
+            let status = response.status();
+            if status == reqwest::StatusCode::NOT_FOUND
+                || status == reqwest::StatusCode::METHOD_NOT_ALLOWED
+            {
+                debug!("media download via {} returned {}", url, status);
+                continue;
+            }
+            if !status.is_success() {
+                return Err(anyhow::anyhow!("failed to download media: {}", status));
+            }
+            return Ok(crate::media::MediaStream::from_response(response, url));
+        }
+
+        Err(anyhow::anyhow!(
+            "media mxc://{}/{} not found",
+            server_name,
+            media_id
+        ))
+    }
+
     pub async fn redact_message(
         &self,
         room_id: &str,
@@ -590,22 +880,68 @@ impl MatrixAppservice {
         status_message: &str,
     ) -> Result<()> {
         let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
+        let presence = match presence {
+            "online" | "unavailable" => presence,
+            _ => "offline",
+        };
+        self.request_as_ghost(
+            reqwest::Method::PUT,
+            slack_user_id,
+            &format!(
+                "/_matrix/client/v3/presence/{}/status",
+                urlencoding::encode(&user_id)
+            ),
+            Some(&json!({ "presence": presence, "status_msg": status_message })),
+        )
+        .await?;
+        Ok(())
+    }
 
-        let ghost_client = self.appservice.client.clone();
-        ghost_client
-            .impersonate_user_id(Some(&user_id), None::<&str>)
-            .await;
+    /// Sends a client-server API request as the ghost of `slack_user_id`. The appservice
+    /// asserts the ghost's identity with the `user_id` query parameter, which requests
+    /// about the ghost's own profile, presence or membership require.
+    async fn request_as_ghost(
+        &self,
+        method: reqwest::Method,
+        slack_user_id: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value> {
+        let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
+        self.request_as(method, &user_id, path, body).await
+    }
 
-        let presence_status = match presence {
-            "online" => matrix_bot_sdk::models::Presence::Online,
-            "unavailable" => matrix_bot_sdk::models::Presence::Unavailable,
-            _ => matrix_bot_sdk::models::Presence::Offline,
-        };
+    async fn request_as(
+        &self,
+        method: reqwest::Method,
+        user_id: &str,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<Value> {
+        let url = format!(
+            "{}{}?user_id={}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            path,
+            urlencoding::encode(user_id)
+        );
+        let mut request = reqwest::Client::new().request(method, &url).header(
+            "Authorization",
+            format!("Bearer {}", self.config.registration.appservice_token),
+        );
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("request {} as {} failed", path, user_id))?;
 
-        ghost_client
-            .set_presence_status(presence_status, Some(status_message))
-            .await?;
-        Ok(())
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("request {} as {} failed: {} - {}", path, user_id, status, body);
+        }
+        Ok(response.json().await.unwrap_or(Value::Null))
     }
 
     pub async fn set_slack_user_typing(
@@ -630,8 +966,14 @@ impl MatrixAppservice {
         event_id: &str,
         slack_user_id: &str,
         emoji: &str,
-    ) -> Result<()> {
+        origin_ts: Option<u64>,
+    ) -> Result<String> {
         let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
+        let ghost_client = self.appservice.client.clone();
+        ghost_client
+            .impersonate_user_id(Some(&user_id), None::<&str>)
+            .await;
+
         let content = serde_json::json!({
             "m.relates_to": {
                 "rel_type": "m.annotation",
@@ -639,22 +981,46 @@ impl MatrixAppservice {
                 "key": emoji
             }
         });
-        self.appservice
-            .client
-            .send_raw_event(room_id, "m.reaction", &content, Some(&user_id))
-            .await?;
-        Ok(())
+        self.send_room_event(&ghost_client, room_id, "m.reaction", &content, origin_ts)
+            .await
+    }
+
+    pub async fn send_notice_as_ghost(
+        &self,
+        room_id: &str,
+        slack_user_id: &str,
+        body: &str,
+    ) -> Result<String> {
+        let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
+        let ghost_client = self.appservice.client.clone();
+        ghost_client
+            .impersonate_user_id(Some(&user_id), None::<&str>)
+            .await;
+
+        let content = serde_json::json!({ "msgtype": "m.notice", "body": body });
+        self.send_room_event(&ghost_client, room_id, "m.room.message", &content, None)
+            .await
     }
 
     pub async fn redact_reaction_as_ghost(
         &self,
         room_id: &str,
-        _event_id: &str,
+        reaction_event_id: &str,
         slack_user_id: &str,
-        _emoji: &str,
     ) -> Result<()> {
         let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
-        debug!("reaction redaction requested for user={} room={}", user_id, room_id);
+        let ghost_client = self.appservice.client.clone();
+        ghost_client
+            .impersonate_user_id(Some(&user_id), None::<&str>)
+            .await;
+
+        ghost_client
+            .redact_event(room_id, reaction_event_id, None)
+            .await?;
+        debug!(
+            "redacted reaction {} for user={} room={}",
+            reaction_event_id, user_id, room_id
+        );
         Ok(())
     }
 
@@ -666,6 +1032,19 @@ impl MatrixAppservice {
         Ok(())
     }
 
+    pub async fn set_user_power_level(
+        &self,
+        room_id: &str,
+        user_id: &str,
+        power_level: i64,
+    ) -> Result<()> {
+        self.appservice
+            .client
+            .set_user_power_level(user_id, room_id, power_level)
+            .await?;
+        Ok(())
+    }
+
     pub async fn leave_room(&self, room_id: &str) -> Result<()> {
         self.appservice.client.leave_room(room_id, None).await?;
         Ok(())
@@ -746,6 +1125,39 @@ impl MatrixAppservice {
         Ok(room_id)
     }
 
+    /// DM room between the bridge bot and `partner`. The room is recorded in the bot's
+    /// `m.direct` account data, so the same room is reused after a restart.
+    pub async fn ensure_bot_dm_room(&self, partner: &str) -> Result<String> {
+        let bot_user_id = self.bot_user_id();
+        let path = format!(
+            "/_matrix/client/v3/user/{}/account_data/m.direct",
+            urlencoding::encode(&bot_user_id)
+        );
+        // A bot without DMs yet has no m.direct at all.
+        let current = self
+            .request_as(reqwest::Method::GET, &bot_user_id, &path, None)
+            .await
+            .unwrap_or(Value::Null);
+        if let Some(room_id) = current
+            .get(partner)
+            .and_then(Value::as_array)
+            .and_then(|rooms| rooms.last())
+            .and_then(Value::as_str)
+        {
+            return Ok(room_id.to_string());
+        }
+
+        let room_id = self.create_dm_room(partner).await?;
+        let content = with_direct_room(&current, partner, &room_id);
+        if let Err(err) = self
+            .request_as(reqwest::Method::PUT, &bot_user_id, &path, Some(&content))
+            .await
+        {
+            warn!("failed to record dm room {} with {}: {}", room_id, partner, err);
+        }
+        Ok(room_id)
+    }
+
     pub async fn invite_user_to_room(&self, room_id: &str, user_id: &str) -> Result<()> {
         let url = format!(
             "{}/_matrix/client/v3/rooms/{}/invite",
@@ -892,25 +1304,31 @@ impl MatrixAppservice {
         displayname: &str,
     ) -> Result<()> {
         let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
-
-        let ghost_client = self.appservice.client.clone();
-        ghost_client
-            .impersonate_user_id(Some(&user_id), None::<&str>)
-            .await;
-
-        ghost_client.set_display_name(displayname).await?;
+        self.request_as_ghost(
+            reqwest::Method::PUT,
+            slack_user_id,
+            &format!(
+                "/_matrix/client/v3/profile/{}/displayname",
+                urlencoding::encode(&user_id)
+            ),
+            Some(&json!({ "displayname": displayname })),
+        )
+        .await?;
         Ok(())
     }
 
     pub async fn set_ghost_avatar(&self, slack_user_id: &str, avatar_url: &str) -> Result<()> {
         let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
-
-        let ghost_client = self.appservice.client.clone();
-        ghost_client
-            .impersonate_user_id(Some(&user_id), None::<&str>)
-            .await;
-
-        ghost_client.set_avatar_url(avatar_url).await?;
+        self.request_as_ghost(
+            reqwest::Method::PUT,
+            slack_user_id,
+            &format!(
+                "/_matrix/client/v3/profile/{}/avatar_url",
+                urlencoding::encode(&user_id)
+            ),
+            Some(&json!({ "avatar_url": avatar_url })),
+        )
+        .await?;
         Ok(())
     }
 
@@ -941,45 +1359,138 @@ impl MatrixAppservice {
             .await
     }
 
-    pub async fn set_ghost_room_displayname(
+    /// Updates the ghost's member event in `room_id`, keeping fields that are not given.
+    /// Returns `false` without changing anything when the ghost is not joined to the room.
+    pub async fn set_ghost_room_profile(
         &self,
         slack_user_id: &str,
         room_id: &str,
-        displayname: &str,
-    ) -> Result<()> {
+        displayname: Option<&str>,
+        avatar_url: Option<&str>,
+    ) -> Result<bool> {
         let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
+        let Ok(mut content) = self
+            .appservice
+            .client
+            .get_room_state_event(room_id, "m.room.member", &user_id)
+            .await
+        else {
+            return Ok(false);
+        };
+        if content.get("membership").and_then(Value::as_str) != Some("join") {
+            return Ok(false);
+        }
+        if let Some(displayname) = displayname {
+            content["displayname"] = json!(displayname);
+        }
+        if let Some(avatar_url) = avatar_url {
+            content["avatar_url"] = json!(avatar_url);
+        }
 
-        let content = json!({
-            "displayname": displayname,
-            "membership": "join"
-        });
+        self.request_as_ghost(
+            reqwest::Method::PUT,
+            slack_user_id,
+            &format!(
+                "/_matrix/client/v3/rooms/{}/state/m.room.member/{}",
+                urlencoding::encode(room_id),
+                urlencoding::encode(&user_id)
+            ),
+            Some(&content),
+        )
+        .await?;
+        Ok(true)
+    }
 
-        self.appservice
-            .client
-            .send_state_event(room_id, "m.room.member", &user_id, &content)
-            .await?;
+    pub async fn leave_room_as_ghost(&self, slack_user_id: &str, room_id: &str) -> Result<()> {
+        self.request_as_ghost(
+            reqwest::Method::POST,
+            slack_user_id,
+            &format!(
+                "/_matrix/client/v3/rooms/{}/leave",
+                urlencoding::encode(room_id)
+            ),
+            Some(&json!({})),
+        )
+        .await?;
+        Ok(())
+    }
 
+    pub async fn join_room_as_ghost(&self, slack_user_id: &str, room_id: &str) -> Result<()> {
+        self.request_as_ghost(
+            reqwest::Method::POST,
+            slack_user_id,
+            &format!("/_matrix/client/v3/join/{}", urlencoding::encode(room_id)),
+            Some(&json!({})),
+        )
+        .await?;
         Ok(())
     }
 
-    pub async fn set_ghost_room_avatar(
+    pub async fn invite_as_ghost(
         &self,
         slack_user_id: &str,
         room_id: &str,
-        avatar_mxc: &str,
+        user_id: &str,
     ) -> Result<()> {
-        let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
-
-        let content = json!({
-            "avatar_url": avatar_mxc,
-            "membership": "join"
-        });
+        self.request_as_ghost(
+            reqwest::Method::POST,
+            slack_user_id,
+            &format!(
+                "/_matrix/client/v3/rooms/{}/invite",
+                urlencoding::encode(room_id)
+            ),
+            Some(&json!({ "user_id": user_id })),
+        )
+        .await?;
+        Ok(())
+    }
 
-        self.appservice
-            .client
-            .send_state_event(room_id, "m.room.member", &user_id, &content)
+    /// Creates a DM room owned by the ghost of `slack_user_id` and invites `invite`.
+    pub async fn create_direct_room(
+        &self,
+        slack_user_id: &str,
+        invite: &[String],
+    ) -> Result<String> {
+        let value = self
+            .request_as_ghost(
+                reqwest::Method::POST,
+                slack_user_id,
+                "/_matrix/client/v3/createRoom",
+                Some(&json!({
+                    "visibility": "private",
+                    "preset": "trusted_private_chat",
+                    "is_direct": true,
+                    "invite": invite,
+                })),
+            )
             .await?;
+        value
+            .get("room_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("createRoom response has no room_id"))
+    }
 
+    /// Lists `room_id` as a DM with `partner` in the ghost's `m.direct` account data.
+    pub async fn add_ghost_direct_room(
+        &self,
+        slack_user_id: &str,
+        partner: &str,
+        room_id: &str,
+    ) -> Result<()> {
+        let user_id = ghost_user_id(slack_user_id, &self.config.bridge.domain);
+        let path = format!(
+            "/_matrix/client/v3/user/{}/account_data/m.direct",
+            urlencoding::encode(&user_id)
+        );
+        // A ghost without DMs yet has no m.direct at all.
+        let current = self
+            .request_as_ghost(reqwest::Method::GET, slack_user_id, &path, None)
+            .await
+            .unwrap_or(Value::Null);
+        let content = with_direct_room(&current, partner, room_id);
+        self.request_as_ghost(reqwest::Method::PUT, slack_user_id, &path, Some(&content))
+            .await?;
         Ok(())
     }
 
@@ -1079,7 +1590,11 @@ impl MatrixAppservice {
 
 #[cfg(test)]
 mod tests {
-    use super::{build_matrix_message_content, ghost_user_id, is_namespaced_user};
+    use super::{
+        apply_thread_relation, build_matrix_message_content, ghost_user_id, is_namespaced_user,
+        slack_channel_from_alias, slack_user_from_ghost, with_direct_room,
+    };
+    use serde_json::json;
 
     #[test]
     fn message_content_adds_reply_relation() {
@@ -1103,6 +1618,16 @@ mod tests {
         assert_eq!(content["m.relates_to"]["event_id"], "$old_event");
     }
 
+    #[test]
+    fn thread_relation_falls_back_to_replying_to_the_root() {
+        let mut content = build_matrix_message_content("in thread", None, None, None);
+        apply_thread_relation(&mut content, "$root", None);
+        assert_eq!(content["m.relates_to"]["rel_type"], "m.thread");
+        assert_eq!(content["m.relates_to"]["event_id"], "$root");
+        assert_eq!(content["m.relates_to"]["is_falling_back"], true);
+        assert_eq!(content["m.relates_to"]["m.in_reply_to"]["event_id"], "$root");
+    }
+
     #[test]
     fn ghost_user_id_uses_expected_namespace() {
         let user_id = ghost_user_id("12345", "example.org");
@@ -1117,6 +1642,55 @@ mod tests {
         assert!(!is_namespaced_user("@_slack:example.org"));
     }
 
+    #[test]
+    fn portal_aliases_resolve_to_slack_channel_ids() {
+        let parse = |alias| slack_channel_from_alias(alias, "_slack_", "example.org");
+        assert_eq!(
+            parse("#_slack_C0123ABC:example.org").as_deref(),
+            Some("C0123ABC")
+        );
+        assert_eq!(parse("#_slack_G42:example.org").as_deref(), Some("G42"));
+        assert_eq!(parse("#_slack_C0123ABC:other.org"), None);
+        assert_eq!(parse("#_slack_general:example.org"), None);
+        assert_eq!(parse("#_slack_U123:example.org"), None);
+        assert_eq!(parse("#_slack_:example.org"), None);
+        assert_eq!(
+            slack_channel_from_alias("#_slack_C1:example.org", "", "example.org").as_deref(),
+            Some("C1")
+        );
+    }
+
+    #[test]
+    fn ghost_user_ids_resolve_to_slack_user_ids() {
+        let parse = |user_id| slack_user_from_ghost(user_id, "example.org");
+        assert_eq!(
+            parse("@_slack_U0123ABC:example.org").as_deref(),
+            Some("U0123ABC")
+        );
+        assert_eq!(parse("@_slack_W42:example.org").as_deref(), Some("W42"));
+        assert_eq!(parse("@_slack_U0123ABC:other.org"), None);
+        assert_eq!(parse("@_slack_U1_webhook:example.org"), None);
+        assert_eq!(parse("@_slack_C123:example.org"), None);
+        assert_eq!(parse("@_slack_:example.org"), None);
+    }
+
+    #[test]
+    fn direct_rooms_are_added_once_per_partner() {
+        let current = json!({ "@bob:example.org": ["!old:example.org"], "event_id": "$x" });
+        let direct = with_direct_room(&current, "@alice:example.org", "!dm:example.org");
+        assert_eq!(
+            direct,
+            json!({
+                "@bob:example.org": ["!old:example.org"],
+                "@alice:example.org": ["!dm:example.org"],
+            })
+        );
+        assert_eq!(
+            with_direct_room(&direct, "@alice:example.org", "!dm:example.org"),
+            direct
+        );
+    }
+
     #[test]
     fn message_content_prefers_edit_relation_over_reply_relation() {
         let content =
@@ -19,6 +19,16 @@ pub enum MatrixCommandOutcome {
         channel_id: String,
     },
     UnbridgeRequested,
+    BackfillRequested {
+        limit: Option<u32>,
+    },
+    LoginRequested,
+    LogoutRequested,
+    /// Only honoured for `bridge.admin_mxid`, which the bridge checks.
+    PauseRequested {
+        reason: Option<String>,
+    },
+    ResumeRequested,
 }
 
 #[derive(Debug, Clone)]
@@ -71,7 +81,7 @@ impl MatrixCommandHandler {
                 self.render_help(parsed.args.first().map(String::as_str)),
             ),
             "bridge" => {
-                if let Err(reply) = self.ensure_permission(&permission_check) {
+                if let Err(reply) = self.ensure_permission(&permission_check, true) {
                     return MatrixCommandOutcome::Reply(reply);
                 }
                 if room_is_bridged {
@@ -91,7 +101,7 @@ impl MatrixCommandHandler {
                 }
             }
             "unbridge" => {
-                if let Err(reply) = self.ensure_permission(&permission_check) {
+                if let Err(reply) = self.ensure_permission(&permission_check, true) {
                     return MatrixCommandOutcome::Reply(reply);
                 }
                 if !room_is_bridged {
@@ -99,13 +109,40 @@ impl MatrixCommandHandler {
                 }
                 MatrixCommandOutcome::UnbridgeRequested
             }
+            "backfill" => {
+                if let Err(reply) = self.ensure_permission(&permission_check, false) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let limit = match parsed.args.first() {
+                    None => None,
+                    Some(arg) => match arg.parse::<u32>() {
+                        Ok(limit) if limit > 0 => Some(limit),
+                        _ => {
+                            return MatrixCommandOutcome::Reply(
+                                "Invalid syntax. For more information try `!slack help backfill`"
+                                    .to_string(),
+                            );
+                        }
+                    },
+                };
+                MatrixCommandOutcome::BackfillRequested { limit }
+            }
+            "login" => MatrixCommandOutcome::LoginRequested,
+            "logout" => MatrixCommandOutcome::LogoutRequested,
+            "pause" => MatrixCommandOutcome::PauseRequested {
+                reason: (!parsed.args.is_empty()).then(|| parsed.args.join(" ")),
+            },
+            "resume" => MatrixCommandOutcome::ResumeRequested,
             _ => MatrixCommandOutcome::Reply(
                 "**ERROR:** unknown command. Try `!slack help` to see all commands".to_string(),
             ),
         }
     }
 
-    fn ensure_permission<P>(&self, permission_check: &P) -> Result<(), String>
+    fn ensure_permission<P>(&self, permission_check: &P, self_service: bool) -> Result<(), String>
     where
         P: Fn(MatrixCommandPermission) -> Result<bool, String>,
     {
@@ -113,7 +150,7 @@ impl MatrixCommandHandler {
             required_level: self.provisioning_power_level,
             category: "events",
             subcategory: "m.room.power_levels",
-            self_service: true,
+            self_service,
         };
 
         if permission.self_service && !self.self_service_enabled {
@@ -135,10 +172,25 @@ impl MatrixCommandHandler {
             Some("unbridge") => {
                 "`!slack unbridge`: Unbridges a Slack channel from this room".to_string()
             }
+            Some("backfill") => {
+                "`!slack backfill [limit]`: Copies recent Slack history into this room\nDefaults to, and is capped at, the configured `backfill.max_messages`.".to_string()
+            }
+            Some("login") => {
+                "`!slack login`: Links your Slack account so your messages are sent as you".to_string()
+            }
+            Some("logout") => {
+                "`!slack logout`: Unlinks your Slack account".to_string()
+            }
+            Some("pause") => {
+                "`!slack pause [reason]`: Stops bridging messages until `!slack resume`\nOnly the bridge admin may use it.".to_string()
+            }
+            Some("resume") => {
+                "`!slack resume`: Resumes bridging after `!slack pause`\nOnly the bridge admin may use it.".to_string()
+            }
             Some(_) => "**ERROR:** unknown command! Try `!slack help` to see all commands"
                 .to_string(),
             None => {
-                "Available Commands:\n - `!slack bridge <guildId> <channelId>`: Bridges this room to a Slack channel\n - `!slack unbridge`: Unbridges a Slack channel from this room".to_string()
+                "Available Commands:\n - `!slack bridge <guildId> <channelId>`: Bridges this room to a Slack channel\n - `!slack unbridge`: Unbridges a Slack channel from this room\n - `!slack backfill [limit]`: Copies recent Slack history into this room\n - `!slack login`: Links your Slack account so your messages are sent as you\n - `!slack logout`: Unlinks your Slack account\n - `!slack pause [reason]`: Stops bridging messages (bridge admin only)\n - `!slack resume`: Resumes bridging (bridge admin only)".to_string()
             }
         }
     }
@@ -203,4 +255,59 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn backfill_accepts_optional_limit_even_without_self_service() {
+        let handler = MatrixCommandHandler::new(false, None);
+        assert_eq!(
+            handler.handle("!slack backfill", true, |_| Ok(true)),
+            MatrixCommandOutcome::BackfillRequested { limit: None }
+        );
+        assert_eq!(
+            handler.handle("!slack backfill 25", true, |_| Ok(true)),
+            MatrixCommandOutcome::BackfillRequested { limit: Some(25) }
+        );
+        assert_eq!(
+            handler.handle("!slack backfill lots", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(
+                "Invalid syntax. For more information try `!slack help backfill`".to_string()
+            )
+        );
+        assert_eq!(
+            handler.handle("!slack backfill", false, |_| Ok(true)),
+            MatrixCommandOutcome::Reply("This room is not bridged.".to_string())
+        );
+    }
+
+    #[test]
+    fn login_and_logout_need_no_permissions_or_bridged_room() {
+        let handler = MatrixCommandHandler::new(false, None);
+        assert_eq!(
+            handler.handle("!slack login", false, |_| Ok(false)),
+            MatrixCommandOutcome::LoginRequested
+        );
+        assert_eq!(
+            handler.handle("!slack logout", true, |_| Ok(false)),
+            MatrixCommandOutcome::LogoutRequested
+        );
+    }
+
+    #[test]
+    fn pause_keeps_its_reason() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!slack pause slack maintenance", false, |_| Ok(false)),
+            MatrixCommandOutcome::PauseRequested {
+                reason: Some("slack maintenance".to_string())
+            }
+        );
+        assert_eq!(
+            handler.handle("!slack pause", false, |_| Ok(false)),
+            MatrixCommandOutcome::PauseRequested { reason: None }
+        );
+        assert_eq!(
+            handler.handle("!slack resume", true, |_| Ok(false)),
+            MatrixCommandOutcome::ResumeRequested
+        );
+    }
 }
@@ -47,7 +47,10 @@ impl MatrixEventHandlerImpl {
 impl MatrixEventHandler for MatrixEventHandlerImpl {
     async fn handle_room_message(&self, event: &MatrixEvent) -> Result<()> {
         if let Some(bridge) = &self.bridge {
-            bridge.handle_matrix_message(event).await?;
+            if let Err(err) = bridge.handle_matrix_message(event).await {
+                bridge.report_error("Matrix message", &err).await;
+                return Err(err);
+            }
         } else {
             debug!("matrix message received without bridge binding");
         }
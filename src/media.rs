@@ -1,9 +1,15 @@
 use std::path::Path;
 
 use anyhow::{Result, anyhow};
+use futures::{Stream, StreamExt};
+use hmac::{Hmac, Mac};
 use reqwest::Client;
+use sha2::Sha256;
 use tracing::{debug, warn};
 
+use crate::config::Config;
+use crate::utils::hex::{decode_hex, encode_hex};
+
 const MAX_SLACK_FILE_SIZE: usize = 8 * 1024 * 1024;
 const MAX_MATRIX_FILE_SIZE: usize = 50 * 1024 * 1024;
 
@@ -15,6 +21,168 @@ pub struct MediaInfo {
     pub size: usize,
 }
 
+/// A downloaded file whose body has not been read yet, so it can be piped straight into an
+/// upload without holding the whole file in memory.
+pub struct MediaStream {
+    response: reqwest::Response,
+    pub content_type: String,
+    pub filename: String,
+    pub size: Option<u64>,
+}
+
+impl MediaStream {
+    /// Wraps a successful response; `url` is the originally requested URL, used to name
+    /// the file when the response has no `Content-Disposition`.
+    pub fn from_response(response: reqwest::Response, url: &str) -> Self {
+        let headers = response.headers();
+        let raw_content_type = headers
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned);
+        let filename = headers
+            .get("content-disposition")
+            .and_then(|v| v.to_str().ok())
+            .and_then(filename_from_content_disposition)
+            .or_else(|| filename_from_url(url))
+            .unwrap_or_else(|| "attachment".to_string());
+        let content_type = normalize_content_type(raw_content_type.as_deref(), &filename, &[]);
+        let filename = ensure_filename_extension(&filename, &content_type);
+        let size = response.content_length();
+
+        Self {
+            response,
+            content_type,
+            filename,
+            size,
+        }
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = reqwest::Result<hyper::body::Bytes>> {
+        self.response.bytes_stream()
+    }
+
+    /// Turns the response into a request body that fails once more than `max_size` bytes
+    /// have been read, aborting the upload it feeds.
+    pub fn into_body(self, max_size: u64) -> reqwest::Body {
+        let mut received: u64 = 0;
+        let stream = self.response.bytes_stream().map(move |chunk| {
+            let chunk = chunk.map_err(std::io::Error::other)?;
+            received += chunk.len() as u64;
+            if received > max_size {
+                return Err(std::io::Error::other(format!(
+                    "file exceeds {max_size} bytes"
+                )));
+            }
+            Ok(chunk)
+        });
+        reqwest::Body::wrap_stream(stream)
+    }
+}
+
+/// Builds and checks the signed, expiring links served by the bridge's media proxy route,
+/// `/media/{server_name}/{media_id}?expires=..&sig=..`.
+#[derive(Clone)]
+pub struct MediaProxy {
+    public_url: String,
+    key: Vec<u8>,
+    ttl_secs: i64,
+}
+
+impl MediaProxy {
+    /// Returns `None` while `media_proxy.public_url` is unset.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let public_url = config
+            .media_proxy
+            .public_url
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())?;
+        let key = config
+            .media_proxy
+            .signing_key
+            .as_deref()
+            .filter(|value| !value.is_empty())
+            .unwrap_or(&config.registration.appservice_token);
+        Some(Self {
+            public_url: public_url.trim_end_matches('/').to_string(),
+            key: key.as_bytes().to_vec(),
+            ttl_secs: i64::try_from(config.media_proxy.ttl_secs).unwrap_or(i64::MAX),
+        })
+    }
+
+    /// Signed link for an `mxc://` URI, valid for the configured TTL from `now`.
+    pub fn url_for(&self, mxc_url: &str, now: i64) -> Option<String> {
+        self.url_expiring_at(mxc_url, now.saturating_add(self.ttl_secs))
+    }
+
+    /// Like [`MediaProxy::url_for`], but the expiry is rounded up to the end of the next
+    /// TTL window so the link stays the same within a window and Slack can cache it, as
+    /// with avatars. It is still valid for at least the TTL.
+    pub fn stable_url_for(&self, mxc_url: &str, now: i64) -> Option<String> {
+        let window = self.ttl_secs.max(1);
+        let window_start = now - now.rem_euclid(window);
+        self.url_expiring_at(mxc_url, window_start.saturating_add(window.saturating_mul(2)))
+    }
+
+    fn url_expiring_at(&self, mxc_url: &str, expires: i64) -> Option<String> {
+        let (server_name, media_id) = parse_mxc(mxc_url)?;
+        Some(format!(
+            "{}/media/{}/{}?expires={}&sig={}",
+            self.public_url,
+            server_name,
+            media_id,
+            expires,
+            self.sign(server_name, media_id, expires)
+        ))
+    }
+
+    pub fn verify(
+        &self,
+        server_name: &str,
+        media_id: &str,
+        expires: i64,
+        signature: &str,
+        now: i64,
+    ) -> bool {
+        if expires < now {
+            return false;
+        }
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&self.key) else {
+            return false;
+        };
+        mac.update(format!("{server_name}/{media_id}:{expires}").as_bytes());
+        let Some(expected) = decode_hex(signature) else {
+            return false;
+        };
+        mac.verify_slice(&expected).is_ok()
+    }
+
+    fn sign(&self, server_name: &str, media_id: &str, expires: i64) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(format!("{server_name}/{media_id}:{expires}").as_bytes());
+        encode_hex(&mac.finalize().into_bytes())
+    }
+}
+
+/// Splits `mxc://server/media_id`, rejecting ids that could escape a URL path segment.
+pub fn parse_mxc(mxc_url: &str) -> Option<(&str, &str)> {
+    let (server_name, media_id) = mxc_url.strip_prefix("mxc://")?.split_once('/')?;
+    let valid_server = |value: &str| {
+        !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+    };
+    let valid_media_id = |value: &str| {
+        !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+    };
+    (valid_server(server_name) && valid_media_id(media_id)).then_some((server_name, media_id))
+}
+
 pub struct MediaHandler {
     client: Client,
     homeserver_url: String,
@@ -348,10 +516,46 @@ mod urlencoding {
 #[cfg(test)]
 mod tests {
     use super::{
-        ensure_filename_extension, filename_from_content_disposition, filename_from_url,
-        normalize_content_type,
+        MediaProxy, ensure_filename_extension, filename_from_content_disposition,
+        filename_from_url, normalize_content_type, parse_mxc,
     };
 
+    #[test]
+    fn media_proxy_links_verify_until_they_expire() {
+        let proxy = MediaProxy {
+            public_url: "https://bridge.example.org".to_string(),
+            key: b"secret".to_vec(),
+            ttl_secs: 60,
+        };
+        let url = proxy.url_for("mxc://example.org/abc123", 1_000).unwrap();
+        assert!(url.starts_with("https://bridge.example.org/media/example.org/abc123?expires=1060&sig="));
+        let signature = url.rsplit("sig=").next().unwrap();
+
+        assert!(proxy.verify("example.org", "abc123", 1060, signature, 1_000));
+        assert!(!proxy.verify("example.org", "abc123", 1060, signature, 1_061));
+        assert!(!proxy.verify("example.org", "other", 1060, signature, 1_000));
+        assert!(!proxy.verify("example.org", "abc123", 9999, signature, 1_000));
+        assert!(proxy.url_for("mxc://example.org/../../etc", 1_000).is_none());
+        assert_eq!(parse_mxc("https://example.org/a"), None);
+    }
+
+    #[test]
+    fn stable_media_proxy_links_only_change_between_ttl_windows() {
+        let proxy = MediaProxy {
+            public_url: "https://bridge.example.org".to_string(),
+            key: b"secret".to_vec(),
+            ttl_secs: 60,
+        };
+        let first = proxy.stable_url_for("mxc://example.org/avatar", 1_000).unwrap();
+        assert!(first.contains("expires=1080&"));
+        assert_eq!(proxy.stable_url_for("mxc://example.org/avatar", 1_019).unwrap(), first);
+        let next = proxy.stable_url_for("mxc://example.org/avatar", 1_020).unwrap();
+        assert!(next.contains("expires=1140&"));
+
+        let signature = first.rsplit("sig=").next().unwrap();
+        assert!(proxy.verify("example.org", "avatar", 1080, signature, 1_019));
+    }
+
     #[test]
     fn picks_filename_from_content_disposition_filename_star() {
         let header = "attachment; filename*=UTF-8''outfox-board.png";
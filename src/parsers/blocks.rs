@@ -352,10 +352,10 @@ fn render_rich_text_preformatted(element: &Value) -> Option<String> {
     let mut code = String::new();
 
     for elem in inner {
-        if elem.get("type").and_then(Value::as_str) == Some("text") {
-            if let Some(text) = elem.get("text").and_then(Value::as_str) {
-                code.push_str(&escape_html(text));
-            }
+        if elem.get("type").and_then(Value::as_str) == Some("text")
+            && let Some(text) = elem.get("text").and_then(Value::as_str)
+        {
+            code.push_str(&escape_html(text));
         }
     }
 
@@ -605,10 +605,10 @@ fn render_single_attachment(attachment: &Value) -> Option<String> {
     }
 
     // Pretext
-    if let Some(pretext) = attachment.get("pretext").and_then(Value::as_str) {
-        if !pretext.is_empty() {
-            parts.push(escape_html(pretext));
-        }
+    if let Some(pretext) = attachment.get("pretext").and_then(Value::as_str)
+        && !pretext.is_empty()
+    {
+        parts.push(escape_html(pretext));
     }
 
     // Text/Fallback
@@ -616,10 +616,10 @@ fn render_single_attachment(attachment: &Value) -> Option<String> {
         if !text.is_empty() {
             parts.push(escape_html(text));
         }
-    } else if let Some(fallback) = attachment.get("fallback").and_then(Value::as_str) {
-        if !fallback.is_empty() {
-            parts.push(escape_html(fallback));
-        }
+    } else if let Some(fallback) = attachment.get("fallback").and_then(Value::as_str)
+        && !fallback.is_empty()
+    {
+        parts.push(escape_html(fallback));
     }
 
     // Fields
@@ -639,10 +639,10 @@ fn render_single_attachment(attachment: &Value) -> Option<String> {
     }
 
     // Footer
-    if let Some(footer) = attachment.get("footer").and_then(Value::as_str) {
-        if !footer.is_empty() {
-            parts.push(format!("<em>{}</em>", escape_html(footer)));
-        }
+    if let Some(footer) = attachment.get("footer").and_then(Value::as_str)
+        && !footer.is_empty()
+    {
+        parts.push(format!("<em>{}</em>", escape_html(footer)));
     }
 
     if parts.is_empty() {
@@ -196,6 +196,7 @@ mod tests {
                 bind_address: "127.0.0.1".to_string(),
                 homeserver_url: "http://localhost:8008".to_string(),
                 presence_interval: 500,
+                presence_poll_interval: 60,
                 disable_presence: false,
                 disable_typing_notifications: false,
                 disable_slack_mentions: false,
@@ -218,6 +219,8 @@ mod tests {
                 participant_sync_count: 5,
                 participant_sync_only_on_create: true,
                 mute_channels_by_default: false,
+                reply_mode: Default::default(),
+                room_reply_modes: Default::default(),
             },
             registration: crate::config::RegistrationConfig::default(),
             auth: crate::config::AuthConfig {
@@ -226,6 +229,11 @@ mod tests {
                 client_id: None,
                 client_secret: None,
                 use_privileged_intents: false,
+                api_base_url: None,
+                event_mode: crate::config::SlackEventMode::Socket,
+                signing_secret: None,
+                oauth_redirect_url: None,
+                token_encryption_key: None,
             },
             logging: crate::config::LoggingConfig {
                 level: "info".to_string(),
@@ -269,6 +277,7 @@ mod tests {
             },
             metrics: crate::config::MetricsConfig::default(),
             backfill: Default::default(),
+            media_proxy: Default::default(),
         });
 
         MatrixToSlackConverter::new(Arc::new(MatrixAppservice::new(config).await.unwrap()))
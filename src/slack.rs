@@ -13,12 +13,30 @@ use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::protocol::Message as WsMessage;
 use tracing::{debug, error, info, warn};
 
+use crate::bridge::dedup::SOURCE_SLACK;
+use crate::bridge::queue::ChannelQueue;
 use crate::bridge::{BridgeCore, SlackMessageContext};
-use crate::config::Config;
+use crate::cache::AsyncTimedCache;
+use crate::config::{Config, SlackEventMode};
+use crate::media::MediaStream;
+use crate::web::metrics::Metrics;
+
+use self::rate_limit::SlackRateLimiter;
 
 const INITIAL_LOGIN_RETRY_SECONDS: u64 = 2;
 const MAX_LOGIN_RETRY_SECONDS: u64 = 300;
 const PERMISSION_CACHE_TTL_SECONDS: u64 = 300;
+const SLACK_API_BASE_URL: &str = "https://slack.com/api/";
+const MAX_FILE_REDIRECTS: usize = 5;
+/// How long workspace channel and user listings are reused for directory searches.
+const DIRECTORY_CACHE_TTL_SECONDS: u64 = 300;
+const DIRECTORY_PAGE_SIZE: u32 = 200;
+/// Stops paging through `conversations.list`/`users.list` after this many pages.
+const MAX_DIRECTORY_PAGES: usize = 50;
+/// How long the workspace's `emoji.list` is reused to resolve custom reaction names.
+const EMOJI_LIST_CACHE_TTL_SECONDS: u64 = 300;
+/// How long messages posted with a puppet's token are remembered to drop their echoes.
+const PUPPET_ECHO_TTL_SECONDS: u64 = 600;
 
 static USER_MENTION_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"<@([A-Z0-9]+)(?:\|[^>]+)?>").expect("valid user mention regex"));
@@ -33,6 +51,10 @@ static RAW_LINK_REGEX: Lazy<Regex> =
 
 pub mod command_handler;
 pub mod embed;
+pub mod events_api;
+#[cfg(test)]
+pub(crate) mod mock;
+pub mod rate_limit;
 
 pub use self::command_handler::{SlackCommandHandler, SlackCommandOutcome, ModerationAction};
 pub use self::embed::{
@@ -45,6 +67,33 @@ pub struct SlackUser {
     pub username: String,
     pub discriminator: String,
     pub avatar: Option<String>,
+    /// Slack's `profile.avatar_hash`, which changes whenever the avatar image does.
+    pub avatar_hash: Option<String>,
+    /// Set for deactivated accounts.
+    pub deleted: bool,
+}
+
+impl SlackUser {
+    /// Reads a user object as returned by `users.info` or carried by `user_change`.
+    pub fn from_value(user_id: &str, user: &Value) -> Self {
+        let avatar = user
+            .pointer("/profile/image_512")
+            .and_then(Value::as_str)
+            .or_else(|| user.pointer("/profile/image_192").and_then(Value::as_str))
+            .map(ToOwned::to_owned);
+        Self {
+            id: user_id.to_string(),
+            username: extract_display_name(user).unwrap_or_else(|| user_id.to_string()),
+            discriminator: "0000".to_string(),
+            avatar_hash: user
+                .pointer("/profile/avatar_hash")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+                .or_else(|| avatar.clone()),
+            avatar,
+            deleted: user.get("deleted").and_then(Value::as_bool).unwrap_or(false),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,6 +102,43 @@ pub struct SlackChannel {
     pub name: String,
     pub guild_id: String,
     pub topic: Option<String>,
+    /// Private channels never get a publicly joinable Matrix room.
+    pub is_private: bool,
+    /// Whether the bot is in the channel and so receives its events.
+    pub is_member: bool,
+}
+
+impl SlackChannel {
+    /// Reads a conversation object as returned by `conversations.info` or `conversations.list`.
+    pub fn from_value(channel: &Value, fallback_team: &str) -> Option<Self> {
+        let id = channel.get("id").and_then(Value::as_str)?;
+        Some(Self {
+            id: id.to_string(),
+            name: channel
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or(id)
+                .to_string(),
+            guild_id: channel
+                .get("context_team_id")
+                .and_then(Value::as_str)
+                .unwrap_or(fallback_team)
+                .to_string(),
+            topic: channel
+                .pointer("/topic/value")
+                .and_then(Value::as_str)
+                .filter(|topic| !topic.is_empty())
+                .map(ToOwned::to_owned),
+            is_private: channel
+                .get("is_private")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            is_member: channel
+                .get("is_member")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,14 +156,40 @@ pub struct SlackMessage {
 #[derive(Clone)]
 pub struct SlackClient {
     _config: Arc<Config>,
-    send_lock: Arc<tokio::sync::Mutex<()>>,
+    rate_limiter: Arc<SlackRateLimiter>,
+    api_base_url: String,
     login_state: Arc<tokio::sync::Mutex<SlackLoginState>>,
     bridge: Arc<RwLock<Option<Arc<BridgeCore>>>>,
     http: reqwest::Client,
+    file_http: reqwest::Client,
     bot_user_id: Arc<RwLock<Option<String>>>,
     bot_id: Arc<RwLock<Option<String>>>,
     team_id: Arc<RwLock<Option<String>>>,
     permission_cache: Arc<tokio::sync::Mutex<HashMap<String, CachedPermission>>>,
+    channel_directory: Arc<AsyncTimedCache<(), Arc<Vec<SlackChannel>>>>,
+    user_directory: Arc<AsyncTimedCache<(), Arc<Vec<SlackUser>>>>,
+    emoji_list: Arc<AsyncTimedCache<(), Arc<Value>>>,
+    /// Set on clones that act for a logged-in Matrix user, see [`SlackClient::as_puppet`].
+    puppet: Option<Arc<PuppetAuth>>,
+    /// `channel:ts` of messages posted or edited by puppets, which Slack echoes back as
+    /// that user's own messages, and `channel:file:id` of files they uploaded.
+    puppet_posts: Arc<AsyncTimedCache<String, ()>>,
+    /// Held per channel while a puppet posts or reacts, so an echo that arrives before the
+    /// call returns waits until the post or reaction is recorded.
+    puppet_sends: Arc<ChannelQueue>,
+}
+
+struct PuppetAuth {
+    slack_user_id: String,
+    access_token: String,
+}
+
+/// The Slack user and user token an OAuth login produced.
+#[derive(Debug, Clone)]
+pub struct SlackUserLogin {
+    pub user_id: String,
+    pub team_id: String,
+    pub access_token: String,
 }
 
 #[derive(Default)]
@@ -104,20 +216,79 @@ impl SlackClient {
             .user_agent("matrix-bridge-slack")
             .build()
             .context("failed to construct HTTP client")?;
+        // Redirects are followed by hand so the bot token never leaves Slack's hosts.
+        let file_http = reqwest::Client::builder()
+            .user_agent("matrix-bridge-slack")
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("failed to construct file download HTTP client")?;
+
+        let rate_limiter = Arc::new(SlackRateLimiter::new(
+            config.limits.slack_request_queue_size,
+            Duration::from_millis(config.limits.slack_send_delay),
+            config.limits.slack_rate_limit_retries,
+        ));
+
+        let mut api_base_url = config
+            .auth
+            .api_base_url
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .unwrap_or(SLACK_API_BASE_URL)
+            .to_string();
+        if !api_base_url.ends_with('/') {
+            api_base_url.push('/');
+        }
 
         Ok(Self {
             _config: config,
-            send_lock: Arc::new(tokio::sync::Mutex::new(())),
+            rate_limiter,
+            api_base_url,
             login_state: Arc::new(tokio::sync::Mutex::new(SlackLoginState::default())),
             bridge: Arc::new(RwLock::new(None)),
             http,
+            file_http,
             bot_user_id: Arc::new(RwLock::new(None)),
             bot_id: Arc::new(RwLock::new(None)),
             team_id: Arc::new(RwLock::new(None)),
             permission_cache: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            channel_directory: Arc::new(AsyncTimedCache::new(Duration::from_secs(
+                DIRECTORY_CACHE_TTL_SECONDS,
+            ))),
+            user_directory: Arc::new(AsyncTimedCache::new(Duration::from_secs(
+                DIRECTORY_CACHE_TTL_SECONDS,
+            ))),
+            emoji_list: Arc::new(AsyncTimedCache::new(Duration::from_secs(
+                EMOJI_LIST_CACHE_TTL_SECONDS,
+            ))),
+            puppet: None,
+            puppet_posts: Arc::new(AsyncTimedCache::new(Duration::from_secs(
+                PUPPET_ECHO_TTL_SECONDS,
+            ))),
+            puppet_sends: Arc::new(ChannelQueue::new()),
         })
     }
 
+    /// A client that posts, edits, deletes and reacts as `slack_user_id` with their user
+    /// token. Everything else still uses the bot token.
+    pub fn as_puppet(&self, slack_user_id: &str, access_token: &str) -> Self {
+        Self {
+            puppet: Some(Arc::new(PuppetAuth {
+                slack_user_id: slack_user_id.to_string(),
+                access_token: access_token.to_string(),
+            })),
+            ..self.clone()
+        }
+    }
+
+    /// The Slack user a puppet client acts as.
+    pub fn puppet_user_id(&self) -> Option<&str> {
+        self.puppet
+            .as_ref()
+            .map(|puppet| puppet.slack_user_id.as_str())
+    }
+
     pub async fn set_bridge(&self, bridge: Arc<BridgeCore>) {
         *self.bridge.write().await = Some(bridge);
     }
@@ -133,6 +304,15 @@ impl SlackClient {
         *self.bot_id.write().await = auth.bot_id;
         *self.team_id.write().await = auth.team_id;
 
+        if self._config.auth.event_mode == SlackEventMode::Http {
+            state.is_logged_in = true;
+            info!(
+                "slack events api mode, waiting for requests user={}",
+                auth.user_id
+            );
+            return Ok(());
+        }
+
         let client = self.clone();
         let gateway_task = tokio::spawn(async move {
             client.socket_mode_loop().await;
@@ -181,6 +361,11 @@ impl SlackClient {
         Ok(())
     }
 
+    /// The bot's own Slack user id, known once `auth.test` succeeded at login.
+    pub async fn bot_user_id(&self) -> Option<String> {
+        self.bot_user_id.read().await.clone()
+    }
+
     pub async fn send_message(&self, channel_id: &str, content: &str) -> Result<String> {
         self.send_message_with_metadata(channel_id, content, &[], None, None)
             .await
@@ -217,36 +402,62 @@ impl SlackClient {
         username: Option<&str>,
         avatar_url: Option<&str>,
     ) -> Result<String> {
-        let _guard = self.send_lock.lock().await;
-        let delay = self._config.limits.slack_send_delay;
-        if delay > 0 {
-            tokio::time::sleep(Duration::from_millis(delay)).await;
-        }
-
-        let mut text = content.trim().to_string();
-        for attachment in attachments {
-            if !text.is_empty() {
-                text.push('\n');
-            }
-            text.push_str(attachment);
-        }
-        if text.is_empty() {
-            text = "(empty message)".to_string();
-        }
-        if let Some(name) = username {
-            text = format!("*{}*: {}", name, text);
-        }
-
+        let text = render_user_text(content, attachments, username);
         if let Some(ts) = edit_of {
             return self
                 .chat_update(channel_id, ts, &text, username, avatar_url)
                 .await;
         }
 
-        self.chat_post_message(channel_id, &text, reply_to, username, avatar_url)
+        self.chat_post_message(channel_id, &text, reply_to, None, username, avatar_url)
+            .await
+    }
+
+    /// Posts a message in the channel with a context block pointing at the message it
+    /// replies to, for replies that should not open a thread.
+    pub async fn send_quoted_message_as_user(
+        &self,
+        channel_id: &str,
+        content: &str,
+        quoted_permalink: &str,
+        username: Option<&str>,
+        avatar_url: Option<&str>,
+    ) -> Result<String> {
+        let text = render_user_text(content, &[], username);
+        let blocks = json!([
+            {
+                "type": "context",
+                "elements": [{
+                    "type": "mrkdwn",
+                    "text": format!("Replying to <{quoted_permalink}|this message>"),
+                }],
+            },
+            {
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": text },
+            },
+        ]);
+        self.chat_post_message(channel_id, &text, None, Some(blocks), username, avatar_url)
             .await
     }
 
+    /// Link to a message, used when quoting it from another message.
+    pub async fn get_permalink(&self, channel_id: &str, message_ts: &str) -> Result<String> {
+        let bot_token = self.bot_token()?;
+        let payload = json!({
+            "channel": channel_id,
+            "message_ts": message_ts
+        });
+        let response = self
+            .slack_api_post("chat.getPermalink", &bot_token, payload)
+            .await?;
+        response
+            .get("permalink")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow!("chat.getPermalink missing permalink"))
+    }
+
     pub async fn send_embed_as_user(
         &self,
         channel_id: &str,
@@ -287,6 +498,66 @@ impl SlackClient {
         .await
     }
 
+    /// Opens a `url_private`/`url_private_download` link with the bot token. The body is
+    /// left unread so callers can stream it; a login page in place of the file is an error.
+    pub async fn download_file(&self, url: &str) -> Result<MediaStream> {
+        let bot_token = self.bot_token()?;
+        let mut current = reqwest::Url::parse(url).with_context(|| format!("invalid file URL {url}"))?;
+
+        for _ in 0..=MAX_FILE_REDIRECTS {
+            let mut request = self.file_http.get(current.clone());
+            if self.is_slack_file_host(&current) {
+                request = request.bearer_auth(&bot_token);
+            }
+            let response = request
+                .send()
+                .await
+                .with_context(|| format!("failed to download Slack file {url}"))?;
+
+            let status = response.status();
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| anyhow!("redirect without location for Slack file {url}"))?;
+                current = current
+                    .join(location)
+                    .with_context(|| format!("invalid redirect for Slack file {url}"))?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(anyhow!("failed to download Slack file {url}: status {status}"));
+            }
+
+            let stream = MediaStream::from_response(response, url);
+            if stream.content_type == "text/html" && !stream.filename.ends_with(".html") {
+                return Err(anyhow!(
+                    "Slack returned an HTML page for {url}; check the bot has the files:read scope"
+                ));
+            }
+            return Ok(stream);
+        }
+
+        Err(anyhow!("too many redirects downloading Slack file {url}"))
+    }
+
+    /// Whether the bot token may be sent to `url`: Slack's own hosts over https, or the
+    /// configured API origin as-is.
+    fn is_slack_file_host(&self, url: &reqwest::Url) -> bool {
+        if reqwest::Url::parse(&self.api_base_url).is_ok_and(|base| base.origin() == url.origin()) {
+            return true;
+        }
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        url.scheme() == "https"
+            && (host == "slack.com"
+                || host.ends_with(".slack.com")
+                || host.ends_with(".slack-edge.com"))
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub async fn send_file_as_user(
         &self,
         channel_id: &str,
@@ -295,14 +566,13 @@ impl SlackClient {
         filename: &str,
         username: Option<&str>,
         avatar_url: Option<&str>,
+        thread_ts: Option<&str>,
     ) -> Result<String> {
-        let _guard = self.send_lock.lock().await;
-
-        let bot_token = self.bot_token()?;
+        let token = self.write_token()?;
         let reserve = self
             .slack_api_post(
                 "files.getUploadURLExternal",
-                &bot_token,
+                &token,
                 json!({
                     "filename": filename,
                     "length": data.len()
@@ -319,6 +589,9 @@ impl SlackClient {
             .and_then(Value::as_str)
             .ok_or_else(|| anyhow!("files.getUploadURLExternal missing file_id"))?
             .to_string();
+        // The share message may come back before the upload completes.
+        self.remember_puppet_post(channel_id, &format!("file:{file_id}"))
+            .await;
 
         let upload_response = self
             .http
@@ -339,9 +612,12 @@ impl SlackClient {
         if let Some(name) = username {
             payload["initial_comment"] = json!(format!("Uploaded by {name}"));
         }
+        if let Some(thread_ts) = thread_ts {
+            payload["thread_ts"] = json!(thread_ts);
+        }
 
         let _ = self
-            .slack_api_post("files.completeUploadExternal", &bot_token, payload)
+            .slack_api_post("files.completeUploadExternal", &token, payload)
             .await?;
 
         let _ = avatar_url;
@@ -349,25 +625,29 @@ impl SlackClient {
     }
 
     pub async fn add_reaction(&self, channel_id: &str, message_ts: &str, emoji: &str) -> Result<()> {
-        let bot_token = self.bot_token()?;
+        let token = self.write_token()?;
         let payload = json!({
             "channel": channel_id,
             "timestamp": message_ts,
             "name": emoji
         });
-        self.slack_api_post("reactions.add", &bot_token, payload).await?;
-        Ok(())
+        match self.slack_api_post("reactions.add", &token, payload).await {
+            Err(err) if is_slack_error(&err, "already_reacted") => Ok(()),
+            result => result.map(|_| ()),
+        }
     }
 
     pub async fn remove_reaction(&self, channel_id: &str, message_ts: &str, emoji: &str) -> Result<()> {
-        let bot_token = self.bot_token()?;
+        let token = self.write_token()?;
         let payload = json!({
             "channel": channel_id,
             "timestamp": message_ts,
             "name": emoji
         });
-        self.slack_api_post("reactions.remove", &bot_token, payload).await?;
-        Ok(())
+        match self.slack_api_post("reactions.remove", &token, payload).await {
+            Err(err) if is_slack_error(&err, "no_reaction") => Ok(()),
+            result => result.map(|_| ()),
+        }
     }
 
     pub async fn get_conversation_history(&self, channel_id: &str, limit: Option<u32>, cursor: Option<&str>) -> Result<Value> {
@@ -398,23 +678,9 @@ impl SlackClient {
             }
         };
 
-        let user = match value.get("user") {
-            Some(user) => user,
-            None => return Ok(None),
-        };
-        let username = extract_display_name(user).unwrap_or_else(|| user_id.to_string());
-        let avatar = user
-            .pointer("/profile/image_512")
-            .and_then(Value::as_str)
-            .or_else(|| user.pointer("/profile/image_192").and_then(Value::as_str))
-            .map(ToOwned::to_owned);
-
-        Ok(Some(SlackUser {
-            id: user_id.to_string(),
-            username,
-            discriminator: "0000".to_string(),
-            avatar,
-        }))
+        Ok(value
+            .get("user")
+            .map(|user| SlackUser::from_value(user_id, user)))
     }
 
     pub async fn clear_channel_member_overwrite(
@@ -458,45 +724,203 @@ impl SlackClient {
             }
         };
 
-        let channel = match value.get("channel") {
-            Some(channel) => channel,
-            None => return Ok(None),
+        let Some(channel) = value.get("channel") else {
+            return Ok(None);
         };
 
-        let fallback_team = self
-            .team_id
+        let fallback_team = self.fallback_team_id().await;
+        let mut channel = json!(channel);
+        channel["id"] = json!(channel_id);
+        Ok(SlackChannel::from_value(&channel, &fallback_team))
+    }
+
+    /// Every public channel of the workspace, cached for directory searches.
+    pub async fn list_public_channels(&self) -> Result<Arc<Vec<SlackChannel>>> {
+        if let Some(channels) = self.channel_directory.get(&()).await {
+            return Ok(channels);
+        }
+
+        let fallback_team = self.fallback_team_id().await;
+        let channels: Vec<SlackChannel> = self
+            .list_all(
+                "conversations.list",
+                json!({ "types": "public_channel", "exclude_archived": true }),
+                "channels",
+            )
+            .await?
+            .iter()
+            .filter_map(|channel| SlackChannel::from_value(channel, &fallback_team))
+            .collect();
+        let channels = Arc::new(channels);
+        self.channel_directory.insert((), channels.clone()).await;
+        Ok(channels)
+    }
+
+    /// Every active member of the workspace, cached for directory searches.
+    pub async fn list_users(&self) -> Result<Arc<Vec<SlackUser>>> {
+        if let Some(users) = self.user_directory.get(&()).await {
+            return Ok(users);
+        }
+
+        let users: Vec<SlackUser> = self
+            .list_all("users.list", json!({}), "members")
+            .await?
+            .iter()
+            .filter_map(|user| {
+                let user_id = user.get("id").and_then(Value::as_str)?;
+                Some(SlackUser::from_value(user_id, user))
+            })
+            .filter(|user| !user.deleted)
+            .collect();
+        let users = Arc::new(users);
+        self.user_directory.insert((), users.clone()).await;
+        Ok(users)
+    }
+
+    /// Follows `response_metadata.next_cursor` and collects the `key` array of every page.
+    async fn list_all(&self, method: &str, payload: Value, key: &str) -> Result<Vec<Value>> {
+        let bot_token = self.bot_token()?;
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        for _ in 0..MAX_DIRECTORY_PAGES {
+            let mut page_payload = payload.clone();
+            page_payload["limit"] = json!(DIRECTORY_PAGE_SIZE);
+            if let Some(cursor) = &cursor {
+                page_payload["cursor"] = json!(cursor);
+            }
+            let page = self.slack_api_post(method, &bot_token, page_payload).await?;
+            if let Some(page_items) = page.get(key).and_then(Value::as_array) {
+                items.extend(page_items.iter().cloned());
+            }
+            cursor = page
+                .pointer("/response_metadata/next_cursor")
+                .and_then(Value::as_str)
+                .filter(|cursor| !cursor.is_empty())
+                .map(ToOwned::to_owned);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Opens (or finds) the DM or group DM between the bot and `user_ids`, returning its id.
+    /// Opens a DM or group DM with `user_ids`, as the puppet on puppet clients.
+    pub async fn open_conversation(&self, user_ids: &[&str]) -> Result<String> {
+        let token = self.write_token()?;
+        let value = self
+            .slack_api_post(
+                "conversations.open",
+                &token,
+                json!({ "users": user_ids.join(",") }),
+            )
+            .await?;
+        value
+            .pointer("/channel/id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("conversations.open response has no channel id"))
+    }
+
+    /// Adds the bot to a public channel. Needs the `channels:join` scope.
+    pub async fn join_channel(&self, channel_id: &str) -> Result<()> {
+        let bot_token = self.bot_token()?;
+        self.slack_api_post(
+            "conversations.join",
+            &bot_token,
+            json!({ "channel": channel_id }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// User ids of everyone in a conversation, the bot included.
+    pub async fn get_conversation_members(&self, channel_id: &str) -> Result<Vec<String>> {
+        Ok(self
+            .list_all(
+                "conversations.members",
+                json!({ "channel": channel_id }),
+                "members",
+            )
+            .await?
+            .iter()
+            .filter_map(Value::as_str)
+            .map(ToOwned::to_owned)
+            .collect())
+    }
+
+    pub(crate) async fn fallback_team_id(&self) -> String {
+        self.team_id
             .read()
             .await
             .clone()
-            .unwrap_or_else(|| "slack".to_string());
-
-        Ok(Some(SlackChannel {
-            id: channel_id.to_string(),
-            name: channel
-                .get("name")
-                .and_then(Value::as_str)
-                .unwrap_or(channel_id)
-                .to_string(),
-            guild_id: channel
-                .get("context_team_id")
-                .and_then(Value::as_str)
-                .map(ToOwned::to_owned)
-                .unwrap_or(fallback_team),
-            topic: channel
-                .pointer("/topic/value")
-                .and_then(Value::as_str)
-                .map(ToOwned::to_owned),
-        }))
+            .unwrap_or_else(|| "slack".to_string())
     }
 
     /// Delete a message from a channel using chat.delete API
     pub async fn delete_message(&self, channel_id: &str, message_ts: &str) -> Result<()> {
-        let bot_token = self.bot_token()?;
+        let token = self.write_token()?;
         let payload = json!({
             "channel": channel_id,
             "ts": message_ts
         });
-        self.slack_api_post("chat.delete", &bot_token, payload).await?;
+        self.slack_api_post("chat.delete", &token, payload).await?;
+        Ok(())
+    }
+
+    /// Exchanges the `code` of an OAuth v2 redirect for the user token it grants.
+    pub async fn oauth_access(&self, code: &str, redirect_uri: &str) -> Result<SlackUserLogin> {
+        let auth = &self._config.auth;
+        let (Some(client_id), Some(client_secret)) =
+            (auth.client_id.as_deref(), auth.client_secret.as_deref())
+        else {
+            return Err(anyhow!("auth.client_id and auth.client_secret are required"));
+        };
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("code", code)
+            .append_pair("redirect_uri", redirect_uri)
+            .finish();
+        let value: Value = self
+            .http
+            .post(format!("{}oauth.v2.access", self.api_base_url))
+            .basic_auth(client_id, Some(client_secret))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .context("request to Slack API method oauth.v2.access failed")?
+            .json()
+            .await
+            .context("Slack API method oauth.v2.access returned non-JSON body")?;
+        if !value.get("ok").and_then(Value::as_bool).unwrap_or(false) {
+            let code = value
+                .get("error")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown_error");
+            return Err(anyhow!(
+                "Slack API oauth.v2.access returned ok=false: {}",
+                code
+            ));
+        }
+
+        let field = |pointer: &str| {
+            value
+                .pointer(pointer)
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| anyhow!("oauth.v2.access response has no {pointer}"))
+        };
+        Ok(SlackUserLogin {
+            user_id: field("/authed_user/id")?,
+            team_id: field("/team/id")?,
+            access_token: field("/authed_user/access_token")?,
+        })
+    }
+
+    /// Revokes the token a puppet client posts with.
+    pub async fn revoke_token(&self) -> Result<()> {
+        let token = self.write_token()?;
+        self.slack_api_post("auth.revoke", &token, json!({})).await?;
         Ok(())
     }
 
@@ -561,13 +985,41 @@ impl SlackClient {
         Ok(result)
     }
 
-    /// Get list of custom emojis for the team
-    pub async fn get_emoji_list(&self) -> Result<Value> {
+    /// Get list of custom emojis for the team, cached until it expires or an
+    /// `emoji_changed` event arrives.
+    pub async fn get_emoji_list(&self) -> Result<Arc<Value>> {
+        if let Some(list) = self.emoji_list.get(&()).await {
+            return Ok(list);
+        }
         let bot_token = self.bot_token()?;
-        let result = self.slack_api_post("emoji.list", &bot_token, json!({})).await?;
+        let result = Arc::new(self.slack_api_post("emoji.list", &bot_token, json!({})).await?);
+        self.emoji_list.insert((), result.clone()).await;
         Ok(result)
     }
 
+    /// Get a user's presence (`users.getPresence`)
+    pub async fn get_user_presence(&self, user_id: &str) -> Result<Value> {
+        let bot_token = self.bot_token()?;
+        self.slack_api_post("users.getPresence", &bot_token, json!({ "user": user_id }))
+            .await
+    }
+
+    /// Get a user's Do Not Disturb state (`dnd.info`)
+    pub async fn get_dnd_info(&self, user_id: &str) -> Result<Value> {
+        let bot_token = self.bot_token()?;
+        self.slack_api_post("dnd.info", &bot_token, json!({ "user": user_id }))
+            .await
+    }
+
+    /// Get a user's profile, including their custom status (`users.profile.get`)
+    pub async fn get_user_profile(&self, user_id: &str) -> Result<Value> {
+        let bot_token = self.bot_token()?;
+        let result = self
+            .slack_api_post("users.profile.get", &bot_token, json!({ "user": user_id }))
+            .await?;
+        Ok(result.get("profile").cloned().unwrap_or(Value::Null))
+    }
+
     /// Set the typing indicator in a channel
     pub async fn set_typing(&self, channel_id: &str) -> Result<()> {
         let bot_token = self.bot_token()?;
@@ -663,22 +1115,84 @@ impl SlackClient {
                 .context("failed to ack slack envelope")?;
         }
 
-        if payload.get("type").and_then(Value::as_str) == Some("disconnect") {
-            return Err(anyhow!("received disconnect from Slack"));
+        match payload.get("type").and_then(Value::as_str) {
+            Some("disconnect") => return Err(anyhow!("received disconnect from Slack")),
+            Some("events_api") => {}
+            _ => return Ok(()),
         }
 
-        let Some(events_api) = payload.get("payload") else {
+        let Some(callback) = payload.get("payload") else {
             return Ok(());
         };
-        if events_api.get("type").and_then(Value::as_str) != Some("events_api") {
+        let retry_attempt = payload
+            .get("retry_attempt")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        let retry_reason = payload
+            .get("retry_reason")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        self.dispatch_event_callback(
+            callback,
+            payload.get("envelope_id").and_then(Value::as_str),
+            retry_attempt,
+            retry_reason,
+        )
+        .await
+    }
+
+    /// Entry point shared by Socket Mode and the Events API receiver: drops deliveries
+    /// that were already handled, then hands the inner event to `handle_event`.
+    pub async fn dispatch_event_callback(
+        &self,
+        callback: &Value,
+        delivery_id: Option<&str>,
+        retry_attempt: u64,
+        retry_reason: &str,
+    ) -> Result<()> {
+        if retry_attempt > 0 {
+            Metrics::slack_event_redelivered();
+            debug!(
+                "slack event redelivered attempt={} reason={}",
+                retry_attempt, retry_reason
+            );
+        }
+        if !self.claim_event_callback(callback, delivery_id).await {
             return Ok(());
         }
-        let Some(event) = events_api.get("event") else {
+        let Some(event) = callback.get("event") else {
             return Ok(());
         };
         self.handle_event(event).await
     }
 
+    /// Records an `event_callback` as handled, keyed by its `event_id` (falling back to
+    /// `delivery_id`), and returns `false` for deliveries that were already processed.
+    async fn claim_event_callback(&self, callback: &Value, delivery_id: Option<&str>) -> bool {
+        let Some(key) = callback
+            .get("event_id")
+            .and_then(Value::as_str)
+            .or(delivery_id)
+        else {
+            return true;
+        };
+        let Some(bridge) = self.bridge.read().await.clone() else {
+            return true;
+        };
+        let event_type = callback
+            .pointer("/event/type")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown");
+        let fresh = bridge
+            .deduplicator()
+            .claim(SOURCE_SLACK, event_type, key)
+            .await;
+        if !fresh {
+            Metrics::slack_event_duplicate();
+        }
+        fresh
+    }
+
     async fn handle_event(&self, event: &Value) -> Result<()> {
         match event.get("type").and_then(Value::as_str).unwrap_or("") {
             "message" => self.handle_message_event(event).await?,
@@ -694,6 +1208,7 @@ impl SlackClient {
             "channel_rename" => self.handle_channel_rename_event(event).await?,
             "channel_archive" => self.handle_channel_archive_event(event).await?,
             "emoji_changed" => self.handle_emoji_changed_event(event).await?,
+            "file_deleted" => self.handle_file_deleted_event(event).await?,
             "channel_created" => {
                 let ch_id = event
                     .pointer("/channel/id")
@@ -707,31 +1222,27 @@ impl SlackClient {
                     .pointer("/channel/id")
                     .or_else(|| event.get("channel"))
                     .and_then(Value::as_str);
-                if let Some(channel_id) = channel_id {
-                    if let Some(bot_user_id) = self.bot_user_id.read().await.clone() {
-                        if let Some(bridge) = self.bridge.read().await.clone()
-                            && let Err(err) = bridge
-                                .handle_slack_member_joined_channel(channel_id, &bot_user_id)
-                                .await
-                        {
-                            error!("failed to forward slack channel_joined event: {}", err);
-                        }
-                    }
+                if let Some(channel_id) = channel_id
+                    && let Some(bot_user_id) = self.bot_user_id.read().await.clone()
+                    && let Some(bridge) = self.bridge.read().await.clone()
+                    && let Err(err) = bridge
+                        .handle_slack_member_joined_channel(channel_id, &bot_user_id)
+                        .await
+                {
+                    error!("failed to forward slack channel_joined event: {}", err);
                 }
             }
             "channel_left" => {
                 // channel_left is like member_left_channel but for the bot itself
                 let channel_id = event.get("channel").and_then(Value::as_str);
-                if let Some(channel_id) = channel_id {
-                    if let Some(bot_user_id) = self.bot_user_id.read().await.clone() {
-                        if let Some(bridge) = self.bridge.read().await.clone()
-                            && let Err(err) = bridge
-                                .handle_slack_member_left_channel(channel_id, &bot_user_id)
-                                .await
-                        {
-                            error!("failed to forward slack channel_left event: {}", err);
-                        }
-                    }
+                if let Some(channel_id) = channel_id
+                    && let Some(bot_user_id) = self.bot_user_id.read().await.clone()
+                    && let Some(bridge) = self.bridge.read().await.clone()
+                    && let Err(err) = bridge
+                        .handle_slack_member_left_channel(channel_id, &bot_user_id)
+                        .await
+                {
+                    error!("failed to forward slack channel_left event: {}", err);
                 }
             }
             _ => {}
@@ -782,6 +1293,7 @@ impl SlackClient {
     async fn handle_emoji_changed_event(&self, event: &Value) -> Result<()> {
         let subtype = event.get("subtype").and_then(Value::as_str).unwrap_or("");
         debug!("emoji_changed event: subtype={}", subtype);
+        self.emoji_list.clear().await;
         if let Some(bridge) = self.bridge.read().await.clone()
             && let Err(err) = bridge.handle_slack_emoji_changed(event).await
         {
@@ -821,15 +1333,9 @@ impl SlackClient {
             return Ok(());
         }
 
-        let display_name = extract_display_name(user).unwrap_or_else(|| user_id.to_string());
-        let avatar_url = user
-            .pointer("/profile/image_512")
-            .and_then(Value::as_str)
-            .or_else(|| user.pointer("/profile/image_192").and_then(Value::as_str));
-
         if let Some(bridge) = self.bridge.read().await.clone()
             && let Err(err) = bridge
-                .handle_slack_user_update(user_id, &display_name, avatar_url)
+                .handle_slack_user_update(&SlackUser::from_value(user_id, user))
                 .await
         {
             error!("failed to forward slack user_change event: {}", err);
@@ -855,6 +1361,8 @@ impl SlackClient {
         if self.is_own_message(Some(user_id), None).await {
             return Ok(());
         }
+        // A puppet's reaction is recorded once its reactions.add returns.
+        drop(self.puppet_sends.lock(channel_id).await);
 
         if let Some(bridge) = self.bridge.read().await.clone()
             && let Err(err) = bridge
@@ -976,9 +1484,19 @@ impl SlackClient {
                 else {
                     return Ok(());
                 };
+                let file_ids: Vec<String> = event
+                    .pointer("/previous_message/files")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|file| file.get("id").and_then(Value::as_str))
+                    .map(ToOwned::to_owned)
+                    .collect();
 
                 if let Some(bridge) = self.bridge.read().await.clone()
-                    && let Err(err) = bridge.handle_slack_message_delete(channel_id, deleted_ts).await
+                    && let Err(err) = bridge
+                        .handle_slack_message_delete(channel_id, deleted_ts, &file_ids)
+                        .await
                 {
                     error!("failed to forward slack delete event: {}", err);
                 }
@@ -988,9 +1506,19 @@ impl SlackClient {
                 let Some(channel_id) = event.get("channel").and_then(Value::as_str) else {
                     return Ok(());
                 };
-                if let Some(message) = event.get("message") {
-                    self.forward_message(channel_id, message, true).await?;
+                let Some(message) = event.get("message") else {
+                    return Ok(());
+                };
+                let text_unchanged =
+                    event.pointer("/previous_message/text") == message.get("text");
+                if text_unchanged
+                    && let Some(files) = message.get("files").and_then(Value::as_array)
+                {
+                    // Only the attached files changed, usually one removed from the message.
+                    self.forward_file_changes(channel_id, message, files).await;
+                    return Ok(());
                 }
+                self.forward_message(channel_id, message, true).await?;
                 return Ok(());
             }
             Some("channel_topic") | Some("group_topic") => {
@@ -1038,8 +1566,11 @@ impl SlackClient {
             Some("me_message") => {
                 // Treat /me messages as regular messages (emote)
             }
+            Some("file_share") => {
+                // Files are bridged from the message's `files` array below
+            }
             Some("thread_broadcast") => {
-                // Treat thread broadcast as regular message
+                // Thread reply also sent to the channel; flagged as a broadcast below
             }
             Some("bot_message") => {
                 // Forward bot messages that have text content
@@ -1062,25 +1593,98 @@ impl SlackClient {
         self.forward_message(channel_id, event, false).await
     }
 
-    async fn forward_message(&self, channel_id: &str, message: &Value, is_edit: bool) -> Result<()> {
+    async fn forward_file_changes(&self, channel_id: &str, message: &Value, files: &[Value]) {
         let sender_id = message.get("user").and_then(Value::as_str);
         let bot_id = message.get("bot_id").and_then(Value::as_str);
         if self.is_own_message(sender_id, bot_id).await {
+            return;
+        }
+        let Some(sender_id) = sender_id.or(bot_id) else {
+            return;
+        };
+        if let Some(bridge) = self.bridge.read().await.clone()
+            && let Err(err) = bridge
+                .handle_slack_file_changes(channel_id, sender_id, files)
+                .await
+        {
+            error!("failed to forward slack file changes: {}", err);
+        }
+    }
+
+    async fn handle_file_deleted_event(&self, event: &Value) -> Result<()> {
+        let Some(file_id) = event.get("file_id").and_then(Value::as_str) else {
             return Ok(());
+        };
+        if let Some(bridge) = self.bridge.read().await.clone()
+            && let Err(err) = bridge.handle_slack_file_deleted(file_id).await
+        {
+            error!("failed to forward slack file deletion: {}", err);
         }
+        Ok(())
+    }
 
-        // Use user id if available, fall back to bot_id for bot_message subtypes
-        let Some(sender_id) = sender_id.or(bot_id) else {
+    async fn forward_message(&self, channel_id: &str, message: &Value, is_edit: bool) -> Result<()> {
+        let Some(ctx) = self.message_context(channel_id, message, is_edit).await else {
             return Ok(());
         };
-        let Some(message_ts) = message.get("ts").and_then(Value::as_str) else {
+        let Some(bridge) = self.bridge.read().await.clone() else {
+            debug!("slack message received before bridge binding");
             return Ok(());
         };
 
+        // DMs with the bot get their Matrix room when the first message arrives.
+        let channel_type = message.get("channel_type").and_then(Value::as_str);
+        if !is_edit
+            && matches!(channel_type, Some("im") | Some("mpim"))
+            && let Err(err) = bridge.ensure_direct_portal(channel_id).await
+        {
+            error!(
+                "failed to open matrix room for slack conversation {}: {}",
+                channel_id, err
+            );
+        }
+
+        if let Err(err) = bridge.handle_slack_message_with_context(ctx).await {
+            error!("failed to forward slack message to bridge: {}", err);
+            bridge.report_error("Slack message", &err).await;
+        }
+
+        Ok(())
+    }
+
+    /// Builds the bridge context for a Slack message object, as delivered live or returned
+    /// by `conversations.history`. Returns `None` for the bridge's own and sender-less messages.
+    pub(crate) async fn message_context(
+        &self,
+        channel_id: &str,
+        message: &Value,
+        is_edit: bool,
+    ) -> Option<SlackMessageContext> {
+        let sender_id = message.get("user").and_then(Value::as_str);
+        let bot_id = message.get("bot_id").and_then(Value::as_str);
+        if self.is_own_message(sender_id, bot_id).await {
+            return None;
+        }
+        if self.is_puppet_echo(channel_id, message).await {
+            debug!(
+                "dropping echo of puppeted message {:?} in {}",
+                message.get("ts"),
+                channel_id
+            );
+            return None;
+        }
+
+        // Use user id if available, fall back to bot_id for bot_message subtypes
+        let sender_id = sender_id.or(bot_id)?;
+        let message_ts = message.get("ts").and_then(Value::as_str)?;
+
         let thread_ts = message.get("thread_ts").and_then(Value::as_str);
-        let reply_to = thread_ts
-            .filter(|thread| *thread != message_ts)
-            .map(ToOwned::to_owned);
+        let reply_broadcast = message.get("subtype").and_then(Value::as_str)
+            == Some("thread_broadcast")
+            || message
+                .get("reply_broadcast")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
         let text = normalize_slack_text(
             message
                 .get("text")
@@ -1099,36 +1703,26 @@ impl SlackClient {
             .and_then(Value::as_array)
             .cloned();
 
-        let Some(bridge) = self.bridge.read().await.clone() else {
-            debug!("slack message received before bridge binding");
-            return Ok(());
-        };
-
-        if let Err(err) = bridge
-            .handle_slack_message_with_context(SlackMessageContext {
-                channel_id: channel_id.to_string(),
-                source_message_id: Some(message_ts.to_string()),
-                sender_id: sender_id.to_string(),
-                content: text,
-                attachments,
-                reply_to,
-                edit_of: if is_edit {
-                    Some(message_ts.to_string())
-                } else {
-                    None
-                },
-                permissions,
-                thread_ts: thread_ts.map(ToOwned::to_owned),
-                blocks,
-                slack_attachments,
-                files: files_json,
-            })
-            .await
-        {
-            error!("failed to forward slack message to bridge: {}", err);
-        }
-
-        Ok(())
+        Some(SlackMessageContext {
+            channel_id: channel_id.to_string(),
+            source_message_id: Some(message_ts.to_string()),
+            sender_id: sender_id.to_string(),
+            content: text,
+            attachments,
+            reply_to: None,
+            edit_of: if is_edit {
+                Some(message_ts.to_string())
+            } else {
+                None
+            },
+            permissions,
+            thread_ts: thread_ts.map(ToOwned::to_owned),
+            reply_broadcast,
+            backfill_ts: None,
+            blocks,
+            slack_attachments,
+            files: files_json,
+        })
     }
 
     async fn resolve_permissions(&self, user_id: &str) -> HashSet<String> {
@@ -1199,10 +1793,11 @@ impl SlackClient {
         channel_id: &str,
         text: &str,
         thread_ts: Option<&str>,
+        blocks: Option<Value>,
         username: Option<&str>,
         avatar_url: Option<&str>,
     ) -> Result<String> {
-        let bot_token = self.bot_token()?;
+        let token = self.write_token()?;
         let mut payload = json!({
             "channel": channel_id,
             "text": text,
@@ -1212,20 +1807,26 @@ impl SlackClient {
         if let Some(thread_ts) = thread_ts {
             payload["thread_ts"] = json!(thread_ts);
         }
+        if let Some(blocks) = blocks {
+            payload["blocks"] = blocks;
+        }
+        let _sending = self.lock_puppet_sends(channel_id).await;
         let response = self
             .post_chat_payload_with_customize_fallback(
                 "chat.postMessage",
-                &bot_token,
+                &token,
                 payload,
                 username,
                 avatar_url,
             )
             .await?;
-        response
+        let ts = response
             .get("ts")
             .and_then(Value::as_str)
             .map(ToOwned::to_owned)
-            .ok_or_else(|| anyhow!("chat.postMessage missing ts"))
+            .ok_or_else(|| anyhow!("chat.postMessage missing ts"))?;
+        self.remember_puppet_post(channel_id, &ts).await;
+        Ok(ts)
     }
 
     async fn chat_update(
@@ -1236,26 +1837,81 @@ impl SlackClient {
         username: Option<&str>,
         avatar_url: Option<&str>,
     ) -> Result<String> {
-        let bot_token = self.bot_token()?;
+        let token = self.write_token()?;
         let payload = json!({
             "channel": channel_id,
             "ts": message_ts,
             "text": text
         });
+        let _sending = self.lock_puppet_sends(channel_id).await;
         let response = self
             .post_chat_payload_with_customize_fallback(
                 "chat.update",
-                &bot_token,
+                &token,
                 payload,
                 username,
                 avatar_url,
             )
             .await?;
-        response
+        let ts = response
             .get("ts")
             .and_then(Value::as_str)
             .map(ToOwned::to_owned)
-            .ok_or_else(|| anyhow!("chat.update missing ts"))
+            .ok_or_else(|| anyhow!("chat.update missing ts"))?;
+        self.remember_puppet_post(channel_id, &ts).await;
+        Ok(ts)
+    }
+
+    async fn remember_puppet_post(&self, channel_id: &str, ts: &str) {
+        if self.puppet.is_some() {
+            self.puppet_posts
+                .insert(format!("{channel_id}:{ts}"), ())
+                .await;
+        }
+    }
+
+    /// Locks the channel for a puppet's post or reaction; `None` for the bot, whose echoes
+    /// are recognised by their sender.
+    pub(crate) async fn lock_puppet_sends(
+        &self,
+        channel_id: &str,
+    ) -> Option<tokio::sync::OwnedMutexGuard<()>> {
+        match self.puppet {
+            Some(_) => Some(self.puppet_sends.lock(channel_id).await),
+            None => None,
+        }
+    }
+
+    /// Whether `message` is a puppet's own post or upload coming back from Slack.
+    async fn is_puppet_echo(&self, channel_id: &str, message: &Value) -> bool {
+        // Wait for posts in flight so their ts is known.
+        drop(self.puppet_sends.lock(channel_id).await);
+        if let Some(ts) = message.get("ts").and_then(Value::as_str)
+            && self
+                .puppet_posts
+                .get(&format!("{channel_id}:{ts}"))
+                .await
+                .is_some()
+        {
+            return true;
+        }
+        for file_id in message
+            .get("files")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|file| file.get("id").and_then(Value::as_str))
+        {
+            if self
+                .puppet_posts
+                .get(&format!("{channel_id}:file:{file_id}"))
+                .await
+                .is_some()
+            {
+                return true;
+            }
+        }
+        false
     }
 
     async fn post_chat_payload_with_customize_fallback(
@@ -1322,14 +1978,41 @@ impl SlackClient {
     }
 
     async fn slack_api_post(&self, method: &str, token: &str, payload: Value) -> Result<Value> {
-        let response = self
-            .http
-            .post(format!("https://slack.com/api/{method}"))
-            .bearer_auth(token)
-            .json(&payload)
-            .send()
-            .await
-            .with_context(|| format!("request to Slack API method {method} failed"))?;
+        let channel = payload.get("channel").and_then(Value::as_str);
+        let mut attempt = 0;
+        let response = loop {
+            let _permit = self.rate_limiter.acquire(method, channel).await?;
+            let response = self
+                .http
+                .post(format!("{}{method}", self.api_base_url))
+                .bearer_auth(token)
+                .json(&payload)
+                .send()
+                .await
+                .with_context(|| format!("request to Slack API method {method} failed"))?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                break response;
+            }
+
+            Metrics::slack_rate_limited();
+            let retry_after = rate_limit::retry_after(response.headers());
+            if attempt >= self.rate_limiter.max_retries() {
+                return Err(anyhow!(
+                    "Slack API {} rate limited after {} retries",
+                    method,
+                    attempt
+                ));
+            }
+            attempt += 1;
+            Metrics::slack_request_retried();
+            warn!(
+                "Slack API {} rate limited, retrying in {}s (attempt {})",
+                method,
+                retry_after.as_secs(),
+                attempt
+            );
+            self.rate_limiter.defer(method, channel, retry_after).await;
+        };
         let status = response.status();
         let value: Value = response
             .json()
@@ -1349,6 +2032,18 @@ impl SlackClient {
                 .get("error")
                 .and_then(Value::as_str)
                 .unwrap_or("unknown_error");
+            // A user's revoked token only affects that user, not the bridge.
+            let is_puppet_token = self
+                .puppet
+                .as_ref()
+                .is_some_and(|puppet| puppet.access_token == token);
+            if matches!(code, "invalid_auth" | "token_revoked" | "account_inactive")
+                && !is_puppet_token
+                && let Some(bridge) = self.bridge.read().await.clone()
+            {
+                // Sent in the background so the failing call returns right away.
+                bridge.admin_notifier().spawn_token_invalid_alert();
+            }
             return Err(anyhow!("Slack API {} returned ok=false: {}", method, code));
         }
 
@@ -1363,6 +2058,15 @@ impl SlackClient {
         Ok(token.to_string())
     }
 
+    /// Token for posting, editing, deleting and reacting: the puppet's own token on puppet
+    /// clients and the bot token otherwise.
+    fn write_token(&self) -> Result<String> {
+        match &self.puppet {
+            Some(puppet) => Ok(puppet.access_token.clone()),
+            None => self.bot_token(),
+        }
+    }
+
     fn app_token(&self) -> Result<String> {
         if let Some(token) = self
             ._config
@@ -1393,6 +2097,31 @@ impl SlackClient {
     }
 }
 
+/// Whether `err` is a Slack Web API `ok=false` response carrying `code`.
+pub(crate) fn is_slack_error(err: &anyhow::Error, code: &str) -> bool {
+    err.to_string()
+        .rsplit_once("returned ok=false: ")
+        .is_some_and(|(_, actual)| actual == code)
+}
+
+/// Message text as posted for a Matrix user, with the sender name in front.
+fn render_user_text(content: &str, attachments: &[String], username: Option<&str>) -> String {
+    let mut text = content.trim().to_string();
+    for attachment in attachments {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(attachment);
+    }
+    if text.is_empty() {
+        text = "(empty message)".to_string();
+    }
+    match username {
+        Some(name) => format!("*{}*: {}", name, text),
+        None => text,
+    }
+}
+
 fn extract_display_name(user: &Value) -> Option<String> {
     user.pointer("/profile/display_name")
         .and_then(Value::as_str)
@@ -1467,3 +2196,2249 @@ fn normalize_slack_text(input: &str) -> String {
     text
 }
 
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::mock::{MOCK_TEAM_ID, MockHttpServer, MockResponse, MockSlack, RecordedRequest};
+    use super::*;
+    use crate::bridge::blocker::BridgeState;
+    use crate::db::{DatabaseManager, MessageMapping, ReactionMapping, RoomMapping};
+    use crate::matrix::{MatrixAppservice, MatrixEvent};
+
+    struct Harness {
+        slack: MockSlack,
+        homeserver: MockHttpServer,
+        client: SlackClient,
+        bridge: Arc<BridgeCore>,
+        matrix_client: Arc<MatrixAppservice>,
+        db_manager: Arc<DatabaseManager>,
+        _dir: tempfile::TempDir,
+    }
+
+    async fn harness() -> Harness {
+        harness_with_bridge_config("").await
+    }
+
+    /// `bridge_config` is extra YAML appended to the `bridge:` section.
+    async fn harness_with_bridge_config(bridge_config: &str) -> Harness {
+        harness_with_config(bridge_config, "").await
+    }
+
+    /// `room_config` is the body of a flow mapping used as the `room:` section.
+    async fn harness_with_config(bridge_config: &str, room_config: &str) -> Harness {
+        let slack = MockSlack::start().await;
+        let event_counter = Arc::new(AtomicU64::new(1));
+        let account_data = Arc::new(std::sync::Mutex::new(HashMap::<String, Value>::new()));
+        let homeserver = MockHttpServer::start(Arc::new(move |request| {
+            // Slack ghosts count as joined to every room.
+            if request.method == "GET" && request.path.contains("/state/m%2Eroom%2Emember/%40%5Fslack%5FU") {
+                return (200, json!({ "membership": "join", "displayname": "old name" })).into();
+            }
+            if request.path.contains("/account_data/") {
+                let mut account_data = account_data.lock().unwrap();
+                if request.method == "PUT" {
+                    account_data.insert(request.path.clone(), request.body.clone());
+                    return (200, json!({})).into();
+                }
+                return match account_data.get(&request.path) {
+                    Some(content) => (200, content.clone()).into(),
+                    None => (404, json!({ "errcode": "M_NOT_FOUND" })).into(),
+                };
+            }
+            let sequence = event_counter.fetch_add(1, Ordering::Relaxed);
+            if request.path.contains("/createRoom") {
+                let room_id = format!("!created{sequence}:example.org");
+                return (200, json!({ "room_id": room_id })).into();
+            }
+            (
+                200,
+                json!({
+                    "event_id": format!("$mock{sequence}"),
+                    "room_id": "!room:example.org",
+                    "user_id": "@_slack_:example.org",
+                    "content_uri": format!("mxc://example.org/media{sequence}"),
+                }),
+            )
+                .into()
+        }))
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+bridge:
+  domain: example.org
+  homeserver_url: "{homeserver}"
+{bridge_config}
+registration:
+  bridge_id: slack
+  appservice_token: as_token
+  homeserver_token: hs_token
+  sender_localpart: _slack_
+auth:
+  bot_token: xoxb-test
+  app_token: xapp-test
+  api_base_url: "{slack}"
+  client_id: "111.222"
+  client_secret: client-secret
+  oauth_redirect_url: "https://bridge.example.org/slack/oauth"
+logging: {{}}
+database:
+  url: "sqlite://{db}"
+room: {{ {room_config} }}
+channel: {{}}
+ghosts: {{}}
+"#,
+            homeserver = homeserver.base_url,
+            bridge_config = bridge_config,
+            room_config = room_config,
+            slack = slack.api_base_url(),
+            db = dir.path().join("bridge.db").display(),
+        ))
+        .unwrap();
+        let config = Arc::new(config);
+
+        let db_manager = Arc::new(DatabaseManager::new(&config.database).await.unwrap());
+        db_manager.migrate().await.unwrap();
+        let now = chrono::Utc::now();
+        db_manager
+            .room_store()
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: "!room:example.org".to_string(),
+                slack_channel_id: "C123".to_string(),
+                slack_channel_name: "general".to_string(),
+                slack_team_id: MOCK_TEAM_ID.to_string(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+
+        let matrix_client = Arc::new(MatrixAppservice::new(config.clone()).await.unwrap());
+        let client = SlackClient::new(config).await.unwrap();
+        let bridge = Arc::new(BridgeCore::new(
+            matrix_client.clone(),
+            Arc::new(client.clone()),
+            db_manager.clone(),
+        ));
+        client.set_bridge(bridge.clone()).await;
+        matrix_client.set_bridge(bridge.clone()).await;
+
+        Harness {
+            slack,
+            homeserver,
+            client,
+            bridge,
+            matrix_client,
+            db_manager,
+            _dir: dir,
+        }
+    }
+
+    #[tokio::test]
+    async fn socket_mode_message_is_bridged_to_matrix() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+        assert_eq!(harness.client.bot_user_id().await.as_deref(), Some("UBOT"));
+
+        let envelope_id = harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U123",
+            "text": "hello from slack",
+            "ts": "1700000001.000100",
+        }));
+        harness.slack.wait_for_ack(&envelope_id).await;
+
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.method == "POST"
+                    && request.path.ends_with("/register")
+                    && request.body["username"] == "_slack_U123"
+            })
+            .await;
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.method == "PUT"
+                    && request.path.contains("/send/")
+                    && request.body["body"] == "hello from slack"
+            })
+            .await;
+
+        let mapping = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(mapping) = harness
+                    .db_manager
+                    .message_store()
+                    .get_by_slack_message_id("C123", "1700000001.000100")
+                    .await
+                    .unwrap()
+                {
+                    return mapping;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(mapping.matrix_room_id, "!room:example.org");
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn redelivered_socket_mode_envelope_is_bridged_once() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+
+        let envelope = |envelope_id: &str, retry_attempt: u64| {
+            json!({
+                "envelope_id": envelope_id,
+                "type": "events_api",
+                "retry_attempt": retry_attempt,
+                "retry_reason": if retry_attempt > 0 { "timeout" } else { "" },
+                "payload": {
+                    "type": "event_callback",
+                    "team_id": MOCK_TEAM_ID,
+                    "event_id": "EvRETRY",
+                    "event": {
+                        "type": "message",
+                        "channel": "C123",
+                        "user": "U123",
+                        "text": "only once",
+                        "ts": "1700000002.000100",
+                    },
+                },
+            })
+        };
+        harness.slack.push_envelope(envelope("first", 0));
+        harness.slack.wait_for_ack("first").await;
+        harness.slack.push_envelope(envelope("second", 1));
+        harness.slack.wait_for_ack("second").await;
+        harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U123",
+            "text": "after retry",
+            "ts": "1700000003.000100",
+        }));
+
+        harness
+            .homeserver
+            .wait_for(|request| request.body["body"] == "after retry")
+            .await;
+        let sends = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.body["body"] == "only once")
+            .count();
+        assert_eq!(sends, 1);
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn shared_file_is_uploaded_mapped_and_redacted_with_its_message() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+
+        let file = json!({
+            "id": "F123",
+            "name": "notes.txt",
+            "url_private_download": format!("{}files/F123/notes.txt", harness.slack.api_base_url()),
+        });
+        harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C123",
+            "user": "U123",
+            "text": "",
+            "ts": "1700000004.000100",
+            "files": [file.clone()],
+        }));
+
+        let upload = harness
+            .homeserver
+            .wait_for(|request| request.body["msgtype"] == "m.file")
+            .await;
+        assert_eq!(upload.body["body"], "notes.txt");
+
+        let message_store = harness.db_manager.message_store();
+        let file_link = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(link) = message_store
+                    .get_by_slack_message_id("C123", "file:F123")
+                    .await
+                    .unwrap()
+                    && message_store
+                        .get_by_slack_message_id("C123", "1700000004.000100")
+                        .await
+                        .unwrap()
+                        .is_some()
+                {
+                    return link;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "message_deleted",
+            "channel": "C123",
+            "deleted_ts": "1700000004.000100",
+            "previous_message": { "ts": "1700000004.000100", "files": [file] },
+        }));
+        harness
+            .homeserver
+            .wait_for(|request| request.body["redacts"] == file_link.matrix_event_id.as_str())
+            .await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let redactions = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.body.get("redacts").is_some())
+            .count();
+        assert_eq!(redactions, 1);
+        assert!(
+            message_store
+                .get_by_slack_message_id("C123", "file:F123")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn matrix_reactions_and_redactions_of_bridged_files_use_the_message_ts() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+
+        let file = |id: &str| {
+            json!({
+                "id": id,
+                "name": format!("{id}.txt"),
+                "url_private_download":
+                    format!("{}files/{id}/notes.txt", harness.slack.api_base_url()),
+            })
+        };
+        harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C123",
+            "user": "U123",
+            "text": "",
+            "ts": "1700000004.000100",
+            "files": [file("F123")],
+        }));
+        harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C123",
+            "user": "U123",
+            "text": "see attached",
+            "ts": "1700000005.000100",
+            "files": [file("F456")],
+        }));
+        let file_only = wait_for_mapping(&harness, "1700000004.000100").await;
+        wait_for_mapping(&harness, "1700000005.000100").await;
+        let captioned_file = wait_for_mapping(&harness, "file:F456").await;
+
+        let message_store = harness.db_manager.message_store();
+        let link = message_store
+            .get_by_matrix_event_id(&file_only.matrix_event_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.slack_message_id, "1700000004.000100");
+
+        let reaction = |event_id: &str, target: &str| MatrixEvent {
+            event_id: Some(event_id.to_string()),
+            event_type: "m.reaction".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "m.relates_to": { "rel_type": "m.annotation", "event_id": target, "key": "👍" },
+            })),
+            timestamp: None,
+        };
+        let redaction = |target: &str| MatrixEvent {
+            event_id: Some("$redaction".to_string()),
+            event_type: "m.room.redaction".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({ "redacts": target })),
+            timestamp: None,
+        };
+
+        harness
+            .bridge
+            .handle_matrix_reaction(&reaction("$react-file", &file_only.matrix_event_id))
+            .await
+            .unwrap();
+        harness
+            .bridge
+            .handle_matrix_reaction(&reaction("$react-captioned", &captioned_file.matrix_event_id))
+            .await
+            .unwrap();
+        let reactions = harness.slack.calls("reactions.add").await;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0]["timestamp"], "1700000004.000100");
+
+        harness
+            .bridge
+            .handle_matrix_redaction(&redaction(&file_only.matrix_event_id))
+            .await
+            .unwrap();
+        harness
+            .bridge
+            .handle_matrix_redaction(&redaction(&captioned_file.matrix_event_id))
+            .await
+            .unwrap();
+        let deletes = harness.slack.calls("chat.delete").await;
+        assert_eq!(deletes.len(), 1);
+        assert_eq!(deletes[0]["ts"], "1700000004.000100");
+        assert!(
+            message_store
+                .get_by_matrix_event_id(&file_only.matrix_event_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn bot_token_is_only_sent_to_slack_over_https() {
+        let harness = harness().await;
+        let allowed = |url: &str| {
+            harness
+                .client
+                .is_slack_file_host(&reqwest::Url::parse(url).unwrap())
+        };
+
+        assert!(allowed("https://files.slack.com/files-pri/T1-F1/a.png"));
+        assert!(allowed("https://a.slack-edge.com/img.png"));
+        assert!(allowed(&format!("{}files/a.png", harness.slack.api_base_url())));
+        assert!(!allowed("http://files.slack.com/files-pri/T1-F1/a.png"));
+        assert!(!allowed("https://files.slack.com.example.org/a.png"));
+    }
+
+    #[tokio::test]
+    async fn private_file_is_streamed_with_bot_token_and_oversized_files_become_links() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+
+        let contents = b"%PDF-1.4 quarterly numbers";
+        let url = harness
+            .slack
+            .add_file("F200/report.pdf", "application/pdf", contents);
+        harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C123",
+            "user": "U123",
+            "text": "",
+            "ts": "1700000005.000100",
+            "files": [{
+                "id": "F200",
+                "name": "report.pdf",
+                "mimetype": "application/pdf",
+                "size": contents.len(),
+                "url_private_download": url,
+            }],
+        }));
+
+        let upload = harness
+            .homeserver
+            .wait_for(|request| request.path.starts_with("/_matrix/media/v3/upload"))
+            .await;
+        assert_eq!(upload.raw_body, contents);
+        assert_eq!(upload.headers["content-type"], "application/pdf");
+        let message = harness
+            .homeserver
+            .wait_for(|request| request.body["msgtype"] == "m.file")
+            .await;
+        assert_eq!(message.body["body"], "report.pdf");
+        assert_eq!(message.body["info"]["size"], contents.len());
+        let downloads = harness.slack.file_requests("F200/report.pdf").await;
+        assert_eq!(downloads.len(), 2);
+        assert!(
+            downloads
+                .iter()
+                .all(|request| request.headers["authorization"] == "Bearer xoxb-test")
+        );
+
+        harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C123",
+            "user": "U123",
+            "text": "",
+            "ts": "1700000006.000100",
+            "files": [{
+                "id": "F201",
+                "name": "huge.mov",
+                "size": 200u64 * 1024 * 1024,
+                "url_private_download": harness.slack.add_file("F201/huge.mov", "video/quicktime", b"x"),
+                "permalink": "https://team.slack.com/files/U123/F201/huge.mov",
+            }],
+        }));
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.body["body"] == "huge.mov: https://team.slack.com/files/U123/F201/huge.mov"
+            })
+            .await;
+        assert!(harness.slack.file_requests("F201/huge.mov").await.is_empty());
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn slack_threads_map_to_matrix_threads_in_both_directions() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+
+        let message = |ts: &str, text: &str| {
+            json!({
+                "type": "message",
+                "channel": "C123",
+                "user": "U123",
+                "text": text,
+                "ts": ts,
+                "thread_ts": "1700000007.000100",
+            })
+        };
+        harness.slack.push_event(message("1700000007.000100", "thread root"));
+        let root = harness
+            .homeserver
+            .wait_for(|request| request.body["body"] == "thread root")
+            .await;
+        let message_store = harness.db_manager.message_store();
+        let root_link = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(link) = message_store
+                    .get_by_slack_message_id("C123", "1700000007.000100")
+                    .await
+                    .unwrap()
+                {
+                    return link;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(root.body.get("m.relates_to").is_none());
+
+        harness.slack.push_event(message("1700000007.000200", "in the thread"));
+        let reply = harness
+            .homeserver
+            .wait_for(|request| request.body["body"] == "in the thread")
+            .await;
+        let relation = &reply.body["m.relates_to"];
+        assert_eq!(relation["rel_type"], "m.thread");
+        assert_eq!(relation["event_id"], root_link.matrix_event_id.as_str());
+        assert_eq!(relation["is_falling_back"], true);
+
+        let mut broadcast = message("1700000007.000300", "also in channel");
+        broadcast["subtype"] = json!("thread_broadcast");
+        harness.slack.push_event(broadcast);
+        let broadcast = harness
+            .homeserver
+            .wait_for(|request| {
+                request.body["body"]
+                    .as_str()
+                    .is_some_and(|body| body.ends_with("also in channel"))
+            })
+            .await;
+        let relation = &broadcast.body["m.relates_to"];
+        assert!(relation.get("rel_type").is_none());
+        assert_eq!(
+            relation["m.in_reply_to"]["event_id"],
+            root_link.matrix_event_id.as_str()
+        );
+
+        harness
+            .bridge
+            .handle_matrix_message(&MatrixEvent {
+                event_id: Some("$matrix-thread-reply".to_string()),
+                event_type: "m.room.message".to_string(),
+                room_id: "!room:example.org".to_string(),
+                sender: "@alice:example.org".to_string(),
+                state_key: None,
+                content: Some(json!({
+                    "msgtype": "m.text",
+                    "body": "reply from matrix",
+                    "m.relates_to": {
+                        "rel_type": "m.thread",
+                        "event_id": root_link.matrix_event_id,
+                        "is_falling_back": true,
+                        "m.in_reply_to": { "event_id": root_link.matrix_event_id },
+                    },
+                })),
+                timestamp: None,
+            })
+            .await
+            .unwrap();
+        let posted = harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| {
+                body["text"]
+                    .as_str()
+                    .is_some_and(|text| text.contains("reply from matrix"))
+            })
+            .await;
+        assert_eq!(posted["thread_ts"], "1700000007.000100");
+        assert!(
+            message_store
+                .get_by_matrix_event_id("$matrix-thread-reply")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        harness.client.stop().await.unwrap();
+    }
+
+    fn matrix_reply(event_id: &str, body: &str, in_reply_to: &str) -> MatrixEvent {
+        MatrixEvent {
+            event_id: Some(event_id.to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": body,
+                "m.relates_to": { "m.in_reply_to": { "event_id": in_reply_to } },
+            })),
+            timestamp: None,
+        }
+    }
+
+    async fn wait_for_mapping(harness: &Harness, slack_ts: &str) -> MessageMapping {
+        let message_store = harness.db_manager.message_store();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(link) = message_store
+                    .get_by_slack_message_id("C123", slack_ts)
+                    .await
+                    .unwrap()
+                {
+                    return link;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn wait_for_room_mapping(harness: &Harness, slack_channel_id: &str) -> RoomMapping {
+        let room_store = harness.db_manager.room_store();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(mapping) = room_store
+                    .get_room_by_slack_channel(slack_channel_id)
+                    .await
+                    .unwrap()
+                {
+                    return mapping;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn matrix_replies_land_in_the_thread_of_their_slack_target() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+
+        harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U123",
+            "text": "top level",
+            "ts": "1700000008.000100",
+        }));
+        harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U123",
+            "text": "deep in a thread",
+            "ts": "1700000008.000300",
+            "thread_ts": "1700000008.000200",
+        }));
+        let top_level = wait_for_mapping(&harness, "1700000008.000100").await;
+        let threaded = wait_for_mapping(&harness, "1700000008.000300").await;
+        assert_eq!(top_level.slack_thread_ts, None);
+        assert_eq!(threaded.slack_thread_ts.as_deref(), Some("1700000008.000200"));
+
+        harness
+            .bridge
+            .handle_matrix_message(&matrix_reply(
+                "$reply-in-thread",
+                "answering the thread",
+                &threaded.matrix_event_id,
+            ))
+            .await
+            .unwrap();
+        let posted = harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| {
+                body["text"]
+                    .as_str()
+                    .is_some_and(|text| text.contains("answering the thread"))
+            })
+            .await;
+        assert_eq!(posted["thread_ts"], "1700000008.000200");
+
+        harness
+            .bridge
+            .handle_matrix_message(&matrix_reply(
+                "$reply-top-level",
+                "starting a thread",
+                &top_level.matrix_event_id,
+            ))
+            .await
+            .unwrap();
+        let posted = harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| {
+                body["text"]
+                    .as_str()
+                    .is_some_and(|text| text.contains("starting a thread"))
+            })
+            .await;
+        assert_eq!(posted["thread_ts"], "1700000008.000100");
+        let link = harness
+            .db_manager
+            .message_store()
+            .get_by_matrix_event_id("$reply-top-level")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.slack_thread_ts.as_deref(), Some("1700000008.000100"));
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn matrix_replies_to_top_level_messages_quote_in_quote_rooms() {
+        let harness = harness_with_bridge_config("  room_reply_modes:\n    C123: quote").await;
+        harness.client.login().await.unwrap();
+
+        harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U123",
+            "text": "quote me",
+            "ts": "1700000009.000100",
+        }));
+        let top_level = wait_for_mapping(&harness, "1700000009.000100").await;
+
+        harness
+            .bridge
+            .handle_matrix_message(&matrix_reply(
+                "$quoting-reply",
+                "quoting it",
+                &top_level.matrix_event_id,
+            ))
+            .await
+            .unwrap();
+        let posted = harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| {
+                body["text"]
+                    .as_str()
+                    .is_some_and(|text| text.contains("quoting it"))
+            })
+            .await;
+        assert!(posted.get("thread_ts").is_none());
+        let context = posted["blocks"][0]["elements"][0]["text"].as_str().unwrap();
+        assert!(context.contains("https://mock.slack.com/archives/C123/p1700000009000100"));
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn backfill_replays_history_threads_and_reactions_with_slack_times() {
+        let harness = harness().await;
+        harness.slack.respond(
+            "conversations.history",
+            json!({
+                "ok": true,
+                "has_more": false,
+                "messages": [
+                    { "type": "message", "user": "U123", "text": "joined", "subtype": "channel_join", "ts": "1700000102.000000" },
+                    {
+                        "type": "message",
+                        "user": "U123",
+                        "text": "*release* shipped",
+                        "ts": "1700000101.250000",
+                        "reply_count": 1,
+                        "blocks": [{
+                            "type": "rich_text",
+                            "elements": [{
+                                "type": "rich_text_section",
+                                "elements": [
+                                    { "type": "text", "text": "release", "style": { "bold": true } },
+                                    { "type": "text", "text": " shipped" },
+                                ],
+                            }],
+                        }],
+                        "reactions": [{ "name": "tada", "users": ["U456"], "count": 1 }],
+                    },
+                    { "type": "message", "user": "U123", "text": "first", "ts": "1700000100.000000" },
+                ],
+            }),
+        );
+        harness.slack.respond(
+            "conversations.replies",
+            json!({
+                "ok": true,
+                "has_more": false,
+                "messages": [
+                    { "type": "message", "user": "U123", "text": "*release* shipped", "ts": "1700000101.250000", "thread_ts": "1700000101.250000" },
+                    { "type": "message", "user": "U456", "text": "nice", "ts": "1700000103.000000", "thread_ts": "1700000101.250000" },
+                ],
+            }),
+        );
+        let mapping = harness
+            .db_manager
+            .room_store()
+            .get_room_by_slack_channel("C123")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let sent = harness.bridge.backfill_channel(&mapping, 10).await.unwrap();
+        assert_eq!(sent, 3);
+
+        let sends: Vec<_> = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.method == "PUT" && request.path.contains("/send/"))
+            .collect();
+        let bodies: Vec<_> = sends
+            .iter()
+            .filter_map(|request| request.body["body"].as_str())
+            .collect();
+        assert_eq!(bodies, ["first", "release shipped", "nice"]);
+        assert!(sends[0].path.contains("ts=1700000100000"));
+        assert!(sends[1].path.contains("ts=1700000101250"));
+        assert!(
+            sends[1].body["formatted_body"]
+                .as_str()
+                .is_some_and(|html| html.contains("<strong>release</strong>"))
+        );
+        let root = wait_for_mapping(&harness, "1700000101.250000").await;
+        let reply = sends
+            .iter()
+            .find(|request| request.body["body"] == "nice")
+            .unwrap();
+        assert_eq!(reply.body["m.relates_to"]["rel_type"], "m.thread");
+        assert_eq!(reply.body["m.relates_to"]["event_id"], root.matrix_event_id.as_str());
+
+        let reaction = sends
+            .iter()
+            .find(|request| request.path.contains("/send/m%2Ereaction/"))
+            .expect("reaction was backfilled");
+        assert_eq!(reaction.body["m.relates_to"]["event_id"], root.matrix_event_id.as_str());
+        assert!(reaction.path.contains("ts=1700000101250"));
+
+        // A second run only fills gaps.
+        assert_eq!(harness.bridge.backfill_channel(&mapping, 10).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn polled_slack_presence_sets_ghost_presence_with_status() {
+        let harness = harness().await;
+        harness.slack.respond(
+            "users.getPresence",
+            json!({ "ok": true, "presence": "active" }),
+        );
+        harness.slack.respond(
+            "dnd.info",
+            json!({ "ok": true, "dnd_enabled": false, "snooze_enabled": true }),
+        );
+        harness.slack.respond(
+            "users.profile.get",
+            json!({
+                "ok": true,
+                "profile": { "display_name": "alice", "status_emoji": ":house:", "status_text": "Working remotely" },
+            }),
+        );
+
+        harness.bridge.refresh_slack_presence("U123").await.unwrap();
+        assert!(harness.bridge.process_next_presence().await.unwrap());
+
+        let request = harness
+            .homeserver
+            .wait_for(|request| request.method == "PUT" && request.path.contains("/presence/"))
+            .await;
+        assert!(request.path.contains("user_id=%40_slack_U123%3Aexample.org"));
+        assert_eq!(request.body["presence"], "online");
+        assert_eq!(
+            request.body["status_msg"],
+            "Do not disturb | 🏠 Working remotely"
+        );
+    }
+
+    #[tokio::test]
+    async fn slack_profile_changes_update_ghost_profile_and_room_member_state() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+        let avatars = MockHttpServer::start(Arc::new(|_| {
+            MockResponse::bytes("image/png", b"\x89PNG avatar")
+        }))
+        .await;
+        let user_change = |name: &str, hash: &str, deleted: bool| {
+            json!({
+                "type": "user_change",
+                "user": {
+                    "id": "U123",
+                    "deleted": deleted,
+                    "profile": {
+                        "display_name": name,
+                        "avatar_hash": hash,
+                        "image_512": format!("{}/{hash}_512.png", avatars.base_url),
+                    },
+                },
+            })
+        };
+        const AS_GHOST: &str = "user_id=%40_slack_U123%3Aexample.org";
+        async fn ghost_requests(harness: &Harness, fragment: &str) -> Vec<RecordedRequest> {
+            harness
+                .homeserver
+                .requests()
+                .await
+                .into_iter()
+                .filter(|request| {
+                    request.path.contains(fragment) && request.path.contains(AS_GHOST)
+                })
+                .collect()
+        }
+
+        // The first message creates the user mapping that later profile changes update.
+        harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U123",
+            "text": "hello",
+            "ts": "1700000020.000100",
+        }));
+        wait_for_mapping(&harness, "1700000020.000100").await;
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/displayname")
+                    && request.path.contains(AS_GHOST)
+                    && request.body["displayname"] == "User U123#0000"
+            })
+            .await;
+
+        for _ in 0..2 {
+            let envelope_id = harness.slack.push_event(user_change("alice", "h1", false));
+            harness.slack.wait_for_ack(&envelope_id).await;
+        }
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/state/m.room.member/")
+                    && request.path.contains(AS_GHOST)
+                    && request.body["displayname"] == "alice#0000"
+            })
+            .await;
+        let member: Vec<_> = ghost_requests(&harness, "/state/m.room.member/")
+            .await
+            .into_iter()
+            .filter(|request| request.body["displayname"] == "alice#0000")
+            .collect();
+        assert_eq!(member.len(), 1);
+        assert_eq!(member[0].method, "PUT");
+        assert!(
+            member[0].body["avatar_url"]
+                .as_str()
+                .is_some_and(|url| url.starts_with("mxc://example.org/media"))
+        );
+        assert_eq!(member[0].body["membership"], "join");
+        assert_eq!(ghost_requests(&harness, "/avatar_url").await.len(), 1);
+        let uploads = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.contains("/upload"))
+            .count();
+        assert_eq!(uploads, 1);
+        let mapping = harness
+            .db_manager
+            .user_store()
+            .get_user_by_slack_id("U123")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(mapping.slack_username, "alice#0000");
+        assert_eq!(mapping.slack_avatar_hash.as_deref(), Some("h1"));
+
+        harness.slack.push_event(user_change("alice", "h1", true));
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.method == "POST"
+                    && request.path.contains("/rooms/%21room%3Aexample.org/leave")
+                    && request.path.contains(AS_GHOST)
+            })
+            .await;
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn blocked_bridge_drops_messages_with_one_notice_per_room() {
+        let harness = harness_with_bridge_config("  user_limit: 0").await;
+        harness.client.login().await.unwrap();
+        let blocker = harness.bridge.blocker();
+        let slack_message = |text: &str, ts: &str| {
+            json!({
+                "type": "message",
+                "channel": "C123",
+                "user": "U123",
+                "text": text,
+                "ts": ts,
+            })
+        };
+        let matrix_message = |event_id: &str, body: &str| MatrixEvent {
+            event_id: Some(event_id.to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": body })),
+            timestamp: None,
+        };
+
+        // The first user pushes the bridge over its limit of zero.
+        harness
+            .slack
+            .push_event(slack_message("hello", "1700000030.000100"));
+        wait_for_mapping(&harness, "1700000030.000100").await;
+        assert_eq!(
+            blocker.check_and_update().await.unwrap(),
+            BridgeState::Limited
+        );
+        assert_eq!(blocker.get_status().await.active_users, 1);
+
+        for ts in ["1700000031.000100", "1700000032.000100"] {
+            let envelope_id = harness.slack.push_event(slack_message("dropped", ts));
+            harness.slack.wait_for_ack(&envelope_id).await;
+        }
+        for event_id in ["$blocked1", "$blocked2"] {
+            harness
+                .bridge
+                .handle_matrix_message(&matrix_message(event_id, "dropped too"))
+                .await
+                .unwrap();
+        }
+        harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| {
+                body["text"]
+                    .as_str()
+                    .is_some_and(|text| text.contains("user limit"))
+            })
+            .await;
+        let posted = harness.slack.calls("chat.postMessage").await;
+        assert_eq!(posted.len(), 1);
+        let notices: Vec<_> = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.body["msgtype"] == "m.notice")
+            .collect();
+        assert_eq!(notices.len(), 1);
+        assert!(
+            notices[0].body["body"]
+                .as_str()
+                .is_some_and(|body| body.contains("user limit"))
+        );
+        let sends = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.body["body"] == "dropped")
+            .count();
+        assert_eq!(sends, 0);
+        let message_store = harness.db_manager.message_store();
+        assert!(
+            message_store
+                .get_by_slack_message_id("C123", "1700000031.000100")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Senders of dropped messages don't keep the bridge over its limit.
+        blocker.check_and_update().await.unwrap();
+        assert_eq!(blocker.get_status().await.active_users, 1);
+
+        // A pause is kept across checks and only lifted by resuming.
+        blocker.pause("maintenance").await.unwrap();
+        assert_eq!(
+            blocker.check_and_update().await.unwrap(),
+            BridgeState::Paused
+        );
+        blocker.resume().await.unwrap();
+        harness
+            .bridge
+            .handle_matrix_message(&matrix_message("$allowed", "back again"))
+            .await
+            .unwrap();
+        harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| {
+                body["text"]
+                    .as_str()
+                    .is_some_and(|text| text.contains("back again"))
+            })
+            .await;
+
+        let status = blocker.get_status().await;
+        assert_eq!(status.state, BridgeState::Active);
+        assert_eq!(status.messages_received, 1);
+        assert_eq!(status.messages_sent, 1);
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn user_limit_follows_persisted_activity_across_restarts() {
+        let harness = harness_with_bridge_config("  user_limit: 2").await;
+        let start_blocker = || {
+            crate::bridge::blocker::BridgeBlocker::new(
+                harness.db_manager.clone(),
+                harness.matrix_client.config(),
+                harness.bridge.admin_notifier(),
+            )
+        };
+
+        let blocker = start_blocker();
+        for user_id in ["@alice:example.org", "U1"] {
+            blocker.record_active_user(user_id).await;
+        }
+        assert_eq!(blocker.check_and_update().await.unwrap(), BridgeState::Active);
+        blocker.record_active_user("U2").await;
+        assert_eq!(blocker.check_and_update().await.unwrap(), BridgeState::Limited);
+
+        // Activity is read back from the database after a restart.
+        let blocker = start_blocker();
+        assert_eq!(blocker.check_and_update().await.unwrap(), BridgeState::Limited);
+        assert_eq!(blocker.get_status().await.active_users, 3);
+
+        // Users stop counting once their last message leaves the window.
+        harness
+            .db_manager
+            .bridge_state_store()
+            .touch_active_user("U2", chrono::Utc::now() - chrono::Duration::days(31))
+            .await
+            .unwrap();
+        assert_eq!(blocker.check_and_update().await.unwrap(), BridgeState::Active);
+        assert_eq!(blocker.get_status().await.active_users, 2);
+
+        // So does a pause.
+        blocker.pause("maintenance").await.unwrap();
+        let blocker = start_blocker();
+        assert_eq!(blocker.check_and_update().await.unwrap(), BridgeState::Paused);
+        assert_eq!(
+            blocker.get_status().await.pause_reason.as_deref(),
+            Some("maintenance")
+        );
+        blocker.resume().await.unwrap();
+        assert_eq!(
+            start_blocker().check_and_update().await.unwrap(),
+            BridgeState::Active
+        );
+    }
+
+    /// Logs `matrix_user_id` in as `slack_user_id` with the user token `token`.
+    async fn log_in_puppet(harness: &Harness, matrix_user_id: &str, slack_user_id: &str, token: &str) {
+        let cipher = crate::bridge::puppet::TokenCipher::from_config(&harness.matrix_client.config());
+        let now = chrono::Utc::now();
+        harness
+            .db_manager
+            .puppet_store()
+            .upsert_puppet(&crate::db::SlackPuppet {
+                id: 0,
+                matrix_user_id: matrix_user_id.to_string(),
+                slack_user_id: slack_user_id.to_string(),
+                slack_team_id: MOCK_TEAM_ID.to_string(),
+                access_token: cipher.seal(token).unwrap(),
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn slack_login_links_are_sent_by_dm_and_work_once() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+        let login = MatrixEvent {
+            event_id: Some("$login".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": "!slack login" })),
+            timestamp: None,
+        };
+        harness.bridge.handle_matrix_message(&login).await.unwrap();
+
+        let create_room = harness
+            .homeserver
+            .wait_for(|request| request.path.ends_with("/createRoom"))
+            .await;
+        assert_eq!(create_room.body["invite"], json!(["@alice:example.org"]));
+        assert_eq!(create_room.body["is_direct"], true);
+        let link = harness
+            .homeserver
+            .wait_for(|request| {
+                request.body["body"]
+                    .as_str()
+                    .is_some_and(|body| body.contains("oauth/v2/authorize"))
+            })
+            .await;
+        assert!(link.path.contains("%21created"));
+        let reply = harness
+            .homeserver
+            .wait_for(|request| {
+                request.body["body"]
+                    .as_str()
+                    .is_some_and(|body| body.contains("direct message"))
+            })
+            .await;
+        assert!(reply.path.contains("/rooms/%21room%3Aexample%2Eorg/"));
+        assert!(
+            harness
+                .homeserver
+                .requests()
+                .await
+                .iter()
+                .filter(|request| request.path.contains("/rooms/%21room%3Aexample%2Eorg/"))
+                .all(|request| !request.body.to_string().contains("oauth/v2/authorize"))
+        );
+
+        let body = link.body["body"].as_str().unwrap();
+        let url = body.split_whitespace().nth(1).unwrap();
+        let state = url::Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .map(|(_, value)| value.into_owned())
+            .unwrap();
+        harness.slack.respond(
+            "oauth.v2.access",
+            json!({
+                "ok": true,
+                "team": { "id": MOCK_TEAM_ID },
+                "authed_user": { "id": "U111", "access_token": "xoxp-alice" },
+            }),
+        );
+        harness.bridge.complete_slack_login("code", &state).await.unwrap();
+        assert!(
+            harness
+                .db_manager
+                .puppet_store()
+                .get_puppet_by_matrix_user("@alice:example.org")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        let reused = harness
+            .bridge
+            .complete_slack_login("code", &state)
+            .await
+            .unwrap_err();
+        assert!(reused.to_string().contains("already been used"));
+
+        // The next login link goes to the same DM.
+        harness.bridge.handle_matrix_message(&login).await.unwrap();
+        let rooms_created = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.ends_with("/createRoom"))
+            .count();
+        assert_eq!(rooms_created, 1);
+    }
+
+    #[tokio::test]
+    async fn puppets_send_attachments_from_their_own_account() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+        log_in_puppet(&harness, "@alice:example.org", "U111", "xoxp-alice").await;
+        harness.slack.respond(
+            "files.getUploadURLExternal",
+            json!({
+                "ok": true,
+                "upload_url": format!("{}upload", harness.slack.api_base_url()),
+                "file_id": "F900",
+            }),
+        );
+        let media = |size: usize| crate::media::MediaInfo {
+            data: b"hello".to_vec(),
+            content_type: "text/plain".to_string(),
+            filename: "hello.txt".to_string(),
+            size,
+        };
+
+        harness
+            .bridge
+            .send_to_slack_with_attachments(
+                "C123",
+                crate::bridge::message_flow::OutboundSlackMessage {
+                    content: String::new(),
+                    reply_to: None,
+                    edit_of: None,
+                    thread_ts: None,
+                    quote_permalink: None,
+                    attachments: Vec::new(),
+                    embed: None,
+                    use_embed: false,
+                },
+                "@alice:example.org",
+                vec![
+                    ("mxc://example.org/small".to_string(), Some(media(5))),
+                    ("mxc://example.org/large".to_string(), Some(media(usize::MAX))),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let upload = harness
+            .slack
+            .api_requests("files.completeUploadExternal")
+            .await;
+        assert_eq!(upload.len(), 1);
+        assert_eq!(upload[0].headers["authorization"], "Bearer xoxp-alice");
+        assert!(upload[0].body.get("initial_comment").is_none());
+        let link = harness.slack.api_requests("chat.postMessage").await;
+        assert_eq!(link.len(), 1);
+        assert_eq!(link[0].headers["authorization"], "Bearer xoxp-alice");
+        let text = link[0].body["text"].as_str().unwrap();
+        assert!(text.starts_with("hello.txt: "), "{text}");
+
+        // Slack shares the upload as alice's own message; it must not come back.
+        let echo = harness.slack.push_event(json!({
+            "type": "message",
+            "subtype": "file_share",
+            "channel": "C123",
+            "user": "U111",
+            "text": "echoed upload",
+            "ts": "1700000050.000100",
+            "files": [{ "id": "F900", "name": "hello.txt" }],
+        }));
+        harness.slack.wait_for_ack(&echo).await;
+        let after = harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U222",
+            "text": "after the upload",
+            "ts": "1700000051.000100",
+        }));
+        harness.slack.wait_for_ack(&after).await;
+        harness
+            .homeserver
+            .wait_for(|request| request.body["body"] == "after the upload")
+            .await;
+        assert!(
+            harness
+                .homeserver
+                .requests()
+                .await
+                .iter()
+                .all(|request| request.body["body"] != "echoed upload")
+        );
+    }
+
+    #[tokio::test]
+    async fn puppet_echoes_arriving_before_the_post_returns_are_dropped() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+        let puppet = harness.client.as_puppet("U111", "xoxp-alice");
+
+        // The echo is delivered while the post is still in flight.
+        let sending = puppet.lock_puppet_sends("C123").await;
+        let echo = harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U111",
+            "text": "too early",
+            "ts": "1700000060.000100",
+        }));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        puppet.remember_puppet_post("C123", "1700000060.000100").await;
+        drop(sending);
+        harness.slack.wait_for_ack(&echo).await;
+
+        let after = harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "C123",
+            "user": "U222",
+            "text": "after the post",
+            "ts": "1700000061.000100",
+        }));
+        harness.slack.wait_for_ack(&after).await;
+        harness
+            .homeserver
+            .wait_for(|request| request.body["body"] == "after the post")
+            .await;
+        assert!(
+            harness
+                .homeserver
+                .requests()
+                .await
+                .iter()
+                .all(|request| request.body["body"] != "too early")
+        );
+    }
+
+    #[tokio::test]
+    async fn puppet_reaction_echoes_arriving_before_the_call_returns_are_dropped() {
+        let harness = harness().await;
+        harness.client.login().await.unwrap();
+        let now = chrono::Utc::now();
+        harness
+            .db_manager
+            .message_store()
+            .upsert_message_mapping(&MessageMapping {
+                id: 0,
+                slack_message_id: "1700000070.000100".to_string(),
+                slack_channel_id: "C123".to_string(),
+                slack_team_id: MOCK_TEAM_ID.to_string(),
+                matrix_room_id: "!room:example.org".to_string(),
+                matrix_event_id: "$target".to_string(),
+                slack_thread_ts: None,
+                created_at: now,
+                updated_at: now,
+            })
+            .await
+            .unwrap();
+        let reaction_added = |user: &str| {
+            json!({
+                "type": "reaction_added",
+                "user": user,
+                "reaction": "thumbsup",
+                "item": { "type": "message", "channel": "C123", "ts": "1700000070.000100" },
+            })
+        };
+        let puppet = harness.client.as_puppet("U111", "xoxp-alice");
+
+        // The echo is delivered while reactions.add is still in flight.
+        let sending = puppet.lock_puppet_sends("C123").await;
+        let echo = harness.slack.push_event(reaction_added("U111"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        harness
+            .db_manager
+            .reaction_store()
+            .create_reaction(&ReactionMapping {
+                id: 0,
+                slack_channel_id: "C123".to_string(),
+                slack_message_ts: "1700000070.000100".to_string(),
+                slack_user_id: "U111".to_string(),
+                emoji: "thumbsup".to_string(),
+                matrix_room_id: "!room:example.org".to_string(),
+                matrix_event_id: "$alice-reaction".to_string(),
+                matrix_sender: "@alice:example.org".to_string(),
+                created_at: now,
+            })
+            .await
+            .unwrap();
+        drop(sending);
+        harness.slack.wait_for_ack(&echo).await;
+
+        let other = harness.slack.push_event(reaction_added("U222"));
+        harness.slack.wait_for_ack(&other).await;
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/send/m%2Ereaction/")
+            })
+            .await;
+        let reactions_sent = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.contains("/send/m%2Ereaction/"))
+            .count();
+        assert_eq!(reactions_sent, 1);
+    }
+
+    #[tokio::test]
+    async fn only_the_bridge_admin_can_pause_and_resume_the_bridge() {
+        let harness = harness_with_bridge_config("  admin_mxid: \"@admin:example.org\"").await;
+        let blocker = harness.bridge.blocker();
+        let command = |sender: &str, body: &str| MatrixEvent {
+            event_id: Some(format!("${}", body.replace(' ', "-"))),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: sender.to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": body })),
+            timestamp: None,
+        };
+
+        harness
+            .bridge
+            .handle_matrix_message(&command("@alice:example.org", "!slack pause"))
+            .await
+            .unwrap();
+        assert_eq!(blocker.get_status().await.state, BridgeState::Active);
+
+        harness
+            .bridge
+            .handle_matrix_message(&command("@admin:example.org", "!slack pause upgrading"))
+            .await
+            .unwrap();
+        let status = blocker.get_status().await;
+        assert_eq!(status.state, BridgeState::Paused);
+        assert_eq!(status.pause_reason.as_deref(), Some("upgrading"));
+
+        // Commands still run while the bridge is paused.
+        harness
+            .bridge
+            .handle_matrix_message(&command("@admin:example.org", "!slack resume"))
+            .await
+            .unwrap();
+        assert_eq!(blocker.get_status().await.state, BridgeState::Active);
+    }
+
+    #[tokio::test]
+    async fn admin_is_alerted_once_about_a_revoked_token_and_about_pauses() {
+        let harness = harness_with_bridge_config(
+            "  admin_mxid: \"@admin:example.org\"\n  invalid_token_message: \"Rotate the Slack token\"",
+        )
+        .await;
+        harness.client.login().await.unwrap();
+        harness.slack.respond(
+            "chat.postMessage",
+            json!({ "ok": false, "error": "token_revoked" }),
+        );
+        let matrix_message = |event_id: &str| MatrixEvent {
+            event_id: Some(event_id.to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": "hello" })),
+            timestamp: None,
+        };
+        let notices = |requests: Vec<RecordedRequest>| -> Vec<String> {
+            requests
+                .into_iter()
+                .filter(|request| request.body["msgtype"] == "m.notice")
+                .filter_map(|request| request.body["body"].as_str().map(ToOwned::to_owned))
+                .collect()
+        };
+
+        for event_id in ["$first", "$second"] {
+            assert!(
+                harness
+                    .bridge
+                    .handle_matrix_message(&matrix_message(event_id))
+                    .await
+                    .is_err()
+            );
+        }
+        let create_room = harness
+            .homeserver
+            .wait_for(|request| request.path.ends_with("/createRoom"))
+            .await;
+        assert_eq!(create_room.body["invite"], json!(["@admin:example.org"]));
+        assert_eq!(create_room.body["is_direct"], true);
+        harness
+            .homeserver
+            .wait_for(|request| request.body["msgtype"] == "m.notice")
+            .await;
+        // The second alert was throttled before its failing call returned.
+        let sent = notices(harness.homeserver.requests().await);
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains("Rotate the Slack token"));
+
+        harness.bridge.blocker().pause("maintenance").await.unwrap();
+        let requests = harness.homeserver.requests().await;
+        let sent = notices(requests.clone());
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].contains("Reason: maintenance"));
+        let rooms_created = requests
+            .iter()
+            .filter(|request| request.path.ends_with("/createRoom"))
+            .count();
+        assert_eq!(rooms_created, 1);
+
+        // After a restart the DM room is found again through the bot's m.direct.
+        let restarted = crate::admin::AdminNotifier::from_config(
+            harness.matrix_client.config(),
+            harness.matrix_client.clone(),
+            harness.db_manager.clone(),
+        );
+        restarted.notify_bridge_resumed().await.unwrap();
+        let requests = harness.homeserver.requests().await;
+        let rooms_created = requests
+            .iter()
+            .filter(|request| request.path.ends_with("/createRoom"))
+            .count();
+        assert_eq!(rooms_created, 1);
+        let first_notice = requests
+            .iter()
+            .find(|request| request.body["msgtype"] == "m.notice")
+            .unwrap();
+        let resumed = requests
+            .iter()
+            .rfind(|request| request.body["msgtype"] == "m.notice")
+            .unwrap();
+        assert!(resumed.body["body"].as_str().unwrap().contains("Bridge Resumed"));
+        let room_of = |path: &str| path.split("/rooms/").nth(1).unwrap().split('/').next().unwrap().to_string();
+        assert_eq!(room_of(&resumed.path), room_of(&first_notice.path));
+
+        harness.client.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn portal_alias_query_creates_a_room_for_public_channels_only() {
+        let harness = harness_with_config(
+            "",
+            "enable_room_creation: true, room_alias_prefix: _slack_, default_visibility: public",
+        )
+        .await;
+
+        assert!(
+            harness
+                .bridge
+                .handle_room_alias_query("#_slack_C777:example.org")
+                .await
+                .unwrap()
+        );
+        let create_room = harness
+            .homeserver
+            .wait_for(|request| request.path.ends_with("/createRoom"))
+            .await;
+        assert_eq!(create_room.body["room_alias_name"], "_slack_C777");
+        assert_eq!(create_room.body["name"], "[Slack] #general");
+        assert_eq!(create_room.body["visibility"], "public");
+        assert_eq!(create_room.body["preset"], "public_chat");
+        assert_eq!(
+            create_room.body["power_level_content_override"]["users"]["@_slack_:example.org"],
+            100
+        );
+        let mapping = harness
+            .db_manager
+            .room_store()
+            .get_room_by_slack_channel("C777")
+            .await
+            .unwrap()
+            .expect("portal mapping was recorded");
+        assert!(mapping.matrix_room_id.starts_with("!created"));
+        assert_eq!(mapping.slack_channel_name, "general");
+        harness
+            .slack
+            .wait_for_call("conversations.join", |body| body["channel"] == "C777")
+            .await;
+
+        // Already bridged channels get the alias pointed at their room instead.
+        assert!(
+            harness
+                .bridge
+                .handle_room_alias_query("#_slack_C123:example.org")
+                .await
+                .unwrap()
+        );
+        let alias = harness
+            .homeserver
+            .wait_for(|request| request.path.contains("/directory/room/"))
+            .await;
+        assert_eq!(alias.body["room_id"], "!room:example.org");
+
+        harness.slack.respond(
+            "conversations.info",
+            json!({ "ok": true, "channel": { "id": "G999", "name": "secret", "is_private": true } }),
+        );
+        assert!(
+            !harness
+                .bridge
+                .handle_room_alias_query("#_slack_G999:example.org")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !harness
+                .bridge
+                .handle_room_alias_query("#_slack_general:example.org")
+                .await
+                .unwrap()
+        );
+
+        // Channels the bot can't join would never relay anything.
+        harness.slack.respond(
+            "conversations.info",
+            json!({ "ok": true, "channel": { "id": "C888", "name": "random" } }),
+        );
+        harness.slack.respond(
+            "conversations.join",
+            json!({ "ok": false, "error": "missing_scope" }),
+        );
+        assert!(
+            !harness
+                .bridge
+                .handle_room_alias_query("#_slack_C888:example.org")
+                .await
+                .unwrap()
+        );
+        harness.slack.respond(
+            "conversations.info",
+            json!({ "ok": true, "channel": { "id": "C999", "name": "members", "is_member": true } }),
+        );
+        assert!(
+            harness
+                .bridge
+                .handle_room_alias_query("#_slack_C999:example.org")
+                .await
+                .unwrap()
+        );
+        let joins = harness.slack.calls("conversations.join").await;
+        assert!(joins.iter().all(|body| body["channel"] != "C999"));
+
+        let rooms_created = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.ends_with("/createRoom"))
+            .count();
+        assert_eq!(rooms_created, 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_portal_alias_queries_create_one_room() {
+        let harness =
+            harness_with_config("", "enable_room_creation: true, room_alias_prefix: _slack_").await;
+
+        let (first, second) = tokio::join!(
+            harness.bridge.handle_room_alias_query("#_slack_C777:example.org"),
+            harness.bridge.handle_room_alias_query("#_slack_C777:example.org"),
+        );
+        assert!(first.unwrap());
+        assert!(second.unwrap());
+        let rooms_created = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.ends_with("/createRoom"))
+            .count();
+        assert_eq!(rooms_created, 1);
+    }
+
+    #[tokio::test]
+    async fn portal_alias_query_is_refused_when_room_creation_is_disabled() {
+        let harness = harness().await;
+        assert!(
+            !harness
+                .bridge
+                .handle_room_alias_query("#_slack_C777:example.org")
+                .await
+                .unwrap()
+        );
+        assert!(harness.homeserver.requests().await.is_empty());
+    }
+
+    fn web_router(harness: &Harness) -> salvo::Router {
+        crate::web::root_router(crate::web::WebState {
+            db_manager: harness.db_manager.clone(),
+            matrix_client: harness.matrix_client.clone(),
+            bridge: harness.bridge.clone(),
+            started_at: std::time::Instant::now(),
+        })
+        .push(harness.matrix_client.appservice.router())
+    }
+
+    #[tokio::test]
+    async fn third_party_lookups_require_the_homeserver_token() {
+        use salvo::test::TestClient;
+
+        let harness = harness().await;
+        let service = salvo::Service::new(web_router(&harness));
+        let url = "http://127.0.0.1/_matrix/app/v1/thirdparty/location/slack?channel_id=general";
+
+        let missing = TestClient::get(url).send(&service).await;
+        assert_eq!(missing.status_code, Some(salvo::http::StatusCode::UNAUTHORIZED));
+        let wrong = TestClient::get(url).bearer_auth("wrong").send(&service).await;
+        assert_eq!(wrong.status_code, Some(salvo::http::StatusCode::FORBIDDEN));
+        let user = TestClient::get("http://127.0.0.1/_matrix/app/v1/thirdparty/user?access_token=wrong")
+            .send(&service)
+            .await;
+        assert_eq!(user.status_code, Some(salvo::http::StatusCode::FORBIDDEN));
+        assert!(harness.slack.calls("conversations.list").await.is_empty());
+
+        let authorized = TestClient::get(url).bearer_auth("hs_token").send(&service).await;
+        assert_eq!(authorized.status_code, Some(salvo::http::StatusCode::OK));
+    }
+
+    #[tokio::test]
+    async fn homeserver_user_queries_are_answered_through_the_appservice_routes() {
+        use salvo::conn::{Acceptor, Listener};
+
+        let harness = harness().await;
+        let acceptor = salvo::conn::TcpListener::new("127.0.0.1:0").bind().await;
+        let addr = acceptor.holdings()[0].local_addr.clone().into_std().unwrap();
+        let router = web_router(&harness);
+        tokio::spawn(salvo::Server::new(acceptor).serve(router));
+
+        let query = |user_id: &str| {
+            reqwest::Client::new()
+                .get(format!(
+                    "http://{}/_matrix/app/v1/users/{}",
+                    addr,
+                    url::form_urlencoded::byte_serialize(user_id.as_bytes()).collect::<String>()
+                ))
+                .bearer_auth("hs_token")
+                .send()
+        };
+        assert_eq!(query("@_slack_U456:example.org").await.unwrap().status(), 200);
+        harness
+            .homeserver
+            .wait_for(|request| request.path.contains("/register"))
+            .await;
+        assert_eq!(query("@alice:example.org").await.unwrap().status(), 404);
+
+        let unauthorized = reqwest::Client::new()
+            .get(format!("http://{}/_matrix/app/v1/users/%40_slack_U456%3Aexample.org", addr))
+            .bearer_auth("wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(unauthorized.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn user_query_provisions_ghosts_of_active_slack_users() {
+        let harness = harness().await;
+
+        assert!(
+            harness
+                .bridge
+                .handle_user_query("@_slack_U456:example.org")
+                .await
+                .unwrap()
+        );
+        let register = harness
+            .homeserver
+            .wait_for(|request| request.path.contains("/register"))
+            .await;
+        assert_eq!(register.body["username"], "_slack_U456");
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/displayname")
+                    && request.path.contains("user_id=%40_slack_U456%3Aexample.org")
+                    && request.body["displayname"] == "User U456#0000"
+            })
+            .await;
+        assert!(
+            harness
+                .db_manager
+                .user_store()
+                .get_user_by_slack_id("U456")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        harness.slack.respond(
+            "users.info",
+            json!({ "ok": true, "user": { "id": "U789", "name": "gone", "deleted": true } }),
+        );
+        assert!(
+            !harness
+                .bridge
+                .handle_user_query("@_slack_U789:example.org")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !harness
+                .bridge
+                .handle_user_query("@_slack_C123:example.org")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !harness
+                .bridge
+                .handle_user_query("@alice:example.org")
+                .await
+                .unwrap()
+        );
+        let registrations = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.contains("/register"))
+            .count();
+        assert_eq!(registrations, 1);
+    }
+
+    #[tokio::test]
+    async fn slack_dm_with_the_bot_opens_a_matrix_dm_for_the_admin() {
+        let harness = harness_with_bridge_config("  admin_mxid: \"@admin:example.org\"").await;
+        harness.client.login().await.unwrap();
+        harness.slack.respond(
+            "conversations.members",
+            json!({ "ok": true, "members": ["UBOT", "U555"] }),
+        );
+
+        let envelope_id = harness.slack.push_event(json!({
+            "type": "message",
+            "channel": "D555",
+            "channel_type": "im",
+            "user": "U555",
+            "text": "psst",
+            "ts": "1700000030.000100",
+        }));
+        harness.slack.wait_for_ack(&envelope_id).await;
+
+        let create_room = harness
+            .homeserver
+            .wait_for(|request| request.path.contains("/createRoom"))
+            .await;
+        assert!(
+            create_room
+                .path
+                .contains("user_id=%40_slack_U555%3Aexample.org")
+        );
+        assert_eq!(create_room.body["is_direct"], true);
+        assert_eq!(
+            create_room.body["invite"],
+            json!(["@admin:example.org", "@_slack_:example.org"])
+        );
+        let m_direct = harness
+            .homeserver
+            .wait_for(|request| request.method == "PUT" && request.path.contains("/m.direct"))
+            .await;
+        assert!(
+            m_direct.body["@admin:example.org"][0]
+                .as_str()
+                .is_some_and(|room_id| room_id.starts_with("!created"))
+        );
+
+        let message = harness
+            .homeserver
+            .wait_for(|request| request.body["body"] == "psst")
+            .await;
+        assert!(message.path.contains("%21created"));
+        let mapping = wait_for_room_mapping(&harness, "D555").await;
+        assert!(mapping.matrix_room_id.starts_with("!created"));
+        assert_eq!(mapping.slack_channel_name, "User U555");
+    }
+
+    #[tokio::test]
+    async fn concurrent_messages_of_a_new_slack_dm_open_one_matrix_room() {
+        let harness = harness_with_bridge_config("  admin_mxid: \"@admin:example.org\"").await;
+        harness.client.login().await.unwrap();
+        harness.slack.respond(
+            "conversations.members",
+            json!({ "ok": true, "members": ["UBOT", "U555"] }),
+        );
+
+        let (first, second) = tokio::join!(
+            harness.bridge.ensure_direct_portal("D555"),
+            harness.bridge.ensure_direct_portal("D555"),
+        );
+        first.unwrap();
+        second.unwrap();
+
+        let rooms_created = harness
+            .homeserver
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.contains("/createRoom"))
+            .count();
+        assert_eq!(rooms_created, 1);
+    }
+
+    #[tokio::test]
+    async fn matrix_dm_invite_of_a_ghost_opens_the_slack_conversation() {
+        let harness = harness_with_bridge_config("  admin_mxid: \"@alice:example.org\"").await;
+        const AS_GHOST: &str = "user_id=%40_slack_U777%3Aexample.org";
+        harness.slack.respond(
+            "conversations.open",
+            json!({ "ok": true, "channel": { "id": "D777" } }),
+        );
+        let invite = |room_id: &str| MatrixEvent {
+            event_id: Some(format!("$invite{room_id}")),
+            event_type: "m.room.member".to_string(),
+            room_id: room_id.to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: Some("@_slack_U777:example.org".to_string()),
+            content: Some(json!({ "membership": "invite", "is_direct": true })),
+            timestamp: None,
+        };
+
+        harness
+            .bridge
+            .handle_matrix_member(&invite("!dm:example.org"))
+            .await
+            .unwrap();
+        assert_eq!(
+            harness.slack.calls("conversations.open").await[0]["users"],
+            "U777"
+        );
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/join/%21dm%3Aexample.org")
+                    && request.path.contains(AS_GHOST)
+            })
+            .await;
+        let mapping = harness
+            .db_manager
+            .room_store()
+            .get_room_by_matrix_room("!dm:example.org")
+            .await
+            .unwrap()
+            .expect("dm mapping was recorded");
+        assert_eq!(mapping.slack_channel_id, "D777");
+
+        harness
+            .bridge
+            .handle_matrix_message(&MatrixEvent {
+                event_id: Some("$hello".to_string()),
+                event_type: "m.room.message".to_string(),
+                room_id: "!dm:example.org".to_string(),
+                sender: "@alice:example.org".to_string(),
+                state_key: None,
+                content: Some(json!({ "msgtype": "m.text", "body": "hi there" })),
+                timestamp: None,
+            })
+            .await
+            .unwrap();
+        let post = harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| body["channel"] == "D777")
+            .await;
+        assert!(post["text"].as_str().unwrap().ends_with("hi there"));
+
+        // The bot has a single DM per Slack user, so a second room can't claim it.
+        harness
+            .bridge
+            .handle_matrix_member(&invite("!other:example.org"))
+            .await
+            .unwrap();
+        let notice = harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/rooms/%21other%3Aexample%2Eorg/send/")
+                    && request.body["msgtype"] == "m.notice"
+            })
+            .await;
+        assert!(notice.body["body"].as_str().unwrap().contains("already bridged"));
+        harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("%21other%3Aexample.org/leave")
+                    && request.path.contains(AS_GHOST)
+            })
+            .await;
+        assert!(
+            harness
+                .db_manager
+                .room_store()
+                .get_room_by_matrix_room("!other:example.org")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn matrix_dm_invites_use_the_inviters_slack_login() {
+        let harness = harness_with_bridge_config("  admin_mxid: \"@admin:example.org\"").await;
+        harness.slack.respond(
+            "conversations.open",
+            json!({ "ok": true, "channel": { "id": "D888" } }),
+        );
+        let invite = |room_id: &str, sender: &str| MatrixEvent {
+            event_id: Some(format!("$invite{room_id}")),
+            event_type: "m.room.member".to_string(),
+            room_id: room_id.to_string(),
+            sender: sender.to_string(),
+            state_key: Some("@_slack_U777:example.org".to_string()),
+            content: Some(json!({ "membership": "invite", "is_direct": true })),
+            timestamp: None,
+        };
+
+        // Without a login, nobody but the admin may take the bot's DM with the user.
+        harness
+            .bridge
+            .handle_matrix_member(&invite("!stranger:example.org", "@mallory:evil.example"))
+            .await
+            .unwrap();
+        let notice = harness
+            .homeserver
+            .wait_for(|request| {
+                request.path.contains("/rooms/%21stranger%3Aexample%2Eorg/send/")
+                    && request.body["msgtype"] == "m.notice"
+            })
+            .await;
+        assert!(notice.body["body"].as_str().unwrap().contains("!slack login"));
+        harness
+            .homeserver
+            .wait_for(|request| request.path.contains("%21stranger%3Aexample.org/leave"))
+            .await;
+        assert!(harness.slack.calls("conversations.open").await.is_empty());
+
+        log_in_puppet(&harness, "@bob:example.org", "U222", "xoxp-bob").await;
+        harness
+            .bridge
+            .handle_matrix_member(&invite("!bob:example.org", "@bob:example.org"))
+            .await
+            .unwrap();
+        let open = harness.slack.api_requests("conversations.open").await;
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].headers["authorization"], "Bearer xoxp-bob");
+        let mapping = harness
+            .db_manager
+            .room_store()
+            .get_room_by_matrix_room("!bob:example.org")
+            .await
+            .unwrap()
+            .expect("dm mapping was recorded");
+        assert_eq!(mapping.slack_channel_id, "D888");
+    }
+
+    #[tokio::test]
+    async fn directory_listings_skip_deleted_users_and_are_cached() {
+        let harness = harness().await;
+        harness.slack.respond(
+            "conversations.list",
+            json!({
+                "ok": true,
+                "channels": [
+                    { "id": "C1", "name": "general", "context_team_id": "T1" },
+                    { "id": "C2", "name": "random", "topic": { "value": "" } },
+                ],
+                "response_metadata": { "next_cursor": "" },
+            }),
+        );
+        harness.slack.respond(
+            "users.list",
+            json!({
+                "ok": true,
+                "members": [
+                    { "id": "U1", "name": "alice" },
+                    { "id": "U2", "name": "bob", "deleted": true },
+                ],
+            }),
+        );
+        let slack = harness.bridge.slack_client().await;
+
+        for _ in 0..2 {
+            let channels = slack.list_public_channels().await.unwrap();
+            let ids: Vec<&str> = channels.iter().map(|channel| channel.id.as_str()).collect();
+            assert_eq!(ids, ["C1", "C2"]);
+            assert_eq!(channels[0].guild_id, "T1");
+            assert!(channels[1].topic.is_none());
+
+            let users = slack.list_users().await.unwrap();
+            let ids: Vec<&str> = users.iter().map(|user| user.id.as_str()).collect();
+            assert_eq!(ids, ["U1"]);
+        }
+
+        let list_calls = harness.slack.calls("conversations.list").await;
+        assert_eq!(list_calls.len(), 1);
+        assert_eq!(list_calls[0]["types"], "public_channel");
+        assert_eq!(harness.slack.calls("users.list").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn emoji_list_is_cached_until_emoji_change() {
+        let harness = harness().await;
+        harness.slack.respond(
+            "emoji.list",
+            json!({ "ok": true, "emoji": { "partyparrot": "https://emoji.example/parrot.gif" } }),
+        );
+
+        for _ in 0..2 {
+            let list = harness.client.get_emoji_list().await.unwrap();
+            assert!(list["emoji"].get("partyparrot").is_some());
+        }
+        assert_eq!(harness.slack.calls("emoji.list").await.len(), 1);
+
+        harness
+            .client
+            .handle_emoji_changed_event(&json!({ "type": "emoji_changed", "subtype": "add" }))
+            .await
+            .unwrap();
+        harness.client.get_emoji_list().await.unwrap();
+        assert_eq!(harness.slack.calls("emoji.list").await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn matrix_message_is_posted_through_configured_api_base_url() {
+        let harness = harness().await;
+
+        harness
+            .bridge
+            .handle_matrix_message(&MatrixEvent {
+                event_id: Some("$matrix".to_string()),
+                event_type: "m.room.message".to_string(),
+                room_id: "!room:example.org".to_string(),
+                sender: "@alice:example.org".to_string(),
+                state_key: None,
+                content: Some(json!({ "msgtype": "m.text", "body": "hello from matrix" })),
+                timestamp: None,
+            })
+            .await
+            .unwrap();
+
+        let posted = harness
+            .slack
+            .wait_for_call("chat.postMessage", |body| body["channel"] == "C123")
+            .await;
+        assert!(
+            posted["text"]
+                .as_str()
+                .is_some_and(|text| text.contains("hello from matrix"))
+        );
+    }
+}
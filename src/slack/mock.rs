@@ -0,0 +1,483 @@
+//! Offline stand-in for Slack used by tests: a Web API endpoint that records every call
+//! and answers with canned responses, and a Socket Mode websocket that delivers events.
+//! `MockHttpServer` is also usable on its own as a homeserver stub.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex, mpsc};
+use tokio_tungstenite::tungstenite::protocol::Message as WsMessage;
+
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const MOCK_BOT_USER_ID: &str = "UBOT";
+pub(crate) const MOCK_TEAM_ID: &str = "T0001";
+
+#[derive(Debug, Clone)]
+pub(crate) struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: Value,
+    pub raw_body: Vec<u8>,
+}
+
+pub(crate) struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockResponse {
+    pub fn bytes(content_type: &str, body: &[u8]) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body: body.to_vec(),
+        }
+    }
+
+    pub fn redirect(location: &str) -> Self {
+        Self {
+            status: 302,
+            headers: vec![("Location".to_string(), location.to_string())],
+            body: Vec::new(),
+        }
+    }
+}
+
+impl From<(u16, Value)> for MockResponse {
+    fn from((status, body): (u16, Value)) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+        }
+    }
+}
+
+/// Private files by name, with their content type.
+type HostedFiles = HashMap<String, (String, Vec<u8>)>;
+
+pub(crate) type Responder = Arc<dyn Fn(&RecordedRequest) -> MockResponse + Send + Sync>;
+
+/// Bare-bones HTTP/1.1 server answering one request per connection.
+pub(crate) struct MockHttpServer {
+    pub base_url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockHttpServer {
+    pub async fn start(responder: Responder) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock http listener");
+        let base_url = format!("http://{}", listener.local_addr().expect("mock http address"));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let responder = responder.clone();
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let _ = serve_http(stream, responder, recorded).await;
+                });
+            }
+        });
+
+        Self { base_url, requests }
+    }
+
+    pub async fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().await.clone()
+    }
+
+    /// Waits for a request matching `predicate`, panicking after a few seconds.
+    pub async fn wait_for(
+        &self,
+        predicate: impl Fn(&RecordedRequest) -> bool,
+    ) -> RecordedRequest {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            loop {
+                if let Some(found) = self.requests.lock().await.iter().find(|r| predicate(r)) {
+                    return found.clone();
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("timed out waiting for mock request")
+    }
+}
+
+async fn serve_http(
+    mut stream: TcpStream,
+    responder: Responder,
+    recorded: Arc<Mutex<Vec<RecordedRequest>>>,
+) -> Result<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .get("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body_end = buffer.len().min(header_end + content_length);
+    let raw_body = buffer[header_end..body_end].to_vec();
+    let body = serde_json::from_slice(&raw_body).unwrap_or(Value::Null);
+
+    let request = RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+        raw_body,
+    };
+    let response = responder(&request);
+    recorded.lock().await.push(request);
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status,
+        if response.status < 400 { "OK" } else { "Error" }
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Slack Web API plus Socket Mode. Point `auth.api_base_url` at [`MockSlack::api_base_url`].
+pub(crate) struct MockSlack {
+    http: MockHttpServer,
+    overrides: Arc<StdMutex<HashMap<String, Value>>>,
+    files: Arc<StdMutex<HostedFiles>>,
+    envelopes: mpsc::UnboundedSender<Value>,
+    acks: Arc<Mutex<Vec<String>>>,
+    next_envelope: AtomicU64,
+}
+
+impl MockSlack {
+    pub async fn start() -> Self {
+        let socket = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock socket mode listener");
+        let socket_url = format!(
+            "ws://{}/link",
+            socket.local_addr().expect("mock socket mode address")
+        );
+
+        let (envelopes, receiver) = mpsc::unbounded_channel();
+        let acks = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(serve_socket_mode(socket, receiver, acks.clone()));
+
+        let overrides: Arc<StdMutex<HashMap<String, Value>>> = Arc::default();
+        let next_ts = Arc::new(AtomicU64::new(1));
+        let responder_overrides = overrides.clone();
+        let files: Arc<StdMutex<HostedFiles>> = Arc::default();
+        let responder_files = files.clone();
+        let responder: Responder = Arc::new(move |request| {
+            if let Some(response) = serve_private_file(request, &responder_files) {
+                return response;
+            }
+            let api_method = request.path.trim_start_matches("/api/");
+            if let Some(response) = responder_overrides
+                .lock()
+                .expect("mock overrides lock")
+                .get(api_method)
+            {
+                return (200, response.clone()).into();
+            }
+            (
+                200,
+                default_slack_response(api_method, &request.body, &socket_url, &next_ts),
+            )
+                .into()
+        });
+
+        Self {
+            http: MockHttpServer::start(responder).await,
+            overrides,
+            files,
+            envelopes,
+            acks,
+            next_envelope: AtomicU64::new(1),
+        }
+    }
+
+    pub fn api_base_url(&self) -> String {
+        format!("{}/api/", self.http.base_url)
+    }
+
+    /// Hosts a private file and returns its `url_private_download`. Like Slack, the URL
+    /// redirects to the download host, which answers with a login page unless the request
+    /// carries a bearer token.
+    pub fn add_file(&self, name: &str, content_type: &str, data: &[u8]) -> String {
+        self.files
+            .lock()
+            .expect("mock files lock")
+            .insert(name.to_string(), (content_type.to_string(), data.to_vec()));
+        format!("{}/files-pri/{name}", self.http.base_url)
+    }
+
+    /// Requests made for the hosted file `name`, including redirect hops.
+    pub async fn file_requests(&self, name: &str) -> Vec<RecordedRequest> {
+        self.http
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path.ends_with(&format!("/{name}")))
+            .collect()
+    }
+
+    /// Replaces the response for a Web API method, e.g. to return `ok: false`.
+    pub fn respond(&self, api_method: &str, response: Value) {
+        self.overrides
+            .lock()
+            .expect("mock overrides lock")
+            .insert(api_method.to_string(), response);
+    }
+
+    pub async fn calls(&self, api_method: &str) -> Vec<Value> {
+        let path = format!("/api/{api_method}");
+        self.http
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path == path)
+            .map(|request| request.body)
+            .collect()
+    }
+
+    /// Web API requests for `api_method`, with their headers.
+    pub async fn api_requests(&self, api_method: &str) -> Vec<RecordedRequest> {
+        let path = format!("/api/{api_method}");
+        self.http
+            .requests()
+            .await
+            .into_iter()
+            .filter(|request| request.path == path)
+            .collect()
+    }
+
+    pub async fn wait_for_call(
+        &self,
+        api_method: &str,
+        predicate: impl Fn(&Value) -> bool,
+    ) -> Value {
+        let path = format!("/api/{api_method}");
+        self.http
+            .wait_for(|request| request.path == path && predicate(&request.body))
+            .await
+            .body
+    }
+
+    /// Delivers `event` in an `events_api` envelope and returns the envelope id.
+    pub fn push_event(&self, event: Value) -> String {
+        let sequence = self.next_envelope.fetch_add(1, Ordering::Relaxed);
+        let envelope_id = format!("envelope-{sequence}");
+        self.push_envelope(json!({
+            "envelope_id": envelope_id,
+            "type": "events_api",
+            "accepts_response_payload": false,
+            "retry_attempt": 0,
+            "retry_reason": "",
+            "payload": {
+                "type": "event_callback",
+                "team_id": MOCK_TEAM_ID,
+                "event_id": format!("Ev{sequence:08}"),
+                "event_time": 1_700_000_000 + sequence,
+                "event": event,
+            },
+        }));
+        envelope_id
+    }
+
+    pub fn push_envelope(&self, envelope: Value) {
+        self.envelopes
+            .send(envelope)
+            .expect("mock socket mode task stopped");
+    }
+
+    pub async fn wait_for_ack(&self, envelope_id: &str) {
+        tokio::time::timeout(WAIT_TIMEOUT, async {
+            loop {
+                if self.acks.lock().await.iter().any(|id| id == envelope_id) {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("timed out waiting for envelope ack")
+    }
+}
+
+fn serve_private_file(
+    request: &RecordedRequest,
+    files: &StdMutex<HostedFiles>,
+) -> Option<MockResponse> {
+    if let Some(name) = request.path.strip_prefix("/files-pri/") {
+        return Some(MockResponse::redirect(&format!("/download/{name}")));
+    }
+    let name = request.path.strip_prefix("/download/")?;
+    let authorized = request
+        .headers
+        .get("authorization")
+        .is_some_and(|value| value.starts_with("Bearer "));
+    if !authorized {
+        return Some(MockResponse::bytes(
+            "text/html; charset=utf-8",
+            b"<html><body>Sign in to Slack</body></html>",
+        ));
+    }
+    let files = files.lock().expect("mock files lock");
+    Some(match files.get(name) {
+        Some((content_type, data)) => MockResponse::bytes(content_type, data),
+        None => (404, json!({ "ok": false, "error": "file_not_found" })).into(),
+    })
+}
+
+fn default_slack_response(
+    api_method: &str,
+    body: &Value,
+    socket_url: &str,
+    next_ts: &AtomicU64,
+) -> Value {
+    let channel = body.get("channel").cloned().unwrap_or(Value::Null);
+    match api_method {
+        "auth.test" => json!({
+            "ok": true,
+            "user_id": MOCK_BOT_USER_ID,
+            "bot_id": "BBOT",
+            "team_id": MOCK_TEAM_ID,
+        }),
+        "apps.connections.open" => json!({ "ok": true, "url": socket_url }),
+        "chat.postMessage" => json!({
+            "ok": true,
+            "channel": channel,
+            "ts": format!("1700000000.{:06}", next_ts.fetch_add(1, Ordering::Relaxed)),
+        }),
+        "chat.update" => json!({ "ok": true, "channel": channel, "ts": body.get("ts") }),
+        "chat.getPermalink" => {
+            let ts = body.get("message_ts").and_then(Value::as_str).unwrap_or_default();
+            json!({
+                "ok": true,
+                "channel": channel,
+                "permalink": format!(
+                    "https://mock.slack.com/archives/{}/p{}",
+                    channel.as_str().unwrap_or_default(),
+                    ts.replace('.', "")
+                ),
+            })
+        }
+        "users.info" => {
+            let user = body.get("user").and_then(Value::as_str).unwrap_or("U0");
+            json!({
+                "ok": true,
+                "user": {
+                    "id": user,
+                    "name": user.to_lowercase(),
+                    "team_id": MOCK_TEAM_ID,
+                    "profile": { "display_name": format!("User {user}") },
+                },
+            })
+        }
+        "conversations.info" => json!({
+            "ok": true,
+            "channel": { "id": channel, "name": "general" },
+        }),
+        _ => json!({ "ok": true }),
+    }
+}
+
+async fn serve_socket_mode(
+    listener: TcpListener,
+    receiver: mpsc::UnboundedReceiver<Value>,
+    acks: Arc<Mutex<Vec<String>>>,
+) {
+    let receiver = Mutex::new(receiver);
+    while let Ok((stream, _)) = listener.accept().await {
+        let Ok(mut socket) = tokio_tungstenite::accept_async(stream).await else {
+            continue;
+        };
+        let hello = json!({ "type": "hello", "num_connections": 1 });
+        if socket
+            .send(WsMessage::Text(hello.to_string().into()))
+            .await
+            .is_err()
+        {
+            continue;
+        }
+
+        let mut receiver = receiver.lock().await;
+        loop {
+            tokio::select! {
+                envelope = receiver.recv() => {
+                    let Some(envelope) = envelope else {
+                        return;
+                    };
+                    if socket.send(WsMessage::Text(envelope.to_string().into())).await.is_err() {
+                        break;
+                    }
+                }
+                frame = socket.next() => {
+                    match frame {
+                        Some(Ok(WsMessage::Text(text))) => {
+                            let envelope_id = serde_json::from_str::<Value>(&text)
+                                .ok()
+                                .and_then(|ack| ack.get("envelope_id").and_then(Value::as_str).map(ToOwned::to_owned));
+                            if let Some(envelope_id) = envelope_id {
+                                acks.lock().await.push(envelope_id);
+                            }
+                        }
+                        Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {}
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Client-side scheduling for Slack Web API calls. Each method draws from a token bucket
+//! sized for its Slack rate-limit tier, `chat.postMessage` is limited per channel, and a
+//! `429` pauses the bucket for the advertised `Retry-After` before the call is retried.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+
+use crate::web::metrics::Metrics;
+
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const POST_MESSAGE: &str = "chat.postMessage";
+
+/// Slack's published rate-limit tiers, plus the per-channel `chat.postMessage` limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateTier {
+    Tier1,
+    Tier2,
+    Tier3,
+    Tier4,
+    PostMessage,
+}
+
+impl RateTier {
+    pub fn for_method(method: &str) -> Self {
+        match method {
+            POST_MESSAGE => Self::PostMessage,
+            "apps.connections.open" => Self::Tier1,
+            "conversations.create"
+            | "conversations.list"
+            | "conversations.rename"
+            | "conversations.setPurpose"
+            | "conversations.setTopic"
+            | "emoji.list"
+            | "reactions.remove"
+            | "search.messages"
+            | "users.list" => Self::Tier2,
+            "auth.test"
+            | "chat.getPermalink"
+            | "conversations.members"
+            | "files.completeUploadExternal"
+            | "files.getUploadURLExternal"
+            | "users.info"
+            | "users.profile.get" => Self::Tier4,
+            _ => Self::Tier3,
+        }
+    }
+
+    /// Requests per minute Slack allows for the tier. Slack only documents a floor for
+    /// `chat.postMessage` (one per second per channel), which is what is used here.
+    pub fn per_minute(self) -> u32 {
+        match self {
+            Self::Tier1 => 1,
+            Self::Tier2 => 20,
+            Self::Tier3 => 50,
+            Self::Tier4 => 100,
+            Self::PostMessage => 60,
+        }
+    }
+
+    fn burst(self) -> f64 {
+        match self {
+            Self::PostMessage => 1.0,
+            tier => (f64::from(tier.per_minute()) / 4.0).max(1.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: f64, refill_per_sec: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Takes a token and returns how long the caller must wait before using it. Tokens
+    /// may go negative, which queues later callers behind earlier ones.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_per_sec)
+        }
+    }
+
+    fn pause(&mut self, now: Instant, retry_after: Duration) {
+        self.refill(now);
+        self.tokens = self
+            .tokens
+            .min(-retry_after.as_secs_f64() * self.refill_per_sec);
+    }
+}
+
+/// Shared by every clone of a `SlackClient`. The queue bounds how many calls may be in
+/// flight at once; callers beyond that wait for a slot instead of being dropped. Calls
+/// held back by their bucket don't take a slot, so one throttled method or channel can't
+/// stall the others.
+pub struct SlackRateLimiter {
+    buckets: Mutex<HashMap<String, Bucket>>,
+    queue: Semaphore,
+    post_message_interval: Duration,
+    max_retries: u32,
+}
+
+impl SlackRateLimiter {
+    pub fn new(queue_size: usize, post_message_interval: Duration, max_retries: u32) -> Self {
+        Self {
+            buckets: Mutex::new(HashMap::new()),
+            queue: Semaphore::new(queue_size.max(1)),
+            post_message_interval: post_message_interval.max(Duration::from_secs(1)),
+            max_retries,
+        }
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Waits until `method` may be called. The returned permit holds a queue slot and
+    /// should be kept until the response has been read.
+    pub async fn acquire(
+        &self,
+        method: &str,
+        channel: Option<&str>,
+    ) -> Result<SemaphorePermit<'_>> {
+        Metrics::slack_request_queued();
+        let wait = self.reserve(method, channel).await;
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        let permit = self.queue.acquire().await;
+        Metrics::slack_request_dequeued();
+        permit.map_err(|_| anyhow!("Slack request queue is closed"))
+    }
+
+    /// Holds back further calls to `method` after Slack answered with HTTP 429.
+    pub async fn defer(&self, method: &str, channel: Option<&str>, retry_after: Duration) {
+        let now = Instant::now();
+        let key = bucket_key(method, channel);
+        let mut buckets = self.buckets.lock().await;
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| self.new_bucket(method, now));
+        bucket.pause(now, retry_after);
+    }
+
+    async fn reserve(&self, method: &str, channel: Option<&str>) -> Duration {
+        let now = Instant::now();
+        let key = bucket_key(method, channel);
+        let mut buckets = self.buckets.lock().await;
+        buckets
+            .entry(key)
+            .or_insert_with(|| self.new_bucket(method, now))
+            .reserve(now)
+    }
+
+    fn new_bucket(&self, method: &str, now: Instant) -> Bucket {
+        let tier = RateTier::for_method(method);
+        let refill_per_sec = match tier {
+            RateTier::PostMessage => 1.0 / self.post_message_interval.as_secs_f64(),
+            tier => f64::from(tier.per_minute()) / 60.0,
+        };
+        Bucket::new(tier.burst(), refill_per_sec, now)
+    }
+}
+
+fn bucket_key(method: &str, channel: Option<&str>) -> String {
+    match channel {
+        Some(channel) if method == POST_MESSAGE => format!("{method}:{channel}"),
+        _ => method.to_string(),
+    }
+}
+
+/// Reads `Retry-After` (in seconds) from a 429 response, defaulting to one second.
+pub fn retry_after(headers: &HeaderMap) -> Duration {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn methods_map_to_slack_tiers() {
+        assert_eq!(RateTier::for_method("chat.postMessage"), RateTier::PostMessage);
+        assert_eq!(RateTier::for_method("apps.connections.open"), RateTier::Tier1);
+        assert_eq!(RateTier::for_method("emoji.list"), RateTier::Tier2);
+        assert_eq!(RateTier::for_method("chat.update"), RateTier::Tier3);
+        assert_eq!(RateTier::for_method("users.info"), RateTier::Tier4);
+    }
+
+    #[test]
+    fn bucket_queues_callers_once_the_burst_is_spent() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(2.0, 1.0, now);
+
+        assert_eq!(bucket.reserve(now), Duration::ZERO);
+        assert_eq!(bucket.reserve(now), Duration::ZERO);
+        assert_eq!(bucket.reserve(now), Duration::from_secs(1));
+        assert_eq!(bucket.reserve(now), Duration::from_secs(2));
+
+        let later = now + Duration::from_secs(4);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+    }
+
+    #[test]
+    fn pause_holds_the_bucket_for_retry_after() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(5.0, 1.0, now);
+
+        bucket.pause(now, Duration::from_secs(30));
+        assert_eq!(bucket.reserve(now), Duration::from_secs(31));
+    }
+
+    #[tokio::test]
+    async fn throttled_channel_does_not_hold_a_queue_slot() {
+        let limiter = std::sync::Arc::new(SlackRateLimiter::new(1, Duration::from_secs(1), 0));
+        limiter
+            .defer("chat.postMessage", Some("C1"), Duration::from_secs(30))
+            .await;
+
+        let throttled = limiter.clone();
+        let post = tokio::spawn(async move {
+            throttled.acquire("chat.postMessage", Some("C1")).await.map(drop)
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let reaction = tokio::time::timeout(
+            Duration::from_secs(1),
+            limiter.acquire("reactions.add", Some("C1")),
+        )
+        .await;
+        assert!(reaction.is_ok_and(|permit| permit.is_ok()));
+        assert!(!post.is_finished());
+        post.abort();
+    }
+
+    #[test]
+    fn post_message_buckets_are_per_channel() {
+        assert_eq!(bucket_key("chat.postMessage", Some("C1")), "chat.postMessage:C1");
+        assert_eq!(bucket_key("chat.update", Some("C1")), "chat.update");
+    }
+
+    #[test]
+    fn retry_after_defaults_to_one_second() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), Duration::from_secs(1));
+
+        headers.insert(RETRY_AFTER, "12".parse().unwrap());
+        assert_eq!(retry_after(&headers), Duration::from_secs(12));
+    }
+}
@@ -1,6 +1,4 @@
-pub mod alert;
 pub mod error;
 pub mod formatting;
+pub mod hex;
 pub mod logging;
-
-pub use self::alert::AdminNotifier;
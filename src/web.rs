@@ -2,7 +2,6 @@ use std::sync::Arc;
 use std::time::Instant;
 
 use anyhow::Result;
-use once_cell::sync::OnceCell;
 use salvo::prelude::*;
 use tracing::info;
 
@@ -11,14 +10,24 @@ use crate::config::Config;
 use crate::db::DatabaseManager;
 use crate::matrix::MatrixAppservice;
 
+mod auth;
 mod health;
-mod metrics;
+mod media_proxy;
+pub mod metrics;
+mod oauth;
+mod portal;
 mod provisioning;
+mod slack_events;
 mod thirdparty;
 
+use auth::require_homeserver_token;
 use health::{get_status, health_check};
+use media_proxy::proxy_media;
 use metrics::metrics_endpoint;
+use oauth::complete_slack_oauth;
+use portal::query_room_alias;
 use provisioning::{create_bridge, delete_bridge, get_bridge_info, list_rooms};
+use slack_events::receive_slack_event;
 use thirdparty::{get_locations, get_networks, get_protocol, get_users};
 
 #[derive(Clone)]
@@ -29,18 +38,32 @@ pub struct WebState {
     pub started_at: Instant,
 }
 
-static WEB_STATE: OnceCell<WebState> = OnceCell::new();
+struct InjectWebState(WebState);
 
-pub fn web_state() -> &'static WebState {
-    WEB_STATE
-        .get()
-        .expect("web state is not initialized before handler execution")
+#[async_trait]
+impl Handler for InjectWebState {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        depot.inject(self.0.clone());
+    }
+}
+
+/// Shared state of the handlers, injected into the depot of every request.
+pub fn web_state(depot: &Depot) -> &WebState {
+    depot
+        .obtain::<WebState>()
+        .expect("web state is injected before handler execution")
 }
 
 #[derive(Clone)]
 pub struct WebServer {
     config: Arc<Config>,
-    matrix_client: Arc<MatrixAppservice>,
+    state: WebState,
 }
 
 impl WebServer {
@@ -50,16 +73,14 @@ impl WebServer {
         db_manager: Arc<DatabaseManager>,
         bridge: Arc<BridgeCore>,
     ) -> Result<Self> {
-        let _ = WEB_STATE.set(WebState {
-            db_manager,
-            matrix_client: matrix_client.clone(),
-            bridge,
-            started_at: Instant::now(),
-        });
-
         Ok(Self {
             config,
-            matrix_client,
+            state: WebState {
+                db_manager,
+                matrix_client,
+                bridge,
+                started_at: Instant::now(),
+            },
         })
     }
 
@@ -71,22 +92,33 @@ impl WebServer {
         info!("starting web server on {}", bind_addr);
 
         let acceptor = TcpListener::new(bind_addr).bind().await;
-        let appservice_router = self.matrix_client.appservice.router();
-        let main_router = root_router().push(appservice_router);
+        let appservice_router = self.state.matrix_client.appservice.router();
+        let main_router = root_router(self.state.clone()).push(appservice_router);
         Server::new(acceptor).serve(main_router).await;
 
         Ok(())
     }
 }
 
-pub fn root_router() -> Router {
+pub fn root_router(state: WebState) -> Router {
     Router::new()
+        .hoop(InjectWebState(state))
         .push(Router::with_path("health").get(health_check))
         .push(Router::with_path("status").get(get_status))
         .push(Router::with_path("metrics").get(metrics_endpoint))
+        .push(Router::with_path("slack/events").post(receive_slack_event))
+        .push(Router::with_path("slack/oauth").get(complete_slack_oauth))
+        .push(Router::with_path("media/{server_name}/{media_id}").get(proxy_media))
         .push(
             Router::with_path("_matrix/app/v1")
                 .push(Router::with_path("rooms").get(list_rooms))
+                // Portal rooms are created by the bridge so it can record their mapping; the
+                // SDK's alias route creates the room itself and never reports its id.
+                .push(
+                    Router::with_path("rooms/{room_alias}")
+                        .hoop(require_homeserver_token)
+                        .get(query_room_alias),
+                )
                 .push(Router::with_path("bridges").post(create_bridge))
                 .push(
                     Router::with_path("bridges/{id}")
@@ -95,6 +127,7 @@ pub fn root_router() -> Router {
                 )
                 .push(
                     Router::with_path("thirdparty")
+                        .hoop(require_homeserver_token)
                         .push(Router::with_path("protocol").get(get_protocol))
                         .push(Router::with_path("protocol/slack").get(get_protocol))
                         .push(Router::with_path("network").get(get_networks))
@@ -0,0 +1,52 @@
+use salvo::http::header::AUTHORIZATION;
+use salvo::prelude::*;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+
+use crate::web::web_state;
+
+fn render_error(res: &mut Response, status: StatusCode, errcode: &str, message: &str) {
+    res.status_code(status);
+    res.render(Json(json!({ "errcode": errcode, "error": message })));
+}
+
+/// Token the homeserver sent, from the `Authorization` header or the legacy query parameter.
+fn homeserver_token(req: &Request) -> Option<String> {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(ToOwned::to_owned)
+        .or_else(|| req.query::<String>("access_token"))
+}
+
+/// Rejects appservice requests that don't carry the registration's homeserver token.
+#[handler]
+pub async fn require_homeserver_token(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let Some(token) = homeserver_token(req) else {
+        render_error(
+            res,
+            StatusCode::UNAUTHORIZED,
+            "M_UNAUTHORIZED",
+            "missing homeserver token",
+        );
+        ctrl.skip_rest();
+        return;
+    };
+    let config = web_state(depot).matrix_client.config();
+    let expected = config.registration.homeserver_token.as_bytes();
+    if !bool::from(token.as_bytes().ct_eq(expected)) {
+        render_error(
+            res,
+            StatusCode::FORBIDDEN,
+            "M_FORBIDDEN",
+            "invalid homeserver token",
+        );
+        ctrl.skip_rest();
+    }
+}
@@ -9,9 +9,11 @@ pub async fn health_check(res: &mut Response) {
 }
 
 #[handler]
-pub async fn get_status(res: &mut Response) {
-    let state = web_state();
+pub async fn get_status(depot: &mut Depot, res: &mut Response) {
+    let state = web_state(depot);
     let uptime_seconds = state.started_at.elapsed().as_secs();
+    let blocker = state.bridge.blocker();
+    let blocker_status = blocker.get_status().await;
 
     let status = json!({
         "status": "running",
@@ -19,6 +21,14 @@ pub async fn get_status(res: &mut Response) {
         "uptime_seconds": uptime_seconds,
         "bridge": {
             "domain": state.matrix_client.registration_preview().get("url"),
+            "state": blocker_status.state.as_str(),
+            "reason": blocker_status.pause_reason,
+            "active_users": blocker_status.active_users,
+            "user_limit": blocker.user_limit(),
+            "total_rooms": blocker_status.total_rooms,
+            "messages_sent": blocker_status.messages_sent,
+            "messages_received": blocker_status.messages_received,
+            "last_check_seconds_ago": blocker_status.last_check.elapsed().as_secs(),
         }
     });
 
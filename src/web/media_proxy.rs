@@ -0,0 +1,74 @@
+use salvo::http::header::{
+    CONTENT_DISPOSITION, CONTENT_SECURITY_POLICY, CONTENT_TYPE, HeaderValue,
+    X_CONTENT_TYPE_OPTIONS,
+};
+use salvo::prelude::*;
+use serde_json::json;
+use tracing::warn;
+
+use crate::media::parse_mxc;
+use crate::web::web_state;
+
+fn render_error(res: &mut Response, status: StatusCode, message: &str) {
+    res.status_code(status);
+    res.render(Json(json!({ "error": message })));
+}
+
+#[handler]
+pub async fn proxy_media(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let state = web_state(depot);
+    let Some(proxy) = state.bridge.media_proxy() else {
+        render_error(res, StatusCode::NOT_FOUND, "media proxy is disabled");
+        return;
+    };
+
+    let server_name = req.param::<String>("server_name").unwrap_or_default();
+    let media_id = req.param::<String>("media_id").unwrap_or_default();
+    let expires = req.query::<i64>("expires").unwrap_or_default();
+    let signature = req.query::<String>("sig").unwrap_or_default();
+    if parse_mxc(&format!("mxc://{server_name}/{media_id}")).is_none() {
+        render_error(res, StatusCode::NOT_FOUND, "unknown media");
+        return;
+    }
+    let now = chrono::Utc::now().timestamp();
+    if !proxy.verify(&server_name, &media_id, expires, &signature, now) {
+        render_error(res, StatusCode::FORBIDDEN, "invalid or expired media link");
+        return;
+    }
+
+    let media = match state.matrix_client.open_media(&server_name, &media_id).await {
+        Ok(media) => media,
+        Err(err) => {
+            warn!(
+                "media proxy failed to fetch mxc://{}/{}: {}",
+                server_name, media_id, err
+            );
+            render_error(res, StatusCode::BAD_GATEWAY, "failed to fetch media");
+            return;
+        }
+    };
+
+    // Served from the bridge's own origin, so the content must never run as a page.
+    let disposition = if ["image/", "video/", "audio/"]
+        .iter()
+        .any(|prefix| media.content_type.starts_with(prefix))
+    {
+        "inline"
+    } else {
+        "attachment"
+    };
+    let filename = media.filename.replace(['"', '\\'], "_");
+    let headers = res.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&media.content_type) {
+        headers.insert(CONTENT_TYPE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("{disposition}; filename=\"{filename}\"")) {
+        headers.insert(CONTENT_DISPOSITION, value);
+    }
+    headers.insert(
+        CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("sandbox; default-src 'none'"),
+    );
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    res.stream(media.into_stream());
+}
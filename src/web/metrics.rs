@@ -21,6 +21,11 @@ static EDITS_PROCESSED: AtomicU64 = AtomicU64::new(0);
 static DELETES_PROCESSED: AtomicU64 = AtomicU64::new(0);
 static ATTACHMENTS_UPLOADED: AtomicU64 = AtomicU64::new(0);
 static EMOJI_CONVERTED: AtomicU64 = AtomicU64::new(0);
+static SLACK_REQUEST_QUEUE_DEPTH: AtomicU64 = AtomicU64::new(0);
+static SLACK_RATE_LIMITED: AtomicU64 = AtomicU64::new(0);
+static SLACK_REQUEST_RETRIES: AtomicU64 = AtomicU64::new(0);
+static SLACK_EVENTS_REDELIVERED: AtomicU64 = AtomicU64::new(0);
+static SLACK_EVENTS_DUPLICATE: AtomicU64 = AtomicU64::new(0);
 
 pub struct Metrics {
     started_at: Instant,
@@ -107,6 +112,32 @@ impl Metrics {
     pub fn emoji_converted() {
         EMOJI_CONVERTED.fetch_add(1, Ordering::Relaxed);
     }
+
+    pub fn slack_request_queued() {
+        SLACK_REQUEST_QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn slack_request_dequeued() {
+        let _ = SLACK_REQUEST_QUEUE_DEPTH.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+            Some(depth.saturating_sub(1))
+        });
+    }
+
+    pub fn slack_rate_limited() {
+        SLACK_RATE_LIMITED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn slack_request_retried() {
+        SLACK_REQUEST_RETRIES.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn slack_event_redelivered() {
+        SLACK_EVENTS_REDELIVERED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn slack_event_duplicate() {
+        SLACK_EVENTS_DUPLICATE.fetch_add(1, Ordering::Relaxed);
+    }
 }
 
 pub fn format_prometheus() -> String {
@@ -129,6 +160,11 @@ pub fn format_prometheus() -> String {
     let deletes = DELETES_PROCESSED.load(Ordering::Relaxed);
     let attachments = ATTACHMENTS_UPLOADED.load(Ordering::Relaxed);
     let emoji = EMOJI_CONVERTED.load(Ordering::Relaxed);
+    let slack_queue_depth = SLACK_REQUEST_QUEUE_DEPTH.load(Ordering::Relaxed);
+    let slack_rate_limited = SLACK_RATE_LIMITED.load(Ordering::Relaxed);
+    let slack_retries = SLACK_REQUEST_RETRIES.load(Ordering::Relaxed);
+    let slack_redelivered = SLACK_EVENTS_REDELIVERED.load(Ordering::Relaxed);
+    let slack_duplicates = SLACK_EVENTS_DUPLICATE.load(Ordering::Relaxed);
 
     let total_cache = cache_hits + cache_misses;
     let cache_hit_rate = if total_cache > 0 {
@@ -219,6 +255,26 @@ attachments_uploaded_total {}
 # HELP emoji_converted_total Total number of emojis converted
 # TYPE emoji_converted_total counter
 emoji_converted_total {}
+
+# HELP slack_request_queue_depth Slack API calls waiting for a rate-limit slot
+# TYPE slack_request_queue_depth gauge
+slack_request_queue_depth {}
+
+# HELP slack_rate_limited_total Slack API responses with HTTP 429
+# TYPE slack_rate_limited_total counter
+slack_rate_limited_total {}
+
+# HELP slack_request_retries_total Slack API calls retried after Retry-After
+# TYPE slack_request_retries_total counter
+slack_request_retries_total {}
+
+# HELP slack_events_redelivered_total Slack event deliveries marked as retries
+# TYPE slack_events_redelivered_total counter
+slack_events_redelivered_total {}
+
+# HELP slack_events_duplicate_total Slack events dropped because they were already handled
+# TYPE slack_events_duplicate_total counter
+slack_events_duplicate_total {}
 "#,
         uptime,
         matrix_received,
@@ -239,6 +295,11 @@ emoji_converted_total {}
         deletes,
         attachments,
         emoji,
+        slack_queue_depth,
+        slack_rate_limited,
+        slack_retries,
+        slack_redelivered,
+        slack_duplicates,
     )
 }
 
@@ -265,6 +326,10 @@ mod tests {
         Metrics::delete_processed();
         Metrics::attachment_uploaded();
         Metrics::emoji_converted();
+        Metrics::slack_rate_limited();
+        Metrics::slack_request_retried();
+        Metrics::slack_event_redelivered();
+        Metrics::slack_event_duplicate();
 
         assert_eq!(MATRIX_MESSAGES_RECEIVED.load(Ordering::Relaxed), 1);
         assert_eq!(MATRIX_MESSAGES_SUCCESS.load(Ordering::Relaxed), 1);
@@ -276,6 +341,10 @@ mod tests {
         assert_eq!(DELETES_PROCESSED.load(Ordering::Relaxed), 1);
         assert_eq!(ATTACHMENTS_UPLOADED.load(Ordering::Relaxed), 1);
         assert_eq!(EMOJI_CONVERTED.load(Ordering::Relaxed), 1);
+        assert_eq!(SLACK_RATE_LIMITED.load(Ordering::Relaxed), 1);
+        assert_eq!(SLACK_REQUEST_RETRIES.load(Ordering::Relaxed), 1);
+        assert!(SLACK_EVENTS_REDELIVERED.load(Ordering::Relaxed) >= 1);
+        assert!(SLACK_EVENTS_DUPLICATE.load(Ordering::Relaxed) >= 1);
     }
 
     #[test]
@@ -294,5 +363,10 @@ mod tests {
         assert!(output.contains("deletes_processed_total"));
         assert!(output.contains("attachments_uploaded_total"));
         assert!(output.contains("emoji_converted_total"));
+        assert!(output.contains("slack_request_queue_depth"));
+        assert!(output.contains("slack_rate_limited_total"));
+        assert!(output.contains("slack_request_retries_total"));
+        assert!(output.contains("slack_events_redelivered_total"));
+        assert!(output.contains("slack_events_duplicate_total"));
     }
 }
@@ -0,0 +1,30 @@
+use salvo::prelude::*;
+use tracing::warn;
+
+use crate::web::web_state;
+
+/// Slack redirects here after a `!slack login`; the page is shown in the user's browser.
+#[handler]
+pub async fn complete_slack_oauth(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    if let Some(error) = req.query::<String>("error") {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render(format!("Slack login was cancelled: {error}"));
+        return;
+    }
+    let code = req.query::<String>("code").unwrap_or_default();
+    let state = req.query::<String>("state").unwrap_or_default();
+    if code.is_empty() || state.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        res.render("Missing code or state, run `!slack login` again.");
+        return;
+    }
+
+    match web_state(depot).bridge.complete_slack_login(&code, &state).await {
+        Ok(message) => res.render(message),
+        Err(err) => {
+            warn!("slack login failed: {}", err);
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(format!("Slack login failed: {err}"));
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use salvo::prelude::*;
+use serde_json::json;
+use tracing::warn;
+
+use crate::web::web_state;
+
+fn render_error(res: &mut Response, status: StatusCode, errcode: &str, message: &str) {
+    res.status_code(status);
+    res.render(Json(json!({ "errcode": errcode, "error": message })));
+}
+
+/// Appservice room alias query. Portal aliases of Slack channels are created on demand
+/// so they can be joined straight from the room directory.
+#[handler]
+pub async fn query_room_alias(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let alias = req.param::<String>("room_alias").unwrap_or_default();
+    match web_state(depot).bridge.handle_room_alias_query(&alias).await {
+        Ok(true) => res.render(Json(json!({}))),
+        Ok(false) => render_error(res, StatusCode::NOT_FOUND, "M_NOT_FOUND", "no such room"),
+        Err(err) => {
+            warn!("failed to create portal room for {}: {}", alias, err);
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "M_UNKNOWN",
+                "failed to create room",
+            );
+        }
+    }
+}
@@ -11,11 +11,11 @@ fn render_error(res: &mut Response, status: StatusCode, message: &str) {
 }
 
 #[handler]
-pub async fn list_rooms(req: &mut Request, res: &mut Response) {
+pub async fn list_rooms(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let limit = req.query::<i64>("limit").unwrap_or(100).clamp(1, 1000);
     let offset = req.query::<i64>("offset").unwrap_or(0).max(0);
 
-    match web_state()
+    match web_state(depot)
         .db_manager
         .room_store()
         .list_room_mappings(limit, offset)
@@ -40,7 +40,7 @@ pub async fn list_rooms(req: &mut Request, res: &mut Response) {
 }
 
 #[handler]
-pub async fn create_bridge(req: &mut Request, res: &mut Response) {
+pub async fn create_bridge(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let matrix_room_id = match req.query::<String>("matrix_room_id") {
         Some(v) if !v.is_empty() => v,
         _ => {
@@ -67,7 +67,7 @@ pub async fn create_bridge(req: &mut Request, res: &mut Response) {
         .query::<String>("slack_team_id")
         .unwrap_or_else(|| "unknown_guild".to_string());
 
-    let bridge = web_state().bridge.clone();
+    let bridge = web_state(depot).bridge.clone();
 
     match bridge
         .bridge_matrix_room(&matrix_room_id, &slack_team_id, &slack_channel_id)
@@ -91,7 +91,7 @@ pub async fn create_bridge(req: &mut Request, res: &mut Response) {
 }
 
 #[handler]
-pub async fn delete_bridge(req: &mut Request, res: &mut Response) {
+pub async fn delete_bridge(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let id = match req.param::<i64>("id") {
         Some(v) if v > 0 => v,
         _ => {
@@ -101,7 +101,7 @@ pub async fn delete_bridge(req: &mut Request, res: &mut Response) {
     };
 
     // Find mapping by ID first to get matrix_room_id
-    let room_store = web_state().db_manager.room_store();
+    let room_store = web_state(depot).db_manager.room_store();
     let mapping = match room_store.get_room_by_id(id).await {
         Ok(Some(m)) => m,
         Ok(None) => {
@@ -114,7 +114,7 @@ pub async fn delete_bridge(req: &mut Request, res: &mut Response) {
         }
     };
 
-    match web_state()
+    match web_state(depot)
         .bridge
         .unbridge_matrix_room(&mapping.matrix_room_id)
         .await
@@ -129,7 +129,7 @@ pub async fn delete_bridge(req: &mut Request, res: &mut Response) {
 }
 
 #[handler]
-pub async fn get_bridge_info(req: &mut Request, res: &mut Response) {
+pub async fn get_bridge_info(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let id = match req.param::<i64>("id") {
         Some(v) if v > 0 => v,
         _ => {
@@ -138,7 +138,7 @@ pub async fn get_bridge_info(req: &mut Request, res: &mut Response) {
         }
     };
 
-    match web_state().db_manager.room_store().get_room_by_id(id).await {
+    match web_state(depot).db_manager.room_store().get_room_by_id(id).await {
         Ok(Some(mapping)) => {
             res.render(Json(json!({ "mapping": mapping })));
         }
@@ -0,0 +1,205 @@
+use salvo::prelude::*;
+use serde_json::{Value, json};
+use tracing::{debug, warn};
+
+use crate::config::SlackEventMode;
+use crate::slack::events_api::{
+    RETRY_NUM_HEADER, RETRY_REASON_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER, verify_signature,
+};
+use crate::web::web_state;
+
+fn render_error(res: &mut Response, status: StatusCode, message: &str) {
+    res.status_code(status);
+    res.render(Json(json!({ "error": message })));
+}
+
+#[handler]
+pub async fn receive_slack_event(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let state = web_state(depot);
+    let config = state.matrix_client.config();
+    let signing_secret = match (&config.auth.event_mode, config.auth.signing_secret.as_deref()) {
+        (SlackEventMode::Http, Some(secret)) if !secret.trim().is_empty() => secret.trim(),
+        _ => {
+            render_error(res, StatusCode::NOT_FOUND, "events api receiver is disabled");
+            return;
+        }
+    };
+
+    let timestamp = req.header::<String>(TIMESTAMP_HEADER).unwrap_or_default();
+    let signature = req.header::<String>(SIGNATURE_HEADER).unwrap_or_default();
+    let retry_attempt = req.header::<u64>(RETRY_NUM_HEADER).unwrap_or(0);
+    let retry_reason = req.header::<String>(RETRY_REASON_HEADER).unwrap_or_default();
+    let body = match req.payload().await {
+        Ok(body) => body.clone(),
+        Err(err) => {
+            render_error(res, StatusCode::BAD_REQUEST, &format!("invalid body: {err}"));
+            return;
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    if !verify_signature(signing_secret, &timestamp, &body, &signature, now) {
+        warn!("rejected slack events api request with invalid signature");
+        render_error(res, StatusCode::UNAUTHORIZED, "invalid slack signature");
+        return;
+    }
+
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(err) => {
+            render_error(res, StatusCode::BAD_REQUEST, &format!("invalid JSON: {err}"));
+            return;
+        }
+    };
+
+    match payload.get("type").and_then(Value::as_str) {
+        Some("url_verification") => {
+            let challenge = payload
+                .get("challenge")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            res.render(Json(json!({ "challenge": challenge })));
+        }
+        Some("event_callback") => {
+            // Slack expects an answer within three seconds, so the event is handled in the
+            // background and redeliveries are caught by the dedup in the dispatcher.
+            let slack_client = state.bridge.slack_client().await;
+            tokio::spawn(async move {
+                if let Err(err) = slack_client
+                    .dispatch_event_callback(&payload, None, retry_attempt, &retry_reason)
+                    .await
+                {
+                    warn!("events api payload handling failed: {}", err);
+                }
+            });
+            res.status_code(StatusCode::OK);
+        }
+        other => {
+            debug!("ignoring slack events api request type={:?}", other);
+            res.status_code(StatusCode::OK);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::sync::Arc;
+
+    use hmac::{Hmac, Mac};
+    use salvo::test::{RequestBuilder, ResponseExt, TestClient};
+    use serde_json::{Value, json};
+    use sha2::Sha256;
+
+    use crate::bridge::BridgeCore;
+    use crate::config::Config;
+    use crate::db::DatabaseManager;
+    use crate::matrix::MatrixAppservice;
+    use crate::slack::SlackClient;
+    use crate::utils::hex::encode_hex;
+    use crate::web::{WebState, root_router};
+
+    use salvo::http::StatusCode;
+
+    const SECRET: &str = "signing-secret";
+    const URL: &str = "http://127.0.0.1/slack/events";
+
+    /// Web service for a bridge whose `auth` section ends with `auth_config`. Nothing in these
+    /// tests reaches the homeserver or Slack, so both point at closed ports.
+    async fn service(dir: &tempfile::TempDir, auth_config: &str) -> salvo::Service {
+        let config: Config = serde_yaml::from_str(&format!(
+            r#"
+bridge:
+  domain: example.org
+  homeserver_url: "http://127.0.0.1:9"
+registration:
+  bridge_id: slack
+  appservice_token: as_token
+  homeserver_token: hs_token
+  sender_localpart: _slack_
+auth:
+  bot_token: xoxb-test
+  app_token: xapp-test
+  api_base_url: "http://127.0.0.1:9/api/"
+{auth_config}
+logging: {{}}
+database:
+  url: "sqlite://{db}"
+room: {{}}
+channel: {{}}
+ghosts: {{}}
+"#,
+            db = dir.path().join("bridge.db").display(),
+        ))
+        .unwrap();
+        let config = Arc::new(config);
+
+        let db_manager = Arc::new(DatabaseManager::new(&config.database).await.unwrap());
+        db_manager.migrate().await.unwrap();
+        let matrix_client = Arc::new(MatrixAppservice::new(config.clone()).await.unwrap());
+        let slack_client = Arc::new(SlackClient::new(config).await.unwrap());
+        let bridge = Arc::new(BridgeCore::new(
+            matrix_client.clone(),
+            slack_client,
+            db_manager.clone(),
+        ));
+        salvo::Service::new(root_router(WebState {
+            db_manager,
+            matrix_client,
+            bridge,
+            started_at: std::time::Instant::now(),
+        }))
+    }
+
+    fn signed(body: &Value, secret: &str) -> RequestBuilder {
+        let body = body.to_string();
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("v0:{timestamp}:{body}").as_bytes());
+        let signature = format!("v0={}", encode_hex(&mac.finalize().into_bytes()));
+        TestClient::post(URL)
+            .add_header("x-slack-request-timestamp", timestamp, true)
+            .add_header("x-slack-signature", signature, true)
+            .raw_json(body)
+    }
+
+    const HTTP_MODE: &str = "  event_mode: http\n  signing_secret: signing-secret";
+
+    #[tokio::test]
+    async fn url_verification_echoes_the_challenge() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = service(&dir, HTTP_MODE).await;
+
+        let body = json!({ "type": "url_verification", "challenge": "abc123" });
+        let mut res = signed(&body, SECRET).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::OK));
+        let payload: Value = res.take_json().await.unwrap();
+        assert_eq!(payload, json!({ "challenge": "abc123" }));
+    }
+
+    #[tokio::test]
+    async fn requests_with_a_bad_signature_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = service(&dir, HTTP_MODE).await;
+
+        let body = json!({ "type": "url_verification", "challenge": "abc123" });
+        let forged = signed(&body, "other-secret").send(&service).await;
+        assert_eq!(forged.status_code, Some(StatusCode::UNAUTHORIZED));
+        let unsigned = TestClient::post(URL)
+            .raw_json(body.to_string())
+            .send(&service)
+            .await;
+        assert_eq!(unsigned.status_code, Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn receiver_is_disabled_in_socket_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let service = service(&dir, "").await;
+
+        let body = json!({ "type": "url_verification", "challenge": "abc123" });
+        let mut res = signed(&body, SECRET).send(&service).await;
+        assert_eq!(res.status_code, Some(StatusCode::NOT_FOUND));
+        let payload: Value = res.take_json().await.unwrap();
+        assert_eq!(payload["error"], "events api receiver is disabled");
+    }
+}
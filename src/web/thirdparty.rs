@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use salvo::prelude::*;
 use serde::Serialize;
 use serde_json::json;
+use tracing::warn;
 
 use crate::web::web_state;
 
@@ -95,15 +96,15 @@ fn protocol_payload(bot_user_id: &str) -> ThirdPartyProtocol {
 }
 
 #[handler]
-pub async fn get_protocol(res: &mut Response) {
-    let matrix_client = &web_state().matrix_client;
+pub async fn get_protocol(depot: &mut Depot, res: &mut Response) {
+    let matrix_client = &web_state(depot).matrix_client;
     let bot_user_id = matrix_client.bot_user_id();
     res.render(Json(protocol_payload(&bot_user_id)));
 }
 
 #[handler]
-pub async fn get_networks(res: &mut Response) {
-    let room_store = web_state().db_manager.room_store();
+pub async fn get_networks(depot: &mut Depot, res: &mut Response) {
+    let room_store = web_state(depot).db_manager.room_store();
     match room_store.list_room_mappings(i64::MAX, 0).await {
         Ok(mappings) => {
             let mut by_guild: HashMap<String, ThirdPartyNetwork> = HashMap::new();
@@ -132,37 +133,71 @@ pub async fn get_networks(res: &mut Response) {
     }
 }
 
+/// Upper bound on the entries returned by a location or user lookup.
+const MAX_LOOKUP_RESULTS: usize = 100;
+
+/// Whether a lookup field value matches any of `candidates`. Matching ignores case and
+/// a leading `#` or `@`; missing or empty values match everything.
+fn lookup_matches(query: Option<&str>, candidates: &[&str]) -> bool {
+    let Some(query) = query
+        .map(|query| query.trim().trim_start_matches(['#', '@']).to_lowercase())
+        .filter(|query| !query.is_empty())
+    else {
+        return true;
+    };
+    candidates
+        .iter()
+        .any(|candidate| candidate.to_lowercase().contains(&query))
+}
+
+/// Public channels of the workspace plus every bridged channel, searched by id or name.
 #[handler]
-pub async fn get_locations(req: &mut Request, res: &mut Response) {
+pub async fn get_locations(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let guild_filter = req.query::<String>("guild_id");
     let channel_filter = req.query::<String>("channel_id");
-    let domain = web_state().matrix_client.config().bridge.domain.clone();
+    let state = web_state(depot);
 
-    let room_store = web_state().db_manager.room_store();
-    match room_store.list_room_mappings(i64::MAX, 0).await {
+    let mut channels: Vec<(String, String, String)> = match state
+        .bridge
+        .slack_client()
+        .await
+        .list_public_channels()
+        .await
+    {
+        Ok(channels) => channels
+            .iter()
+            .map(|channel| {
+                (
+                    channel.guild_id.clone(),
+                    channel.id.clone(),
+                    channel.name.clone(),
+                )
+            })
+            .collect(),
+        Err(err) => {
+            warn!("failed to list slack channels for location lookup: {}", err);
+            Vec::new()
+        }
+    };
+    match state
+        .db_manager
+        .room_store()
+        .list_room_mappings(i64::MAX, 0)
+        .await
+    {
         Ok(mappings) => {
-            let locations: Vec<ThirdPartyLocation> = mappings
-                .into_iter()
-                .filter(|mapping| {
-                    guild_filter
-                        .as_ref()
-                        .map(|guild| mapping.slack_team_id == *guild)
-                        .unwrap_or(true)
-                        && channel_filter
-                            .as_ref()
-                            .map(|channel| mapping.slack_channel_id == *channel)
-                            .unwrap_or(true)
-                })
-                .map(|mapping| ThirdPartyLocation {
-                    alias: format!("#_slack_{}:{}", mapping.slack_channel_id, domain),
-                    protocol: "slack".to_string(),
-                    fields: HashMap::from([
-                        ("guild_id".to_string(), mapping.slack_team_id),
-                        ("channel_id".to_string(), mapping.slack_channel_id),
-                    ]),
-                })
-                .collect();
-            res.render(Json(locations));
+            for mapping in mappings {
+                if !channels
+                    .iter()
+                    .any(|(_, id, _)| *id == mapping.slack_channel_id)
+                {
+                    channels.push((
+                        mapping.slack_team_id,
+                        mapping.slack_channel_id,
+                        mapping.slack_channel_name,
+                    ));
+                }
+            }
         }
         Err(err) => {
             render_error(
@@ -170,35 +205,59 @@ pub async fn get_locations(req: &mut Request, res: &mut Response) {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 &format!("database error: {}", err),
             );
+            return;
         }
     }
+
+    let locations: Vec<ThirdPartyLocation> = channels
+        .into_iter()
+        .filter(|(guild_id, channel_id, name)| {
+            guild_filter
+                .as_ref()
+                .map(|guild| guild_id == guild)
+                .unwrap_or(true)
+                && lookup_matches(channel_filter.as_deref(), &[channel_id, name])
+        })
+        .take(MAX_LOOKUP_RESULTS)
+        .map(|(guild_id, channel_id, name)| ThirdPartyLocation {
+            alias: state.matrix_client.portal_alias(&channel_id),
+            protocol: "slack".to_string(),
+            fields: HashMap::from([
+                ("guild_id".to_string(), guild_id),
+                ("channel_id".to_string(), channel_id),
+                ("channel_name".to_string(), name),
+            ]),
+        })
+        .collect();
+    res.render(Json(locations));
 }
 
+/// Members of the workspace plus every bridged user, searched by id or display name.
 #[handler]
-pub async fn get_users(req: &mut Request, res: &mut Response) {
+pub async fn get_users(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let user_filter = req
         .query::<String>("userid")
         .or_else(|| req.query::<String>("user_id"));
-    let domain = web_state().matrix_client.config().bridge.domain.clone();
+    let state = web_state(depot);
 
-    let user_store = web_state().db_manager.user_store();
-    match user_store.get_all_user_ids().await {
+    let mut users: Vec<(String, String)> =
+        match state.bridge.slack_client().await.list_users().await {
+            Ok(users) => users
+                .iter()
+                .map(|user| (user.id.clone(), user.username.clone()))
+                .collect(),
+            Err(err) => {
+                warn!("failed to list slack users for user lookup: {}", err);
+                Vec::new()
+            }
+        };
+    match state.db_manager.user_store().get_all_user_ids().await {
         Ok(user_ids) => {
-            let users: Vec<ThirdPartyUser> = user_ids
-                .into_iter()
-                .filter(|user_id| {
-                    user_filter
-                        .as_ref()
-                        .map(|filter| user_id.contains(filter))
-                        .unwrap_or(true)
-                })
-                .map(|slack_user_id| ThirdPartyUser {
-                    userid: format!("@_slack_{}:{}", slack_user_id, domain),
-                    protocol: "slack".to_string(),
-                    fields: HashMap::from([("userid".to_string(), slack_user_id)]),
-                })
-                .collect();
-            res.render(Json(users));
+            for user_id in user_ids {
+                if !users.iter().any(|(id, _)| *id == user_id) {
+                    users.push((user_id.clone(), user_id));
+                }
+            }
         }
         Err(err) => {
             render_error(
@@ -206,13 +265,29 @@ pub async fn get_users(req: &mut Request, res: &mut Response) {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 &format!("database error: {}", err),
             );
+            return;
         }
     }
+
+    let users: Vec<ThirdPartyUser> = users
+        .into_iter()
+        .filter(|(user_id, name)| lookup_matches(user_filter.as_deref(), &[user_id, name]))
+        .take(MAX_LOOKUP_RESULTS)
+        .map(|(slack_user_id, name)| ThirdPartyUser {
+            userid: state.matrix_client.ghost_user_id(&slack_user_id),
+            protocol: "slack".to_string(),
+            fields: HashMap::from([
+                ("userid".to_string(), slack_user_id),
+                ("display_name".to_string(), name),
+            ]),
+        })
+        .collect();
+    res.render(Json(users));
 }
 
 #[cfg(test)]
 mod tests {
-    use super::protocol_payload;
+    use super::{lookup_matches, protocol_payload};
 
     #[test]
     fn protocol_payload_contains_expected_fields() {
@@ -221,5 +296,14 @@ mod tests {
         assert!(!payload.location_fields.is_empty());
         assert_eq!(payload.instances[0].network_id, "slack");
     }
-}
 
+    #[test]
+    fn lookup_matches_ids_and_names_ignoring_case_and_sigils() {
+        assert!(lookup_matches(None, &["C123", "general"]));
+        assert!(lookup_matches(Some(" "), &["C123", "general"]));
+        assert!(lookup_matches(Some("#Gen"), &["C123", "general"]));
+        assert!(lookup_matches(Some("c12"), &["C123", "general"]));
+        assert!(lookup_matches(Some("@alice"), &["U1", "Alice Smith"]));
+        assert!(!lookup_matches(Some("random"), &["C123", "general"]));
+    }
+}